@@ -5,13 +5,16 @@ layout(r8ui, binding = 0) uniform uimage2D current_state;
 layout(r8ui, binding = 1) uniform uimage2D next_state;
 
 uniform ivec2 world_size;
-uniform uvec2 tile_offset;
+// See `terrain::tiles::MapDispatch`
+uniform uvec2 u_tile_offset;
 // uniform uint current_tick;
 
+#include<terrain/topology.glsl>
+
 void calc_cell(ivec2 pos);
 
 void main() {
-    uvec2 pos = gl_WorkGroupID.xy * gl_WorkGroupSize.xy + gl_LocalInvocationID.xy + tile_offset;
+    uvec2 pos = gl_WorkGroupID.xy * gl_WorkGroupSize.xy + gl_LocalInvocationID.xy + u_tile_offset;
     if (pos.y >= world_size.y || pos.x >= world_size.x) {
         return;
     }
@@ -21,18 +24,26 @@ void main() {
 void calc_cell(ivec2 pos) {
     uint neighbours = 0;
 
-    neighbours += imageLoad(current_state, pos + ivec2(-1, -1)).x;
-    neighbours += imageLoad(current_state, pos + ivec2(-1, 0)).x;
-    neighbours += imageLoad(current_state, pos + ivec2(-1, 1)).x;
-    neighbours += imageLoad(current_state, pos + ivec2(0, -1)).x;
-    neighbours += imageLoad(current_state, pos + ivec2(0, 1)).x;
-    neighbours += imageLoad(current_state, pos + ivec2(1, -1)).x;
-    neighbours += imageLoad(current_state, pos + ivec2(1, 0)).x;
-    neighbours += imageLoad(current_state, pos + ivec2(1, 1)).x;
+    neighbours += imageLoad(current_state, wrap_cell(pos + ivec2(-1, -1), world_size)).x;
+    neighbours += imageLoad(current_state, wrap_cell(pos + ivec2(-1, 0), world_size)).x;
+    neighbours += imageLoad(current_state, wrap_cell(pos + ivec2(-1, 1), world_size)).x;
+    neighbours += imageLoad(current_state, wrap_cell(pos + ivec2(0, -1), world_size)).x;
+    neighbours += imageLoad(current_state, wrap_cell(pos + ivec2(0, 1), world_size)).x;
+    neighbours += imageLoad(current_state, wrap_cell(pos + ivec2(1, -1), world_size)).x;
+    neighbours += imageLoad(current_state, wrap_cell(pos + ivec2(1, 0), world_size)).x;
+    neighbours += imageLoad(current_state, wrap_cell(pos + ivec2(1, 1), world_size)).x;
 
     bool cell_updated = imageLoad(current_state, pos).x > 0 ? (neighbours == 2 || neighbours == 3) : (neighbours == 3);
 
-    if (pos.x == 0 || pos.y == 0 || pos.x == (world_size.x - 1) || pos.y == (world_size.y - 1)) {
+    // Only the edges which do not wrap, the others have their neighbours on the other side
+    bool on_edge = false;
+#if !WORLD_WRAP_X
+    on_edge = on_edge || pos.x == 0 || pos.x == (world_size.x - 1);
+#endif
+#if !WORLD_WRAP_Y
+    on_edge = on_edge || pos.y == 0 || pos.y == (world_size.y - 1);
+#endif
+    if (on_edge) {
         cell_updated = !cell_updated;
     }
 
@@ -10,22 +10,28 @@ uniform float u_camera_zoom;
 uniform int u_antialiasing;
 
 uniform uint u_render_type;
+uniform float u_sea_level;      // `WorldSettings::sea_level`
 
 uniform usampler2D u_world_texture;
 uniform isampler2D u_landscape;
+uniform usampler2D u_lakes;
+uniform usampler2D u_rivers;    // Strahler order, 0 - no river
+uniform usampler2D u_swamps;
+uniform isampler2D u_scree;     // material deposited by thermal erosion
+uniform sampler2D u_water;      // water depth of the shallow water erosion
 
 float get_pixel(ivec2 pos);
 
 #include<terrain/terrain.glsl>
 
+// `WORLD_WRAP_X` and `WORLD_WRAP_Y` come from the world topology
+#include<terrain/topology.glsl>
+
 #define GRADIENT_GLSL__GET_PIXEL(pos) get_pixel(pos)
 #define GRADIENT_GLSL__MAP_WIDTH u_world_size.x
 #define GRADIENT_GLSL__MAP_HEIGHT u_world_size.y
 #include<terrain/gradient.glsl>
 
-// #define WORLD_WRAP_X
-// #define WORLD_WRAP_Y
-
 float cube(float x);
 float interp1(float x);
 vec4 get_world_color(vec2 world_pos);
@@ -87,11 +93,11 @@ vec4 render(vec2 frag_pos) {
     float cam_scale = pow(2.0, u_camera_zoom);
     vec2 world_coords = (frag_pos + u_camera_pos * cam_scale) / cam_scale;
 
-    #ifdef WORLD_WRAP_X
+    #if WORLD_WRAP_X
         world_coords.x = mod(mod(world_coords.x, u_world_size.x) + u_world_size.x, u_world_size.x);
     #endif
 
-    #ifdef WORLD_WRAP_Y
+    #if WORLD_WRAP_Y
         world_coords.y = mod(mod(world_coords.y, u_world_size.y) + u_world_size.y, u_world_size.y);
     #endif
 
@@ -108,7 +114,6 @@ vec4 render(vec2 frag_pos) {
     return color;
 }
 
-#define INT_VAL_RANGE 1000000
 float get_pixel(ivec2 pos) {
     int int_val = texelFetch(u_landscape, pos, 0).x;
     return float(int_val) / float(INT_VAL_RANGE);
@@ -119,19 +124,29 @@ vec4 get_world_color(vec2 world_coords) {
 
     //uint terrain_type = texelFetch(u_world_texture, ivec2(world_coords), 0).x;
     int i_height = texelFetch(u_landscape, ivec2(world_coords), 0).x;
-    float height = float(i_height) / 1000000.0;
+    float height = float(i_height) / float(INT_VAL_RANGE);
     vec3 color;
 
-    float water_level = 0.43;
+    float water_level = u_sea_level;
     float beach_level = water_level + 0.03;
     float mountain_level = 0.9;
     float snow_level = 0.99;
+    bool is_lake = texelFetch(u_lakes, ivec2(world_coords), 0).x != uint(0);
+    vec3 lake_color = vec3(0.15, 0.45, 0.75) - (1.0 - height) / 4.0;
+    uint river_order = texelFetch(u_rivers, ivec2(world_coords), 0).x;
+    bool is_swamp = texelFetch(u_swamps, ivec2(world_coords), 0).x != uint(0);
+    float scree = float(texelFetch(u_scree, ivec2(world_coords), 0).x) / float(INT_VAL_RANGE);
+    float water_depth = texelFetch(u_water, ivec2(world_coords), 0).x;
 
     if (u_render_type == uint(0)) {
         color = vec3(height);
     } else if (u_render_type == uint(1)) {
-        if (height <= water_level) {
+        if (is_lake) {
+            color = lake_color;
+        } else if (height <= water_level) {
             color = vec3(height / 3.0, height / 2.0,  0.6);
+        } else if (is_swamp) {
+            color = getTerrainColor(Terrain_Swamp) + (height - water_level);
         } else if (height <= beach_level) {
             color = getTerrainColor(Terrain_Beach) + height - 0.4;
         } else if (height <= mountain_level) {
@@ -145,8 +160,12 @@ vec4 get_world_color(vec2 world_coords) {
         /*if (height <= 0.4) {
             color = vec3(height / 3.0, height / 2.0,  0.6);
         }*/
-        if (height <= water_level) {
+        if (is_lake) {
+            color = lake_color;
+        } else if (height <= water_level) {
             color = vec3(height / 3.0, height / 2.0,  0.6);
+        } else if (is_swamp) {
+            color = getTerrainColor(Terrain_Swamp) + (height - water_level);
         } else if (height <= beach_level) {
             color = getTerrainColor(Terrain_Beach) + height - 0.4;
         } else {
@@ -166,12 +185,28 @@ vec4 get_world_color(vec2 world_coords) {
             if (height >= mountain_level) {
                 color = getTerrainColor(Terrain_Mountains) + height - 0.9;
             }
+
+            // Scree piles up below collapsed cliffs
+            float full_scree = 0.02;
+            color = mix(color, vec3(0.55, 0.5, 0.45), min(scree / full_scree, 1.0) * 0.8);
         }
     } else {
         vec2 grad = CalculateHeightAndGradient(world_coords).gradient;
         color = vec3(abs(grad) * 8.0, 0.0) * vec3(height);
     }
 
+    // Running water of the shallow water erosion, deeper water is more visible
+    if ((u_render_type == uint(1) || u_render_type == uint(2)) && water_depth > 0.0005 && height > water_level && !is_lake) {
+        float full_water_depth = 0.02;
+        color = mix(color, vec3(0.2, 0.45, 0.8), min(water_depth / full_water_depth, 1.0) * 0.85);
+    }
+
+    // Rivers overlay, bigger rivers are more visible
+    if ((u_render_type == uint(1) || u_render_type == uint(2)) && river_order > uint(0) && !is_lake) {
+        float river_strength = min(0.4 + 0.15 * float(river_order), 1.0);
+        color = mix(color, vec3(0.2, 0.4, 0.85), river_strength);
+    }
+
     return in_range ? vec4(color, 1.0) : vec4(0.0, 0.0, 0.0, 1.0);
 }
 
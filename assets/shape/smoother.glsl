@@ -9,12 +9,29 @@ layout(std430, binding = 2) buffer buf_cells_taken {
 };
 
 uniform ivec2 u_size;
+// See `terrain::tiles::MapDispatch`
+uniform uvec2 u_tile_offset;
+
+#include<../terrain/topology.glsl>
 
 void main() {
-    ivec2 pos = ivec2(gl_WorkGroupID.xy * gl_WorkGroupSize.xy + gl_LocalInvocationID.xy);
+    ivec2 pos = ivec2(gl_WorkGroupID.xy * gl_WorkGroupSize.xy + gl_LocalInvocationID.xy + u_tile_offset);
 
-    if (pos.x <= 0 || pos.y <= 0 || pos.x >= u_size.x - 1 || pos.y >= u_size.y - 1)
+    // Cells at the edges which do not wrap are set on the CPU
+#if WORLD_WRAP_X
+    if (pos.x >= u_size.x)
+        return;
+#else
+    if (pos.x <= 0 || pos.x >= u_size.x - 1)
+        return;
+#endif
+#if WORLD_WRAP_Y
+    if (pos.y >= u_size.y)
+        return;
+#else
+    if (pos.y <= 0 || pos.y >= u_size.y - 1)
         return;
+#endif
 
     uint state = imageLoad(current_state, pos).x;
     if (state != 0) {
@@ -25,10 +42,10 @@ void main() {
     // if any neighbour is true, make true
     uint neighbours = 0;
 
-    neighbours += imageLoad(current_state, pos + ivec2(-1, 0)).x == 2 ? uint(1) : uint(0);
-    neighbours += imageLoad(current_state, pos + ivec2(0, -1)).x == 2 ? uint(1) : uint(0);
-    neighbours += imageLoad(current_state, pos + ivec2(0, 1)).x == 2 ? uint(1) : uint(0);
-    neighbours += imageLoad(current_state, pos + ivec2(1, 0)).x == 2 ? uint(1) : uint(0);
+    neighbours += imageLoad(current_state, wrap_cell(pos + ivec2(-1, 0), u_size)).x == 2 ? uint(1) : uint(0);
+    neighbours += imageLoad(current_state, wrap_cell(pos + ivec2(0, -1), u_size)).x == 2 ? uint(1) : uint(0);
+    neighbours += imageLoad(current_state, wrap_cell(pos + ivec2(0, 1), u_size)).x == 2 ? uint(1) : uint(0);
+    neighbours += imageLoad(current_state, wrap_cell(pos + ivec2(1, 0), u_size)).x == 2 ? uint(1) : uint(0);
 
     if (neighbours > 0) {
         imageStore(next_state, pos, uvec4(2));
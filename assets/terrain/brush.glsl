@@ -1,12 +1,11 @@
 #ifdef BRUSH_GLSL__MAP_WIDTH
 #ifdef BRUSH_GLSL__MAP_HEIGHT
+#ifdef BRUSH_GLSL__RADIUS
 
-#ifndef BRUSH_GLSL__DATA_BINDING
-    #define BRUSH_GLSL__DATA_BINDING 1
-#endif
-#ifndef BRUSH_GLSL__PTRS_BINDING
-    #define BRUSH_GLSL__PTRS_BINDING 2
-#endif
+// Same kernel as `terrain::BrushKernel`: a disk with weights falling linearly
+// from the center. Cells beyond wrapped edges come from the other side (needs
+// `topology.glsl`), cells outside of the map are dropped and the remaining
+// weights are renormalised on the fly.
 
 struct BrushIndex {
     int cell_x;
@@ -14,39 +13,58 @@ struct BrushIndex {
     float weight;
 };
 
-struct BrushPointer {
-    int start_index;
-    int array_length;
-};
+bool brush_cell_in_map(ivec2 cell) {
+    return cell.x >= 0 && cell.y >= 0 &&
+        cell.x < int(BRUSH_GLSL__MAP_WIDTH) &&
+        cell.y < int(BRUSH_GLSL__MAP_HEIGHT);
+}
 
-// 2D array
-layout(std430, binding = BRUSH_GLSL__DATA_BINDING) buffer buf_erosion_brush_data {
-    BrushIndex brush_indices[];
-};
-layout(std430, binding = BRUSH_GLSL__PTRS_BINDING) buffer buf_erosion_brush_pointers {
-    BrushPointer brush_pointers[];
-};
+// Not normalised weight of the kernel cell, 0.0 outside of the disk
+float brush_kernel_weight(ivec2 offset) {
+    int radius = int(BRUSH_GLSL__RADIUS);
+    int sqr_dist = offset.x * offset.x + offset.y * offset.y;
+    if (sqr_dist > radius * radius) {
+        return 0.0;
+    }
+    return 1.0 - sqrt(float(sqr_dist)) / float(radius);
+}
 
-int get_brush_indices_count(ivec2 point) {
-    if (point.x < 0 || point.y < 0 ||
-        point.x >= int(BRUSH_GLSL__MAP_WIDTH) ||
-        point.y >= int(BRUSH_GLSL__MAP_HEIGHT)) {
-        return 0;
-    } else {
-        int id = point.y * int(BRUSH_GLSL__MAP_WIDTH) + point.x;
-        return brush_pointers[id].array_length;
+ivec2 brush_kernel_offset(int id) {
+    int radius = int(BRUSH_GLSL__RADIUS);
+    int diameter = 2 * radius + 1;
+    return ivec2(id % diameter, id / diameter) - ivec2(radius);
+}
+
+int get_brush_indices_count() {
+    int diameter = 2 * int(BRUSH_GLSL__RADIUS) + 1;
+    return diameter * diameter;
+}
+
+// Sum of weights of all kernel cells which are inside of the map
+float get_brush_weights_sum(ivec2 point) {
+    float sum = 0.0;
+    for (int id = 0; id < get_brush_indices_count(); id++) {
+        ivec2 offset = brush_kernel_offset(id);
+        if (brush_cell_in_map(wrap_cell(point + offset, ivec2(BRUSH_GLSL__MAP_WIDTH, BRUSH_GLSL__MAP_HEIGHT)))) {
+            sum += brush_kernel_weight(offset);
+        }
     }
+    return sum;
 }
 
-BrushIndex get_brush_index(ivec2 point, int id) {
-    if (get_brush_indices_count(point) <= id) {
+// `weights_sum` has to be taken from `get_brush_weights_sum(point)`
+BrushIndex get_brush_index(ivec2 point, int id, float weights_sum) {
+    ivec2 offset = brush_kernel_offset(id);
+    ivec2 cell = wrap_cell(point + offset, ivec2(BRUSH_GLSL__MAP_WIDTH, BRUSH_GLSL__MAP_HEIGHT));
+    float weight = brush_kernel_weight(offset);
+
+    if (!brush_cell_in_map(cell) || weight <= 0.0 || weights_sum <= 0.0) {
         return BrushIndex(-1, -1, 0.0);
     } else {
-        int ptr_id = point.y * (BRUSH_GLSL__MAP_WIDTH) + point.x;
-        int start_index = brush_pointers[ptr_id].start_index;
-        return brush_indices[start_index + id];
+        return BrushIndex(cell.x, cell.y, weight / weights_sum);
     }
 }
 
 #endif
 #endif
+#endif
@@ -1,17 +1,41 @@
 #version 430
-layout(local_size_x = 8, local_size_y = 8, local_size_z = 1) in;
+layout(local_size_x = 64, local_size_y = 1, local_size_z = 1) in;
 
 layout(r32i, binding = 0) readonly uniform iimage2D current_state;
 layout(r32i, binding = 1) coherent uniform iimage2D next_state;
+// Soil thickness, bedrock lies this deep under `current_state`. Used only if `u_layered`
+layout(r32i, binding = 2) coherent uniform iimage2D soil_state;
+layout(r32f, binding = 3) readonly uniform image2D rock_erodibility;
 uniform ivec2 u_map_size;
 uniform int u_random_seed;
 
+// -- Erosion parameters (see `ErosionParams`) --
+uniform uint u_droplets_count;
+uniform int u_brush_radius;
+uniform int u_max_droplet_lifetime;
+uniform float u_inertia;
+uniform float u_soil_capacity_per_water;
+uniform float u_min_soil_capacity;
+uniform float u_deposit_speed;
+uniform float u_erode_speed;
+uniform float u_evaporate_speed;
+
+// -- Terrain layers (see `ErodibilityParams`) --
+uniform bool u_layered;
+uniform float u_soil_erodibility;
+uniform float u_rock_erodibility;
+
 float get_pixel(ivec2 pos);
 float atomic_add_pixel(ivec2 pos, float diff);
+void deposit(ivec2 pos, float amount);
+float erode_layers(ivec2 pos, float amount);
 
 // -- Includes --
+#include<topology.glsl>
+
 #define BRUSH_GLSL__MAP_WIDTH   u_map_size.x
 #define BRUSH_GLSL__MAP_HEIGHT  u_map_size.y
+#define BRUSH_GLSL__RADIUS      u_brush_radius
 #include<brush.glsl>
 
 #define GRADIENT_GLSL__GET_PIXEL(pos) get_pixel(pos)
@@ -23,21 +47,16 @@ float atomic_add_pixel(ivec2 pos, float diff);
 
 // -- Defines and functions signatures --
 #define EPS 0.000001
-#define MAX_DROPLET_LIFETIME 30
 #define INITIAL_WATER_VOLUME 1.0
-#define INERTIA 0.2
-
-#define SOIL_CAPACITY_PER_WATER 4.0
-#define MIN_SOIL_CAPACITY 0.02
-#define DEPOSIT_SPEED 0.3
-#define ERODE_SPEED 0.3
-#define EVAPORATE_SPEED 0.01
 
 void simulate_droplet(vec2 pos);
 
 // -- Code --
 void main() {
-    uvec2 invoc_seed = gl_WorkGroupID.xy * gl_WorkGroupSize.xy + gl_LocalInvocationID.xy;
+    if (gl_GlobalInvocationID.x >= u_droplets_count)
+        return;
+
+    uvec2 invoc_seed = gl_GlobalInvocationID.xy;
     uvec2 unbound_pos = u2hash2( invoc_seed + uvec2(u_map_size) * uint(u_random_seed) * 17 );
     uvec2 pos = uvec2(unbound_pos.x % u_map_size.x, unbound_pos.y % u_map_size.y);
 
@@ -51,17 +70,18 @@ void simulate_droplet(vec2 pos) {
     float soil_amount = 0.0;
     //atomic_add_pixel(ivec2(pos), -get_pixel(ivec2(pos)));
 
-    for (int lifetime = 0; lifetime < MAX_DROPLET_LIFETIME; lifetime++) {
+    for (int lifetime = 0; lifetime < u_max_droplet_lifetime; lifetime++) {
         ivec2 current_texel = ivec2(pos);
         vec2 cell_offset = fract(pos);
 
         HeightAndGradient height_and_grad = CalculateHeightAndGradient(pos);
-        vel = (vel * INERTIA - height_and_grad.gradient * (1.0 - INERTIA));
+        vel = (vel * u_inertia - height_and_grad.gradient * (1.0 - u_inertia));
 
         float vel_length = length(vel);
         vec2 direction = vel / vel_length;
-        pos += direction;
+        pos = wrap_point(pos + direction, vec2(u_map_size));
 
+        // Droplets flow over wrapped edges and die at the others
         if (vel_length < EPS || pos.x < 0.0 || pos.y < 0.0 || pos.x >= float(u_map_size.x) || pos.y >= float(u_map_size.y))
             break;
 
@@ -69,37 +89,42 @@ void simulate_droplet(vec2 pos) {
         float new_height = CalculateHeightAndGradient(pos).height;
         float delta_height = new_height - height_and_grad.height;
 
-        float cur_soil_capacity = max(-delta_height * vel_length * water_volume * SOIL_CAPACITY_PER_WATER, MIN_SOIL_CAPACITY);
+        float cur_soil_capacity = max(-delta_height * vel_length * water_volume * u_soil_capacity_per_water, u_min_soil_capacity);
         //atomic_add_pixel(ivec2(pos), -0.1 * get_pixel(ivec2(pos)));
 
         if (soil_amount > cur_soil_capacity || delta_height > 0.0) {
             float amount_to_deposit = (delta_height > 0.0) ?
-                min(delta_height, soil_amount) : (soil_amount - cur_soil_capacity) * DEPOSIT_SPEED;
+                min(delta_height, soil_amount) : (soil_amount - cur_soil_capacity) * u_deposit_speed;
 
             soil_amount -= amount_to_deposit;
 
-            atomic_add_pixel(current_texel + ivec2(0, 0), amount_to_deposit * (1.0 - cell_offset.x) * (1.0 - cell_offset.y));
-            atomic_add_pixel(current_texel + ivec2(1, 0), amount_to_deposit * cell_offset.x * (1.0 - cell_offset.y));
-            atomic_add_pixel(current_texel + ivec2(0, 1), amount_to_deposit * (1.0 - cell_offset.x) * cell_offset.y);
-            atomic_add_pixel(current_texel + ivec2(1, 1), amount_to_deposit * cell_offset.x * cell_offset.y);
+            deposit(current_texel + ivec2(0, 0), amount_to_deposit * (1.0 - cell_offset.x) * (1.0 - cell_offset.y));
+            deposit(current_texel + ivec2(1, 0), amount_to_deposit * cell_offset.x * (1.0 - cell_offset.y));
+            deposit(current_texel + ivec2(0, 1), amount_to_deposit * (1.0 - cell_offset.x) * cell_offset.y);
+            deposit(current_texel + ivec2(1, 1), amount_to_deposit * cell_offset.x * cell_offset.y);
         } else {
             // Erode a fraction of the droplet's current carry capacity.
             // Clamp the erosion to the change in height so that it doesn't dig a hole in the terrain behind the droplet
-            float amountToErode = min((cur_soil_capacity - soil_amount) * ERODE_SPEED, -delta_height);
+            float amountToErode = min((cur_soil_capacity - soil_amount) * u_erode_speed, -delta_height);
 
             // Use erosion brush to erode from all nodes inside the droplet's erosion radius
-            for (int brushPointIndex = 0; brushPointIndex < get_brush_indices_count(current_texel); brushPointIndex++) {
-                BrushIndex index = get_brush_index(current_texel, brushPointIndex);
+            float brush_weights_sum = get_brush_weights_sum(current_texel);
+            for (int brushPointIndex = 0; brushPointIndex < get_brush_indices_count(); brushPointIndex++) {
+                BrushIndex index = get_brush_index(current_texel, brushPointIndex, brush_weights_sum);
+                if (index.weight <= 0.0)
+                    continue;
                 float weighedErodeAmount = amountToErode * index.weight;
 
                 ivec2 node = ivec2(index.cell_x, index.cell_y);
                 float pixel_value = get_pixel(node);
                 float delta_soil = (pixel_value < weighedErodeAmount) ? max(pixel_value, 0.0) : weighedErodeAmount;
+                if (u_layered)
+                    delta_soil = erode_layers(node, delta_soil);
                 atomic_add_pixel(node, -delta_soil);
                 soil_amount += delta_soil;
             }
         }
-        water_volume *= (1.0 - EVAPORATE_SPEED);
+        water_volume *= (1.0 - u_evaporate_speed);
     }
 
     /*ivec2 current_texel = ivec2(pos);
@@ -110,16 +135,35 @@ void simulate_droplet(vec2 pos) {
     atomic_add_pixel(current_texel + ivec2(1, 1), soil_amount * cell_offset.x * cell_offset.y);*/
 }
 
-// Value of 1.0 converted to int value
-#define INT_VAL_RANGE 1000000
-
 float get_pixel(ivec2 pos) {
     int int_val = imageLoad(current_state, pos).x;
     return float(int_val) / float(INT_VAL_RANGE);
 }
 
 float atomic_add_pixel(ivec2 pos, float diff) {
+    pos = wrap_cell(pos, u_map_size);
     int add_val = int(diff * float(INT_VAL_RANGE));
     int old_val = imageAtomicAdd(next_state, pos, add_val);
     return float(old_val) / float(INT_VAL_RANGE);
-}
\ No newline at end of file
+}
+
+// Deposited material always becomes soil
+void deposit(ivec2 pos, float amount) {
+    pos = wrap_cell(pos, u_map_size);
+    atomic_add_pixel(pos, amount);
+    if (u_layered)
+        imageAtomicAdd(soil_state, pos, int(amount * float(INT_VAL_RANGE)));
+}
+
+// Soil goes first at its own rate. The erosion power which is left once the
+// soil is gone is spent on the bedrock. Returns the total eroded amount.
+// Mirrored by `ErodibilityParams::erode`.
+float erode_layers(ivec2 pos, float amount) {
+    float soil = max(float(imageLoad(soil_state, pos).x) / float(INT_VAL_RANGE), 0.0);
+    float from_soil = min(amount * u_soil_erodibility, soil);
+    float rest = (u_soil_erodibility > EPS) ? amount - from_soil / u_soil_erodibility : amount;
+    float from_rock = rest * u_rock_erodibility * imageLoad(rock_erodibility, pos).x;
+
+    imageAtomicAdd(soil_state, pos, -int(from_soil * float(INT_VAL_RANGE)));
+    return from_soil + from_rock;
+}
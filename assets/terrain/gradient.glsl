@@ -12,12 +12,14 @@ HeightAndGradient CalculateHeightAndGradient(vec2 pos) {
     float x = fract(pos.x);
     float y = fract(pos.y);
 
-    ivec2 bounds = ivec2(GRADIENT_GLSL__MAP_WIDTH, GRADIENT_GLSL__MAP_HEIGHT) - 1;
+    // Nodes beyond a wrapped edge come from the other side (`wrap_cell` of `topology.glsl`)
+    ivec2 size = ivec2(GRADIENT_GLSL__MAP_WIDTH, GRADIENT_GLSL__MAP_HEIGHT);
+    ivec2 bounds = size - 1;
     // Calculate heights of the four nodes of the droplet's cell
-    float height_nx_ny = GRADIENT_GLSL__GET_PIXEL( min(coord + ivec2(0, 0), bounds) );
-    float height_px_ny = GRADIENT_GLSL__GET_PIXEL( min(coord + ivec2(1, 0), bounds) );
-    float height_nx_py = GRADIENT_GLSL__GET_PIXEL( min(coord + ivec2(0, 1), bounds) );
-    float height_px_py = GRADIENT_GLSL__GET_PIXEL( min(coord + ivec2(1, 1), bounds) );
+    float height_nx_ny = GRADIENT_GLSL__GET_PIXEL( min(wrap_cell(coord + ivec2(0, 0), size), bounds) );
+    float height_px_ny = GRADIENT_GLSL__GET_PIXEL( min(wrap_cell(coord + ivec2(1, 0), size), bounds) );
+    float height_nx_py = GRADIENT_GLSL__GET_PIXEL( min(wrap_cell(coord + ivec2(0, 1), size), bounds) );
+    float height_px_py = GRADIENT_GLSL__GET_PIXEL( min(wrap_cell(coord + ivec2(1, 1), size), bounds) );
 
     // Calculate droplet's direction of flow with bilinear interpolation of height difference along the edges
     float dx = (height_px_ny - height_nx_ny) * (1.0 - y) + (height_px_py - height_nx_py) * y;
@@ -0,0 +1,32 @@
+// Shared parts of the shallow water (virtual pipes) passes, see `terrain::shallow_water`
+// for the CPU reference. Flux channels: x - left, y - right, z - top (y - 1), w - bottom (y + 1).
+
+uniform ivec2 u_map_size;
+// See `terrain::tiles::MapDispatch`
+uniform uvec2 u_tile_offset;
+uniform float u_time_step;
+uniform float u_rain_rate;
+uniform float u_gravity;
+uniform float u_pipe_area;
+uniform float u_cell_width;
+uniform float u_sediment_capacity;
+uniform float u_dissolve_rate;
+uniform float u_deposit_rate;
+uniform float u_evaporation_rate;
+uniform float u_min_tilt;
+uniform float u_sea_level;
+
+#define EPS 0.000001
+
+const ivec2 PIPES[4] = ivec2[4](ivec2(-1, 0), ivec2(1, 0), ivec2(0, -1), ivec2(0, 1));
+
+#include<../topology.glsl>
+
+// Across a wrapped edge the pipe leads to the other side of the map
+ivec2 neighbour(ivec2 pos, int pipe) {
+    return wrap_cell(pos + PIPES[pipe], u_map_size);
+}
+
+bool in_map(ivec2 pos) {
+    return pos.x >= 0 && pos.y >= 0 && pos.x < u_map_size.x && pos.y < u_map_size.y;
+}
@@ -0,0 +1,45 @@
+#version 430
+layout(local_size_x = 32, local_size_y = 32, local_size_z = 1) in;
+
+// Step 4: erosion and deposition, depending on the sediment transport capacity
+layout(r32i, binding = 0) readonly uniform iimage2D terrain;
+layout(rg32f, binding = 1) readonly uniform image2D velocity;
+layout(r32f, binding = 2) readonly uniform image2D sediment;
+layout(r32i, binding = 3) writeonly uniform iimage2D terrain_next;
+layout(r32f, binding = 4) writeonly uniform image2D sediment_next;
+
+#include<common.glsl>
+
+float get_height(ivec2 pos) {
+    return float(imageLoad(terrain, pos).x) / float(INT_VAL_RANGE);
+}
+
+float height_or(ivec2 pos, float fallback) {
+    return in_map(pos) ? get_height(pos) : fallback;
+}
+
+void main() {
+    ivec2 pos = ivec2(gl_GlobalInvocationID.xy + u_tile_offset);
+    if (!in_map(pos))
+        return;
+
+    float height = get_height(pos);
+    vec2 grad = vec2(
+        height_or(neighbour(pos, 1), height) - height_or(neighbour(pos, 0), height),
+        height_or(neighbour(pos, 3), height) - height_or(neighbour(pos, 2), height)
+    ) / (2.0 * u_cell_width);
+    float grad_len = length(grad);
+    float tilt = max(u_min_tilt, grad_len / sqrt(1.0 + grad_len * grad_len));
+
+    float capacity = u_sediment_capacity * tilt * length(imageLoad(velocity, pos).xy);
+    float suspended = imageLoad(sediment, pos).x;
+    float amount;
+    if (capacity > suspended) {
+        amount = -u_dissolve_rate * (capacity - suspended) * u_time_step;
+    } else {
+        amount = u_deposit_rate * (suspended - capacity) * u_time_step;
+    }
+
+    imageStore(terrain_next, pos, ivec4(int((height + amount) * float(INT_VAL_RANGE))));
+    imageStore(sediment_next, pos, vec4(suspended - amount));
+}
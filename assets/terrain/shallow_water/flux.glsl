@@ -0,0 +1,42 @@
+#version 430
+layout(local_size_x = 32, local_size_y = 32, local_size_z = 1) in;
+
+// Steps 1-2: rain and outflow flux through the virtual pipes
+layout(r32i, binding = 0) readonly uniform iimage2D terrain;
+layout(r32f, binding = 1) readonly uniform image2D water;
+layout(rgba32f, binding = 2) readonly uniform image2D flux;
+layout(rgba32f, binding = 3) writeonly uniform image2D flux_next;
+
+#include<common.glsl>
+
+float surface(ivec2 pos) {
+    float height = float(imageLoad(terrain, pos).x) / float(INT_VAL_RANGE);
+    return height + imageLoad(water, pos).x + u_time_step * u_rain_rate;
+}
+
+void main() {
+    ivec2 pos = ivec2(gl_GlobalInvocationID.xy + u_tile_offset);
+    if (!in_map(pos))
+        return;
+
+    float water_after_rain = imageLoad(water, pos).x + u_time_step * u_rain_rate;
+    float center = surface(pos);
+    vec4 old_flux = imageLoad(flux, pos);
+    vec4 new_flux = vec4(0.0);
+
+    for (int i = 0; i < 4; i++) {
+        ivec2 next = neighbour(pos, i);
+        if (!in_map(next))
+            continue;
+
+        float diff = center - surface(next);
+        new_flux[i] = max(0.0, old_flux[i] + u_time_step * u_pipe_area * u_gravity * diff / u_cell_width);
+    }
+
+    // Never let out more water than the cell has
+    float total = new_flux.x + new_flux.y + new_flux.z + new_flux.w;
+    if (total > EPS)
+        new_flux *= min(1.0, water_after_rain * u_cell_width * u_cell_width / (total * u_time_step));
+
+    imageStore(flux_next, pos, new_flux);
+}
@@ -0,0 +1,46 @@
+#version 430
+layout(local_size_x = 32, local_size_y = 32, local_size_z = 1) in;
+
+// Steps 5-6: sediment transport and evaporation. Sediment leaves a cell in the same
+// share as its water does, so none of it is lost. Water which gets below the sea
+// level leaves the map.
+layout(r32i, binding = 0) readonly uniform iimage2D terrain;
+layout(rgba32f, binding = 1) readonly uniform image2D flux;
+layout(r32f, binding = 2) readonly uniform image2D sediment;
+// Water of the previous step, before the flow
+layout(r32f, binding = 3) readonly uniform image2D water;
+layout(r32f, binding = 4) writeonly uniform image2D sediment_next;
+// Water after the flow, evaporates in place
+layout(r32f, binding = 5) uniform image2D water_next;
+
+#include<common.glsl>
+
+float outflow_share(ivec2 pos, int pipe) {
+    float water_after_rain = imageLoad(water, pos).x + u_time_step * u_rain_rate;
+    if (water_after_rain <= EPS)
+        return 0.0;
+    return imageLoad(flux, pos)[pipe] * u_time_step / (u_cell_width * u_cell_width * water_after_rain);
+}
+
+float inflow(ivec2 pos, int pipe) {
+    return in_map(pos) ? imageLoad(sediment, pos).x * outflow_share(pos, pipe) : 0.0;
+}
+
+void main() {
+    ivec2 pos = ivec2(gl_GlobalInvocationID.xy + u_tile_offset);
+    if (!in_map(pos))
+        return;
+
+    float outflow = outflow_share(pos, 0) + outflow_share(pos, 1) + outflow_share(pos, 2) + outflow_share(pos, 3);
+    float moved_sediment = imageLoad(sediment, pos).x * max(0.0, 1.0 - outflow) +
+        inflow(neighbour(pos, 0), 1) + inflow(neighbour(pos, 1), 0) +
+        inflow(neighbour(pos, 2), 3) + inflow(neighbour(pos, 3), 2);
+
+    float height = float(imageLoad(terrain, pos).x) / float(INT_VAL_RANGE);
+    float new_water = 0.0;
+    if (height > u_sea_level)
+        new_water = imageLoad(water_next, pos).x * max(0.0, 1.0 - u_evaporation_rate * u_time_step);
+
+    imageStore(sediment_next, pos, vec4(moved_sediment));
+    imageStore(water_next, pos, vec4(new_water));
+}
@@ -0,0 +1,42 @@
+#version 430
+layout(local_size_x = 32, local_size_y = 32, local_size_z = 1) in;
+
+// Step 3: water height and velocity from the new flux
+layout(r32f, binding = 0) readonly uniform image2D water;
+layout(rgba32f, binding = 1) readonly uniform image2D flux;
+layout(r32f, binding = 2) writeonly uniform image2D water_next;
+layout(rg32f, binding = 3) writeonly uniform image2D velocity;
+
+#include<common.glsl>
+
+float inflow(ivec2 pos, int pipe) {
+    return in_map(pos) ? imageLoad(flux, pos)[pipe] : 0.0;
+}
+
+void main() {
+    ivec2 pos = ivec2(gl_GlobalInvocationID.xy + u_tile_offset);
+    if (!in_map(pos))
+        return;
+
+    float water_after_rain = imageLoad(water, pos).x + u_time_step * u_rain_rate;
+    vec4 out_flux = imageLoad(flux, pos);
+
+    float from_left = inflow(neighbour(pos, 0), 1);
+    float from_right = inflow(neighbour(pos, 1), 0);
+    float from_top = inflow(neighbour(pos, 2), 3);
+    float from_bottom = inflow(neighbour(pos, 3), 2);
+    float outflow = out_flux.x + out_flux.y + out_flux.z + out_flux.w;
+
+    float volume_change = u_time_step * (from_left + from_right + from_top + from_bottom - outflow);
+    float new_water = max(0.0, water_after_rain + volume_change / (u_cell_width * u_cell_width));
+
+    float avg_water = (water_after_rain + new_water) / 2.0;
+    vec2 flow = vec2(
+        from_left - out_flux.x + out_flux.y - from_right,
+        from_top - out_flux.z + out_flux.w - from_bottom
+    ) / 2.0;
+    vec2 new_velocity = avg_water > EPS ? flow / (u_cell_width * avg_water) : vec2(0.0);
+
+    imageStore(water_next, pos, vec4(new_water));
+    imageStore(velocity, pos, vec4(new_velocity, 0.0, 0.0));
+}
@@ -0,0 +1,37 @@
+#version 430
+layout(local_size_x = 32, local_size_y = 32, local_size_z = 1) in;
+
+// Tectonic uplift and isostatic rebound, see `terrain::tectonics::Tectonics::step` for the CPU reference.
+layout(r32i, binding = 0) readonly uniform iimage2D current_state;
+layout(r32i, binding = 1) writeonly uniform iimage2D next_state;
+// Heights right after the previous step
+layout(r32i, binding = 2) coherent uniform iimage2D reference_state;
+// See `terrain::tectonics::UpliftField`
+layout(r32f, binding = 3) readonly uniform image2D convergence;
+
+uniform ivec2 u_map_size;
+// See `terrain::tiles::MapDispatch`
+uniform uvec2 u_tile_offset;
+uniform bool u_has_reference;
+uniform float u_uplift_rate;
+uniform float u_subsidence_rate;
+uniform float u_isostasy;
+uniform int u_ticks;
+
+void main() {
+    ivec2 pos = ivec2(gl_GlobalInvocationID.xy + u_tile_offset);
+    if (pos.x >= u_map_size.x || pos.y >= u_map_size.y)
+        return;
+
+    int height = imageLoad(current_state, pos).x;
+    int eroded = u_has_reference ? imageLoad(reference_state, pos).x - height : 0;
+    int rebound = int(floor(float(eroded) * u_isostasy + 0.5));
+
+    float squeeze = imageLoad(convergence, pos).x;
+    float rate = squeeze > 0.0 ? squeeze * u_uplift_rate : squeeze * u_subsidence_rate;
+    int uplift = int(floor(rate * float(INT_VAL_RANGE) + 0.5)) * u_ticks;
+
+    int result = height + rebound + uplift;
+    imageStore(next_state, pos, ivec4(result));
+    imageStore(reference_state, pos, ivec4(result));
+}
@@ -0,0 +1,75 @@
+#version 430
+layout(local_size_x = 32, local_size_y = 32, local_size_z = 1) in;
+
+// Thermal (talus) erosion, see `terrain::thermal` for the CPU reference.
+// `next_state` has to be a copy of `current_state` before the dispatch.
+layout(r32i, binding = 0) readonly uniform iimage2D current_state;
+layout(r32i, binding = 1) coherent uniform iimage2D next_state;
+// Total amount of material each cell has received
+layout(r32i, binding = 2) coherent uniform iimage2D scree_state;
+
+uniform ivec2 u_map_size;
+// See `terrain::tiles::MapDispatch`
+uniform uvec2 u_tile_offset;
+uniform float u_talus_height;
+uniform float u_transfer_rate;
+
+#include<topology.glsl>
+
+const ivec2 NEIGHBOURS[8] = ivec2[8](
+    ivec2(-1, -1), ivec2(0, -1), ivec2(1, -1),
+    ivec2(-1, 0), ivec2(1, 0),
+    ivec2(-1, 1), ivec2(0, 1), ivec2(1, 1)
+);
+
+float get_pixel(ivec2 pos) {
+    return float(imageLoad(current_state, pos).x) / float(INT_VAL_RANGE);
+}
+
+bool in_map(ivec2 pos) {
+    return pos.x >= 0 && pos.y >= 0 && pos.x < u_map_size.x && pos.y < u_map_size.y;
+}
+
+void main() {
+    ivec2 pos = ivec2(gl_GlobalInvocationID.xy + u_tile_offset);
+    if (!in_map(pos))
+        return;
+
+    float center = get_pixel(pos);
+    float excess[8];
+    float max_excess = 0.0;
+    float total_excess = 0.0;
+
+    for (int i = 0; i < 8; i++) {
+        excess[i] = 0.0;
+        ivec2 neighbour = wrap_cell(pos + NEIGHBOURS[i], u_map_size);
+        if (!in_map(neighbour))
+            continue;
+
+        float distance = (NEIGHBOURS[i].x != 0 && NEIGHBOURS[i].y != 0) ? sqrt(2.0) : 1.0;
+        float cell_excess = center - get_pixel(neighbour) - u_talus_height * distance;
+        if (cell_excess > 0.0) {
+            excess[i] = cell_excess;
+            max_excess = max(max_excess, cell_excess);
+            total_excess += cell_excess;
+        }
+    }
+
+    if (total_excess <= 0.0)
+        return;
+
+    // Half of the difference, so that two cells never swap their order
+    float moved = u_transfer_rate * max_excess / 2.0;
+    int total_moved = 0;
+    for (int i = 0; i < 8; i++) {
+        int amount = int(moved * excess[i] / total_excess * float(INT_VAL_RANGE));
+        if (amount <= 0)
+            continue;
+
+        ivec2 neighbour = wrap_cell(pos + NEIGHBOURS[i], u_map_size);
+        imageAtomicAdd(next_state, neighbour, amount);
+        imageAtomicAdd(scree_state, neighbour, amount);
+        total_moved += amount;
+    }
+    imageAtomicAdd(next_state, pos, -total_moved);
+}
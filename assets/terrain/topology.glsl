@@ -0,0 +1,31 @@
+// Wrap-around of the map edges, see `map::Topology`. `WORLD_WRAP_X` and
+// `WORLD_WRAP_Y` (`0` or `1`) are defined by `ShaderContext`.
+
+#ifndef WORLD_WRAP_X
+#define WORLD_WRAP_X 0
+#endif
+#ifndef WORLD_WRAP_Y
+#define WORLD_WRAP_Y 0
+#endif
+
+// Moves a cell which is less than a map size beyond a wrapped edge to the other
+// side of the map. Cells beyond the other edges are left as they are.
+ivec2 wrap_cell(ivec2 pos, ivec2 size) {
+#if WORLD_WRAP_X
+    pos.x += (pos.x < 0) ? size.x : ((pos.x >= size.x) ? -size.x : 0);
+#endif
+#if WORLD_WRAP_Y
+    pos.y += (pos.y < 0) ? size.y : ((pos.y >= size.y) ? -size.y : 0);
+#endif
+    return pos;
+}
+
+vec2 wrap_point(vec2 pos, vec2 size) {
+#if WORLD_WRAP_X
+    pos.x = mod(pos.x, size.x);
+#endif
+#if WORLD_WRAP_Y
+    pos.y = mod(pos.y, size.y);
+#endif
+    return pos;
+}
@@ -0,0 +1,108 @@
+use egui_sdl2_gl::egui::{Button, ComboBox, DragValue, Grid, TextEdit, Ui};
+
+use crate::map::{self, Height, Landscape};
+use crate::terrain::mesh::{self, MeshFormat, MeshParams};
+
+pub struct MeshMenu {
+	params: MeshParams,
+	sea_plane: bool,
+	format: MeshFormat,
+	export_path: String,
+	export_status: Option<String>,
+}
+
+impl Default for MeshMenu {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl MeshMenu {
+	pub fn new() -> Self {
+		MeshMenu {
+			params: MeshParams::default(),
+			sea_plane: false,
+			format: MeshFormat::Gltf,
+			export_path: format!("landscape.{}", MeshFormat::Gltf.extension()),
+			export_status: None,
+		}
+	}
+
+	/// Vertices get the colours of `biomes` if they are of the same size as the landscape
+	pub fn export(&mut self, landscape: &Landscape, sea_level: Height, biomes: Option<&map::Map>) {
+		let biomes = biomes.filter(|biomes| biomes.size() == landscape.size());
+		self.params.sea_plane = if self.sea_plane { Some(sea_level) } else { None };
+		self.export_status = Some(match mesh::export_mesh(landscape, biomes, &self.params, self.format, &self.export_path) {
+			Ok(()) => "Exported".to_owned(),
+			Err(error) => error.to_string(),
+		});
+	}
+
+	/// Returns true if the mesh has to be exported, see `export`.
+	/// `is_exporting` is set while the landscape snapshot is on its way
+	pub fn show(&mut self, ui: &mut Ui, is_exporting: bool) -> bool {
+		Grid::new("mesh_params")
+			.num_columns(2)
+			.spacing((40.0, 4.0))
+			.show(ui, |ui| {
+				ui.label("Format");
+				let format = self.format;
+				ComboBox::new("mesh_format", "")
+					.selected_text(self.format.localized_name())
+					.show_ui(ui, |ui| {
+						for format in MeshFormat::all() {
+							ui.selectable_value(&mut self.format, *format, format.localized_name());
+						}
+					});
+				if self.format != format {
+					self.export_path = std::path::Path::new(&self.export_path)
+						.with_extension(self.format.extension())
+						.to_string_lossy()
+						.into_owned();
+				}
+				ui.end_row();
+
+				ui.label("Cell size");
+				ui.add(DragValue::new(&mut self.params.cell_size).speed(0.01).clamp_range(MeshParams::CELL_SIZE_RANGE));
+				ui.end_row();
+
+				ui.label("Vertical exaggeration");
+				ui.add(DragValue::new(&mut self.params.exaggeration).speed(0.1).clamp_range(MeshParams::EXAGGERATION_RANGE));
+				ui.end_row();
+
+				let mut simplify = self.params.max_error.is_some();
+				if ui.checkbox(&mut simplify, "Simplify").on_hover_text("Largest height error of the quad-tree").changed() {
+					self.params.max_error = if simplify { MeshParams::default().max_error } else { None };
+				}
+				if let Some(max_error) = &mut self.params.max_error {
+					ui.add(DragValue::new(max_error).speed(0.0001).clamp_range(MeshParams::MAX_ERROR_RANGE));
+				}
+				ui.end_row();
+
+				ui.checkbox(&mut self.sea_plane, "Sea plane");
+				ui.end_row();
+
+				let mut solid = self.params.base.is_some();
+				if ui.checkbox(&mut solid, "Solid").on_hover_text("Walls and a bottom for 3D printing").changed() {
+					self.params.base = if solid { Some(self.params.cell_size * 4.0) } else { None };
+				}
+				if let Some(base) = &mut self.params.base {
+					ui.add(DragValue::new(base).speed(0.1).clamp_range(MeshParams::BASE_RANGE));
+				}
+				ui.end_row();
+			});
+
+		let export = ui.horizontal_wrapped(|ui| {
+			ui.add(TextEdit::singleline(&mut self.export_path).desired_width(150.0));
+			let export = ui.add_enabled(!is_exporting, Button::new("Export mesh")).clicked();
+			if export {
+				self.export_status = Some("Exporting...".to_owned());
+			}
+			export
+		}).inner;
+		if let Some(status) = &self.export_status {
+			ui.label(status);
+		}
+		export
+	}
+}
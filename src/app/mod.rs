@@ -1,12 +1,24 @@
 use std::collections::HashMap;
 use egui_sdl2_gl::egui;
-use egui_sdl2_gl::egui::{Align, ColorImage, ComboBox, DragValue, Grid, ImageButton, Layout, Rect, ScrollArea, Slider, TextureHandle, Ui, Vec2};
+use egui_sdl2_gl::egui::{Align, Button, ColorImage, ComboBox, DragValue, Grid, ImageButton, Layout, Rect, ScrollArea, Slider, TextEdit, TextureHandle, Ui, Vec2};
 use egui_sdl2_gl::egui::panel::Side;
+use crate::map::Landscape;
+use crate::save::WorldSave;
+use crate::seed::WorldSeed;
+use crate::terrain::{ErosionModel, ErosionParams, ErosionPreset};
+use crate::terrain::layers::ErodibilityParams;
+use crate::terrain::shallow_water::ShallowWaterParams;
+use crate::terrain::tectonics::{PlateSource, TectonicParams};
+use crate::terrain::thermal::ThermalParams;
 use crate::util::Camera;
-use crate::world::World;
+use crate::world::{LayersSnapshot, World, WorldSettings};
 
+use self::mesh::MeshMenu;
+use self::stats::StatsMenu;
 use self::worldgen::WorldgenMenu;
 
+pub mod mesh;
+pub mod stats;
 pub mod worldgen;
 
 const ICON_PLAY: &[u8] = include_bytes!("../img/play.png");
@@ -91,6 +103,14 @@ impl Page {
 	}
 }
 
+/// What a landscape snapshot is requested for, see `App::landscape_snapshot_ready`
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SnapshotUse {
+	Statistics,
+	Save,
+	Mesh,
+}
+
 pub struct App {
 	pub run_simulation: bool,
 	run_exactly: u64,
@@ -108,14 +128,33 @@ pub struct App {
 	pub ups_limit: u32,
 	pub antialiasing: AntiAliasing,
 
+	pub erosion_params: ErosionParams,
+	erosion_preset: Option<ErosionPreset>,
+
+	seed_text: String,
+	pub world_settings: WorldSettings,
+	pub regenerate_world: bool,
+	/// Remap the current landscape with `world_settings.hypsometry`, see `World::normalize_landscape`
+	pub normalize_landscape: bool,
+	/// See `World::recompute_overlays`
+	pub recompute_overlays: bool,
+	save_path: String,
+	save_status: Option<String>,
+	/// World to replace the current one with
+	pub loaded_save: Option<WorldSave>,
+	/// Everything waiting for the landscape snapshot, see `World::request_landscape_snapshot`
+	snapshot_uses: Vec<SnapshotUse>,
+
 	page: Page,
 	worldgen: WorldgenMenu,
+	stats: StatsMenu,
+	mesh: MeshMenu,
 
 	images: HashMap<String, TextureHandle>,
 }
 
 impl App {
-	pub fn new(ctx: &egui::Context, camera_pos: (f32, f32)) -> Self {
+	pub fn new(ctx: &egui::Context, camera_pos: (f32, f32), world_settings: WorldSettings) -> Self {
 		let images: [(&str, &[u8]); 4] = [
 			("play", ICON_PLAY),
 			("pause", ICON_PAUSE),
@@ -150,8 +189,23 @@ impl App {
 			images,
 			antialiasing: AntiAliasing::SSAAx16,
 
+			erosion_params: ErosionParams::default(),
+			erosion_preset: Some(ErosionPreset::Default),
+
+			seed_text: world_settings.seed.to_string(),
+			world_settings,
+			regenerate_world: false,
+			normalize_landscape: false,
+			recompute_overlays: false,
+			save_path: format!("world.{}", crate::save::EXTENSION),
+			save_status: None,
+			loaded_save: None,
+			snapshot_uses: vec![],
+
 			page: Page::Simulation,
 			worldgen: WorldgenMenu::new(),
+			stats: StatsMenu::new(),
+			mesh: MeshMenu::new(),
 		}
 	}
 
@@ -162,6 +216,44 @@ impl App {
 	pub fn page(&self) -> &Page {
 		&self.page
 	}
+
+	/// Set until `landscape_snapshot_ready` is called
+	pub fn waits_for_landscape_snapshot(&self) -> bool {
+		!self.snapshot_uses.is_empty()
+	}
+
+	/// Hands the snapshot of `world` landscape to everything which waits for it
+	pub fn landscape_snapshot_ready(&mut self, landscape: Landscape, world: &World) {
+		for snapshot_use in std::mem::take(&mut self.snapshot_uses) {
+			match snapshot_use {
+				SnapshotUse::Statistics => self.stats.analyze(&landscape, world.sea_level(), self.worldgen.biome_map()),
+				SnapshotUse::Save => {
+					let save = world.to_save(landscape.clone(), self.worldgen.biome_map());
+					self.save_status = Some(match save.save(&self.save_path) {
+						Ok(()) => "Saved".to_owned(),
+						Err(error) => error.to_string(),
+					});
+				}
+				SnapshotUse::Mesh => self.mesh.export(&landscape, world.sea_level(), self.worldgen.biome_map()),
+			}
+		}
+	}
+
+	/// Set until `layers_snapshot_ready` is called
+	pub fn waits_for_layers_snapshot(&self) -> bool {
+		self.worldgen.waits_for_simulation_map()
+	}
+
+	/// Hands the snapshot of `world` layers to the biomes conversion which waits for it
+	pub fn layers_snapshot_ready(&mut self, snapshot: LayersSnapshot, world: &World) {
+		self.worldgen.simulation_map_ready(&snapshot, world.settings());
+	}
+
+	fn request_landscape_snapshot(&mut self, snapshot_use: SnapshotUse) {
+		if !self.snapshot_uses.contains(&snapshot_use) {
+			self.snapshot_uses.push(snapshot_use);
+		}
+	}
 }
 
 impl App {
@@ -184,7 +276,9 @@ impl App {
 				ScrollArea::vertical()
 					.show(ui, |ui| {
 						if self.page == Page::Worldgen {
-							self.worldgen.show(ui);
+							if self.worldgen.show(ui, &mut self.world_settings) {
+								self.regenerate_world = true;
+							}
 						} else if self.page == Page::Simulation {
 							ui.add_space(5.0);
 							let btn_size = ui.spacing().icon_width;
@@ -233,6 +327,53 @@ impl App {
 							});
 
 							ui.label(format!("World size: {}×{}", size_x, size_y));
+							ui.horizontal(|ui| {
+								ui.label("Seed");
+								ui.text_edit_singleline(&mut self.seed_text);
+								if ui.button("Random").clicked() {
+									self.seed_text = WorldSeed::random().to_string();
+								}
+							});
+							let parsed_seed = self.seed_text.parse::<WorldSeed>();
+							if ui.add_enabled(parsed_seed.is_ok(), Button::new("Regenerate world")).clicked() {
+								if let Ok(seed) = parsed_seed {
+									self.world_settings.seed = seed;
+									self.regenerate_world = true;
+								}
+							}
+							ui.horizontal_wrapped(|ui| {
+								ui.add(TextEdit::singleline(&mut self.save_path).desired_width(150.0));
+								let is_saving = self.snapshot_uses.contains(&SnapshotUse::Save);
+								if ui.add_enabled(!is_saving, Button::new("Save")).clicked() {
+									self.request_landscape_snapshot(SnapshotUse::Save);
+									self.save_status = Some("Saving...".to_owned());
+								}
+								if ui.button("Load").clicked() {
+									self.save_status = Some(match WorldSave::load(&self.save_path) {
+										Ok(save) => {
+											self.world_settings.seed = save.seed;
+											self.world_settings.topology = save.landscape.topology();
+											self.world_settings.sea_level = save.sea_level;
+											self.seed_text = save.seed.to_string();
+											if let Some(map) = &save.map {
+												self.worldgen.set_biome_map(map.clone());
+											}
+											self.loaded_save = Some(save);
+											"Loaded".to_owned()
+										}
+										Err(error) => error.to_string(),
+									});
+								}
+							});
+							if let Some(status) = &self.save_status {
+								ui.label(status);
+							}
+							ui.collapsing("3D mesh", |ui| {
+								let is_exporting = self.snapshot_uses.contains(&SnapshotUse::Mesh);
+								if self.mesh.show(ui, is_exporting) {
+									self.request_landscape_snapshot(SnapshotUse::Mesh);
+								}
+							});
 							ui.label(format!("UPS: {:.02}", tps));
 							ui.label(format!("Total entities: -"));
 
@@ -347,14 +488,235 @@ impl App {
 										});
 								}
 								MenuTab::Params => {
+									ui.heading("Erosion");
+									ComboBox::new("erosion_preset", "Preset")
+										.selected_text(self.erosion_preset.map_or("Custom", |preset| preset.localized_name()))
+										.show_ui(ui, |ui| {
+											for preset in ErosionPreset::all() {
+												if ui.selectable_value(&mut self.erosion_preset, Some(*preset), preset.localized_name()).clicked() {
+													self.erosion_params = preset.params();
+												}
+											}
+										});
+
+									let params = &mut self.erosion_params;
+									let mut changed = false;
+									ComboBox::new("erosion_model", "Model")
+										.selected_text(params.model.localized_name())
+										.show_ui(ui, |ui| {
+											for model in ErosionModel::all() {
+												changed |= ui.selectable_value(&mut params.model, *model, model.localized_name()).clicked();
+											}
+										});
 
+									match params.model {
+										ErosionModel::Droplets => {
+											Grid::new("erosion_params")
+												.num_columns(2)
+												.spacing((40.0, 4.0))
+												.show(ui, |ui| {
+													ui.label("Droplets per 256² block");
+													changed |= ui.add(DragValue::new(&mut params.droplets_count).clamp_range(ErosionParams::DROPLETS_COUNT_RANGE)).changed();
+													ui.end_row();
+
+													ui.label("Brush radius");
+													changed |= ui.add(Slider::new(&mut params.brush_radius, ErosionParams::BRUSH_RADIUS_RANGE)).changed();
+													ui.end_row();
+
+													ui.label("Droplet lifetime");
+													changed |= ui.add(DragValue::new(&mut params.max_droplet_lifetime).clamp_range(ErosionParams::MAX_DROPLET_LIFETIME_RANGE)).changed();
+													ui.end_row();
+
+													ui.label("Inertia");
+													changed |= ui.add(Slider::new(&mut params.inertia, ErosionParams::INERTIA_RANGE)).changed();
+													ui.end_row();
+
+													ui.label("Soil capacity per water");
+													changed |= ui.add(DragValue::new(&mut params.soil_capacity_per_water).speed(0.05).clamp_range(ErosionParams::SOIL_CAPACITY_PER_WATER_RANGE)).changed();
+													ui.end_row();
+
+													ui.label("Min soil capacity");
+													changed |= ui.add(DragValue::new(&mut params.min_soil_capacity).speed(0.001).clamp_range(ErosionParams::MIN_SOIL_CAPACITY_RANGE)).changed();
+													ui.end_row();
+
+													ui.label("Deposit speed");
+													changed |= ui.add(Slider::new(&mut params.deposit_speed, ErosionParams::SPEED_RANGE)).changed();
+													ui.end_row();
+
+													ui.label("Erode speed");
+													changed |= ui.add(Slider::new(&mut params.erode_speed, ErosionParams::SPEED_RANGE)).changed();
+													ui.end_row();
+
+													ui.label("Evaporate speed");
+													changed |= ui.add(Slider::new(&mut params.evaporate_speed, ErosionParams::SPEED_RANGE)).changed();
+													ui.end_row();
+												});
+
+											let mut layers_enabled = params.layers.is_some();
+											if ui.checkbox(&mut layers_enabled, "Soil and bedrock").changed() {
+												params.layers = if layers_enabled { Some(ErodibilityParams::default()) } else { None };
+												changed = true;
+											}
+											if let Some(layers) = &mut params.layers {
+												Grid::new("erodibility_params")
+													.num_columns(2)
+													.spacing((40.0, 4.0))
+													.show(ui, |ui| {
+														ui.label("Soil erodibility");
+														changed |= ui.add(Slider::new(&mut layers.soil, ErodibilityParams::ERODIBILITY_RANGE)).changed();
+														ui.end_row();
+
+														ui.label("Rock erodibility");
+														changed |= ui.add(Slider::new(&mut layers.rock, ErodibilityParams::ERODIBILITY_RANGE)).changed();
+														ui.end_row();
+													});
+											}
+										}
+										ErosionModel::ShallowWater => {
+											let water = &mut params.shallow_water;
+											Grid::new("shallow_water_params")
+												.num_columns(2)
+												.spacing((40.0, 4.0))
+												.show(ui, |ui| {
+													ui.label("Time step");
+													changed |= ui.add(DragValue::new(&mut water.time_step).speed(0.0001).clamp_range(ShallowWaterParams::TIME_STEP_RANGE)).changed();
+													ui.end_row();
+
+													ui.label("Rain rate");
+													changed |= ui.add(DragValue::new(&mut water.rain_rate).speed(0.001).clamp_range(ShallowWaterParams::RATE_RANGE)).changed();
+													ui.end_row();
+
+													ui.label("Cell width");
+													changed |= ui.add(DragValue::new(&mut water.cell_width).speed(0.001).clamp_range(ShallowWaterParams::CELL_WIDTH_RANGE)).changed();
+													ui.end_row();
+
+													ui.label("Sediment capacity");
+													changed |= ui.add(DragValue::new(&mut water.sediment_capacity).speed(0.01).clamp_range(ShallowWaterParams::RATE_RANGE)).changed();
+													ui.end_row();
+
+													ui.label("Dissolve rate");
+													changed |= ui.add(DragValue::new(&mut water.dissolve_rate).speed(0.01).clamp_range(ShallowWaterParams::RATE_RANGE)).changed();
+													ui.end_row();
+
+													ui.label("Deposit rate");
+													changed |= ui.add(DragValue::new(&mut water.deposit_rate).speed(0.01).clamp_range(ShallowWaterParams::RATE_RANGE)).changed();
+													ui.end_row();
+
+													ui.label("Evaporation rate");
+													changed |= ui.add(DragValue::new(&mut water.evaporation_rate).speed(0.01).clamp_range(ShallowWaterParams::RATE_RANGE)).changed();
+													ui.end_row();
+
+													ui.label("Min tilt");
+													changed |= ui.add(Slider::new(&mut water.min_tilt, 0.0..=1.0)).changed();
+													ui.end_row();
+												});
+										}
+									}
+
+									ui.heading("Thermal erosion");
+									let mut thermal_enabled = params.thermal.is_some();
+									if ui.checkbox(&mut thermal_enabled, "Enabled").changed() {
+										params.thermal = if thermal_enabled { Some(ThermalParams::default()) } else { None };
+										changed = true;
+									}
+									if let Some(thermal) = &mut params.thermal {
+										Grid::new("thermal_params")
+											.num_columns(2)
+											.spacing((40.0, 4.0))
+											.show(ui, |ui| {
+												ui.label("Talus angle");
+												changed |= ui.add(Slider::new(&mut thermal.talus_angle, ThermalParams::TALUS_ANGLE_RANGE).suffix("°")).changed();
+												ui.end_row();
+
+												ui.label("Cell width");
+												changed |= ui.add(DragValue::new(&mut thermal.cell_width).speed(0.001).clamp_range(ThermalParams::CELL_WIDTH_RANGE)).changed();
+												ui.end_row();
+
+												ui.label("Passes per tick");
+												changed |= ui.add(DragValue::new(&mut thermal.iterations).clamp_range(ThermalParams::ITERATIONS_RANGE)).changed();
+												ui.end_row();
+
+												ui.label("Transfer rate");
+												changed |= ui.add(Slider::new(&mut thermal.transfer_rate, ThermalParams::TRANSFER_RATE_RANGE)).changed();
+												ui.end_row();
+											});
+									}
+
+									ui.heading("Tectonics");
+									let mut tectonics_enabled = params.tectonics.is_some();
+									if ui.checkbox(&mut tectonics_enabled, "Enabled").changed() {
+										params.tectonics = if tectonics_enabled { Some(TectonicParams::default()) } else { None };
+										changed = true;
+									}
+									if let Some(tectonics) = &mut params.tectonics {
+										Grid::new("tectonic_params")
+											.num_columns(2)
+											.spacing((40.0, 4.0))
+											.show(ui, |ui| {
+												ui.label("Plates");
+												ComboBox::new("plate_source", "")
+													.selected_text(tectonics.source.localized_name())
+													.show_ui(ui, |ui| {
+														for source in PlateSource::all() {
+															changed |= ui.selectable_value(&mut tectonics.source, *source, source.localized_name()).clicked();
+														}
+													});
+												ui.end_row();
+
+												ui.label("Uplift rate");
+												changed |= ui.add(DragValue::new(&mut tectonics.uplift_rate).speed(0.00001).clamp_range(TectonicParams::RATE_RANGE)).changed();
+												ui.end_row();
+
+												ui.label("Subsidence rate");
+												changed |= ui.add(DragValue::new(&mut tectonics.subsidence_rate).speed(0.00001).clamp_range(TectonicParams::RATE_RANGE)).changed();
+												ui.end_row();
+
+												ui.label("Isostasy");
+												changed |= ui.add(Slider::new(&mut tectonics.isostasy, TectonicParams::ISOSTASY_RANGE)).changed();
+												ui.end_row();
+
+												ui.label("Boundary width");
+												changed |= ui.add(DragValue::new(&mut tectonics.boundary_width).speed(0.5).clamp_range(TectonicParams::BOUNDARY_WIDTH_RANGE)).changed();
+												ui.end_row();
+
+												ui.label("Ticks per step");
+												changed |= ui.add(DragValue::new(&mut tectonics.interval).clamp_range(TectonicParams::INTERVAL_RANGE)).changed();
+												ui.end_row();
+											});
+									}
+
+									ui.heading("Heights");
+									let land = format!("Land fraction of the generated world: {}", match &self.world_settings.hypsometry {
+										Some(params) => format!("{:.0}%", params.land_fraction * 100.0),
+										None => "as generated".to_string(),
+									});
+									if ui.button("Normalize heights").on_hover_text(land).clicked() {
+										self.normalize_landscape = true;
+									}
+									if ui.button("Recompute lakes and rivers").on_hover_text("Also swamps and climate, for the eroded landscape").clicked() {
+										self.recompute_overlays = true;
+									}
+
+									if changed {
+										self.erosion_preset = ErosionPreset::all().iter()
+											.copied()
+											.find(|preset| preset.params() == self.erosion_params);
+									}
 								}
 								MenuTab::Entity => {}
-								MenuTab::Stats => {}
+								MenuTab::Stats => {
+									let is_analyzing = self.snapshot_uses.contains(&SnapshotUse::Statistics);
+									if self.stats.show(ui, is_analyzing) {
+										self.request_landscape_snapshot(SnapshotUse::Statistics);
+									}
+								}
 								MenuTab::ProgramSettings => {}
 							}
-						} else {
-							
+						} else if self.page == Page::Statistics {
+							let is_analyzing = self.snapshot_uses.contains(&SnapshotUse::Statistics);
+							if self.stats.show(ui, is_analyzing) {
+								self.request_landscape_snapshot(SnapshotUse::Statistics);
+							}
 						}
 						});
 			});
@@ -0,0 +1,186 @@
+use egui_sdl2_gl::egui::{Button, CollapsingHeader, DragValue, Grid, TextEdit, Ui};
+use egui_sdl2_gl::egui::plot::{Bar, BarChart, Plot};
+
+use crate::map::{self, Height, Landscape};
+use crate::terrain::stats::{self, Histogram, StatsParams, TerrainStats};
+
+pub struct StatsMenu {
+	params: StatsParams,
+	stats: Option<TerrainStats>,
+	export_path: String,
+	export_status: Option<String>,
+}
+
+impl Default for StatsMenu {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl StatsMenu {
+	pub fn new() -> Self {
+		StatsMenu {
+			params: StatsParams::default(),
+			stats: None,
+			export_path: "stats.csv".to_owned(),
+			export_status: None,
+		}
+	}
+
+	/// `biomes` are taken into account if they are of the same size as `landscape`
+	pub fn analyze(&mut self, landscape: &Landscape, sea_level: Height, biomes: Option<&map::Map>) {
+		let biomes = biomes.filter(|biomes| biomes.size() == landscape.size());
+		self.params.sea_level = sea_level;
+		self.stats = Some(stats::compute_stats(landscape, biomes, &self.params));
+		self.export_status = None;
+	}
+
+	/// Returns true if the world has to be analyzed, see `analyze`.
+	/// `is_analyzing` is set while the landscape snapshot is on its way
+	pub fn show(&mut self, ui: &mut Ui, is_analyzing: bool) -> bool {
+		Grid::new("stats_params")
+			.num_columns(2)
+			.spacing((40.0, 4.0))
+			.show(ui, |ui| {
+				ui.label("Height bins");
+				ui.add(DragValue::new(&mut self.params.height_bins).clamp_range(StatsParams::BINS_RANGE));
+				ui.end_row();
+
+				ui.label("Slope bins");
+				ui.add(DragValue::new(&mut self.params.slope_bins).clamp_range(StatsParams::BINS_RANGE));
+				ui.end_row();
+
+				ui.label("Cell width");
+				ui.add(DragValue::new(&mut self.params.cell_width).speed(0.001).clamp_range(StatsParams::CELL_WIDTH_RANGE));
+				ui.end_row();
+			});
+
+		let analyze = ui.add_enabled(!is_analyzing, Button::new("Analyze world")).clicked();
+		if is_analyzing {
+			ui.label("Waiting for the landscape...");
+		}
+
+		let stats = match &self.stats {
+			Some(stats) => stats,
+			None => return analyze,
+		};
+
+		let percent = |share: f32| format!("{:.1}%", share * 100.0);
+		let largest = |areas: &[u32]| areas.first().map_or("-".to_owned(), |area| area.to_string());
+		Grid::new("terrain_stats")
+			.num_columns(2)
+			.spacing((40.0, 4.0))
+			.show(ui, |ui| {
+				ui.label("Size");
+				ui.label(format!("{}×{}", stats.size.0, stats.size.1));
+				ui.end_row();
+
+				ui.label("Sea level");
+				ui.label(format!("{:.3}", stats.sea_level));
+				ui.end_row();
+
+				ui.label("Land");
+				ui.label(percent(stats.area.land_share()));
+				ui.end_row();
+
+				ui.label("Sea");
+				ui.label(percent(stats.area.sea_share()));
+				ui.end_row();
+
+				ui.label("Lakes");
+				ui.label(format!("{} ({})", percent(stats.area.lake_share()), stats.area.lakes_count));
+				ui.end_row();
+
+				ui.label("Coastline length");
+				ui.label(stats.coastline_length.to_string());
+				ui.end_row();
+
+				ui.label("Islands");
+				ui.label(format!("{}, largest {}", stats.islands.len(), largest(&stats.islands)));
+				ui.end_row();
+
+				ui.label("Drainage basins");
+				ui.label(format!("{}, largest {}", stats.drainage_basins.len(), largest(&stats.drainage_basins)));
+				ui.end_row();
+			});
+
+		CollapsingHeader::new("Heights").show(ui, |ui| {
+			Self::show_histogram(ui, "height_histogram", &stats.heights);
+		});
+
+		CollapsingHeader::new("Slopes, °").show(ui, |ui| {
+			Self::show_histogram(ui, "slope_histogram", &stats.slopes);
+		});
+
+		CollapsingHeader::new("Aspect").show(ui, |ui| {
+			Grid::new("aspect_stats")
+				.num_columns(2)
+				.spacing((40.0, 4.0))
+				.show(ui, |ui| {
+					let cells = stats.area.cells.max(1) as f32;
+					for (name, count) in stats::ASPECT_NAMES.iter().zip(stats.aspect) {
+						ui.label(*name);
+						ui.label(percent(count as f32 / cells));
+						ui.end_row();
+					}
+					ui.label("Flat");
+					ui.label(percent(stats.flat_cells as f32 / cells));
+					ui.end_row();
+				});
+		});
+
+		if let Some(biomes) = &stats.biomes {
+			CollapsingHeader::new("Biomes").show(ui, |ui| {
+				Grid::new("biome_stats")
+					.num_columns(2)
+					.spacing((40.0, 4.0))
+					.show(ui, |ui| {
+						for (biome, share) in biomes {
+							ui.label(biome.localized_name());
+							ui.label(percent(*share));
+							ui.end_row();
+						}
+					});
+			});
+		}
+
+		ui.horizontal_wrapped(|ui| {
+			ui.add(TextEdit::singleline(&mut self.export_path).desired_width(150.0));
+			if ui.button("Export CSV").clicked() {
+				self.export_status = Some(match stats.export_csv(&self.export_path) {
+					Ok(()) => "Exported".to_owned(),
+					Err(error) => error.to_string(),
+				});
+			}
+			if ui.button("Export JSON").clicked() {
+				self.export_status = Some(match stats.export_json(&self.export_path) {
+					Ok(()) => "Exported".to_owned(),
+					Err(error) => error.to_string(),
+				});
+			}
+		});
+		if let Some(status) = &self.export_status {
+			ui.label(status);
+		}
+		analyze
+	}
+
+	fn show_histogram(ui: &mut Ui, id: &str, histogram: &Histogram) {
+		let bars: Vec<Bar> = (0..histogram.counts.len())
+			.map(|bin| {
+				let (from, to) = histogram.bin_range(bin);
+				Bar::new(((from + to) / 2.0) as f64, (histogram.share(bin) * 100.0) as f64)
+					.width((to - from) as f64)
+			})
+			.collect();
+
+		Plot::new(id)
+			.height(120.0)
+			.allow_drag(false)
+			.allow_zoom(false)
+			.allow_scroll(false)
+			.allow_boxed_zoom(false)
+			.include_y(0.0)
+			.show(ui, |plot_ui| plot_ui.bar_chart(BarChart::new(bars)));
+	}
+}
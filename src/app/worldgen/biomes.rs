@@ -1,18 +1,155 @@
-use egui_sdl2_gl::egui::Ui;
+use egui_sdl2_gl::egui::{Ui, Grid, DragValue, Slider, TextEdit, self};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::pdn;
+use crate::seed::{SeedStage, WorldSeed};
+use crate::terrain::export;
+use crate::terrain::resources::{self, Distribution, ResourceCounts, ResourceKind, ResourceParams};
 
 use super::{EditMap, MapType};
 
 #[derive(Debug, Clone)]
 pub struct BiomesEditor {
-    
+    resource_params: ResourceParams,
+    density_scale: f32,
+    counts: Option<ResourceCounts>,
+    compound_target: u64,
+
+    export_path: String,
+    export_status: Option<String>,
+
+    pdn_path: String,
 }
 
 impl BiomesEditor {
     pub fn new() -> Self {
-        BiomesEditor {}
+        BiomesEditor {
+            resource_params: ResourceParams::default(),
+            density_scale: 1.0,
+            counts: None,
+            compound_target: 800,
+
+            export_path: "biomes.png".to_owned(),
+            export_status: None,
+
+            pdn_path: "biomes.pdn".to_owned(),
+        }
     }
 
-    pub fn show(&mut self, ui: &mut Ui, map: &mut Option<EditMap>, page: &mut MapType) {
+    pub fn show(&mut self, ui: &mut Ui, map: &mut Option<EditMap>, page: &mut MapType, seed: WorldSeed, fertility: Option<&[f32]>) {
+        const SPACE: f32 = 15.0;
         ui.heading("Biomes");
+
+        let biomes = match map {
+            Some(EditMap::Biomes(biomes)) => Some(biomes),
+            _ => None,
+        };
+        let Some(biomes) = biomes else {
+            ui.label("Create a landscape and convert it to a biomes map first");
+            if ui.button("Back to landscape").clicked() {
+                *page = MapType::Landscape;
+            }
+            return;
+        };
+
+        ui.horizontal_wrapped(|ui| {
+            ui.add(TextEdit::singleline(&mut self.export_path).desired_width(150.0));
+            if ui.button("Export image").on_hover_text("Indexed PNG and a JSON legend next to it").clicked() {
+                self.export_status = Some(match export::export_biome_map(biomes, &self.export_path) {
+                    Ok(()) => "Exported".to_owned(),
+                    Err(error) => error.to_string(),
+                });
+            }
+        });
+        ui.horizontal_wrapped(|ui| {
+            ui.add(TextEdit::singleline(&mut self.pdn_path).desired_width(150.0));
+            if ui.button("Save as Paint.NET").on_hover_text("Layers \"biomes\" and \"resources\" 1-4").clicked() {
+                let saved = pdn::maps::world_document(None, Some(biomes)).and_then(|document| document.save(&self.pdn_path));
+                self.export_status = Some(match saved {
+                    Ok(()) => "Saved".to_owned(),
+                    Err(error) => error.to_string(),
+                });
+            }
+        });
+        if let Some(status) = &self.export_status {
+            ui.label(status);
+        }
+
+        ui.add_space(SPACE);
+        ui.heading("Resources");
+        Grid::new("resource_params")
+            .num_columns(2)
+            .spacing((40.0, 4.0))
+            .show(ui, |ui| {
+                ui.label("Density");
+                ui.add(Slider::new(&mut self.density_scale, 0.0..=4.0));
+                ui.end_row();
+
+                ui.label("Max compound per cell");
+                ui.add(DragValue::new(&mut self.resource_params.max_compound_count).clamp_range(1..=u8::MAX));
+                ui.end_row();
+            });
+        ui.collapsing("Spawn tables", |ui| {
+            Grid::new("spawn_tables")
+                .num_columns(4)
+                .spacing((20.0, 4.0))
+                .show(ui, |ui| {
+                    for table in &mut self.resource_params.tables {
+                        for (i, rule) in table.rules.iter_mut().enumerate() {
+                            ui.label(if i == 0 { table.biome.localized_name() } else { "" });
+                            ui.label(rule.resource.localized_name());
+                            ui.add(DragValue::new(&mut rule.density).speed(0.001).clamp_range(0.0..=1.0));
+                            let distribution = ui.label(rule.distribution.localized_name());
+                            match rule.distribution {
+                                Distribution::Uniform => {}
+                                Distribution::Clustered { frequency, strength } => {
+                                    distribution.on_hover_text(format!("Frequency {}, strength {}", frequency, strength));
+                                }
+                                Distribution::PoissonDisk { min_distance } => {
+                                    distribution.on_hover_text(format!("At least {} cells apart", min_distance));
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    }
+                });
+        });
+
+        if ui.button("Generate resources").clicked() {
+            let mut params = self.resource_params.clone();
+            for rule in params.tables.iter_mut().flat_map(|table| table.rules.iter_mut()) {
+                rule.density *= self.density_scale;
+            }
+            for cell in biomes.cells_mut() {
+                cell.1 = Default::default();
+            }
+            self.counts = Some(resources::place_resources(biomes, &params, fertility, seed.derive(SeedStage::Resources)));
+        }
+
+        let Some(counts) = &self.counts else {
+            return;
+        };
+        ui.add_space(SPACE);
+        Grid::new("resource_counts")
+            .num_columns(2)
+            .spacing((40.0, 4.0))
+            .show(ui, |ui| {
+                for kind in ResourceKind::all() {
+                    ui.label(kind.localized_name());
+                    ui.label(counts.get(*kind).to_string());
+                    ui.end_row();
+                }
+            });
+
+        ui.label(format!("Compound amount: {}", counts.compound_amount()));
+        ui.horizontal_wrapped(|ui| {
+            ui.add(DragValue::new(&mut self.compound_target));
+            if ui.add(egui::Button::new(format!("Set to {}", self.compound_target))).clicked() {
+                let mut rng = StdRng::seed_from_u64(seed.derive(SeedStage::Resources));
+                resources::set_compound_total(biomes, self.compound_target, &mut rng);
+                self.counts = Some(ResourceCounts::of(biomes));
+            }
+        });
     }
-}
\ No newline at end of file
+}
@@ -1,6 +1,19 @@
-use egui_sdl2_gl::egui::{Ui, Grid, DragValue, self};
+use egui_sdl2_gl::egui::{Ui, Grid, DragValue, ComboBox, Slider, TextEdit, self};
 
-use crate::map;
+use crate::map::{self, Biome};
+use crate::pdn::{self, PdnDocument, PdnError};
+use crate::terrain::{climate, flood, rivers, thermal, ErosionParams};
+use crate::terrain::biomes::{self, BiomeLayers, BiomeParams, BiomeRule, BiomeTable};
+use crate::terrain::climate::ClimateParams;
+use crate::terrain::continents::{ContinentParams, ContinentPlacement};
+use crate::terrain::erosion_cpu::ErosionCpu;
+use crate::terrain::export::{self, HillshadeParams};
+use crate::terrain::generators::{DiamondSquareParams, DomainWarp, GeneratorKind, LayeredNoiseParams, NoiseKind, NoiseLayer, PlateParams};
+use crate::terrain::heightmap::{self, HeightChannel, HeightmapParams, Resampling};
+use crate::terrain::hypsometry::{self, HypsometryParams};
+use crate::terrain::layers::{HardnessSource, LayerParams, TerrainLayers};
+use crate::seed::SeedStage;
+use crate::world::{LayersSnapshot, WorldSettings};
 
 use super::{EditMap, MapType};
 
@@ -31,6 +44,32 @@ pub struct LandscapeEditor {
     create_height: f64,
 
     view: View,
+
+    heightmap_path: String,
+    heightmap_params: HeightmapParams,
+    heightmap_status: Option<String>,
+
+    pdn_path: String,
+    pdn_status: Option<String>,
+
+    export_path: String,
+    hillshade: HillshadeParams,
+    export_status: Option<String>,
+
+    /// Of `ErosionCpu`, the map does not have to fit into the GPU
+    erosion_params: ErosionParams,
+    erosion_iterations: u64,
+
+    biome_params: BiomeParams,
+    biome_table_path: String,
+    biome_table_status: Option<String>,
+
+    hardness_map_path: String,
+    hardness_map_status: Option<String>,
+    /// Of the last map converted to biomes
+    fertility: Option<Box<[f32]>>,
+    /// Waits for `World::request_layers_snapshot` to convert the simulation map
+    simulation_map_requested: bool,
 }
 
 impl LandscapeEditor {
@@ -40,21 +79,94 @@ impl LandscapeEditor {
             create_height: 1.0,
 
             view: View::BasicBiomes,
+
+            heightmap_path: "heightmap.png".to_owned(),
+            heightmap_params: HeightmapParams::default(),
+            heightmap_status: None,
+
+            pdn_path: "world.pdn".to_owned(),
+            pdn_status: None,
+
+            export_path: "landscape.png".to_owned(),
+            hillshade: HillshadeParams::default(),
+            export_status: None,
+
+            erosion_params: ErosionParams { droplets_count: 4096, ..Default::default() },
+            erosion_iterations: 16,
+
+            biome_params: BiomeParams::default(),
+            biome_table_path: "biomes.csv".to_owned(),
+            biome_table_status: None,
+
+            hardness_map_path: "hardness.png".to_owned(),
+            hardness_map_status: None,
+            fertility: None,
+            simulation_map_requested: false,
         }
     }
 
-    pub fn show(&mut self, ui: &mut Ui, map: &mut Option<EditMap>, page: &mut MapType) {
+    pub fn show(&mut self, ui: &mut Ui, map: &mut Option<EditMap>, page: &mut MapType, settings: &mut WorldSettings) -> bool {
         const SPACE: f32 = 15.0;
         ui.heading("Load or create");
         
-        if ui.button("Load from image").clicked() {};
+        ui.horizontal_wrapped(|ui| {
+            ui.add(TextEdit::singleline(&mut self.heightmap_path).desired_width(150.0));
+            if ui.button("Load from image").on_hover_text("Resampled to the size below").clicked() {
+                self.heightmap_status = Some(match image::open(&self.heightmap_path) {
+                    Ok(image) => {
+                        let landscape = heightmap::import_heightmap(&image, self.create_size, settings.sea_level, &self.heightmap_params);
+                        *map = Some(EditMap::Landscape(landscape.with_topology(settings.topology)));
+                        "Loaded".to_owned()
+                    }
+                    Err(error) => error.to_string(),
+                });
+            }
+        });
+        if let Some(status) = &self.heightmap_status {
+            ui.label(status);
+        }
+        ui.collapsing("Image import", |ui| Self::show_heightmap_params(ui, &mut self.heightmap_params));
+
+        ui.horizontal_wrapped(|ui| {
+            ui.add(TextEdit::singleline(&mut self.pdn_path).desired_width(150.0));
+            let hint = "Layers \"height\", or \"biomes\" and \"resources\" to get a biomes map";
+            if ui.button("Load from Paint.NET").on_hover_text(hint).clicked() {
+                self.pdn_status = Some(match Self::load_pdn(&self.pdn_path, map, page, settings) {
+                    Ok(()) => "Loaded".to_owned(),
+                    Err(error) => error.to_string(),
+                });
+            }
+            if let Some(EditMap::Landscape(landscape)) = map {
+                if ui.button("Save as Paint.NET").clicked() {
+                    let saved = pdn::maps::world_document(Some(landscape), None).and_then(|document| document.save(&self.pdn_path));
+                    self.pdn_status = Some(match saved {
+                        Ok(()) => "Saved".to_owned(),
+                        Err(error) => error.to_string(),
+                    });
+                }
+            }
+        });
+        if let Some(status) = &self.pdn_status {
+            ui.label(status);
+        }
 
         let enabled = match map {
-            Some(EditMap::Biomes(_)) => true,
+            Some(EditMap::Landscape(_)) => true,
             _ => false,
         };
-        if ui.add_enabled(enabled, egui::Button::new("Convert biomes map (roughly)")).clicked() {};
-        if ui.add_enabled(false, egui::Button::new("Convert simulation map (roughly)")).clicked() {};
+        if ui.add_enabled(enabled, egui::Button::new("Convert to biomes map")).clicked() {
+            if let Some(EditMap::Landscape(landscape)) = map {
+                let terrain_layers = TerrainLayers::new(landscape, &settings.layers, settings.seed.noise_seed(SeedStage::Layers));
+                let biomes = self.convert_to_biomes(landscape, settings, &terrain_layers, None);
+                *map = Some(EditMap::Biomes(biomes));
+                *page = MapType::Biomes;
+            }
+        };
+        let hint = "Eroded landscape of the simulation with its current soil";
+        let text = if self.simulation_map_requested { "Converting simulation map..." } else { "Convert simulation map" };
+        if ui.add_enabled(!self.simulation_map_requested, egui::Button::new(text)).on_hover_text(hint).clicked() {
+            self.simulation_map_requested = true;
+        }
 
         ui.add_space(SPACE);
         Grid::new("tab_grid")
@@ -66,7 +178,14 @@ impl LandscapeEditor {
                 ui.end_row();
                 
                 ui.label("Size Y");
-                ui.add(DragValue::new(&mut self.create_size.0).clamp_range(0..=map::MAX_MAP_SIZE.0));
+                ui.add(DragValue::new(&mut self.create_size.1).clamp_range(0..=map::MAX_MAP_SIZE.1));
+                ui.end_row();
+
+                ui.label("Edges");
+                ui.horizontal(|ui| {
+                    ui.checkbox(&mut settings.topology.wrap_x, "Wrap X");
+                    ui.checkbox(&mut settings.topology.wrap_y, "Wrap Y");
+                });
                 ui.end_row();
 
                 ui.label("Landscape height");
@@ -74,7 +193,11 @@ impl LandscapeEditor {
                 ui.end_row();
             });
 
-        if ui.button("Create new map").clicked() {};
+        if ui.button("Create new map").clicked() {
+            let area = (self.create_size.0 as usize) * (self.create_size.1 as usize);
+            let height = vec![map::Height::from_num(self.create_height); area].into_boxed_slice();
+            *map = Some(EditMap::Landscape(map::Landscape::new(self.create_size, height).with_topology(settings.topology)));
+        };
         let enabled = match map {
             Some(EditMap::Landscape(_)) => true,
             _ => false,
@@ -88,5 +211,667 @@ impl LandscapeEditor {
                 ui.selectable_value(&mut self.view, *view, view.localized_name());
             }
         });
+
+        if let Some(EditMap::Landscape(landscape)) = map {
+            ui.add_space(SPACE);
+            ui.heading("Export");
+            ui.collapsing("Hillshade", |ui| Self::show_hillshade_params(ui, &mut self.hillshade));
+            ui.horizontal_wrapped(|ui| {
+                ui.add(TextEdit::singleline(&mut self.export_path).desired_width(150.0));
+                if ui.button("Heights (16-bit)").clicked() {
+                    self.export_status = Some(match export::export_height_png(landscape, &self.export_path) {
+                        Ok(()) => "Exported".to_owned(),
+                        Err(error) => error.to_string(),
+                    });
+                }
+                if ui.button("Hillshade").clicked() {
+                    let params = HillshadeParams { sea_level: settings.sea_level, ..self.hillshade.clone() };
+                    self.export_status = Some(match export::export_hillshade_png(landscape, &params, &self.export_path) {
+                        Ok(()) => "Exported".to_owned(),
+                        Err(error) => error.to_string(),
+                    });
+                }
+            });
+            if let Some(status) = &self.export_status {
+                ui.label(status);
+            }
+        }
+
+        ui.add_space(SPACE);
+        ui.heading("Landscape generation");
+        let mut generate = false;
+        ui.collapsing("Generator", |ui| {
+            let terrain = &mut settings.terrain;
+            ComboBox::new("terrain_generator", "")
+                .selected_text(terrain.generator.localized_name())
+                .show_ui(ui, |ui| {
+                    for generator in GeneratorKind::all() {
+                        ui.selectable_value(&mut terrain.generator, *generator, generator.localized_name());
+                    }
+                });
+
+            match terrain.generator {
+                GeneratorKind::Continents => Self::show_continent_params(ui, &mut settings.continents),
+                GeneratorKind::LayeredNoise => Self::show_noise_params(ui, &mut terrain.noise),
+                GeneratorKind::DiamondSquare => Self::show_diamond_square_params(ui, &mut terrain.diamond_square),
+                GeneratorKind::TectonicPlates => Self::show_plate_params(ui, &mut terrain.plates),
+            }
+            generate |= ui.button("Generate world").clicked();
+        });
+        ui.collapsing("Sea level and heights", |ui| {
+            Grid::new("sea_level")
+                .num_columns(2)
+                .spacing((40.0, 4.0))
+                .show(ui, |ui| {
+                    let mut sea_level = settings.sea_level.to_num::<f32>();
+                    ui.label("Sea level");
+                    if ui.add(Slider::new(&mut sea_level, 0.0..=1.0)).changed() {
+                        settings.sea_level = map::height_from_f32(sea_level);
+                    }
+                    ui.end_row();
+
+                    if let Some(EditMap::Landscape(landscape)) = map {
+                        ui.label("Land of this map");
+                        ui.label(format!("{:.1}%", hypsometry::land_fraction(landscape, settings.sea_level) * 100.0));
+                        ui.end_row();
+                    }
+                });
+
+            let mut has_hypsometry = settings.hypsometry.is_some();
+            if ui.checkbox(&mut has_hypsometry, "Normalize heights").changed() {
+                settings.hypsometry = if has_hypsometry { Some(HypsometryParams::default()) } else { None };
+            }
+            if let Some(params) = &mut settings.hypsometry {
+                Self::show_hypsometry_params(ui, params);
+                if let Some(EditMap::Landscape(landscape)) = map {
+                    if ui.button("Normalize this map").clicked() {
+                        hypsometry::normalize(landscape, settings.sea_level, params);
+                    }
+                }
+            }
+            generate |= ui.button("Generate world").clicked();
+        });
+        ui.collapsing("Climate", |ui| {
+            Self::show_climate_params(ui, &mut settings.climate);
+            generate |= ui.button("Generate world").clicked();
+        });
+        ui.collapsing("Soil and bedrock", |ui| {
+            self.show_layer_params(ui, &mut settings.layers);
+            generate |= ui.button("Generate world").clicked();
+        });
+
+        if let Some(EditMap::Landscape(landscape)) = map {
+            ui.add_space(SPACE);
+            ui.heading("Erosion");
+            Grid::new("cpu_erosion")
+                .num_columns(2)
+                .spacing((40.0, 4.0))
+                .show(ui, |ui| {
+                    ui.label("Iterations");
+                    ui.add(DragValue::new(&mut self.erosion_iterations).clamp_range(1..=1000));
+                    ui.end_row();
+
+                    ui.label("Droplets per 256² block");
+                    ui.add(DragValue::new(&mut self.erosion_params.droplets_count).speed(256).clamp_range(ErosionParams::DROPLETS_COUNT_RANGE));
+                    ui.end_row();
+                });
+            if ui.button("Erode this map").on_hover_text("Droplets on the CPU, tile by tile").clicked() {
+                let seed = settings.seed.derive(SeedStage::Erosion);
+                ErosionCpu::default().erode(landscape, self.erosion_iterations, seed, &self.erosion_params);
+            }
+        }
+
+        ui.add_space(SPACE);
+        ui.heading("Biomes conversion");
+        ui.collapsing("Biome table", |ui| self.show_biome_table(ui));
+        generate
+    }
+
+    /// Opens the biomes map if the document has one, the landscape otherwise
+    fn load_pdn(path: &str, map: &mut Option<EditMap>, page: &mut MapType, settings: &WorldSettings) -> Result<(), PdnError> {
+        let document = PdnDocument::load(path)?;
+        if pdn::maps::has_map(&document) {
+            *map = Some(EditMap::Biomes(pdn::maps::import_map(&document)?));
+            *page = MapType::Biomes;
+        } else {
+            let landscape = pdn::maps::import_landscape(&document)?;
+            *map = Some(EditMap::Landscape(landscape.with_topology(settings.topology)));
+        }
+        Ok(())
+    }
+
+    pub fn waits_for_simulation_map(&self) -> bool {
+        self.simulation_map_requested
+    }
+
+    /// Converts the simulation map once its snapshot is read back
+    pub fn simulation_map_ready(&mut self, map: &mut Option<EditMap>, page: &mut MapType, snapshot: &LayersSnapshot, settings: &WorldSettings) {
+        self.simulation_map_requested = false;
+        let biomes = self.convert_to_biomes(&snapshot.landscape, settings, &snapshot.layers, Some(&snapshot.scree));
+        *map = Some(EditMap::Biomes(biomes));
+        *page = MapType::Biomes;
+    }
+
+    /// Of the last converted map, see `TerrainLayers::fertility`
+    pub fn fertility(&self) -> Option<&[f32]> {
+        self.fertility.as_deref()
+    }
+
+    /// `scree` is the material received from thermal erosion, see `World::scree_snapshot`
+    fn convert_to_biomes(&mut self, landscape: &map::Landscape, settings: &WorldSettings, terrain_layers: &TerrainLayers, scree: Option<&[f32]>) -> map::Map {
+        let sea_level = settings.sea_level;
+        self.biome_params.sea_level = sea_level;
+        let climate = climate::compute_climate(landscape, &settings.climate_params());
+        let lakes = flood::detect_lakes(landscape, sea_level);
+        let river_params = rivers::RiverParams { sea_level, ..Default::default() };
+        let rivers = rivers::extract_rivers(landscape, &river_params, None);
+        let fertility = terrain_layers.fertility(settings.layers.fertile_depth);
+        let scree = scree.map(|deposits| thermal::scree_mask(deposits, self.biome_params.scree_deposit));
+
+        let layers = BiomeLayers {
+            climate: Some(&climate),
+            rivers: Some(&rivers),
+            lakes: Some(&lakes),
+            fertility: Some(&fertility),
+            scree: scree.as_deref(),
+        };
+        let biomes = biomes::classify_biomes(landscape, layers, &self.biome_params);
+        self.fertility = Some(fertility);
+        biomes
+    }
+
+    fn show_layer_params(&mut self, ui: &mut Ui, params: &mut LayerParams) {
+        let sources = [
+            HardnessSource::Uniform,
+            HardnessSource::Noise { frequency: 0.02, contrast: 0.8 },
+        ];
+
+        Grid::new("layer_params")
+            .num_columns(2)
+            .spacing((40.0, 4.0))
+            .show(ui, |ui| {
+                ui.label("Bedrock hardness");
+                ComboBox::new("hardness_source", "")
+                    .selected_text(params.hardness.localized_name())
+                    .show_ui(ui, |ui| {
+                        for source in sources {
+                            let is_selected = std::mem::discriminant(&params.hardness) == std::mem::discriminant(&source);
+                            if ui.selectable_label(is_selected, source.localized_name()).clicked() && !is_selected {
+                                params.hardness = source;
+                            }
+                        }
+                    });
+                ui.end_row();
+
+                if let HardnessSource::Noise { frequency, contrast } = &mut params.hardness {
+                    ui.label("Frequency");
+                    ui.add(DragValue::new(frequency).speed(0.001).clamp_range(0.0..=1.0));
+                    ui.end_row();
+
+                    ui.label("Contrast");
+                    ui.add(Slider::new(contrast, 0.0..=1.0));
+                    ui.end_row();
+                }
+
+                ui.label("Soil depth");
+                ui.add(Slider::new(&mut params.soil_depth, 0.0..=0.2));
+                ui.end_row();
+
+                ui.label("Max soil slope");
+                ui.add(Slider::new(&mut params.max_soil_slope, 0.001..=0.2));
+                ui.end_row();
+
+                ui.label("Fertile depth");
+                ui.add(Slider::new(&mut params.fertile_depth, 0.001..=0.2));
+                ui.end_row();
+            });
+
+        ui.horizontal_wrapped(|ui| {
+            ui.add(TextEdit::singleline(&mut self.hardness_map_path).desired_width(150.0));
+            if ui.button("Load hardness map").clicked() {
+                self.hardness_map_status = Some(match image::open(&self.hardness_map_path) {
+                    Ok(image) => {
+                        params.hardness = HardnessSource::Image(image.into_luma8());
+                        "Loaded".to_owned()
+                    }
+                    Err(error) => error.to_string(),
+                });
+            }
+        });
+        if let Some(status) = &self.hardness_map_status {
+            ui.label(status);
+        }
+    }
+
+    fn show_biome_table(&mut self, ui: &mut Ui) {
+        let rules = &mut self.biome_params.table.rules;
+        let mut removed = None;
+
+        Grid::new("biome_table")
+            .num_columns(4)
+            .spacing((10.0, 4.0))
+            .show(ui, |ui| {
+                ui.label("Biome");
+                ui.label("Temperature, °C");
+                ui.label("Moisture");
+                ui.end_row();
+
+                for (i, rule) in rules.iter_mut().enumerate() {
+                    ComboBox::new(("biome_rule", i), "")
+                        .selected_text(rule.biome.localized_name())
+                        .show_ui(ui, |ui| {
+                            for biome in Biome::all() {
+                                ui.selectable_value(&mut rule.biome, *biome, biome.localized_name());
+                            }
+                        });
+                    ui.horizontal(|ui| {
+                        ui.add(DragValue::new(&mut rule.temperature.0).speed(0.5).clamp_range(-100.0..=rule.temperature.1));
+                        ui.add(DragValue::new(&mut rule.temperature.1).speed(0.5).clamp_range(rule.temperature.0..=100.0));
+                    });
+                    ui.horizontal(|ui| {
+                        ui.add(DragValue::new(&mut rule.moisture.0).speed(0.01).clamp_range(0.0..=rule.moisture.1));
+                        ui.add(DragValue::new(&mut rule.moisture.1).speed(0.01).clamp_range(rule.moisture.0..=1.0));
+                    });
+                    if ui.button("Remove").clicked() {
+                        removed = Some(i);
+                    }
+                    ui.end_row();
+                }
+            });
+
+        if let Some(i) = removed {
+            self.biome_params.table.rules.remove(i);
+        }
+        ui.horizontal_wrapped(|ui| {
+            if ui.button("Add rule").clicked() {
+                self.biome_params.table.rules.push(BiomeRule { biome: Biome::Plains, temperature: (0.0, 30.0), moisture: (0.0, 1.0) });
+            }
+            if ui.button("Reset").clicked() {
+                self.biome_params.table = BiomeTable::default();
+            }
+        });
+
+        ui.horizontal_wrapped(|ui| {
+            ui.add(TextEdit::singleline(&mut self.biome_table_path).desired_width(150.0));
+            if ui.button("Save").clicked() {
+                self.biome_table_status = Some(match self.biome_params.table.save(&self.biome_table_path) {
+                    Ok(()) => "Saved".to_owned(),
+                    Err(error) => error.to_string(),
+                });
+            }
+            if ui.button("Load").clicked() {
+                self.biome_table_status = Some(match BiomeTable::load(&self.biome_table_path) {
+                    Ok(table) => {
+                        self.biome_params.table = table;
+                        "Loaded".to_owned()
+                    }
+                    Err(error) => error.to_string(),
+                });
+            }
+        });
+        if let Some(status) = &self.biome_table_status {
+            ui.label(status);
+        }
+    }
+
+    fn show_climate_params(ui: &mut Ui, params: &mut ClimateParams) {
+        Grid::new("climate_params")
+            .num_columns(2)
+            .spacing((40.0, 4.0))
+            .show(ui, |ui| {
+                ui.label("Equator temperature");
+                ui.add(DragValue::new(&mut params.equator_temperature).speed(0.5).clamp_range(-100.0..=100.0).suffix("°C"));
+                ui.end_row();
+
+                ui.label("Pole temperature");
+                ui.add(DragValue::new(&mut params.pole_temperature).speed(0.5).clamp_range(-100.0..=100.0).suffix("°C"));
+                ui.end_row();
+
+                ui.label("Equator position");
+                ui.add(Slider::new(&mut params.equator, 0.0..=1.0));
+                ui.end_row();
+
+                ui.label("Cooling with height");
+                ui.add(DragValue::new(&mut params.lapse_rate).speed(0.5).clamp_range(0.0..=200.0));
+                ui.end_row();
+
+                let mut angle = params.wind.1.atan2(params.wind.0).to_degrees();
+                ui.label("Wind direction");
+                if ui.add(Slider::new(&mut angle, -180.0..=180.0).suffix("°")).changed() {
+                    let angle = angle.to_radians();
+                    params.wind = (angle.cos(), angle.sin());
+                }
+                ui.end_row();
+
+                ui.label("Humidity at the border");
+                ui.add(Slider::new(&mut params.border_humidity, 0.0..=1.0));
+                ui.end_row();
+
+                ui.label("Evaporation over sea");
+                ui.add(Slider::new(&mut params.evaporation, 0.0..=1.0));
+                ui.end_row();
+
+                ui.label("Rain rate");
+                ui.add(Slider::new(&mut params.rain_rate, 0.0..=1.0));
+                ui.end_row();
+
+                ui.label("Rain on slopes");
+                ui.add(DragValue::new(&mut params.orographic_rain).speed(0.5).clamp_range(0.0..=1000.0));
+                ui.end_row();
+
+                ui.label("Coast distance");
+                ui.add(DragValue::new(&mut params.coast_distance).speed(0.5).clamp_range(1.0..=1024.0));
+                ui.end_row();
+
+                ui.label("Coast influence");
+                ui.add(Slider::new(&mut params.coast_influence, 0.0..=1.0));
+                ui.end_row();
+            });
+    }
+
+    fn show_noise_params(ui: &mut Ui, params: &mut LayeredNoiseParams) {
+        Grid::new("noise_params")
+            .num_columns(2)
+            .spacing((40.0, 4.0))
+            .show(ui, |ui| {
+                ui.label("Base height");
+                ui.add(Slider::new(&mut params.base_height, 0.0..=1.0));
+                ui.end_row();
+
+                let mut has_warp = params.domain_warp.is_some();
+                ui.checkbox(&mut has_warp, "Domain warp");
+                ui.end_row();
+                let mut warp = params.domain_warp.unwrap_or(DomainWarp { frequency: 0.01, strength: 20.0 });
+                ui.label("Warp frequency");
+                ui.add_enabled(has_warp, DragValue::new(&mut warp.frequency).speed(0.001).clamp_range(0.0..=1.0));
+                ui.end_row();
+                ui.label("Warp strength");
+                ui.add_enabled(has_warp, DragValue::new(&mut warp.strength).speed(0.5).clamp_range(0.0..=1024.0));
+                ui.end_row();
+                params.domain_warp = if has_warp { Some(warp) } else { None };
+            });
+
+        let mut removed = None;
+        for (i, layer) in params.layers.iter_mut().enumerate() {
+            ui.separator();
+            Grid::new(("noise_layer", i))
+                .num_columns(2)
+                .spacing((40.0, 4.0))
+                .show(ui, |ui| {
+                    ui.label("Kind");
+                    ComboBox::new(("noise_kind", i), "")
+                        .selected_text(layer.kind.localized_name())
+                        .show_ui(ui, |ui| {
+                            for kind in NoiseKind::all() {
+                                ui.selectable_value(&mut layer.kind, *kind, kind.localized_name());
+                            }
+                        });
+                    ui.end_row();
+
+                    ui.label("Frequency");
+                    ui.add(DragValue::new(&mut layer.frequency).speed(0.001).clamp_range(0.0..=1.0));
+                    ui.end_row();
+
+                    ui.label("Octaves");
+                    ui.add(DragValue::new(&mut layer.octaves).clamp_range(LayeredNoiseParams::OCTAVES_RANGE));
+                    ui.end_row();
+
+                    ui.label("Lacunarity");
+                    ui.add(DragValue::new(&mut layer.lacunarity).speed(0.01).clamp_range(1.0..=4.0));
+                    ui.end_row();
+
+                    ui.label("Persistence");
+                    ui.add(Slider::new(&mut layer.persistence, 0.0..=1.0));
+                    ui.end_row();
+
+                    ui.label("Amplitude");
+                    ui.add(Slider::new(&mut layer.amplitude, 0.0..=1.0));
+                    ui.end_row();
+                });
+            if ui.button("Remove layer").clicked() {
+                removed = Some(i);
+            }
+        }
+        if let Some(i) = removed {
+            params.layers.remove(i);
+        }
+        if ui.button("Add layer").clicked() {
+            params.layers.push(NoiseLayer::default());
+        }
+    }
+
+    fn show_hillshade_params(ui: &mut Ui, params: &mut HillshadeParams) {
+        Grid::new("hillshade_params")
+            .num_columns(2)
+            .spacing((40.0, 4.0))
+            .show(ui, |ui| {
+                ui.label("Light azimuth");
+                ui.add(Slider::new(&mut params.azimuth, HillshadeParams::AZIMUTH_RANGE).suffix("°"));
+                ui.end_row();
+
+                ui.label("Light altitude");
+                ui.add(Slider::new(&mut params.altitude, HillshadeParams::ALTITUDE_RANGE).suffix("°"));
+                ui.end_row();
+
+                ui.label("Vertical exaggeration");
+                ui.add(DragValue::new(&mut params.exaggeration).speed(0.5).clamp_range(HillshadeParams::EXAGGERATION_RANGE));
+                ui.end_row();
+
+                ui.label("Ambient light");
+                ui.add(Slider::new(&mut params.ambient, HillshadeParams::AMBIENT_RANGE));
+                ui.end_row();
+            });
+    }
+
+    fn show_heightmap_params(ui: &mut Ui, params: &mut HeightmapParams) {
+        Grid::new("heightmap_params")
+            .num_columns(2)
+            .spacing((40.0, 4.0))
+            .show(ui, |ui| {
+                ui.label("Channel");
+                ComboBox::new("heightmap_channel", "")
+                    .selected_text(params.channel.localized_name())
+                    .show_ui(ui, |ui| {
+                        for channel in HeightChannel::all() {
+                            ui.selectable_value(&mut params.channel, *channel, channel.localized_name());
+                        }
+                    });
+                ui.end_row();
+
+                ui.label("Black height");
+                ui.add(Slider::new(&mut params.min_height, HeightmapParams::HEIGHT_RANGE));
+                ui.end_row();
+
+                ui.label("White height");
+                ui.add(Slider::new(&mut params.max_height, HeightmapParams::HEIGHT_RANGE));
+                ui.end_row();
+
+                ui.label("Stretch levels");
+                ui.checkbox(&mut params.stretch, "");
+                ui.end_row();
+
+                let mut calibrated = params.sea_value.is_some();
+                if ui.checkbox(&mut calibrated, "Sea level at").changed() {
+                    params.sea_value = if calibrated { Some(0.5) } else { None };
+                }
+                if let Some(sea_value) = &mut params.sea_value {
+                    ui.add(Slider::new(sea_value, HeightmapParams::VALUE_RANGE));
+                }
+                ui.end_row();
+
+                ui.label("Resampling");
+                ComboBox::new("heightmap_resampling", "")
+                    .selected_text(params.resampling.localized_name())
+                    .show_ui(ui, |ui| {
+                        for resampling in Resampling::all() {
+                            ui.selectable_value(&mut params.resampling, *resampling, resampling.localized_name());
+                        }
+                    });
+                ui.end_row();
+            });
+    }
+
+    fn show_hypsometry_params(ui: &mut Ui, params: &mut HypsometryParams) {
+        Grid::new("hypsometry_params")
+            .num_columns(2)
+            .spacing((40.0, 4.0))
+            .show(ui, |ui| {
+                ui.label("Land fraction");
+                ui.add(Slider::new(&mut params.land_fraction, HypsometryParams::FRACTION_RANGE));
+                ui.end_row();
+
+                ui.label("Max depth");
+                ui.add(Slider::new(&mut params.max_depth, HypsometryParams::HEIGHT_RANGE));
+                ui.end_row();
+
+                ui.label("Max altitude");
+                ui.add(Slider::new(&mut params.max_altitude, HypsometryParams::HEIGHT_RANGE));
+                ui.end_row();
+
+                ui.label("Land curve");
+                ui.add(Slider::new(&mut params.land_exponent, HypsometryParams::EXPONENT_RANGE).logarithmic(true));
+                ui.end_row();
+
+                ui.label("Sea curve");
+                ui.add(Slider::new(&mut params.sea_exponent, HypsometryParams::EXPONENT_RANGE).logarithmic(true));
+                ui.end_row();
+
+                ui.label("Strength");
+                ui.add(Slider::new(&mut params.strength, HypsometryParams::FRACTION_RANGE));
+                ui.end_row();
+            });
+    }
+
+    fn show_diamond_square_params(ui: &mut Ui, params: &mut DiamondSquareParams) {
+        Grid::new("diamond_square_params")
+            .num_columns(2)
+            .spacing((40.0, 4.0))
+            .show(ui, |ui| {
+                ui.label("Smoothness");
+                ui.add(Slider::new(&mut params.smoothness, 0.0..=1.0));
+                ui.end_row();
+
+                ui.label("Min height");
+                ui.add(Slider::new(&mut params.height_range.0, 0.0..=params.height_range.1));
+                ui.end_row();
+
+                ui.label("Max height");
+                ui.add(Slider::new(&mut params.height_range.1, params.height_range.0..=1.0));
+                ui.end_row();
+            });
+    }
+
+    fn show_plate_params(ui: &mut Ui, params: &mut PlateParams) {
+        Grid::new("plate_params")
+            .num_columns(2)
+            .spacing((40.0, 4.0))
+            .show(ui, |ui| {
+                ui.label("Plates count");
+                ui.add(DragValue::new(&mut params.count).clamp_range(1..=256));
+                ui.end_row();
+
+                ui.label("Oceanic plates");
+                ui.add(Slider::new(&mut params.oceanic_fraction, 0.0..=1.0));
+                ui.end_row();
+
+                ui.label("Ocean height");
+                ui.add(Slider::new(&mut params.ocean_height, 0.0..=1.0));
+                ui.end_row();
+
+                ui.label("Continent height");
+                ui.add(Slider::new(&mut params.continent_height, 0.0..=1.0));
+                ui.end_row();
+
+                ui.label("Uplift");
+                ui.add(Slider::new(&mut params.uplift, 0.0..=1.0));
+                ui.end_row();
+
+                ui.label("Rift depth");
+                ui.add(Slider::new(&mut params.rift_depth, 0.0..=1.0));
+                ui.end_row();
+
+                ui.label("Boundary width");
+                ui.add(DragValue::new(&mut params.boundary_width).speed(0.5).clamp_range(1.0..=1024.0));
+                ui.end_row();
+
+                ui.label("Boundary noise");
+                ui.add(DragValue::new(&mut params.boundary_noise).speed(0.5).clamp_range(0.0..=1024.0));
+                ui.end_row();
+
+                ui.label("Detail");
+                ui.add(Slider::new(&mut params.detail, 0.0..=0.5));
+                ui.end_row();
+            });
+    }
+
+    fn show_continent_params(ui: &mut Ui, params: &mut ContinentParams) {
+        let placements = [
+            ContinentPlacement::Grid { columns: 5 },
+            ContinentPlacement::Random,
+            ContinentPlacement::PoissonDisk { min_distance: 64.0 },
+        ];
+
+        Grid::new("continent_params")
+            .num_columns(2)
+            .spacing((40.0, 4.0))
+            .show(ui, |ui| {
+                ui.label("Continents count");
+                ui.add(DragValue::new(&mut params.count).clamp_range(0..=256));
+                ui.end_row();
+
+                ui.label("Placement");
+                ComboBox::new("continent_placement", "")
+                    .selected_text(params.placement.localized_name())
+                    .show_ui(ui, |ui| {
+                        for placement in placements {
+                            let is_selected = std::mem::discriminant(&params.placement) == std::mem::discriminant(&placement);
+                            if ui.selectable_label(is_selected, placement.localized_name()).clicked() && !is_selected {
+                                params.placement = placement;
+                            }
+                        }
+                    });
+                ui.end_row();
+
+                match &mut params.placement {
+                    ContinentPlacement::Grid { columns } => {
+                        ui.label("Grid columns");
+                        ui.add(DragValue::new(columns).clamp_range(1..=64));
+                        ui.end_row();
+                    }
+                    ContinentPlacement::Random => {}
+                    ContinentPlacement::PoissonDisk { min_distance } => {
+                        ui.label("Min distance");
+                        ui.add(DragValue::new(min_distance).clamp_range(0.0..=map::MAX_MAP_SIZE.0 as f32));
+                        ui.end_row();
+                    }
+                }
+
+                ui.label("Walk length (min)");
+                ui.add(DragValue::new(&mut params.walk_length.0).clamp_range(1..=params.walk_length.1));
+                ui.end_row();
+
+                ui.label("Walk length (max)");
+                ui.add(DragValue::new(&mut params.walk_length.1).clamp_range(params.walk_length.0..=u32::MAX));
+                ui.end_row();
+
+                let mut has_land_fraction = params.land_fraction.is_some();
+                ui.checkbox(&mut has_land_fraction, "Land fraction");
+                let mut land_fraction = params.land_fraction.unwrap_or(0.3);
+                ui.add_enabled(has_land_fraction, Slider::new(&mut land_fraction, 0.0..=1.0));
+                params.land_fraction = if has_land_fraction { Some(land_fraction) } else { None };
+                ui.end_row();
+
+                ui.label("Coast height");
+                ui.add(Slider::new(&mut params.coast_height, 0.0..=1.0));
+                ui.end_row();
+
+                ui.label("Max height");
+                ui.add(Slider::new(&mut params.max_height, 0.0..=1.0));
+                ui.end_row();
+
+                ui.label("Coast falloff");
+                ui.add(DragValue::new(&mut params.coast_falloff).speed(0.1).clamp_range(0.1..=1024.0));
+                ui.end_row();
+            });
     }
 }
\ No newline at end of file
@@ -1,6 +1,7 @@
 use egui_sdl2_gl::egui::{Ui, Layout, Align, Grid, DragValue};
 
 use crate::map;
+use crate::world::{LayersSnapshot, WorldSettings};
 
 use self::{landscape::LandscapeEditor, biomes::BiomesEditor};
 
@@ -58,7 +59,30 @@ impl WorldgenMenu {
         }
     }
 
-    pub fn show(&mut self, ui: &mut Ui) {
+    /// Map being edited, if it has been converted to biomes already
+    pub fn biome_map(&self) -> Option<&map::Map> {
+        match &self.map {
+            Some(EditMap::Biomes(biomes)) => Some(biomes),
+            _ => None,
+        }
+    }
+
+    pub fn set_biome_map(&mut self, biomes: map::Map) {
+        self.map = Some(EditMap::Biomes(biomes));
+    }
+
+    /// Set once the simulation map is to be converted to biomes, until `simulation_map_ready`
+    pub fn waits_for_simulation_map(&self) -> bool {
+        self.landscape.waits_for_simulation_map()
+    }
+
+    /// `settings` are the ones of the simulated world
+    pub fn simulation_map_ready(&mut self, snapshot: &LayersSnapshot, settings: &WorldSettings) {
+        self.landscape.simulation_map_ready(&mut self.map, &mut self.page, snapshot, settings);
+    }
+
+    /// Returns `true` if the world has to be generated again with the new `settings`
+    pub fn show(&mut self, ui: &mut Ui, settings: &mut WorldSettings) -> bool {
         ui.heading("Mode");
         ui.horizontal_wrapped(|ui| {
             ui.selectable_value(&mut self.page, MapType::Landscape, "Landscape");
@@ -67,8 +91,11 @@ impl WorldgenMenu {
         ui.separator();
 
         match self.page.clone() {
-            MapType::Landscape => self.landscape.show(ui, &mut self.map, &mut self.page),
-            MapType::Biomes => self.biomes.show(ui, &mut self.map, &mut self.page),
+            MapType::Landscape => self.landscape.show(ui, &mut self.map, &mut self.page, settings),
+            MapType::Biomes => {
+                self.biomes.show(ui, &mut self.map, &mut self.page, settings.seed, self.landscape.fertility());
+                false
+            }
         }
     }
 }
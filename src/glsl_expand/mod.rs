@@ -40,6 +40,7 @@ pub struct ShaderContext {
     data: HashMap<PathBuf, ShaderFile>,
 
     def_parse_rules: ParseRules,
+    defines: Vec<(String, String)>,
 
     include_regex: Regex,
     comment_regexes: Vec<(Regex, usize)>,
@@ -94,6 +95,7 @@ impl ShaderContext {
             main_dir: dir,
             data: HashMap::new(),
             def_parse_rules: ParseRules::new(),
+            defines: Vec::new(),
 
             include_regex,
             comment_regexes,
@@ -106,6 +108,17 @@ impl ShaderContext {
     }
 
 
+    /// Adds `#define name value` right after the `#version` line of every shader.
+    /// Already processed files are processed again on the next request.
+    pub fn define<V: ToString>(&mut self, name: &str, value: V) {
+        let value = value.to_string();
+        match self.defines.iter_mut().find(|(define, _)| define == name) {
+            Some(define) => define.1 = value,
+            None => self.defines.push((name.to_owned(), value)),
+        }
+        self.data.clear();
+    }
+
     // Main functionality
     pub fn get_file_processed<P: Into<PathBuf>>(&mut self, path: P) -> Result<&ShaderFile, ExpandError> {
         let path_buf = path.into();
@@ -199,6 +212,21 @@ impl ShaderContext {
         Ok(comments.text_move())
     }
     fn postprocess_text(&self, text: MarkedText<PathBuf>, _log: &mut ParseLog) -> Result<MarkedText<PathBuf>, ExpandError> {
+        let mut text = text;
+        if self.defines.is_empty() {
+            return Ok(text);
+        }
+
+        // Only main files have `#version`, included ones get the defines from them
+        let version_line_end = text.text().find("#version")
+            .map(|start| text.text()[start..].find('\n').map_or(text.text().len(), |end| start + end + 1));
+        if let Some(position) = version_line_end {
+            let defines: String = self.defines.iter()
+                .map(|(name, value)| format!("#define {} {}\n", name, value))
+                .collect();
+            text.text_mut().insert_str(position, &defines);
+            text.shift_marks(position, defines.len() as isize);
+        }
         Ok(text)
     }
 
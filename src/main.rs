@@ -8,6 +8,9 @@ mod world;
 mod glsl_expand;
 mod terrain;
 mod map;
+mod seed;
+mod save;
+mod pdn;
 
 use std::sync::Arc;
 use app::Page;
@@ -28,8 +31,9 @@ use sdl2::video::{GLContext, SwapInterval, Window};
 
 use crate::app::App;
 use crate::glsl_expand::ShaderContext;
+use crate::seed::WorldSeed;
 use crate::util::RateManager;
-use crate::world::{PaintData, World};
+use crate::world::{PaintData, World, WorldSettings};
 
 pub struct WindowData {
 	pub sdl_context: Sdl,
@@ -69,9 +73,12 @@ fn main() {
 
 	// Tools:
 	let mut glsl_manager = ShaderContext::new().unwrap();
+	// Value of 1.0 of height converted to int, used by every shader which reads a landscape texture
+	glsl_manager.define("INT_VAL_RANGE", map::HEIGHT_INT_RANGE);
 
-	let world = World::new(win_data.gl.clone(), (256, 256), &mut glsl_manager);
-	let app = App::new(&egui_ctx, (world.size().0 as f32 / 2.0, world.size().1 as f32 / 2.0));
+	let settings = WorldSettings { seed: WorldSeed::random(), ..Default::default() };
+	let world = World::new(win_data.gl.clone(), (256, 256), settings, &mut glsl_manager);
+	let app = App::new(&egui_ctx, (world.size().0 as f32 / 2.0, world.size().1 as f32 / 2.0), world.settings().clone());
 
 	let data = TediousDataBundle {
 		sdl_context: 		win_data.sdl_context,
@@ -87,7 +94,7 @@ fn main() {
 		egui_scale: 		1.0,
 	};
 
-	run_loop(data, world, app);
+	run_loop(data, world, app, glsl_manager);
 }
 
 pub fn set_up_window(title: &str, width: u32, height: u32) -> WindowData {
@@ -201,7 +208,7 @@ impl TediousDataBundle {
 	}
 }
 
-fn run_loop(mut data: TediousDataBundle, mut world: World, mut app: App) {
+fn run_loop(mut data: TediousDataBundle, mut world: World, mut app: App, mut glsl_manager: ShaderContext) {
 	let mut ups_manager = RateManager::new(5, 2);
 	let mut fps_manager = RateManager::new(60, 60);
 	let mut prev_ups_limit = 0;
@@ -220,6 +227,39 @@ fn run_loop(mut data: TediousDataBundle, mut world: World, mut app: App) {
 		if fps_manager.tick_rate() != app.target_fps as u32 {
 			fps_manager.set_tick_rate(app.target_fps as u32);
 		}
+		if app.regenerate_world {
+			app.regenerate_world = false;
+			world = World::new(data.gl.clone(), world.size(), app.world_settings.clone(), &mut glsl_manager);
+		}
+		if let Some(save) = app.loaded_save.take() {
+			world = World::from_save(data.gl.clone(), &save, app.world_settings.clone(), &mut glsl_manager);
+		}
+		if app.normalize_landscape {
+			app.normalize_landscape = false;
+			world.normalize_landscape(&app.world_settings.hypsometry.unwrap_or_default());
+		}
+		if app.recompute_overlays {
+			app.recompute_overlays = false;
+			world.recompute_overlays();
+		}
+		if app.waits_for_landscape_snapshot() {
+			// The world could have been replaced since the request, then it is made again
+			match world.poll_landscape_snapshot() {
+				Some(landscape) => app.landscape_snapshot_ready(landscape, &world),
+				None if !world.is_landscape_snapshot_pending() => world.request_landscape_snapshot(),
+				None => {}
+			}
+		}
+		if app.waits_for_layers_snapshot() {
+			match world.poll_layers_snapshot() {
+				Some(snapshot) => app.layers_snapshot_ready(snapshot, &world),
+				None if !world.is_layers_snapshot_pending() => world.request_layers_snapshot(),
+				None => {}
+			}
+		}
+		if world.erosion_params() != &app.erosion_params {
+			world.set_erosion_params(app.erosion_params);
+		}
 
 		let time_left = next_render_time - now;
 		let max_ticks_to_do = ((time_left.as_secs_f64() * assumed_ups) as u32).max(1);
@@ -266,6 +306,14 @@ fn run_loop(mut data: TediousDataBundle, mut world: World, mut app: App) {
 		if now >= next_render_time {
 			// RENDER
 			world.no_tick(); // Update TPS counter
+			// The camera never leaves a wrapped world, it comes back from the other side
+			let topology = world.settings().topology;
+			if topology.wrap_x {
+				app.camera.wrap_x(world.size().0 as f32);
+			}
+			if topology.wrap_y {
+				app.camera.wrap_y(world.size().1 as f32);
+			}
 			data.render_all(&mut app, &world);
 			fps_manager.register_tick();
 		}
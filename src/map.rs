@@ -2,6 +2,28 @@ use fixed::{FixedI32, types::extra::U20};
 
 pub const MAX_MAP_SIZE: (u32, u32) = (16384, 16384);
 
+/// Height of a landscape cell. `R32I` landscape textures store its raw bits, so
+/// heights survive a round trip through the GPU without any loss.
+pub type Height = FixedI32<U20>;
+/// `1.0` of height in the landscape textures (`Height` has 20 fractional bits).
+/// Shaders get it as `INT_VAL_RANGE`
+pub const HEIGHT_INT_RANGE: i32 = 1 << 20;
+
+pub fn height_to_int(height: Height) -> i32 {
+    height.to_bits()
+}
+
+pub fn height_from_int(value: i32) -> Height {
+    Height::from_bits(value)
+}
+
+/// Out of range values are clamped
+pub fn height_from_f32(value: f32) -> Height {
+    Height::saturating_from_num(value)
+}
+/// Default of `WorldSettings::sea_level`
+pub const DEFAULT_SEA_LEVEL: f64 = 0.43;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Biome {
     Sea,
@@ -39,6 +61,42 @@ impl Biome {
             Biome::SnowyMountain => "Snowy mountain",
         }
     }
+
+    /// Colour of the biome in exported images
+    pub fn color(&self) -> [u8; 3] {
+        match self {
+            Biome::Sea => [28, 72, 140],
+            Biome::Shoal => [60, 130, 190],
+            Biome::Beach => [230, 215, 160],
+            Biome::Plains => [140, 190, 90],
+            Biome::Swamp => [80, 110, 70],
+            Biome::Forest => [40, 120, 50],
+            Biome::Desert => [220, 190, 110],
+            Biome::Scree => [150, 140, 130],
+            Biome::Mountain => [120, 105, 95],
+            Biome::SnowyMountain => [240, 240, 245],
+        }
+    }
+
+    /// Stable name used in saved files
+    pub fn key(&self) -> &'static str {
+        match self {
+            Biome::Sea => "sea",
+            Biome::Shoal => "shoal",
+            Biome::Beach => "beach",
+            Biome::Plains => "plains",
+            Biome::Swamp => "swamp",
+            Biome::Forest => "forest",
+            Biome::Desert => "desert",
+            Biome::Scree => "scree",
+            Biome::Mountain => "mountain",
+            Biome::SnowyMountain => "snowy_mountain",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::all().iter().copied().find(|biome| biome.key() == key)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -51,8 +109,8 @@ pub enum Resource {
     EnigmaticCrystal,
 }
 
-const MAX_RESOURCES_IN_CELL: usize = 4;
-type MapCell = (Biome, [Option<Resource>; MAX_RESOURCES_IN_CELL]);
+pub const MAX_RESOURCES_IN_CELL: usize = 4;
+pub type MapCell = (Biome, [Option<Resource>; MAX_RESOURCES_IN_CELL]);
 
 #[derive(Debug, Clone)]
 pub struct Map {
@@ -68,20 +126,168 @@ impl Map {
     pub fn disassemble(self) -> ((u32, u32), Box<[MapCell]>) {
         (self.size, self.biomes)
     }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    /// All cells, row by row (`cells[y * width + x]`)
+    pub fn cells(&self) -> &[MapCell] {
+        &self.biomes
+    }
+
+    pub fn cells_mut(&mut self) -> &mut [MapCell] {
+        &mut self.biomes
+    }
+}
+
+/// How the edges of the map are connected. A wrapped edge is glued to the opposite
+/// one, so the map is a cylinder or a torus and nothing can leave it that way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Topology {
+    pub wrap_x: bool,
+    pub wrap_y: bool,
+}
+impl Topology {
+    pub const FLAT: Topology = Topology { wrap_x: false, wrap_y: false };
+
+    pub fn is_flat(&self) -> bool {
+        !self.wrap_x && !self.wrap_y
+    }
+
+    /// Cell `(x, y)` of a map of `size` moved across the wrapped edges.
+    /// `None` if it is beyond an edge which does not wrap.
+    pub fn cell(&self, size: (u32, u32), x: i64, y: i64) -> Option<(u32, u32)> {
+        let axis = |value: i64, side: u32, wrap: bool| match wrap {
+            true => Some(value.rem_euclid(side as i64) as u32),
+            false => (value >= 0 && value < side as i64).then_some(value as u32),
+        };
+        Some((axis(x, size.0, self.wrap_x)?, axis(y, size.1, self.wrap_y)?))
+    }
+
+    /// Same as `cell`, but clamped to the edges which do not wrap
+    pub fn clamp_cell(&self, size: (u32, u32), x: i64, y: i64) -> (u32, u32) {
+        let axis = |value: i64, side: u32, wrap: bool| match wrap {
+            true => value.rem_euclid(side as i64) as u32,
+            false => value.clamp(0, side as i64 - 1) as u32,
+        };
+        (axis(x, size.0, self.wrap_x), axis(y, size.1, self.wrap_y))
+    }
+
+    /// Point moved across the wrapped edges, other coordinates are left as they are
+    pub fn wrap_point(&self, size: (u32, u32), point: (f32, f32)) -> (f32, f32) {
+        // `rem_euclid` of a tiny negative value may round up to `side`
+        let axis = |value: f32, side: u32, wrap: bool| match wrap {
+            true => Some(value.rem_euclid(side as f32)).filter(|value| *value < side as f32).unwrap_or(0.0),
+            false => value,
+        };
+        (axis(point.0, size.0, self.wrap_x), axis(point.1, size.1, self.wrap_y))
+    }
+
+    /// Offset from `from` to `to`, the shortest one across the wrapped edges
+    pub fn delta(&self, size: (u32, u32), from: (f32, f32), to: (f32, f32)) -> (f32, f32) {
+        let axis = |delta: f32, side: u32, wrap: bool| match wrap {
+            true => delta - (delta / side as f32).round() * side as f32,
+            false => delta,
+        };
+        (axis(to.0 - from.0, size.0, self.wrap_x), axis(to.1 - from.1, size.1, self.wrap_y))
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Landscape {
     size: (u32, u32),
-    height: Box<[FixedI32<U20>]>,
+    height: Box<[Height]>,
+    topology: Topology,
 }
 impl Landscape {
-    pub fn new(size: (u32, u32), height: Box<[FixedI32<U20>]>) -> Self {
+    /// Flat topology, see `with_topology`
+    pub fn new(size: (u32, u32), height: Box<[Height]>) -> Self {
         assert!(height.len() == (size.0 as usize) * (size.1 as usize));
-        Self { size, height }
+        Self { size, height, topology: Topology::FLAT }
+    }
+
+    pub fn with_topology(self, topology: Topology) -> Self {
+        Self { topology, ..self }
+    }
+
+    pub fn topology(&self) -> Topology {
+        self.topology
     }
 
-    pub fn disassemble(self) -> ((u32, u32), Box<[FixedI32<U20>]>) {
+    pub fn disassemble(self) -> ((u32, u32), Box<[Height]>) {
         (self.size, self.height)
     }
+
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+
+    /// Heights of all cells, row by row (`height[y * width + x]`)
+    pub fn height(&self) -> &[Height] {
+        &self.height
+    }
+
+    pub fn height_mut(&mut self) -> &mut [Height] {
+        &mut self.height
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: (u32, u32) = (10, 6);
+    const CYLINDER: Topology = Topology { wrap_x: true, wrap_y: false };
+    const TORUS: Topology = Topology { wrap_x: true, wrap_y: true };
+
+    #[test]
+    fn height_ints_are_exact_inverses() {
+        for bits in [i32::MIN, i32::MIN + 1, -HEIGHT_INT_RANGE, -1, 0, 1, HEIGHT_INT_RANGE, i32::MAX - 1, i32::MAX] {
+            assert_eq!(height_to_int(height_from_int(bits)), bits);
+        }
+        for height in [Height::MIN, -Height::ONE, -Height::DELTA, Height::ZERO, Height::DELTA, Height::ONE, Height::MAX] {
+            assert_eq!(height_from_int(height_to_int(height)), height);
+        }
+        // The whole range with a prime step, so the fractional bits vary too
+        for bits in (i32::MIN..=i32::MAX).step_by(4099) {
+            assert_eq!(height_to_int(height_from_int(bits)), bits);
+        }
+        assert_eq!(height_to_int(Height::ONE), HEIGHT_INT_RANGE);
+    }
+
+    #[test]
+    fn cells_cross_only_wrapped_edges() {
+        assert_eq!(Topology::FLAT.cell(SIZE, -1, 0), None);
+        assert_eq!(Topology::FLAT.cell(SIZE, 9, 5), Some((9, 5)));
+        assert_eq!(CYLINDER.cell(SIZE, -1, 0), Some((9, 0)));
+        assert_eq!(CYLINDER.cell(SIZE, 10, 5), Some((0, 5)));
+        assert_eq!(CYLINDER.cell(SIZE, 0, 6), None);
+        assert_eq!(TORUS.cell(SIZE, -1, -1), Some((9, 5)));
+        assert_eq!(TORUS.cell(SIZE, 21, 13), Some((1, 1)));
+    }
+
+    #[test]
+    fn clamped_cells_wrap_first() {
+        assert_eq!(Topology::FLAT.clamp_cell(SIZE, -3, 8), (0, 5));
+        assert_eq!(CYLINDER.clamp_cell(SIZE, -3, 8), (7, 5));
+        assert_eq!(TORUS.clamp_cell(SIZE, -3, 8), (7, 2));
+    }
+
+    #[test]
+    fn points_wrap_into_the_map() {
+        assert_eq!(Topology::FLAT.wrap_point(SIZE, (-0.5, 7.0)), (-0.5, 7.0));
+        assert_eq!(CYLINDER.wrap_point(SIZE, (-0.5, 7.0)), (9.5, 7.0));
+        assert_eq!(TORUS.wrap_point(SIZE, (10.0, -1.0)), (0.0, 5.0));
+        let (x, _) = TORUS.wrap_point(SIZE, (-1e-9, 0.0));
+        assert!((0.0..10.0).contains(&x));
+    }
+
+    #[test]
+    fn deltas_take_the_shortest_way() {
+        assert_eq!(Topology::FLAT.delta(SIZE, (9.0, 5.0), (0.0, 0.0)), (-9.0, -5.0));
+        assert_eq!(CYLINDER.delta(SIZE, (9.0, 5.0), (0.0, 0.0)), (1.0, -5.0));
+        assert_eq!(TORUS.delta(SIZE, (9.0, 5.0), (0.0, 0.0)), (1.0, 1.0));
+        assert_eq!(TORUS.delta(SIZE, (0.5, 0.0), (9.5, 2.0)), (-1.0, 2.0));
+    }
 }
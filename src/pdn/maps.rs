@@ -0,0 +1,201 @@
+use image::{Pixel, Rgba, RgbaImage};
+
+use crate::map::{self, Biome, Landscape, Map, MapCell, Resource, MAX_RESOURCES_IN_CELL};
+use crate::terrain::resources::ResourceKind;
+
+use super::{PdnDocument, PdnError, PdnLayer};
+
+/// Grayscale, black is `0.0` of height and white is `1.0`
+pub const HEIGHT_LAYER: &str = "height";
+/// Each pixel has the `Biome::color` of its cell, alpha is ignored
+pub const BIOMES_LAYER: &str = "biomes";
+/// One layer per resource slot of a cell. Transparent pixels have no resource, others
+/// have the `ResourceKind::color` of theirs. Alpha of compound is its count
+pub const RESOURCE_LAYERS: [&str; MAX_RESOURCES_IN_CELL] = ["resources", "resources 2", "resources 3", "resources 4"];
+
+pub fn has_map(document: &PdnDocument) -> bool {
+	document.layer(BIOMES_LAYER).is_some()
+}
+
+pub fn import_landscape(document: &PdnDocument) -> Result<Landscape, PdnError> {
+	let layer = document.layer(HEIGHT_LAYER).ok_or(PdnError::MissingLayer { layer: HEIGHT_LAYER })?;
+	let height = layer.pixels.pixels()
+		.map(|pixel| map::height_from_f32(pixel.to_luma().0[0] as f32 / u8::MAX as f32))
+		.collect();
+	Ok(Landscape::new(document.size(), height))
+}
+
+/// Biomes and resources. Every pixel has to be of a known colour, the first unknown one is an error
+pub fn import_map(document: &PdnDocument) -> Result<Map, PdnError> {
+	let layer = document.layer(BIOMES_LAYER).ok_or(PdnError::MissingLayer { layer: BIOMES_LAYER })?;
+	let unknown_color = |layer: &PdnLayer, x: u32, y: u32, color: Rgba<u8>| PdnError::UnknownColor {
+		layer: layer.name.clone(), x, y, color: color.0,
+	};
+
+	let mut cells: Vec<MapCell> = Vec::with_capacity(layer.pixels.len() / 4);
+	for (x, y, color) in layer.pixels.enumerate_pixels() {
+		let biome = Biome::all().iter()
+			.find(|biome| biome.color() == color.to_rgb().0)
+			.ok_or_else(|| unknown_color(layer, x, y, *color))?;
+		cells.push((*biome, Default::default()));
+	}
+
+	for (slot, name) in RESOURCE_LAYERS.iter().enumerate() {
+		let Some(layer) = document.layer(name) else {
+			continue;
+		};
+		for ((x, y, color), cell) in layer.pixels.enumerate_pixels().zip(cells.iter_mut()) {
+			let alpha = color.0[3];
+			if alpha == 0 {
+				continue;
+			}
+			let kind = ResourceKind::all().iter()
+				.find(|kind| kind.color() == color.to_rgb().0)
+				.ok_or_else(|| unknown_color(layer, x, y, *color))?;
+			cell.1[slot] = Some(match kind {
+				ResourceKind::Compound => Resource::Compound { count: alpha },
+				ResourceKind::Rock => Resource::Rock,
+				ResourceKind::Fruit => Resource::Fruit,
+				ResourceKind::PoisonedFruit => Resource::PoisonedFruit,
+				ResourceKind::FireCrystal => Resource::FireCrystal,
+				ResourceKind::EnigmaticCrystal => Resource::EnigmaticCrystal,
+			});
+		}
+	}
+	Ok(Map::new(document.size(), cells.into_boxed_slice()))
+}
+
+pub fn height_layer(landscape: &Landscape) -> PdnLayer {
+	let (width, height) = landscape.size();
+	let heights = landscape.height();
+	let pixels = RgbaImage::from_fn(width, height, |x, y| {
+		let value = heights[y as usize * width as usize + x as usize].to_num::<f32>().clamp(0.0, 1.0);
+		let value = (value * u8::MAX as f32).round() as u8;
+		Rgba([value, value, value, u8::MAX])
+	});
+	PdnLayer::new(HEIGHT_LAYER, pixels)
+}
+
+/// The biomes layer and a layer for each resource slot
+pub fn map_layers(map: &Map) -> Vec<PdnLayer> {
+	let (width, height) = map.size();
+	let cell = |x: u32, y: u32| &map.cells()[y as usize * width as usize + x as usize];
+
+	let biomes = RgbaImage::from_fn(width, height, |x, y| {
+		let [r, g, b] = cell(x, y).0.color();
+		Rgba([r, g, b, u8::MAX])
+	});
+	let mut layers = vec![PdnLayer::new(BIOMES_LAYER, biomes)];
+	for (slot, name) in RESOURCE_LAYERS.iter().enumerate() {
+		let pixels = RgbaImage::from_fn(width, height, |x, y| match &cell(x, y).1[slot] {
+			Some(resource) => {
+				let [r, g, b] = ResourceKind::of(resource).color();
+				let alpha = match resource {
+					Resource::Compound { count } => (*count).max(1),
+					_ => u8::MAX,
+				};
+				Rgba([r, g, b, alpha])
+			}
+			None => Rgba([0, 0, 0, 0]),
+		});
+		layers.push(PdnLayer::new(*name, pixels));
+	}
+	layers
+}
+
+/// Document with the height layer at the bottom and the map layers over it
+pub fn world_document(landscape: Option<&Landscape>, map: Option<&Map>) -> Result<PdnDocument, PdnError> {
+	let size = match (landscape, map) {
+		(Some(landscape), Some(map)) if landscape.size() != map.size() => {
+			return Err(PdnError::SizeMismatch { landscape: landscape.size(), map: map.size() });
+		}
+		(Some(landscape), _) => landscape.size(),
+		(None, Some(map)) => map.size(),
+		(None, None) => (0, 0),
+	};
+
+	let mut document = PdnDocument::new(size);
+	for layer in landscape.map(height_layer).into_iter().chain(map.map(map_layers).unwrap_or_default()) {
+		document.push_layer(layer)?;
+	}
+	Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn landscape(size: (u32, u32)) -> Landscape {
+		let heights = (0..size.0 * size.1).map(|id| map::height_from_f32(id as f32 / (size.0 * size.1) as f32)).collect();
+		Landscape::new(size, heights)
+	}
+
+	fn map(size: (u32, u32)) -> Map {
+		let cells = (0..size.0 * size.1).map(|id| {
+			let mut cell: MapCell = (Biome::all()[id as usize % Biome::all().len()], Default::default());
+			if id % 3 == 0 {
+				cell.1[0] = Some(Resource::Compound { count: 7 });
+				cell.1[2] = Some(Resource::FireCrystal);
+			}
+			cell
+		});
+		Map::new(size, cells.collect())
+	}
+
+	#[test]
+	fn world_goes_through_layers() {
+		let (landscape, map) = (landscape((8, 6)), map((8, 6)));
+		let document = world_document(Some(&landscape), Some(&map)).unwrap();
+		assert_eq!(document.layers().len(), 2 + MAX_RESOURCES_IN_CELL);
+		assert!(has_map(&document));
+
+		let imported = import_map(&document).unwrap();
+		assert!(imported.cells() == map.cells());
+		let heights = import_landscape(&document).unwrap();
+		for (imported, height) in heights.height().iter().zip(landscape.height()) {
+			assert!((imported.to_num::<f32>() - height.to_num::<f32>()).abs() <= 0.5 / u8::MAX as f32);
+		}
+	}
+
+	#[test]
+	fn missing_layers_are_errors() {
+		let document = world_document(Some(&landscape((4, 4))), None).unwrap();
+		assert!(!has_map(&document));
+		assert!(matches!(import_map(&document), Err(PdnError::MissingLayer { layer: BIOMES_LAYER })));
+
+		let document = world_document(None, Some(&map((4, 4)))).unwrap();
+		assert!(matches!(import_landscape(&document), Err(PdnError::MissingLayer { layer: HEIGHT_LAYER })));
+	}
+
+	#[test]
+	fn unknown_colors_are_errors() {
+		let mut document = world_document(None, Some(&map((4, 4)))).unwrap();
+		let mut biomes = document.layer(BIOMES_LAYER).unwrap().clone();
+		biomes.pixels.put_pixel(2, 1, Rgba([1, 2, 3, 255]));
+		document.push_layer(biomes).unwrap();
+		match import_map(&document) {
+			Err(PdnError::UnknownColor { layer, x, y, color }) => {
+				assert_eq!((layer.as_str(), x, y, color), (BIOMES_LAYER, 2, 1, [1, 2, 3, 255]));
+			}
+			other => panic!("{:?}", other.map(|map| map.size())),
+		}
+
+		// Transparent resource pixels are empty slots whatever their colour is
+		let mut document = world_document(None, Some(&map((4, 4)))).unwrap();
+		let mut resources = document.layer(RESOURCE_LAYERS[1]).unwrap().clone();
+		resources.pixels.put_pixel(0, 0, Rgba([1, 2, 3, 0]));
+		document.push_layer(resources.clone()).unwrap();
+		assert!(import_map(&document).is_ok());
+		resources.pixels.put_pixel(3, 3, Rgba([1, 2, 3, 4]));
+		document.push_layer(resources).unwrap();
+		assert!(matches!(import_map(&document), Err(PdnError::UnknownColor { x: 3, y: 3, .. })));
+	}
+
+	#[test]
+	fn landscape_and_map_of_other_sizes_are_an_error() {
+		match world_document(Some(&landscape((8, 6))), Some(&map((6, 8)))) {
+			Err(PdnError::SizeMismatch { landscape, map }) => assert_eq!((landscape, map), ((8, 6), (6, 8))),
+			other => panic!("{:?}", other),
+		}
+	}
+}
@@ -0,0 +1,544 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use flate2::Compression;
+use flate2::read::{GzDecoder, MultiGzDecoder};
+use flate2::write::GzEncoder;
+use image::{Rgba, RgbaImage};
+
+use self::nrbf::{MemberType, Stream, Value};
+
+pub mod maps;
+mod nrbf;
+
+pub const EXTENSION: &str = "pdn";
+
+/// Paint.NET document:
+/// ```text
+/// magic (4 bytes) | XML header length: u24 | XML header | graph marker | object graph | pixel data of each layer
+/// ```
+/// The object graph is written by .NET `BinaryFormatter`, see `nrbf`.
+const MAGIC: [u8; 4] = *b"PDN3";
+/// Goes after the header unless the rest of the file is one gzip stream (very old documents)
+const GRAPH_MARKER: [u8; 2] = [0x00, 0x01];
+const GZIP_MARKER: [u8; 2] = [0x1F, 0x8B];
+
+/// Version written into the documents, a Paint.NET release which reads and writes this layout
+const SAVED_WITH: [i32; 4] = [4, 312, 8267, 29064];
+const DATA_LIBRARY: &str = "PaintDotNet.Data, Version=4.312.8267.29064, Culture=neutral, PublicKeyToken=null";
+const CORE_LIBRARY: &str = "PaintDotNet.Core, Version=4.312.8267.29064, Culture=neutral, PublicKeyToken=null";
+const METADATA_ITEM: &str = "System.Collections.Generic.KeyValuePair`2[[System.String, mscorlib, Version=4.0.0.0, Culture=neutral, PublicKeyToken=b77a5c561934e089],[System.String, mscorlib, Version=4.0.0.0, Culture=neutral, PublicKeyToken=b77a5c561934e089]]";
+
+/// Pixel data is compressed in chunks of this size, Paint.NET uses the same one
+const CHUNK_SIZE: u32 = 0x40000;
+const CHUNKS_GZIP: u8 = 0;
+const CHUNKS_RAW: u8 = 1;
+/// Largest width and height Paint.NET allows
+pub const MAX_SIZE: u32 = 65535;
+
+#[derive(Debug)]
+pub enum PdnError {
+	Io { io_error: io::Error },
+	NotAPdnFile,
+	Corrupted { reason: String },
+	/// A layer is not as large as the document
+	LayerSize { layer: String, size: (u32, u32), expected: (u32, u32) },
+	MissingLayer { layer: &'static str },
+	/// A pixel colour which stands for nothing in this layer
+	UnknownColor { layer: String, x: u32, y: u32, color: [u8; 4] },
+	/// Landscape and map exported together are not of the same size
+	SizeMismatch { landscape: (u32, u32), map: (u32, u32) },
+}
+
+impl Display for PdnError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			PdnError::Io { io_error } => write!(f, "Failed to access Paint.NET image: {}", io_error),
+			PdnError::NotAPdnFile => write!(f, "Not a .{} file", EXTENSION),
+			PdnError::Corrupted { reason } => write!(f, "Paint.NET image is corrupted: {}", reason),
+			PdnError::LayerSize { layer, size, expected } => write!(
+				f, "Layer \"{}\" is {}x{}, expected {}x{}", layer, size.0, size.1, expected.0, expected.1,
+			),
+			PdnError::MissingLayer { layer } => write!(f, "Paint.NET image has no \"{}\" layer", layer),
+			PdnError::UnknownColor { layer, x, y, color: [r, g, b, a] } => write!(
+				f, "Layer \"{}\" has unknown colour #{:02x}{:02x}{:02x}{:02x} at ({}, {})", layer, r, g, b, a, x, y,
+			),
+			PdnError::SizeMismatch { landscape, map } => write!(
+				f, "Landscape is {}x{} but biomes map is {}x{}", landscape.0, landscape.1, map.0, map.1,
+			),
+		}
+	}
+}
+
+impl From<io::Error> for PdnError {
+	fn from(io_error: io::Error) -> Self {
+		match io_error.kind() {
+			io::ErrorKind::InvalidData => PdnError::Corrupted { reason: io_error.to_string() },
+			io::ErrorKind::UnexpectedEof => PdnError::Corrupted { reason: "file is truncated".to_owned() },
+			_ => PdnError::Io { io_error },
+		}
+	}
+}
+
+fn corrupted(reason: impl Into<String>) -> PdnError {
+	PdnError::Corrupted { reason: reason.into() }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdnLayer {
+	pub name: String,
+	pub visible: bool,
+	pub opacity: u8,
+	/// Not premultiplied, as Paint.NET keeps them
+	pub pixels: RgbaImage,
+}
+
+impl PdnLayer {
+	pub fn new(name: impl Into<String>, pixels: RgbaImage) -> Self {
+		PdnLayer { name: name.into(), visible: true, opacity: u8::MAX, pixels }
+	}
+}
+
+/// Layers of a document, from the bottom one (the background in Paint.NET) to the top one
+#[derive(Debug, Clone, PartialEq)]
+pub struct PdnDocument {
+	size: (u32, u32),
+	layers: Vec<PdnLayer>,
+}
+
+impl PdnDocument {
+	pub fn new(size: (u32, u32)) -> Self {
+		PdnDocument { size, layers: vec![] }
+	}
+
+	pub fn size(&self) -> (u32, u32) {
+		self.size
+	}
+
+	#[allow(dead_code)]
+	pub fn layers(&self) -> &[PdnLayer] {
+		&self.layers
+	}
+
+	/// Adds a layer on top of the others
+	pub fn push_layer(&mut self, layer: PdnLayer) -> Result<(), PdnError> {
+		if layer.pixels.dimensions() != self.size {
+			return Err(PdnError::LayerSize { layer: layer.name, size: layer.pixels.dimensions(), expected: self.size });
+		}
+		self.layers.push(layer);
+		Ok(())
+	}
+
+	/// The topmost layer with the name, case and surrounding spaces are ignored
+	pub fn layer(&self, name: &str) -> Option<&PdnLayer> {
+		self.layers.iter().rev().find(|layer| layer.name.trim().eq_ignore_ascii_case(name))
+	}
+
+	pub fn save(&self, path: impl AsRef<Path>) -> Result<(), PdnError> {
+		let mut writer = BufWriter::new(File::create(path)?);
+		self.write(&mut writer)?;
+		writer.flush()?;
+		Ok(())
+	}
+
+	pub fn load(path: impl AsRef<Path>) -> Result<Self, PdnError> {
+		Self::read(BufReader::new(File::open(path)?))
+	}
+
+	pub fn read(mut reader: impl Read) -> Result<Self, PdnError> {
+		let mut magic = [0; 4];
+		reader.read_exact(&mut magic).map_err(|_| PdnError::NotAPdnFile)?;
+		if magic != MAGIC {
+			return Err(PdnError::NotAPdnFile);
+		}
+		// The header repeats the size and the layer count, the graph has them all
+		let mut header_length = [0; 4];
+		reader.read_exact(&mut header_length[..3])?;
+		let header_length = u32::from_le_bytes(header_length) as u64;
+		if io::copy(&mut (&mut reader).take(header_length), &mut io::sink())? != header_length {
+			return Err(corrupted("file is truncated"));
+		}
+
+		let mut marker = [0; 2];
+		reader.read_exact(&mut marker)?;
+		match marker {
+			GRAPH_MARKER => Self::read_graph(reader),
+			GZIP_MARKER => Self::read_graph(MultiGzDecoder::new(io::Cursor::new(marker).chain(reader))),
+			_ => Err(corrupted("unknown data after the header")),
+		}
+	}
+
+	fn read_graph(mut reader: impl Read) -> Result<Self, PdnError> {
+		let stream = Stream::read(&mut reader)?;
+		let missing = |what: &str| corrupted(format!("no {}", what));
+		let dimension = |object: &Value, name: &str| {
+			stream.integer(object, name)
+				.and_then(|value| u32::try_from(value).ok())
+				.filter(|value| (1..=MAX_SIZE).contains(value))
+				.ok_or_else(|| corrupted(format!("invalid {}", name)))
+		};
+
+		let document = Value::Reference(stream.root);
+		if stream.class_name(&document) != Some("PaintDotNet.Document") {
+			return Err(missing("document"));
+		}
+		let size = (dimension(&document, "width")?, dimension(&document, "height")?);
+		let layer_list = stream.member(&document, "layers").ok_or_else(|| missing("layer list"))?;
+		let layer_count = stream.integer(layer_list, "ArrayList+_size").ok_or_else(|| missing("layer count"))?;
+		let items = stream.member(layer_list, "ArrayList+_items")
+			.and_then(|items| stream.array(items))
+			.ok_or_else(|| missing("layers"))?;
+		let items = usize::try_from(layer_count).ok()
+			.and_then(|count| items.get(..count))
+			.ok_or_else(|| corrupted("invalid layer count"))?;
+
+		struct LayerInfo {
+			name: String,
+			visible: bool,
+			opacity: u8,
+			size: (u32, u32),
+			stride: usize,
+			block: i32,
+		}
+		let mut layers = vec![];
+		for (i, layer) in items.iter().enumerate() {
+			let surface = stream.member(layer, "surface").ok_or_else(|| missing("layer surface"))?;
+			let properties = stream.member(layer, "Layer+properties").ok_or_else(|| missing("layer properties"))?;
+			let name = stream.string(properties, "name").map(str::to_owned).unwrap_or_else(|| format!("Layer {}", i + 1));
+			let layer_size = (dimension(surface, "width")?, dimension(surface, "height")?);
+			if layer_size != size {
+				return Err(PdnError::LayerSize { layer: name, size: layer_size, expected: size });
+			}
+			let stride = stream.integer(surface, "stride")
+				.and_then(|stride| usize::try_from(stride).ok())
+				.filter(|stride| *stride >= size.0 as usize * 4)
+				.ok_or_else(|| corrupted("invalid stride"))?;
+			let block = match stream.member(surface, "scan0") {
+				Some(Value::Reference(block)) => *block,
+				_ => return Err(missing("layer pixels")),
+			};
+			layers.push(LayerInfo {
+				name,
+				visible: stream.boolean(properties, "visible").unwrap_or(true),
+				opacity: stream.integer(properties, "opacity").and_then(|opacity| u8::try_from(opacity).ok()).unwrap_or(u8::MAX),
+				size: layer_size,
+				stride,
+				block,
+			});
+		}
+
+		// Pixels of the deferred blocks follow the graph in the order the blocks were written
+		let lengths: HashMap<i32, usize> = layers.iter().map(|layer| (layer.block, layer.stride * layer.size.1 as usize)).collect();
+		let mut blocks = HashMap::new();
+		for id in &stream.order {
+			let block = Value::Reference(*id);
+			if stream.class_name(&block) != Some("PaintDotNet.MemoryBlock") {
+				continue;
+			}
+			if stream.boolean(&block, "deferred") != Some(true) {
+				return Err(corrupted("pixels are not stored after the object graph"));
+			}
+			let length = stream.integer(&block, "length64").and_then(|length| usize::try_from(length).ok());
+			match (length, lengths.get(id)) {
+				(Some(length), Some(expected)) if length == *expected => {}
+				_ => return Err(corrupted("invalid pixel data length")),
+			}
+			blocks.insert(*id, read_block(&mut reader, length.unwrap())?);
+		}
+
+		let mut document = PdnDocument::new(size);
+		for layer in layers {
+			let data = blocks.get(&layer.block).ok_or_else(|| missing("layer pixels"))?;
+			let pixels = RgbaImage::from_fn(layer.size.0, layer.size.1, |x, y| {
+				let i = y as usize * layer.stride + x as usize * 4;
+				let [b, g, r, a] = [data[i], data[i + 1], data[i + 2], data[i + 3]];
+				Rgba([r, g, b, a])
+			});
+			document.push_layer(PdnLayer { name: layer.name, visible: layer.visible, opacity: layer.opacity, pixels })?;
+		}
+		Ok(document)
+	}
+
+	pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+		let (width, height) = self.size;
+		let header = format!(
+			"<pdnImage width=\"{}\" height=\"{}\" layers=\"{}\" savedWithVersion=\"{}\"><custom /></pdnImage>",
+			width, height, self.layers.len(), SAVED_WITH.map(|part| part.to_string()).join("."),
+		);
+		writer.write_all(&MAGIC)?;
+		writer.write_all(&(header.len() as u32).to_le_bytes()[..3])?;
+		writer.write_all(header.as_bytes())?;
+		writer.write_all(&GRAPH_MARKER)?;
+
+		self.write_graph(&mut writer)?;
+		for layer in &self.layers {
+			let pixels: Vec<u8> = layer.pixels.pixels().flat_map(|Rgba([r, g, b, a])| [*b, *g, *r, *a]).collect();
+			write_block(&mut writer, &pixels)?;
+		}
+		Ok(())
+	}
+
+	/// Object graph in the layout Paint.NET writes it, the ids do not matter
+	fn write_graph(&self, writer: impl Write) -> io::Result<()> {
+		use nrbf::{BOOLEAN, BYTE, INT32, INT64};
+		let (width, height) = (self.size.0 as i32, self.size.1 as i32);
+		let metadata_items = format!("{}[]", METADATA_ITEM);
+		let (document, layer_list, version, document_metadata, items, layer_metadata) = (1, 3, 4, 5, 6, 7);
+		let (data, core) = (2, 8);
+		// Ids of the objects of each layer follow the fixed ones
+		let layer_ids = |i: usize, object: i32| 10 + i as i32 * 10 + object;
+		let (bitmap_layer, bitmap_properties, surface, properties, blend_op, block, name, blend_mode) = (0, 1, 2, 3, 4, 5, 6, 7);
+
+		let mut writer = nrbf::Writer::new(writer);
+		writer.header(document)?;
+		writer.library(data, DATA_LIBRARY)?;
+		writer.object(document, "PaintDotNet.Document", Some(data), &[
+			("isDisposed", MemberType::Primitive(BOOLEAN)),
+			("layers", MemberType::Class("PaintDotNet.LayerList", data)),
+			("width", MemberType::Primitive(INT32)),
+			("height", MemberType::Primitive(INT32)),
+			("savedWith", MemberType::SystemClass("System.Version")),
+			("userMetadataItems", MemberType::SystemClass(&metadata_items)),
+		])?;
+		writer.boolean(false)?;
+		writer.reference(layer_list)?;
+		writer.int32(width)?;
+		writer.int32(height)?;
+		writer.reference(version)?;
+		writer.reference(document_metadata)?;
+
+		writer.object(layer_list, "PaintDotNet.LayerList", Some(data), &[
+			("parent", MemberType::Class("PaintDotNet.Document", data)),
+			("ArrayList+_items", MemberType::ObjectArray),
+			("ArrayList+_size", MemberType::Primitive(INT32)),
+			("ArrayList+_version", MemberType::Primitive(INT32)),
+		])?;
+		writer.reference(document)?;
+		writer.reference(items)?;
+		writer.int32(self.layers.len() as i32)?;
+		writer.int32(0)?;
+
+		writer.object(version, "System.Version", None, &[
+			("_Major", MemberType::Primitive(INT32)),
+			("_Minor", MemberType::Primitive(INT32)),
+			("_Build", MemberType::Primitive(INT32)),
+			("_Revision", MemberType::Primitive(INT32)),
+		])?;
+		for part in SAVED_WITH {
+			writer.int32(part)?;
+		}
+		writer.system_class_array(document_metadata, 0, METADATA_ITEM)?;
+
+		writer.object_array(items, self.layers.len())?;
+		for i in 0..self.layers.len() {
+			writer.reference(layer_ids(i, bitmap_layer))?;
+		}
+
+		writer.library(core, CORE_LIBRARY)?;
+		for i in 0..self.layers.len() {
+			writer.object(layer_ids(i, bitmap_layer), "PaintDotNet.BitmapLayer", Some(data), &[
+				("properties", MemberType::Class("PaintDotNet.BitmapLayer+BitmapLayerProperties", data)),
+				("surface", MemberType::Class("PaintDotNet.Surface", core)),
+				("Layer+isDisposed", MemberType::Primitive(BOOLEAN)),
+				("Layer+width", MemberType::Primitive(INT32)),
+				("Layer+height", MemberType::Primitive(INT32)),
+				("Layer+properties", MemberType::Class("PaintDotNet.Layer+LayerProperties", data)),
+			])?;
+			writer.reference(layer_ids(i, bitmap_properties))?;
+			writer.reference(layer_ids(i, surface))?;
+			writer.boolean(false)?;
+			writer.int32(width)?;
+			writer.int32(height)?;
+			writer.reference(layer_ids(i, properties))?;
+		}
+
+		for (i, layer) in self.layers.iter().enumerate() {
+			writer.object(layer_ids(i, bitmap_properties), "PaintDotNet.BitmapLayer+BitmapLayerProperties", Some(data), &[
+				("blendOp", MemberType::Class("PaintDotNet.UserBlendOps+NormalBlendOp", data)),
+			])?;
+			writer.reference(layer_ids(i, blend_op))?;
+
+			writer.object(layer_ids(i, surface), "PaintDotNet.Surface", Some(core), &[
+				("width", MemberType::Primitive(INT32)),
+				("height", MemberType::Primitive(INT32)),
+				("stride", MemberType::Primitive(INT32)),
+				("scan0", MemberType::Class("PaintDotNet.MemoryBlock", core)),
+			])?;
+			writer.int32(width)?;
+			writer.int32(height)?;
+			writer.int32(width * 4)?;
+			writer.reference(layer_ids(i, block))?;
+
+			writer.object(layer_ids(i, properties), "PaintDotNet.Layer+LayerProperties", Some(data), &[
+				("name", MemberType::String),
+				("userMetadataItems", MemberType::SystemClass(&metadata_items)),
+				("visible", MemberType::Primitive(BOOLEAN)),
+				("isBackground", MemberType::Primitive(BOOLEAN)),
+				("opacity", MemberType::Primitive(BYTE)),
+				("blendMode", MemberType::Class("PaintDotNet.LayerBlendMode", data)),
+			])?;
+			writer.string(layer_ids(i, name), &layer.name)?;
+			writer.reference(layer_metadata)?;
+			writer.boolean(layer.visible)?;
+			writer.boolean(i == 0)?;
+			writer.byte(layer.opacity)?;
+			// Enums are written inline as structs
+			writer.object(-layer_ids(i, blend_mode), "PaintDotNet.LayerBlendMode", Some(data), &[
+				("value__", MemberType::Primitive(INT32)),
+			])?;
+			writer.int32(0)?;
+		}
+
+		for i in 0..self.layers.len() {
+			writer.object(layer_ids(i, blend_op), "PaintDotNet.UserBlendOps+NormalBlendOp", Some(data), &[])?;
+			writer.object(layer_ids(i, block), "PaintDotNet.MemoryBlock", Some(core), &[
+				("length64", MemberType::Primitive(INT64)),
+				("hasParent", MemberType::Primitive(BOOLEAN)),
+				("deferred", MemberType::Primitive(BOOLEAN)),
+			])?;
+			writer.int64(width as i64 * height as i64 * 4)?;
+			writer.boolean(false)?;
+			writer.boolean(true)?;
+		}
+		writer.system_class_array(layer_metadata, 0, METADATA_ITEM)?;
+		writer.end()
+	}
+}
+
+fn read_u32_be(reader: &mut impl Read) -> io::Result<u32> {
+	let mut bytes = [0; 4];
+	reader.read_exact(&mut bytes)?;
+	Ok(u32::from_be_bytes(bytes))
+}
+
+/// Pixels of a deferred memory block: the format, the chunk size and then the chunks
+/// (in any order), each with its number and compressed size. Integers are big-endian
+fn read_block(reader: &mut impl Read, length: usize) -> Result<Vec<u8>, PdnError> {
+	let mut format = [0];
+	reader.read_exact(&mut format)?;
+	let chunk_size = read_u32_be(reader)? as usize;
+	if chunk_size == 0 {
+		return Err(corrupted("chunk size is zero"));
+	}
+
+	let chunk_count = length.div_ceil(chunk_size);
+	let mut data = vec![0; length];
+	let mut read = vec![false; chunk_count];
+	for _ in 0..chunk_count {
+		let number = read_u32_be(reader)? as usize;
+		let compressed_size = read_u32_be(reader)? as u64;
+		if number >= chunk_count || read[number] {
+			return Err(corrupted("invalid chunk number"));
+		}
+		read[number] = true;
+
+		let chunk = &mut data[number * chunk_size..((number + 1) * chunk_size).min(length)];
+		let mut compressed = reader.take(compressed_size);
+		match format[0] {
+			CHUNKS_GZIP => {
+				let mut decoder = GzDecoder::new(&mut compressed);
+				decoder.read_exact(chunk)?;
+				// Reading to the end checks the CRC of the chunk
+				if decoder.read(&mut [0])? != 0 {
+					return Err(corrupted("chunk is too large"));
+				}
+			}
+			CHUNKS_RAW => compressed.read_exact(chunk)?,
+			format => return Err(corrupted(format!("unknown pixel data format {}", format))),
+		}
+		// Skips what is left of the chunk, if anything
+		let left = compressed.limit();
+		if io::copy(&mut compressed, &mut io::sink())? != left {
+			return Err(corrupted("file is truncated"));
+		}
+	}
+	Ok(data)
+}
+
+fn write_block(writer: &mut impl Write, data: &[u8]) -> io::Result<()> {
+	writer.write_all(&[CHUNKS_GZIP])?;
+	writer.write_all(&CHUNK_SIZE.to_be_bytes())?;
+	for (number, chunk) in data.chunks(CHUNK_SIZE as usize).enumerate() {
+		let mut encoder = GzEncoder::new(vec![], Compression::default());
+		encoder.write_all(chunk)?;
+		let compressed = encoder.finish()?;
+		writer.write_all(&(number as u32).to_be_bytes())?;
+		writer.write_all(&(compressed.len() as u32).to_be_bytes())?;
+		writer.write_all(&compressed)?;
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn layer(name: &str, size: (u32, u32), seed: u8) -> PdnLayer {
+		let pixels = RgbaImage::from_fn(size.0, size.1, |x, y| {
+			Rgba([x as u8 ^ seed, y as u8, seed, (x + y) as u8 | 1])
+		});
+		PdnLayer::new(name, pixels)
+	}
+
+	fn written(document: &PdnDocument) -> Vec<u8> {
+		let mut bytes = vec![];
+		document.write(&mut bytes).unwrap();
+		bytes
+	}
+
+	#[test]
+	fn reads_the_icon() {
+		let icon = PdnDocument::load(concat!(env!("CARGO_MANIFEST_DIR"), "/icon.pdn")).unwrap();
+		assert_eq!(icon.size(), (512, 512));
+		assert_eq!(icon.layers().len(), 5);
+		assert!(icon.layers().iter().all(|layer| layer.pixels.dimensions() == (512, 512)));
+	}
+
+	#[test]
+	fn write_then_read_gives_same_document() {
+		// Large enough for the pixels of a layer to take several chunks
+		let size = (300, 260);
+		let mut document = PdnDocument::new(size);
+		document.push_layer(layer("Background", size, 0)).unwrap();
+		let mut hidden = layer("Hidden, and ünicode", size, 1);
+		hidden.visible = false;
+		hidden.opacity = 100;
+		document.push_layer(hidden).unwrap();
+		document.push_layer(layer("", size, 2)).unwrap();
+
+		let read = PdnDocument::read(written(&document).as_slice()).unwrap();
+		assert_eq!(read, document);
+		assert_eq!(read.layer("hidden, and ünicode").map(|layer| (layer.visible, layer.opacity)), Some((false, 100)));
+	}
+
+	#[test]
+	fn layer_of_other_size_is_an_error() {
+		let mut document = PdnDocument::new((4, 4));
+		match document.push_layer(layer("small", (4, 3), 0)) {
+			Err(PdnError::LayerSize { layer, size, expected }) => {
+				assert_eq!((layer.as_str(), size, expected), ("small", (4, 3), (4, 4)));
+			}
+			other => panic!("{:?}", other),
+		}
+		assert!(document.layers().is_empty());
+	}
+
+	#[test]
+	fn truncated_file_is_an_error() {
+		let mut document = PdnDocument::new((16, 16));
+		document.push_layer(layer("Background", (16, 16), 0)).unwrap();
+		let bytes = written(&document);
+
+		assert!(matches!(PdnDocument::read(&bytes[..3]), Err(PdnError::NotAPdnFile)));
+		assert!(matches!(PdnDocument::read(&b"PNG3 and something"[..]), Err(PdnError::NotAPdnFile)));
+		for length in [6, 40, bytes.len() / 2, bytes.len() - 1] {
+			assert!(
+				matches!(PdnDocument::read(&bytes[..length]), Err(PdnError::Corrupted { .. })),
+				"{} bytes of {}", length, bytes.len(),
+			);
+		}
+	}
+}
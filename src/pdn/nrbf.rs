@@ -0,0 +1,626 @@
+use std::collections::HashMap;
+use std::io::{self, Read, Write};
+
+const SERIALIZED_STREAM_HEADER: u8 = 0;
+const CLASS_WITH_ID: u8 = 1;
+const SYSTEM_CLASS_WITH_MEMBERS: u8 = 2;
+const CLASS_WITH_MEMBERS: u8 = 3;
+const SYSTEM_CLASS_WITH_MEMBERS_AND_TYPES: u8 = 4;
+const CLASS_WITH_MEMBERS_AND_TYPES: u8 = 5;
+const BINARY_OBJECT_STRING: u8 = 6;
+const BINARY_ARRAY: u8 = 7;
+const MEMBER_PRIMITIVE_TYPED: u8 = 8;
+const MEMBER_REFERENCE: u8 = 9;
+const OBJECT_NULL: u8 = 10;
+const MESSAGE_END: u8 = 11;
+const BINARY_LIBRARY: u8 = 12;
+const OBJECT_NULL_MULTIPLE_256: u8 = 13;
+const OBJECT_NULL_MULTIPLE: u8 = 14;
+const ARRAY_SINGLE_PRIMITIVE: u8 = 15;
+const ARRAY_SINGLE_OBJECT: u8 = 16;
+const ARRAY_SINGLE_STRING: u8 = 17;
+
+pub const BOOLEAN: u8 = 1;
+pub const BYTE: u8 = 2;
+pub const INT32: u8 = 8;
+pub const INT64: u8 = 9;
+
+/// Arrays are read one value at a time, so a broken length does not allocate everything at once
+const MAX_PREALLOCATED: usize = 1 << 16;
+
+fn invalid(reason: impl Into<String>) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, reason.into())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Primitive {
+	Boolean(bool),
+	Byte(u8),
+	Char(char),
+	/// As it is written, .NET decimals have no Rust counterpart
+	Decimal(String),
+	Double(f64),
+	Int16(i16),
+	Int32(i32),
+	Int64(i64),
+	SByte(i8),
+	Single(f32),
+	TimeSpan(i64),
+	DateTime(u64),
+	UInt16(u16),
+	UInt32(u32),
+	UInt64(u64),
+	String(String),
+}
+
+impl Primitive {
+	pub fn as_i64(&self) -> Option<i64> {
+		match *self {
+			Primitive::Byte(value) => Some(value as i64),
+			Primitive::Int16(value) => Some(value as i64),
+			Primitive::Int32(value) => Some(value as i64),
+			Primitive::Int64(value) => Some(value),
+			Primitive::SByte(value) => Some(value as i64),
+			Primitive::UInt16(value) => Some(value as i64),
+			Primitive::UInt32(value) => Some(value as i64),
+			Primitive::UInt64(value) => i64::try_from(value).ok(),
+			_ => None,
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+	Null,
+	Primitive(Primitive),
+	/// Id in `Stream::objects`, structs written inline have negative ones
+	Reference(i32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Object {
+	Class { name: String, members: Vec<(String, Value)> },
+	Array(Vec<Value>),
+	String(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinaryType {
+	Primitive(u8),
+	String,
+	Object,
+	SystemClass,
+	Class,
+	ObjectArray,
+	StringArray,
+	PrimitiveArray,
+}
+
+#[derive(Debug, Clone)]
+struct ClassInfo {
+	name: String,
+	members: Vec<String>,
+	/// `None` for the records without member types
+	types: Option<Vec<BinaryType>>,
+}
+
+/// Objects of a .NET `BinaryFormatter` stream (MS-NRBF) by their ids. Method calls
+/// and other remoting records are not supported, Paint.NET documents have none
+#[derive(Debug, Clone)]
+pub struct Stream {
+	pub root: i32,
+	pub objects: HashMap<i32, Object>,
+	/// Ids of the objects in the order they were read
+	pub order: Vec<i32>,
+}
+
+impl Stream {
+	/// Reads records up to the end of the message, the rest of `reader` is left as it is
+	pub fn read(reader: impl Read) -> io::Result<Self> {
+		let mut parser = Parser {
+			reader,
+			classes: HashMap::new(),
+			stream: Stream { root: 0, objects: HashMap::new(), order: vec![] },
+		};
+		loop {
+			match parser.u8()? {
+				MESSAGE_END => break,
+				record => {
+					parser.record(record)?;
+				}
+			}
+		}
+		Ok(parser.stream)
+	}
+
+	pub fn object(&self, value: &Value) -> Option<&Object> {
+		match value {
+			Value::Reference(id) => self.objects.get(id),
+			_ => None,
+		}
+	}
+
+	/// Member of a class object
+	pub fn member(&self, object: &Value, name: &str) -> Option<&Value> {
+		match self.object(object)? {
+			Object::Class { members, .. } => members.iter().find(|(member, _)| member == name).map(|(_, value)| value),
+			_ => None,
+		}
+	}
+
+	pub fn class_name(&self, object: &Value) -> Option<&str> {
+		match self.object(object)? {
+			Object::Class { name, .. } => Some(name),
+			_ => None,
+		}
+	}
+
+	pub fn integer(&self, object: &Value, name: &str) -> Option<i64> {
+		match self.member(object, name)? {
+			Value::Primitive(primitive) => primitive.as_i64(),
+			_ => None,
+		}
+	}
+
+	pub fn boolean(&self, object: &Value, name: &str) -> Option<bool> {
+		match self.member(object, name)? {
+			Value::Primitive(Primitive::Boolean(value)) => Some(*value),
+			_ => None,
+		}
+	}
+
+	pub fn string(&self, object: &Value, name: &str) -> Option<&str> {
+		match self.member(object, name)? {
+			Value::Primitive(Primitive::String(value)) => Some(value),
+			value => match self.object(value)? {
+				Object::String(value) => Some(value),
+				_ => None,
+			},
+		}
+	}
+
+	pub fn array(&self, value: &Value) -> Option<&[Value]> {
+		match self.object(value)? {
+			Object::Array(values) => Some(values),
+			_ => None,
+		}
+	}
+}
+
+struct Parser<R> {
+	reader: R,
+	classes: HashMap<i32, ClassInfo>,
+	stream: Stream,
+}
+
+/// What a record stands for where a value is expected
+enum Item {
+	Value(Value),
+	Nulls(usize),
+}
+
+impl<R: Read> Parser<R> {
+	fn bytes<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+		let mut bytes = [0; N];
+		self.reader.read_exact(&mut bytes)?;
+		Ok(bytes)
+	}
+
+	fn u8(&mut self) -> io::Result<u8> {
+		Ok(self.bytes::<1>()?[0])
+	}
+
+	fn i32(&mut self) -> io::Result<i32> {
+		Ok(i32::from_le_bytes(self.bytes()?))
+	}
+
+	fn length(&mut self) -> io::Result<usize> {
+		usize::try_from(self.i32()?).map_err(|_| invalid("negative length"))
+	}
+
+	/// Length-prefixed UTF-8 string, the length takes 7 bits of each byte
+	fn string(&mut self) -> io::Result<String> {
+		let mut length = 0_usize;
+		for shift in (0..35).step_by(7) {
+			let byte = self.u8()?;
+			length |= ((byte & 0x7F) as usize) << shift;
+			if byte < 0x80 {
+				let mut bytes = vec![];
+				(&mut self.reader).take(length as u64).read_to_end(&mut bytes)?;
+				if bytes.len() != length {
+					return Err(io::ErrorKind::UnexpectedEof.into());
+				}
+				return String::from_utf8(bytes).map_err(|_| invalid("string is not UTF-8"));
+			}
+		}
+		Err(invalid("string length is too long"))
+	}
+
+	fn primitive(&mut self, kind: u8) -> io::Result<Primitive> {
+		Ok(match kind {
+			1 => Primitive::Boolean(self.u8()? != 0),
+			2 => Primitive::Byte(self.u8()?),
+			3 => {
+				// One UTF-8 encoded character
+				let first = self.u8()?;
+				let extra = match first {
+					0x00..=0x7F => 0,
+					0xC0..=0xDF => 1,
+					0xE0..=0xEF => 2,
+					_ => 3,
+				};
+				let mut bytes = vec![first];
+				for _ in 0..extra {
+					bytes.push(self.u8()?);
+				}
+				let text = String::from_utf8(bytes).map_err(|_| invalid("char is not UTF-8"))?;
+				Primitive::Char(text.chars().next().unwrap())
+			}
+			5 => Primitive::Decimal(self.string()?),
+			6 => Primitive::Double(f64::from_le_bytes(self.bytes()?)),
+			7 => Primitive::Int16(i16::from_le_bytes(self.bytes()?)),
+			8 => Primitive::Int32(self.i32()?),
+			9 => Primitive::Int64(i64::from_le_bytes(self.bytes()?)),
+			10 => Primitive::SByte(self.u8()? as i8),
+			11 => Primitive::Single(f32::from_le_bytes(self.bytes()?)),
+			12 => Primitive::TimeSpan(i64::from_le_bytes(self.bytes()?)),
+			13 => Primitive::DateTime(u64::from_le_bytes(self.bytes()?)),
+			14 => Primitive::UInt16(u16::from_le_bytes(self.bytes()?)),
+			15 => Primitive::UInt32(u32::from_le_bytes(self.bytes()?)),
+			16 => Primitive::UInt64(u64::from_le_bytes(self.bytes()?)),
+			18 => Primitive::String(self.string()?),
+			kind => return Err(invalid(format!("unknown primitive type {}", kind))),
+		})
+	}
+
+	fn binary_type(&mut self, kind: u8) -> io::Result<BinaryType> {
+		Ok(match kind {
+			0 => BinaryType::Primitive(0),
+			1 => BinaryType::String,
+			2 => BinaryType::Object,
+			3 => BinaryType::SystemClass,
+			4 => BinaryType::Class,
+			5 => BinaryType::ObjectArray,
+			6 => BinaryType::StringArray,
+			7 => BinaryType::PrimitiveArray,
+			kind => return Err(invalid(format!("unknown member type {}", kind))),
+		})
+	}
+
+	/// Additional information of each member type, goes after all the types
+	fn additional_info(&mut self, kind: BinaryType) -> io::Result<BinaryType> {
+		match kind {
+			BinaryType::Primitive(_) => Ok(BinaryType::Primitive(self.u8()?)),
+			BinaryType::PrimitiveArray => {
+				self.u8()?;
+				Ok(kind)
+			}
+			BinaryType::SystemClass => {
+				self.string()?;
+				Ok(kind)
+			}
+			BinaryType::Class => {
+				self.string()?;
+				self.i32()?;
+				Ok(kind)
+			}
+			_ => Ok(kind),
+		}
+	}
+
+	fn insert(&mut self, id: i32, object: Object) {
+		self.stream.objects.insert(id, object);
+		self.stream.order.push(id);
+	}
+
+	/// Reads a record (its type is already read) and returns the value it stands for
+	fn record(&mut self, record: u8) -> io::Result<Item> {
+		let value = match record {
+			SERIALIZED_STREAM_HEADER => {
+				self.stream.root = self.i32()?;
+				let _header_id = self.i32()?;
+				let (major, minor) = (self.i32()?, self.i32()?);
+				if (major, minor) != (1, 0) {
+					return Err(invalid(format!("unsupported stream version {}.{}", major, minor)));
+				}
+				Value::Null
+			}
+			BINARY_LIBRARY => {
+				self.i32()?;
+				self.string()?;
+				Value::Null
+			}
+			CLASS_WITH_ID => {
+				let id = self.i32()?;
+				let metadata_id = self.i32()?;
+				let info = self.classes.get(&metadata_id).cloned().ok_or_else(|| invalid("unknown class metadata"))?;
+				self.class_members(id, info)?
+			}
+			SYSTEM_CLASS_WITH_MEMBERS | CLASS_WITH_MEMBERS | SYSTEM_CLASS_WITH_MEMBERS_AND_TYPES | CLASS_WITH_MEMBERS_AND_TYPES => {
+				let id = self.i32()?;
+				let name = self.string()?;
+				let count = self.length()?;
+				let members = (0..count).map(|_| self.string()).collect::<io::Result<Vec<_>>>()?;
+				let types = match record {
+					SYSTEM_CLASS_WITH_MEMBERS_AND_TYPES | CLASS_WITH_MEMBERS_AND_TYPES => {
+						let kinds = (0..count).map(|_| self.u8()).collect::<io::Result<Vec<_>>>()?;
+						let kinds = kinds.into_iter().map(|kind| self.binary_type(kind)).collect::<io::Result<Vec<_>>>()?;
+						Some(kinds.into_iter().map(|kind| self.additional_info(kind)).collect::<io::Result<Vec<_>>>()?)
+					}
+					_ => None,
+				};
+				if record == CLASS_WITH_MEMBERS || record == CLASS_WITH_MEMBERS_AND_TYPES {
+					let _library_id = self.i32()?;
+				}
+				let info = ClassInfo { name, members, types };
+				self.classes.insert(id, info.clone());
+				self.class_members(id, info)?
+			}
+			BINARY_OBJECT_STRING => {
+				let id = self.i32()?;
+				let value = self.string()?;
+				self.insert(id, Object::String(value));
+				Value::Reference(id)
+			}
+			MEMBER_PRIMITIVE_TYPED => {
+				let kind = self.u8()?;
+				Value::Primitive(self.primitive(kind)?)
+			}
+			MEMBER_REFERENCE => Value::Reference(self.i32()?),
+			OBJECT_NULL => Value::Null,
+			OBJECT_NULL_MULTIPLE_256 => return Ok(Item::Nulls(self.u8()? as usize)),
+			OBJECT_NULL_MULTIPLE => return Ok(Item::Nulls(self.length()?)),
+			ARRAY_SINGLE_PRIMITIVE => {
+				let id = self.i32()?;
+				let length = self.length()?;
+				let kind = self.u8()?;
+				let mut values = Vec::with_capacity(length.min(MAX_PREALLOCATED));
+				for _ in 0..length {
+					values.push(Value::Primitive(self.primitive(kind)?));
+				}
+				self.insert(id, Object::Array(values));
+				Value::Reference(id)
+			}
+			ARRAY_SINGLE_OBJECT | ARRAY_SINGLE_STRING => {
+				let id = self.i32()?;
+				let length = self.length()?;
+				let values = self.array_values(length, None)?;
+				self.insert(id, Object::Array(values));
+				Value::Reference(id)
+			}
+			BINARY_ARRAY => {
+				let id = self.i32()?;
+				let array_type = self.u8()?;
+				let rank = self.length()?;
+				let lengths = (0..rank).map(|_| self.length()).collect::<io::Result<Vec<_>>>()?;
+				// Arrays with offsets have lower bounds
+				if (3..=5).contains(&array_type) {
+					for _ in 0..rank {
+						self.i32()?;
+					}
+				}
+				let kind = self.u8()?;
+				let kind = self.binary_type(kind)?;
+				let kind = self.additional_info(kind)?;
+				let length = lengths.iter().try_fold(1_usize, |total, length| total.checked_mul(*length))
+					.ok_or_else(|| invalid("array is too large"))?;
+				let primitive = match kind {
+					BinaryType::Primitive(kind) => Some(kind),
+					_ => None,
+				};
+				let values = self.array_values(length, primitive)?;
+				self.insert(id, Object::Array(values));
+				Value::Reference(id)
+			}
+			record => return Err(invalid(format!("unsupported record type {}", record))),
+		};
+		Ok(Item::Value(value))
+	}
+
+	fn class_members(&mut self, id: i32, info: ClassInfo) -> io::Result<Value> {
+		let mut members = Vec::with_capacity(info.members.len());
+		for (i, member) in info.members.iter().enumerate() {
+			let value = match info.types.as_ref().map(|types| types[i]) {
+				Some(BinaryType::Primitive(kind)) => Value::Primitive(self.primitive(kind)?),
+				_ => self.value()?,
+			};
+			members.push((member.clone(), value));
+		}
+		self.insert(id, Object::Class { name: info.name, members });
+		Ok(Value::Reference(id))
+	}
+
+	/// Value of a member, which may be preceded by the libraries it needs
+	fn value(&mut self) -> io::Result<Value> {
+		loop {
+			let record = self.u8()?;
+			match self.record(record)? {
+				Item::Value(Value::Null) if record == BINARY_LIBRARY => continue,
+				Item::Value(value) => return Ok(value),
+				Item::Nulls(_) => return Err(invalid("several nulls in place of one value")),
+			}
+		}
+	}
+
+	fn array_values(&mut self, length: usize, primitive: Option<u8>) -> io::Result<Vec<Value>> {
+		let mut values = Vec::with_capacity(length.min(MAX_PREALLOCATED));
+		while values.len() < length {
+			if let Some(kind) = primitive {
+				values.push(Value::Primitive(self.primitive(kind)?));
+				continue;
+			}
+			let record = self.u8()?;
+			match self.record(record)? {
+				Item::Value(Value::Null) if record == BINARY_LIBRARY => {}
+				Item::Value(value) => values.push(value),
+				Item::Nulls(count) => {
+					if values.len() + count > length {
+						return Err(invalid("too many nulls in an array"));
+					}
+					values.extend(std::iter::repeat(Value::Null).take(count));
+				}
+			}
+		}
+		Ok(values)
+	}
+}
+
+/// Type of a class member in `Writer::class`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MemberType<'a> {
+	Primitive(u8),
+	String,
+	/// Class from `mscorlib`
+	SystemClass(&'a str),
+	/// Class and the id of its library
+	Class(&'a str, i32),
+	ObjectArray,
+}
+
+/// Writes records one by one, the caller keeps track of the ids and of what goes where
+pub struct Writer<W> {
+	writer: W,
+	/// Id of the first object of each class, which carries the class metadata
+	classes: HashMap<String, i32>,
+}
+
+impl<W: Write> Writer<W> {
+	pub fn new(writer: W) -> Self {
+		Writer { writer, classes: HashMap::new() }
+	}
+
+	fn write_i32(&mut self, value: i32) -> io::Result<()> {
+		self.writer.write_all(&value.to_le_bytes())
+	}
+
+	fn write_string(&mut self, value: &str) -> io::Result<()> {
+		let mut length = value.len();
+		loop {
+			let byte = (length & 0x7F) as u8;
+			length >>= 7;
+			if length == 0 {
+				self.writer.write_all(&[byte])?;
+				break;
+			}
+			self.writer.write_all(&[byte | 0x80])?;
+		}
+		self.writer.write_all(value.as_bytes())
+	}
+
+	pub fn header(&mut self, root: i32) -> io::Result<()> {
+		self.writer.write_all(&[SERIALIZED_STREAM_HEADER])?;
+		for value in [root, -1, 1, 0] {
+			self.write_i32(value)?;
+		}
+		Ok(())
+	}
+
+	pub fn library(&mut self, id: i32, name: &str) -> io::Result<()> {
+		self.writer.write_all(&[BINARY_LIBRARY])?;
+		self.write_i32(id)?;
+		self.write_string(name)
+	}
+
+	/// Object of a class, its member values follow in the order of `members`. The metadata
+	/// is written with the first object of the class only. `library` is `None` for `mscorlib` classes
+	pub fn object(&mut self, id: i32, name: &str, library: Option<i32>, members: &[(&str, MemberType)]) -> io::Result<()> {
+		match self.classes.get(name) {
+			Some(metadata_id) => self.class_with_id(id, *metadata_id),
+			None => {
+				self.classes.insert(name.to_owned(), id);
+				self.class(id, name, library, members)
+			}
+		}
+	}
+
+	fn class(&mut self, id: i32, name: &str, library: Option<i32>, members: &[(&str, MemberType)]) -> io::Result<()> {
+		let record = if library.is_some() { CLASS_WITH_MEMBERS_AND_TYPES } else { SYSTEM_CLASS_WITH_MEMBERS_AND_TYPES };
+		self.writer.write_all(&[record])?;
+		self.write_i32(id)?;
+		self.write_string(name)?;
+		self.write_i32(members.len() as i32)?;
+		for (member, _) in members {
+			self.write_string(member)?;
+		}
+		for (_, kind) in members {
+			let code = match kind {
+				MemberType::Primitive(_) => 0,
+				MemberType::String => 1,
+				MemberType::SystemClass(_) => 3,
+				MemberType::Class(..) => 4,
+				MemberType::ObjectArray => 5,
+			};
+			self.writer.write_all(&[code])?;
+		}
+		for (_, kind) in members {
+			match kind {
+				MemberType::Primitive(primitive) => self.writer.write_all(&[*primitive])?,
+				MemberType::SystemClass(name) => self.write_string(name)?,
+				MemberType::Class(name, library) => {
+					self.write_string(name)?;
+					self.write_i32(*library)?;
+				}
+				MemberType::String | MemberType::ObjectArray => {}
+			}
+		}
+		if let Some(library) = library {
+			self.write_i32(library)?;
+		}
+		Ok(())
+	}
+
+	fn class_with_id(&mut self, id: i32, metadata_id: i32) -> io::Result<()> {
+		self.writer.write_all(&[CLASS_WITH_ID])?;
+		self.write_i32(id)?;
+		self.write_i32(metadata_id)
+	}
+
+	pub fn reference(&mut self, id: i32) -> io::Result<()> {
+		self.writer.write_all(&[MEMBER_REFERENCE])?;
+		self.write_i32(id)
+	}
+
+	pub fn string(&mut self, id: i32, value: &str) -> io::Result<()> {
+		self.writer.write_all(&[BINARY_OBJECT_STRING])?;
+		self.write_i32(id)?;
+		self.write_string(value)
+	}
+
+	pub fn boolean(&mut self, value: bool) -> io::Result<()> {
+		self.writer.write_all(&[value as u8])
+	}
+
+	pub fn byte(&mut self, value: u8) -> io::Result<()> {
+		self.writer.write_all(&[value])
+	}
+
+	pub fn int32(&mut self, value: i32) -> io::Result<()> {
+		self.write_i32(value)
+	}
+
+	pub fn int64(&mut self, value: i64) -> io::Result<()> {
+		self.writer.write_all(&value.to_le_bytes())
+	}
+
+	/// `object[]`, the values follow
+	pub fn object_array(&mut self, id: i32, length: usize) -> io::Result<()> {
+		self.writer.write_all(&[ARRAY_SINGLE_OBJECT])?;
+		self.write_i32(id)?;
+		self.write_i32(length as i32)
+	}
+
+	/// One-dimensional array of an `mscorlib` class, the values follow
+	pub fn system_class_array(&mut self, id: i32, length: usize, class: &str) -> io::Result<()> {
+		self.writer.write_all(&[BINARY_ARRAY])?;
+		self.write_i32(id)?;
+		self.writer.write_all(&[0])?;
+		self.write_i32(1)?;
+		self.write_i32(length as i32)?;
+		self.writer.write_all(&[3])?;
+		self.write_string(class)
+	}
+
+	pub fn end(&mut self) -> io::Result<()> {
+		self.writer.write_all(&[MESSAGE_END])
+	}
+}
@@ -0,0 +1,517 @@
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use flate2::{Compression, Crc};
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+
+use crate::map::{self, Biome, Height, Landscape, Map, MapCell, Resource, Topology, MAX_RESOURCES_IN_CELL};
+use crate::seed::WorldSeed;
+
+/// `.ecosim` file:
+/// ```text
+/// magic (8 bytes) | version: u32 | chunk | chunk | ... | "END " chunk
+/// chunk: tag (4 bytes) | length: u32 | CRC-32 of the data: u32 | zlib-compressed data (`length` bytes)
+/// ```
+/// Numbers are little-endian. Chunks with unknown tags are skipped.
+pub const MAGIC: [u8; 8] = *b"ECOSIM\r\n";
+pub const VERSION: u32 = 1;
+pub const EXTENSION: &str = "ecosim";
+
+type Tag = [u8; 4];
+
+/// Size, topology, sea level and tick
+const INFO: Tag = *b"INFO";
+const SEED: Tag = *b"SEED";
+/// `Height` bits of every cell
+const LANDSCAPE: Tag = *b"LAND";
+/// Biome keys, then a biome index and `MAX_RESOURCES_IN_CELL` resources for every cell
+const BIOMES: Tag = *b"BIOM";
+/// Simulation state of every cell
+const CELLS: Tag = *b"CELL";
+const END: Tag = *b"END ";
+
+/// Largest decompressed chunk of a valid file, the biomes of the largest map with the
+/// palette. The `length` of a chunk only limits its compressed data.
+const MAX_CHUNK_LENGTH: u64 =
+    map::MAX_MAP_SIZE.0 as u64 * map::MAX_MAP_SIZE.1 as u64 * (1 + 2 * MAX_RESOURCES_IN_CELL) as u64 + u16::MAX as u64;
+
+/// Upgrades the chunks of a file of version `i + 1` to version `i + 2`. When the
+/// format changes, `VERSION` goes up and the hook for the previous one goes here.
+const MIGRATIONS: [Migration; (VERSION - 1) as usize] = [];
+
+type Migration = fn(&mut RawSave) -> Result<(), SaveError>;
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io { io_error: io::Error },
+    NotASaveFile,
+    UnsupportedVersion { version: u32 },
+    MissingChunk { tag: String },
+    Corrupted { tag: String, reason: &'static str },
+}
+
+impl Display for SaveError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaveError::Io { io_error } => write!(f, "Failed to access save file: {}", io_error),
+            SaveError::NotASaveFile => write!(f, "Not an .{} file", EXTENSION),
+            SaveError::UnsupportedVersion { version } => write!(f, "Save file version {} is not supported (latest is {})", version, VERSION),
+            SaveError::MissingChunk { tag } => write!(f, "Save file has no \"{}\" section", tag),
+            SaveError::Corrupted { tag, reason } => write!(f, "Section \"{}\" is corrupted: {}", tag, reason),
+        }
+    }
+}
+
+impl From<io::Error> for SaveError {
+    fn from(io_error: io::Error) -> Self {
+        SaveError::Io { io_error }
+    }
+}
+
+fn tag_name(tag: Tag) -> String {
+    String::from_utf8_lossy(&tag).trim_end().to_owned()
+}
+
+/// Everything needed to continue a simulation. Generation settings other than the
+/// seed are not kept, the saved landscape is used as it is.
+#[derive(Debug, Clone)]
+pub struct WorldSave {
+    pub seed: WorldSeed,
+    pub sea_level: Height,
+    pub tick: u64,
+    /// With the world topology
+    pub landscape: Landscape,
+    pub map: Option<Map>,
+    /// One value per cell, same layout as `Landscape::height`
+    pub cells: Option<Box<[u8]>>,
+}
+
+impl WorldSave {
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SaveError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SaveError> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    pub fn write(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(&MAGIC)?;
+        writer.write_all(&VERSION.to_le_bytes())?;
+
+        let size = self.landscape.size();
+        let topology = self.landscape.topology();
+        let mut info = vec![];
+        info.extend(size.0.to_le_bytes());
+        info.extend(size.1.to_le_bytes());
+        info.push(topology.wrap_x as u8 | (topology.wrap_y as u8) << 1);
+        info.extend(map::height_to_int(self.sea_level).to_le_bytes());
+        info.extend(self.tick.to_le_bytes());
+        write_chunk(&mut writer, INFO, &info)?;
+
+        write_chunk(&mut writer, SEED, &self.seed.value().to_le_bytes())?;
+
+        let landscape: Vec<u8> = self.landscape.height().iter()
+            .flat_map(|height| map::height_to_int(*height).to_le_bytes())
+            .collect();
+        write_chunk(&mut writer, LANDSCAPE, &landscape)?;
+
+        if let Some(map) = &self.map {
+            write_chunk(&mut writer, BIOMES, &encode_map(map))?;
+        }
+        if let Some(cells) = &self.cells {
+            write_chunk(&mut writer, CELLS, cells)?;
+        }
+        write_chunk(&mut writer, END, &[])
+    }
+
+    pub fn read(reader: impl Read) -> Result<Self, SaveError> {
+        let mut raw = RawSave::read(reader)?;
+        raw.migrate(&MIGRATIONS)?;
+
+        let mut info = ChunkReader::new(INFO, raw.chunk(INFO)?);
+        let size = (info.u32()?, info.u32()?);
+        let wrap = info.u8()?;
+        let topology = Topology { wrap_x: wrap & 1 != 0, wrap_y: wrap & 2 != 0 };
+        let sea_level = map::height_from_int(info.i32()?);
+        let tick = info.u64()?;
+        if size.0 == 0 || size.1 == 0 || size.0 > map::MAX_MAP_SIZE.0 || size.1 > map::MAX_MAP_SIZE.1 {
+            return Err(SaveError::Corrupted { tag: tag_name(INFO), reason: "invalid map size" });
+        }
+        let cells_count = size.0 as usize * size.1 as usize;
+
+        let seed = WorldSeed::new(ChunkReader::new(SEED, raw.chunk(SEED)?).u64()?);
+
+        let mut landscape = ChunkReader::new(LANDSCAPE, raw.chunk(LANDSCAPE)?);
+        let height = (0..cells_count)
+            .map(|_| landscape.i32().map(map::height_from_int))
+            .collect::<Result<Box<[Height]>, _>>()?;
+        landscape.finish()?;
+        let landscape = Landscape::new(size, height).with_topology(topology);
+
+        let map = match raw.find(BIOMES) {
+            Some(data) => Some(decode_map(size, data)?),
+            None => None,
+        };
+        let cells = match raw.find(CELLS) {
+            Some(data) if data.len() != cells_count => {
+                return Err(SaveError::Corrupted { tag: tag_name(CELLS), reason: "wrong number of cells" });
+            }
+            Some(data) => Some(data.into()),
+            None => None,
+        };
+
+        Ok(WorldSave { seed, sea_level, tick, landscape, map, cells })
+    }
+}
+
+fn write_chunk(mut writer: impl Write, tag: Tag, data: &[u8]) -> io::Result<()> {
+    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+    encoder.write_all(data)?;
+    let compressed = encoder.finish()?;
+    let mut crc = Crc::new();
+    crc.update(data);
+
+    writer.write_all(&tag)?;
+    writer.write_all(&(compressed.len() as u32).to_le_bytes())?;
+    writer.write_all(&crc.sum().to_le_bytes())?;
+    writer.write_all(&compressed)
+}
+
+/// Decompressed chunks of a file, as they were written by its version
+pub struct RawSave {
+    pub version: u32,
+    pub chunks: Vec<(Tag, Vec<u8>)>,
+}
+
+impl RawSave {
+    fn read(reader: impl Read) -> Result<Self, SaveError> {
+        Self::read_limited(reader, MAX_CHUNK_LENGTH)
+    }
+
+    fn read_limited(mut reader: impl Read, max_chunk_length: u64) -> Result<Self, SaveError> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic).map_err(|_| SaveError::NotASaveFile)?;
+        if magic != MAGIC {
+            return Err(SaveError::NotASaveFile);
+        }
+        let mut word = [0; 4];
+        reader.read_exact(&mut word)?;
+        let version = u32::from_le_bytes(word);
+
+        let mut chunks = vec![];
+        loop {
+            let mut header = [0; 12];
+            reader.read_exact(&mut header)?;
+            let tag: Tag = header[0..4].try_into().unwrap();
+            let length = u32::from_le_bytes(header[4..8].try_into().unwrap());
+            let crc_sum = u32::from_le_bytes(header[8..12].try_into().unwrap());
+
+            let mut compressed = vec![];
+            (&mut reader).take(length as u64).read_to_end(&mut compressed)?;
+            if compressed.len() != length as usize {
+                return Err(SaveError::Corrupted { tag: tag_name(tag), reason: "unexpected end of file" });
+            }
+            let mut data = vec![];
+            ZlibDecoder::new(compressed.as_slice()).take(max_chunk_length + 1).read_to_end(&mut data)
+                .map_err(|_| SaveError::Corrupted { tag: tag_name(tag), reason: "failed to decompress" })?;
+            if data.len() as u64 > max_chunk_length {
+                return Err(SaveError::Corrupted { tag: tag_name(tag), reason: "chunk is too large" });
+            }
+            let mut crc = Crc::new();
+            crc.update(&data);
+            if crc.sum() != crc_sum {
+                return Err(SaveError::Corrupted { tag: tag_name(tag), reason: "checksum mismatch" });
+            }
+            if tag == END {
+                break;
+            }
+            chunks.push((tag, data));
+        }
+
+        Ok(RawSave { version, chunks })
+    }
+
+    /// Brings the chunks up to the latest version, which is the one after the last migration
+    fn migrate(&mut self, migrations: &[Migration]) -> Result<(), SaveError> {
+        let latest = migrations.len() as u32 + 1;
+        if self.version == 0 || self.version > latest {
+            return Err(SaveError::UnsupportedVersion { version: self.version });
+        }
+        while self.version < latest {
+            migrations[(self.version - 1) as usize](self)?;
+            self.version += 1;
+        }
+        Ok(())
+    }
+
+    pub fn find(&self, tag: Tag) -> Option<&[u8]> {
+        self.chunks.iter()
+            .find(|(chunk_tag, _)| *chunk_tag == tag)
+            .map(|(_, data)| data.as_slice())
+    }
+
+    fn chunk(&self, tag: Tag) -> Result<&[u8], SaveError> {
+        self.find(tag).ok_or(SaveError::MissingChunk { tag: tag_name(tag) })
+    }
+}
+
+struct ChunkReader<'a> {
+    tag: Tag,
+    data: &'a [u8],
+}
+
+impl<'a> ChunkReader<'a> {
+    fn new(tag: Tag, data: &'a [u8]) -> Self {
+        ChunkReader { tag, data }
+    }
+
+    fn corrupted(&self, reason: &'static str) -> SaveError {
+        SaveError::Corrupted { tag: tag_name(self.tag), reason }
+    }
+
+    fn bytes<const N: usize>(&mut self) -> Result<[u8; N], SaveError> {
+        if self.data.len() < N {
+            return Err(self.corrupted("unexpected end"));
+        }
+        let (bytes, rest) = self.data.split_at(N);
+        self.data = rest;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, SaveError> {
+        Ok(self.bytes::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, SaveError> {
+        Ok(u32::from_le_bytes(self.bytes()?))
+    }
+
+    fn i32(&mut self) -> Result<i32, SaveError> {
+        Ok(i32::from_le_bytes(self.bytes()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, SaveError> {
+        Ok(u64::from_le_bytes(self.bytes()?))
+    }
+
+    fn finish(&self) -> Result<(), SaveError> {
+        match self.data.is_empty() {
+            true => Ok(()),
+            false => Err(self.corrupted("unexpected data at the end")),
+        }
+    }
+}
+
+/// Kind and value bytes, `(0, 0)` for no resource
+fn encode_resource(resource: Option<Resource>) -> [u8; 2] {
+    match resource {
+        None => [0, 0],
+        Some(Resource::Compound { count }) => [1, count],
+        Some(Resource::Rock) => [2, 0],
+        Some(Resource::Fruit) => [3, 0],
+        Some(Resource::PoisonedFruit) => [4, 0],
+        Some(Resource::FireCrystal) => [5, 0],
+        Some(Resource::EnigmaticCrystal) => [6, 0],
+    }
+}
+
+fn decode_resource(bytes: [u8; 2]) -> Option<Option<Resource>> {
+    Some(match bytes {
+        [0, _] => None,
+        [1, count] => Some(Resource::Compound { count }),
+        [2, _] => Some(Resource::Rock),
+        [3, _] => Some(Resource::Fruit),
+        [4, _] => Some(Resource::PoisonedFruit),
+        [5, _] => Some(Resource::FireCrystal),
+        [6, _] => Some(Resource::EnigmaticCrystal),
+        _ => return None,
+    })
+}
+
+/// Biomes are stored by their keys, so reordering `Biome` does not break old files
+fn encode_map(map: &Map) -> Vec<u8> {
+    let mut data = vec![Biome::all().len() as u8];
+    for biome in Biome::all() {
+        data.push(biome.key().len() as u8);
+        data.extend(biome.key().as_bytes());
+    }
+    for (biome, resources) in map.cells() {
+        data.push(Biome::all().iter().position(|known| known == biome).unwrap() as u8);
+        for resource in resources {
+            data.extend(encode_resource(*resource));
+        }
+    }
+    data
+}
+
+fn decode_map(size: (u32, u32), data: &[u8]) -> Result<Map, SaveError> {
+    let mut reader = ChunkReader::new(BIOMES, data);
+    let mut palette = vec![];
+    for _ in 0..reader.u8()? {
+        let length = reader.u8()? as usize;
+        if reader.data.len() < length {
+            return Err(reader.corrupted("unexpected end"));
+        }
+        let (key, rest) = reader.data.split_at(length);
+        reader.data = rest;
+        let biome = std::str::from_utf8(key).ok()
+            .and_then(Biome::from_key)
+            .ok_or_else(|| reader.corrupted("unknown biome"))?;
+        palette.push(biome);
+    }
+
+    let cells_count = size.0 as usize * size.1 as usize;
+    let mut cells: Vec<MapCell> = Vec::with_capacity(cells_count);
+    for _ in 0..cells_count {
+        let biome = *palette.get(reader.u8()? as usize).ok_or_else(|| reader.corrupted("unknown biome"))?;
+        let mut resources = [None; MAX_RESOURCES_IN_CELL];
+        for resource in resources.iter_mut() {
+            *resource = decode_resource(reader.bytes()?).ok_or_else(|| reader.corrupted("unknown resource"))?;
+        }
+        cells.push((biome, resources));
+    }
+    reader.finish()?;
+
+    Ok(Map::new(size, cells.into_boxed_slice()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world(map: bool, cells: bool) -> WorldSave {
+        let size = (6, 5);
+        let count = size.0 * size.1;
+        let height = (0..count).map(|id| map::height_from_f32(id as f32 / count as f32 - 0.2)).collect();
+        let map = map.then(|| {
+            let cells = (0..count as usize).map(|id| {
+                let mut resources = [None; MAX_RESOURCES_IN_CELL];
+                resources[id % MAX_RESOURCES_IN_CELL] = Some(Resource::Compound { count: id as u8 });
+                resources[(id + 1) % MAX_RESOURCES_IN_CELL] = Some(Resource::EnigmaticCrystal);
+                (Biome::all()[id % Biome::all().len()], resources)
+            });
+            Map::new(size, cells.collect())
+        });
+        WorldSave {
+            seed: WorldSeed::new(0xDEAD_BEEF_0123_4567),
+            sea_level: map::height_from_f32(0.25),
+            tick: 123_456_789_000,
+            landscape: Landscape::new(size, height).with_topology(Topology { wrap_x: true, wrap_y: false }),
+            map,
+            cells: cells.then(|| (0..count).map(|id| id as u8 * 3).collect()),
+        }
+    }
+
+    fn written(save: &WorldSave) -> Vec<u8> {
+        let mut bytes = vec![];
+        save.write(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn end_chunk() -> Vec<u8> {
+        let mut bytes = vec![];
+        write_chunk(&mut bytes, END, &[]).unwrap();
+        bytes
+    }
+
+    fn assert_same(read: &WorldSave, save: &WorldSave) {
+        assert_eq!((read.seed, read.sea_level, read.tick), (save.seed, save.sea_level, save.tick));
+        assert_eq!(read.landscape.size(), save.landscape.size());
+        assert_eq!(read.landscape.topology(), save.landscape.topology());
+        assert_eq!(read.landscape.height(), save.landscape.height());
+        assert_eq!(read.map.as_ref().map(Map::cells), save.map.as_ref().map(Map::cells));
+        assert_eq!(read.cells, save.cells);
+    }
+
+    #[test]
+    fn write_then_read_gives_same_world() {
+        for (map, cells) in [(false, false), (true, false), (false, true), (true, true)] {
+            let save = world(map, cells);
+            assert_same(&WorldSave::read(written(&save).as_slice()).unwrap(), &save);
+        }
+    }
+
+    #[test]
+    fn unknown_chunks_are_skipped() {
+        let save = world(true, true);
+        let mut bytes = written(&save);
+        bytes.truncate(bytes.len() - end_chunk().len());
+        write_chunk(&mut bytes, *b"NEW!", b"from a later version").unwrap();
+        bytes.extend(end_chunk());
+
+        assert_same(&WorldSave::read(bytes.as_slice()).unwrap(), &save);
+    }
+
+    #[test]
+    fn checksum_mismatch_is_corrupted() {
+        let mut bytes = written(&world(false, false));
+        // CRC of the first chunk, after the magic, the version, the tag and the length
+        bytes[MAGIC.len() + 12] ^= 1;
+        match WorldSave::read(bytes.as_slice()) {
+            Err(SaveError::Corrupted { tag, reason }) => assert_eq!((tag.as_str(), reason), ("INFO", "checksum mismatch")),
+            other => panic!("{:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn unsupported_versions_are_errors() {
+        for version in [0, VERSION + 1] {
+            let mut bytes = written(&world(false, false));
+            bytes[MAGIC.len()..MAGIC.len() + 4].copy_from_slice(&version.to_le_bytes());
+            assert!(matches!(
+                WorldSave::read(bytes.as_slice()),
+                Err(SaveError::UnsupportedVersion { version: read }) if read == version
+            ));
+        }
+    }
+
+    #[test]
+    fn other_files_are_not_saves() {
+        let mut bytes = written(&world(false, false));
+        bytes[0] = b'e';
+        assert!(matches!(WorldSave::read(bytes.as_slice()), Err(SaveError::NotASaveFile)));
+        assert!(matches!(WorldSave::read(&MAGIC[..4]), Err(SaveError::NotASaveFile)));
+    }
+
+    #[test]
+    fn migrations_run_in_order() {
+        fn to_v2(raw: &mut RawSave) -> Result<(), SaveError> {
+            raw.chunks.push((*b"V2  ", vec![raw.version as u8]));
+            Ok(())
+        }
+        fn to_v3(raw: &mut RawSave) -> Result<(), SaveError> {
+            if raw.find(*b"V2  ") != Some(&[1]) {
+                return Err(SaveError::MissingChunk { tag: "V2".to_owned() });
+            }
+            raw.chunks.push((*b"V3  ", vec![raw.version as u8]));
+            Ok(())
+        }
+
+        let mut raw = RawSave::read(written(&world(false, false)).as_slice()).unwrap();
+        raw.migrate(&[to_v2, to_v3]).unwrap();
+        assert_eq!(raw.version, 3);
+        assert_eq!(raw.find(*b"V3  "), Some(&[2][..]));
+
+        let mut raw = RawSave::read(written(&world(false, false)).as_slice()).unwrap();
+        raw.version = 4;
+        assert!(matches!(raw.migrate(&[to_v2, to_v3]), Err(SaveError::UnsupportedVersion { version: 4 })));
+    }
+
+    #[test]
+    fn chunks_are_not_decompressed_past_the_limit() {
+        let bytes = written(&world(false, false));
+        let landscape_length = 6 * 5 * 4;
+        assert!(RawSave::read_limited(bytes.as_slice(), landscape_length).is_ok());
+        match RawSave::read_limited(bytes.as_slice(), landscape_length - 1) {
+            Err(SaveError::Corrupted { tag, reason }) => assert_eq!((tag.as_str(), reason), ("LAND", "chunk is too large")),
+            other => panic!("{:?}", other.map(|raw| raw.version)),
+        }
+    }
+}
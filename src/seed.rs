@@ -0,0 +1,153 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Generation stage which gets its own seed derived from the `WorldSeed`.
+/// Adding a new stage does not change the seeds of the existing ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SeedStage {
+    Continents,
+    LandscapeNoise,
+    InitialCells,
+    Erosion,
+    Resources,
+    Tectonics,
+    Layers,
+}
+impl SeedStage {
+    fn id(&self) -> u64 {
+        match self {
+            SeedStage::Continents => 1,
+            SeedStage::LandscapeNoise => 2,
+            SeedStage::InitialCells => 3,
+            SeedStage::Erosion => 4,
+            SeedStage::Resources => 5,
+            SeedStage::Tectonics => 6,
+            SeedStage::Layers => 7,
+        }
+    }
+}
+
+/// The only source of randomness of a world. Every generation stage takes an
+/// independent sub-seed from it, so the same seed always gives the same world.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct WorldSeed(u64);
+
+impl WorldSeed {
+    pub fn new(value: u64) -> Self {
+        WorldSeed(value)
+    }
+
+    pub fn random() -> Self {
+        WorldSeed(rand::thread_rng().gen())
+    }
+
+    pub fn value(&self) -> u64 {
+        self.0
+    }
+
+    pub fn derive(&self, stage: SeedStage) -> u64 {
+        splitmix64(self.0 ^ splitmix64(stage.id()))
+    }
+
+    pub fn rng(&self, stage: SeedStage) -> StdRng {
+        StdRng::seed_from_u64(self.derive(stage))
+    }
+
+    /// Seed for the `noise` crate generators, which only take `u32`.
+    pub fn noise_seed(&self, stage: SeedStage) -> u32 {
+        let seed = self.derive(stage);
+        (seed ^ (seed >> 32)) as u32
+    }
+}
+
+impl std::fmt::Display for WorldSeed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::str::FromStr for WorldSeed {
+    type Err = std::num::ParseIntError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.trim().parse().map(WorldSeed)
+    }
+}
+
+// https://prng.di.unimi.it/splitmix64.c
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::{Height, Topology};
+    use crate::terrain::continents::{self, ContinentParams};
+    use crate::terrain::generators::{GeneratorKind, TerrainGenerator, TerrainParams};
+
+    const STAGES: [SeedStage; 7] = [
+        SeedStage::Continents,
+        SeedStage::LandscapeNoise,
+        SeedStage::InitialCells,
+        SeedStage::Erosion,
+        SeedStage::Resources,
+        SeedStage::Tectonics,
+        SeedStage::Layers,
+    ];
+
+    /// Everything random of a generator. The continents are smoothed on the GPU after
+    /// the random walk, so only the walk is compared for them
+    fn generate(kind: GeneratorKind, seed: WorldSeed) -> Vec<Height> {
+        let (size, topology) = ((96, 64), Topology { wrap_x: true, wrap_y: false });
+        let terrain = TerrainParams::default();
+        let stage_seed = seed.derive(kind.seed_stage());
+        match kind {
+            GeneratorKind::Continents => {
+                let params = ContinentParams::default();
+                let mut rng = StdRng::seed_from_u64(stage_seed);
+                let centers = continents::continent_centers(size, topology, &params, &mut rng);
+                let mut land = vec![false; (size.0 * size.1) as usize];
+                continents::walk_continents(size, topology, &params, &centers, &mut rng, &mut land);
+                land.iter().map(|is_land| Height::from_num(*is_land as i32)).collect()
+            }
+            GeneratorKind::LayeredNoise => terrain.noise.generate(size, topology, stage_seed).height().to_vec(),
+            GeneratorKind::DiamondSquare => terrain.diamond_square.generate(size, topology, stage_seed).height().to_vec(),
+            GeneratorKind::TectonicPlates => terrain.plates.generate(size, topology, stage_seed).height().to_vec(),
+        }
+    }
+
+    #[test]
+    fn same_seed_gives_same_landscape() {
+        for kind in GeneratorKind::all() {
+            let seed = WorldSeed::new(0x5EED);
+            let first = generate(*kind, seed);
+            assert_eq!(first, generate(*kind, seed), "{:?}", kind);
+            assert_ne!(first, generate(*kind, WorldSeed::new(0x5EED + 1)), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn stages_get_different_seeds() {
+        for value in [0, 1, 0x5EED, u64::MAX] {
+            let seed = WorldSeed::new(value);
+            for (i, first) in STAGES.iter().enumerate() {
+                for second in &STAGES[i + 1..] {
+                    assert_ne!(seed.derive(*first), seed.derive(*second), "{:?} and {:?} of {}", first, second, value);
+                    assert_ne!(seed.noise_seed(*first), seed.noise_seed(*second), "{:?} and {:?} of {}", first, second, value);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn seed_goes_through_text() {
+        let seed = WorldSeed::new(u64::MAX);
+        assert_eq!(seed.to_string().parse::<WorldSeed>(), Ok(seed));
+        assert_eq!(" 42 ".parse::<WorldSeed>(), Ok(WorldSeed::new(42)));
+        assert!("-1".parse::<WorldSeed>().is_err());
+    }
+}
@@ -0,0 +1,418 @@
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::map::{Biome, Height, Landscape, Map, MapCell, MAX_RESOURCES_IN_CELL};
+use super::climate::{self, Climate, ClimateParams};
+use super::continents;
+use super::flood::Lakes;
+use super::rivers::{RiverNetwork, RiverParams};
+
+/// One cell of the Whittaker diagram: `biome` grows where both temperature and
+/// moisture are inside the ranges.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BiomeRule {
+	pub biome: Biome,
+	/// °C
+	pub temperature: (f32, f32),
+	pub moisture: (f32, f32),
+}
+
+impl BiomeRule {
+	fn contains(&self, temperature: f32, moisture: f32) -> bool {
+		temperature >= self.temperature.0 && temperature <= self.temperature.1 &&
+			moisture >= self.moisture.0 && moisture <= self.moisture.1
+	}
+
+	/// Distance to the rule center, with both axes scaled to the rule size
+	fn center_distance(&self, temperature: f32, moisture: f32) -> f32 {
+		let axis = |value: f32, (min, max): (f32, f32)| (value - (min + max) / 2.0) / (max - min).max(f32::EPSILON);
+		let (t, m) = (axis(temperature, self.temperature), axis(moisture, self.moisture));
+		t * t + m * m
+	}
+
+	/// Distance to the closest point of the rule, `0.0` inside of it
+	fn outside_distance(&self, temperature: f32, moisture: f32, temperature_scale: f32) -> f32 {
+		let axis = |value: f32, (min, max): (f32, f32)| (min - value).max(value - max).max(0.0);
+		let t = axis(temperature, self.temperature) / temperature_scale;
+		let m = axis(moisture, self.moisture);
+		t * t + m * m
+	}
+}
+
+/// Whittaker-style lookup from (temperature, moisture) to land biomes.
+///
+/// If several rules contain a point, the one whose center is the closest wins.
+/// If no rule contains it, the closest rule wins. Remaining ties go to the rule
+/// which comes first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BiomeTable {
+	pub rules: Vec<BiomeRule>,
+}
+
+#[derive(Debug)]
+pub enum BiomeTableError {
+	Io { io_error: io::Error },
+	WrongColumnsCount { line: usize, count: usize },
+	UnknownBiome { line: usize, name: String },
+	InvalidNumber { line: usize, value: String },
+	Empty,
+}
+
+impl Display for BiomeTableError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			BiomeTableError::Io { io_error } => write!(f, "Failed to access biome table file: {}", io_error),
+			BiomeTableError::WrongColumnsCount { line, count } => write!(f, "Line {}: expected 5 columns, found {}", line, count),
+			BiomeTableError::UnknownBiome { line, name } => write!(f, "Line {}: unknown biome \"{}\"", line, name),
+			BiomeTableError::InvalidNumber { line, value } => write!(f, "Line {}: \"{}\" is not a number", line, value),
+			BiomeTableError::Empty => write!(f, "Biome table has no rules"),
+		}
+	}
+}
+
+/// Temperature range (°C) which is as wide as the whole moisture range
+const TEMPERATURE_SCALE: f32 = 50.0;
+const TABLE_HEADER: &str = "biome,min_temperature,max_temperature,min_moisture,max_moisture";
+
+impl BiomeTable {
+	pub fn classify(&self, temperature: f32, moisture: f32) -> Option<Biome> {
+		let mut containing = self.rules.iter()
+			.filter(|rule| rule.contains(temperature, moisture))
+			.map(|rule| (rule, rule.center_distance(temperature, moisture)));
+		let mut closest = self.rules.iter()
+			.map(|rule| (rule, rule.outside_distance(temperature, moisture, TEMPERATURE_SCALE)));
+
+		let best = |candidates: &mut dyn Iterator<Item = (&BiomeRule, f32)>| candidates
+			.fold(None, |best: Option<(&BiomeRule, f32)>, (rule, distance)| match best {
+				Some((_, best_distance)) if best_distance <= distance => best,
+				_ => Some((rule, distance)),
+			})
+			.map(|(rule, _)| rule.biome);
+
+		best(&mut containing).or_else(|| best(&mut closest))
+	}
+
+	/// One rule per line: `biome,min_temperature,max_temperature,min_moisture,max_moisture`.
+	/// The first line is a header.
+	pub fn to_text(&self) -> String {
+		let mut text = String::from(TABLE_HEADER);
+		text.push('\n');
+		for rule in &self.rules {
+			text += &format!("{},{},{},{},{}\n", rule.biome.key(),
+							 rule.temperature.0, rule.temperature.1, rule.moisture.0, rule.moisture.1);
+		}
+		text
+	}
+
+	pub fn from_text(text: &str) -> Result<Self, BiomeTableError> {
+		let mut rules = vec![];
+		for (i, line) in text.lines().enumerate() {
+			let line_number = i + 1;
+			let line = line.trim();
+			if line.is_empty() || line.starts_with('#') || line == TABLE_HEADER {
+				continue;
+			}
+
+			let columns: Vec<&str> = line.split(',').map(|column| column.trim()).collect();
+			if columns.len() != 5 {
+				return Err(BiomeTableError::WrongColumnsCount { line: line_number, count: columns.len() });
+			}
+
+			let biome = Biome::from_key(columns[0])
+				.ok_or_else(|| BiomeTableError::UnknownBiome { line: line_number, name: columns[0].to_owned() })?;
+			let number = |value: &str| value.parse::<f32>()
+				.map_err(|_| BiomeTableError::InvalidNumber { line: line_number, value: value.to_owned() });
+
+			rules.push(BiomeRule {
+				biome,
+				temperature: (number(columns[1])?, number(columns[2])?),
+				moisture: (number(columns[3])?, number(columns[4])?),
+			});
+		}
+
+		if rules.is_empty() {
+			return Err(BiomeTableError::Empty);
+		}
+		Ok(BiomeTable { rules })
+	}
+
+	pub fn save(&self, path: impl AsRef<Path>) -> Result<(), BiomeTableError> {
+		fs::write(path, self.to_text()).map_err(|io_error| BiomeTableError::Io { io_error })
+	}
+
+	pub fn load(path: impl AsRef<Path>) -> Result<Self, BiomeTableError> {
+		let text = fs::read_to_string(path).map_err(|io_error| BiomeTableError::Io { io_error })?;
+		Self::from_text(&text)
+	}
+}
+
+impl Default for BiomeTable {
+	fn default() -> Self {
+		let rule = |biome: Biome, temperature: (f32, f32), moisture: (f32, f32)| BiomeRule { biome, temperature, moisture };
+		BiomeTable {
+			rules: vec![
+				rule(Biome::SnowyMountain, (-100.0, -5.0), (0.0, 1.0)),
+				rule(Biome::Desert, (18.0, 100.0), (0.0, 0.25)),
+				rule(Biome::Plains, (-5.0, 100.0), (0.15, 0.6)),
+				rule(Biome::Forest, (0.0, 35.0), (0.5, 1.0)),
+				rule(Biome::Swamp, (15.0, 100.0), (0.85, 1.0)),
+			],
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BiomeParams {
+	pub sea_level: Height,
+	/// Water at most this deep is a shoal
+	pub shoal_depth: Height,
+	/// Land at most this high above the sea level and close to it is a beach
+	pub beach_height: Height,
+	/// Maximum distance (in cells) from a beach to the sea
+	pub beach_distance: f32,
+	/// Heights above the sea level
+	pub mountain_height: Height,
+	pub snow_height: Height,
+	/// Mountains colder than this are snowy even below `snow_height`, °C
+	pub snow_temperature: f32,
+	/// Biggest height difference with a side neighbour, above which land is scree
+	pub scree_slope: Height,
+	/// Land which received at least this much material from thermal erosion is scree,
+	/// see `thermal::scree_mask`
+	pub scree_deposit: f32,
+	/// Rivers of at least this Strahler order are shoals
+	pub shoal_river_order: u8,
+	/// Forests need at least this fertility (see `TerrainLayers::fertility`), otherwise they are plains
+	pub forest_fertility: f32,
+	pub table: BiomeTable,
+}
+
+impl Default for BiomeParams {
+	fn default() -> Self {
+		// Same levels as `render.glsl` uses
+		BiomeParams {
+			sea_level: Height::from_num(crate::map::DEFAULT_SEA_LEVEL),
+			shoal_depth: Height::from_num(0.05),
+			beach_height: Height::from_num(0.03),
+			beach_distance: 3.0,
+			mountain_height: Height::from_num(0.47),
+			snow_height: Height::from_num(0.56),
+			snow_temperature: -10.0,
+			scree_slope: Height::from_num(0.06),
+			scree_deposit: 0.01,
+			shoal_river_order: 4,
+			forest_fertility: 0.3,
+			table: BiomeTable::default(),
+		}
+	}
+}
+
+/// Optional layers which make the classification more precise
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BiomeLayers<'a> {
+	/// If there is none, the default climate of the landscape is used
+	pub climate: Option<&'a Climate>,
+	pub rivers: Option<&'a RiverNetwork>,
+	pub lakes: Option<&'a Lakes>,
+	/// Soil fertility of each cell, everything is fertile if there is none
+	pub fertility: Option<&'a [f32]>,
+	/// Scree piled up by thermal erosion, see `thermal::scree_mask`. If there is none,
+	/// scree is told by the slope only
+	pub scree: Option<&'a [bool]>,
+}
+
+/// Classifies every cell of the landscape. Checks go from the most specific to
+/// the most general: water, mountains, scree, beaches, swamps near rivers and
+/// finally the climate lookup limited by the soil fertility, so the result does
+/// not depend on anything but the inputs.
+pub fn classify_biomes(landscape: &Landscape, layers: BiomeLayers, params: &BiomeParams) -> Map {
+	let size = landscape.size();
+	let (width, height) = (size.0 as i32, size.1 as i32);
+	let heights = landscape.height();
+	let is_sea: Box<[bool]> = heights.iter().map(|height| *height <= params.sea_level).collect();
+
+	let default_climate;
+	let climate = match layers.climate {
+		Some(climate) => climate,
+		None => {
+			let climate_params = ClimateParams { sea_level: params.sea_level, ..Default::default() };
+			default_climate = climate::compute_climate(landscape, &climate_params);
+			&default_climate
+		}
+	};
+
+	let sea_distance = continents::distance_transform((size.0 as usize, size.1 as usize), landscape.topology(), &is_sea);
+	let swamps = layers.rivers.map(|rivers| {
+		let river_params = RiverParams { sea_level: params.sea_level, ..Default::default() };
+		rivers.swamp_mask(landscape, &river_params)
+	});
+
+	let topology = landscape.topology();
+	let slope = |x: i32, y: i32| {
+		let center = heights[(y * width + x) as usize];
+		[(-1, 0), (1, 0), (0, -1), (0, 1)].iter()
+			.filter_map(|(dx, dy)| topology.cell(size, (x + dx) as i64, (y + dy) as i64))
+			.map(|(nx, ny)| (center - heights[ny as usize * size.0 as usize + nx as usize]).abs())
+			.max()
+			.unwrap_or(Height::ZERO)
+	};
+
+	let mut cells: Vec<MapCell> = Vec::with_capacity(heights.len());
+	for y in 0..height {
+		for x in 0..width {
+			let id = (y * width + x) as usize;
+			let cell_height = heights[id];
+			let altitude = cell_height.saturating_sub(params.sea_level);
+			let lake = layers.lakes.and_then(|lakes| lakes.lake_at(x as u32, y as u32));
+			let river_order = layers.rivers.map_or(0, |rivers| rivers.order()[id]);
+
+			let biome = if is_sea[id] {
+				if params.sea_level - cell_height <= params.shoal_depth { Biome::Shoal } else { Biome::Sea }
+			} else if let Some(lake) = lake {
+				if lake.spill_height - cell_height <= params.shoal_depth { Biome::Shoal } else { Biome::Sea }
+			} else if river_order >= params.shoal_river_order.max(1) {
+				Biome::Shoal
+			} else if altitude >= params.snow_height ||
+				(altitude >= params.mountain_height && climate.temperature()[id] <= params.snow_temperature) {
+				Biome::SnowyMountain
+			} else if altitude >= params.mountain_height {
+				Biome::Mountain
+			} else if layers.scree.is_some_and(|scree| scree[id]) || slope(x, y) >= params.scree_slope {
+				Biome::Scree
+			} else if altitude <= params.beach_height && sea_distance[id] <= params.beach_distance {
+				Biome::Beach
+			} else if swamps.as_ref().is_some_and(|swamps| swamps[id]) {
+				Biome::Swamp
+			} else {
+				let fertility = layers.fertility.map_or(1.0, |fertility| fertility[id]);
+				match params.table.classify(climate.temperature()[id], climate.moisture()[id]) {
+					Some(Biome::Forest) if fertility < params.forest_fertility => Biome::Plains,
+					biome => biome.unwrap_or(Biome::Plains),
+				}
+			};
+
+			cells.push((biome, [None; MAX_RESOURCES_IN_CELL]));
+		}
+	}
+
+	Map::new(size, cells.into_boxed_slice())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::terrain::flood;
+	use crate::terrain::rivers;
+	use crate::map::Topology;
+	use crate::terrain::thermal::{self, ThermalParams};
+
+	/// Slopes up to the east with a bump in the middle, so there are sea, shoals, land and mountains
+	fn landscape() -> Landscape {
+		let (width, height) = (64, 48);
+		let heights = (0..height)
+			.flat_map(|y| (0..width).map(move |x| (x, y)))
+			.map(|(x, y)| {
+				let (dx, dy) = (x as f32 - 40.0, y as f32 - 24.0);
+				Height::from_num(x as f32 / width as f32 * 0.8 + 0.3 * (-(dx * dx + dy * dy) / 100.0).exp())
+			})
+			.collect();
+		Landscape::new((width, height), heights)
+	}
+
+	#[test]
+	fn below_sea_level_is_water() {
+		let landscape = landscape();
+		let params = BiomeParams::default();
+		let map = classify_biomes(&landscape, BiomeLayers::default(), &params);
+
+		assert!(landscape.height().iter().any(|height| *height <= params.sea_level));
+		for (cell, height) in map.cells().iter().zip(landscape.height()) {
+			if *height <= params.sea_level {
+				assert!(matches!(cell.0, Biome::Sea | Biome::Shoal), "{:?} at {}", cell.0, height);
+			}
+		}
+	}
+
+	#[test]
+	fn thermal_scree_is_scree() {
+		let mut landscape = landscape();
+		let params = BiomeParams::default();
+		let thermal = ThermalParams { talus_angle: 20.0, iterations: 8, ..Default::default() };
+		let deposits = thermal::thermal_erosion(&mut landscape, &thermal);
+		let scree = thermal::scree_mask(&deposits, params.scree_deposit);
+		let layers = BiomeLayers { scree: Some(&scree), ..Default::default() };
+
+		let by_slope = classify_biomes(&landscape, BiomeLayers::default(), &params);
+		let map = classify_biomes(&landscape, layers, &params);
+		let is_land = |id: usize| landscape.height()[id] > params.sea_level && landscape.height()[id] - params.sea_level < params.mountain_height;
+		let moved: Vec<usize> = (0..scree.len()).filter(|id| scree[*id] && is_land(*id)).collect();
+		assert!(moved.iter().any(|id| by_slope.cells()[*id].0 != Biome::Scree));
+		for id in moved {
+			assert_eq!(map.cells()[id].0, Biome::Scree);
+		}
+	}
+
+	#[test]
+	fn slope_wraps_around_the_edges() {
+		// Flat land with a cliff only across the wrapped edge
+		let (width, height) = (16, 8);
+		let heights = (0..width * height)
+			.map(|id| Height::from_num(if id % width < width / 2 { 0.6 } else { 0.6 + (id % width - width / 2) as f32 * 0.01 }))
+			.collect();
+		let flat = Landscape::new((width, height), heights);
+		let wrapped = flat.clone().with_topology(Topology { wrap_x: true, wrap_y: false });
+		let params = BiomeParams::default();
+
+		let is_scree = |landscape: &Landscape, x: u32| classify_biomes(landscape, BiomeLayers::default(), &params).cells()[(x + width) as usize].0 == Biome::Scree;
+		assert!(!is_scree(&flat, 0));
+		assert!(!is_scree(&flat, width - 1));
+		assert!(is_scree(&wrapped, 0));
+		assert!(is_scree(&wrapped, width - 1));
+	}
+
+	#[test]
+	fn ties_go_to_the_first_rule() {
+		let rule = |biome: Biome| BiomeRule { biome, temperature: (0.0, 20.0), moisture: (0.0, 1.0) };
+		let table = BiomeTable { rules: vec![rule(Biome::Forest), rule(Biome::Plains)] };
+		let reversed = BiomeTable { rules: vec![rule(Biome::Plains), rule(Biome::Forest)] };
+		for (temperature, moisture) in [(10.0, 0.5), (0.0, 0.0), (50.0, 0.5), (-50.0, 2.0)] {
+			assert_eq!(table.classify(temperature, moisture), Some(Biome::Forest));
+			assert_eq!(reversed.classify(temperature, moisture), Some(Biome::Plains));
+		}
+
+		// Containing rules with centers at the same distance, and the same outside of both
+		let table = BiomeTable {
+			rules: vec![
+				BiomeRule { biome: Biome::Desert, temperature: (0.0, 20.0), moisture: (0.0, 0.5) },
+				BiomeRule { biome: Biome::Swamp, temperature: (0.0, 20.0), moisture: (0.5, 1.0) },
+			],
+		};
+		assert_eq!(table.classify(10.0, 0.5), Some(Biome::Desert));
+		assert_eq!(table.classify(30.0, 0.5), Some(Biome::Desert));
+	}
+
+	#[test]
+	fn same_inputs_give_same_map() {
+		let landscape = landscape();
+		let params = BiomeParams::default();
+		let lakes = flood::detect_lakes(&landscape, params.sea_level);
+		let river_params = RiverParams { sea_level: params.sea_level, ..Default::default() };
+		let rivers = rivers::extract_rivers(&landscape, &river_params, None);
+		let fertility: Box<[f32]> = (0..landscape.height().len()).map(|id| (id % 7) as f32 / 6.0).collect();
+		let scree: Box<[bool]> = (0..landscape.height().len()).map(|id| id % 5 == 0).collect();
+		let layers = BiomeLayers {
+			climate: None,
+			rivers: Some(&rivers),
+			lakes: Some(&lakes),
+			fertility: Some(&fertility),
+			scree: Some(&scree),
+		};
+
+		let first = classify_biomes(&landscape, layers, &params);
+		let second = classify_biomes(&landscape, layers, &params);
+		assert_eq!(first.size(), second.size());
+		assert!(first.cells() == second.cells());
+	}
+}
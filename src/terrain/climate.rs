@@ -0,0 +1,354 @@
+use std::f32::consts::FRAC_PI_2;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::map::{Height, Landscape};
+use super::continents;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClimateParams {
+	/// Temperature at the sea level on the equator, °C
+	pub equator_temperature: f32,
+	/// Temperature at the sea level on the poles, °C
+	pub pole_temperature: f32,
+	/// Position of the equator, from `0.0` (top row of the map) to `1.0` (bottom row)
+	pub equator: f32,
+	/// Temperature drop per `1.0` of height above the sea level, °C
+	pub lapse_rate: f32,
+	pub sea_level: Height,
+	/// Prevailing wind direction, does not have to be normalised
+	pub wind: (f32, f32),
+	/// Air humidity (`0.0..=1.0`) of the wind coming from beyond the map edges which do not wrap
+	pub border_humidity: f32,
+	/// Share of the missing humidity the air picks up over each sea cell
+	pub evaporation: f32,
+	/// Share of the air humidity which falls as rain over each land cell
+	pub rain_rate: f32,
+	/// Extra rain caused by the air rising over slopes (per `1.0` of height per cell)
+	pub orographic_rain: f32,
+	/// Distance (in cells) at which the sea influence on moisture falls `e` times
+	pub coast_distance: f32,
+	/// Share of the moisture which depends only on the distance to the sea, the rest comes from rain
+	pub coast_influence: f32,
+}
+
+impl Default for ClimateParams {
+	fn default() -> Self {
+		ClimateParams {
+			equator_temperature: 30.0,
+			pole_temperature: -20.0,
+			equator: 0.5,
+			lapse_rate: 40.0,
+			sea_level: Height::from_num(crate::map::DEFAULT_SEA_LEVEL),
+			wind: (1.0, 0.0),
+			border_humidity: 0.8,
+			evaporation: 0.1,
+			rain_rate: 0.02,
+			orographic_rain: 2.0,
+			coast_distance: 16.0,
+			coast_influence: 0.3,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClimateField {
+	Temperature,
+	Moisture,
+	Precipitation,
+}
+
+impl ClimateField {
+	pub fn all() -> &'static [ClimateField] {
+		&[ClimateField::Temperature, ClimateField::Moisture, ClimateField::Precipitation]
+	}
+
+	pub fn localized_name(&self) -> &'static str {
+		match self {
+			ClimateField::Temperature => "Temperature",
+			ClimateField::Moisture => "Moisture",
+			ClimateField::Precipitation => "Precipitation",
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClimateSample {
+	pub temperature: f32,
+	pub moisture: f32,
+	pub precipitation: f32,
+}
+
+/// Per-cell climate of a landscape, same layout as `Landscape::height`.
+#[derive(Debug, Clone)]
+pub struct Climate {
+	size: (u32, u32),
+	/// °C
+	temperature: Box<[f32]>,
+	/// `0.0` - desert, `1.0` - always wet
+	moisture: Box<[f32]>,
+	/// Rain brought by the prevailing wind, in the same units as air humidity
+	precipitation: Box<[f32]>,
+}
+
+impl Climate {
+	pub fn size(&self) -> (u32, u32) {
+		self.size
+	}
+
+	pub fn temperature(&self) -> &[f32] {
+		&self.temperature
+	}
+
+	pub fn moisture(&self) -> &[f32] {
+		&self.moisture
+	}
+
+	pub fn precipitation(&self) -> &[f32] {
+		&self.precipitation
+	}
+
+	pub fn field(&self, field: ClimateField) -> &[f32] {
+		match field {
+			ClimateField::Temperature => &self.temperature,
+			ClimateField::Moisture => &self.moisture,
+			ClimateField::Precipitation => &self.precipitation,
+		}
+	}
+
+	pub fn at(&self, x: u32, y: u32) -> ClimateSample {
+		let id = (y * self.size.0 + x) as usize;
+		ClimateSample {
+			temperature: self.temperature[id],
+			moisture: self.moisture[id],
+			precipitation: self.precipitation[id],
+		}
+	}
+
+	/// Writes `x,y,temperature,moisture,precipitation` rows with a header
+	pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+		writeln!(writer, "x,y,temperature,moisture,precipitation")?;
+		for y in 0..self.size.1 {
+			for x in 0..self.size.0 {
+				let sample = self.at(x, y);
+				writeln!(writer, "{},{},{},{},{}", x, y, sample.temperature, sample.moisture, sample.precipitation)?;
+			}
+		}
+		Ok(())
+	}
+
+	/// Grayscale image of the field, stretched from its minimum (black) to its maximum (white)
+	pub fn field_image(&self, field: ClimateField) -> image::GrayImage {
+		let values = self.field(field);
+		let min = values.iter().copied().fold(f32::INFINITY, f32::min);
+		let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+		let range = if max > min { max - min } else { 1.0 };
+
+		image::GrayImage::from_fn(self.size.0, self.size.1, |x, y| {
+			let value = values[(y * self.size.0 + x) as usize];
+			image::Luma([((value - min) / range * 255.0).round() as u8])
+		})
+	}
+
+	pub fn export_png(&self, field: ClimateField, path: impl AsRef<Path>) -> image::ImageResult<()> {
+		self.field_image(field).save_with_format(path, image::ImageFormat::Png)
+	}
+}
+
+/// Computes temperature from latitude and altitude, and moisture from the distance
+/// to the sea and the rain brought by the prevailing wind. Air loses its humidity
+/// while rising over mountains, so the leeward side of a ridge gets less rain.
+pub fn compute_climate(landscape: &Landscape, params: &ClimateParams) -> Climate {
+	let size = landscape.size();
+	let (width, height) = (size.0 as i32, size.1 as i32);
+	let heights = landscape.height();
+	let is_sea: Box<[bool]> = heights.iter().map(|height| *height <= params.sea_level).collect();
+	let altitude = |id: usize| (heights[id] - params.sea_level).to_num::<f32>().max(0.0);
+
+	// Temperature
+	let equator = params.equator.clamp(0.0, 1.0);
+	let max_latitude = equator.max(1.0 - equator).max(f32::EPSILON);
+	let mut temperature = vec![0.0; heights.len()].into_boxed_slice();
+	for y in 0..height {
+		let row = if height > 1 { y as f32 / (height - 1) as f32 } else { equator };
+		let latitude = ((row - equator).abs() / max_latitude).min(1.0);
+		let sea_level_temperature = params.pole_temperature +
+			(params.equator_temperature - params.pole_temperature) * (latitude * FRAC_PI_2).cos();
+
+		for x in 0..width {
+			let id = (y * width + x) as usize;
+			temperature[id] = sea_level_temperature - params.lapse_rate * altitude(id);
+		}
+	}
+
+	// Wind advection. Each cell gets air from the point one cell upwind, which lies
+	// between a side and a diagonal neighbour, so cells are processed along the wind.
+	let wind_length = (params.wind.0 * params.wind.0 + params.wind.1 * params.wind.1).sqrt();
+	let wind = if wind_length > 0.0 { (params.wind.0 / wind_length, params.wind.1 / wind_length) } else { (1.0, 0.0) };
+	let (side, diagonal, to_diagonal) = {
+		let (sx, sy) = (-wind.0.signum() as i32, -wind.1.signum() as i32);
+		if wind.0.abs() >= wind.1.abs() {
+			((sx, 0), (sx, sy), wind.1.abs() / wind.0.abs())
+		} else {
+			((0, sy), (sx, sy), wind.0.abs() / wind.1.abs())
+		}
+	};
+
+	let mut order: Vec<usize> = (0..heights.len()).collect();
+	let projection = |id: usize| (id as i32 % width) as f32 * wind.0 + (id as i32 / width) as f32 * wind.1;
+	order.sort_by(|a, b| projection(*a).total_cmp(&projection(*b)).then(a.cmp(b)));
+
+	// Across a wrapped edge the air comes from the other side of the map, which is
+	// processed later. The first pass takes the border humidity there, and the next
+	// ones the humidity of the previous pass, until it settles.
+	const MAX_PASSES: usize = 32;
+	const SETTLED: f32 = 1e-4;
+	let topology = landscape.topology();
+	let passes = if topology.is_flat() { 1 } else { MAX_PASSES };
+	let mut humidity = vec![params.border_humidity; heights.len()].into_boxed_slice();
+	let mut precipitation = vec![0.0_f32; heights.len()].into_boxed_slice();
+	for _ in 0..passes {
+		let mut largest_change = 0.0_f32;
+		for id in order.iter().copied() {
+			let (x, y) = (id as i32 % width, id as i32 / width);
+			let upwind = |(dx, dy): (i32, i32)| topology.cell(size, (x + dx) as i64, (y + dy) as i64)
+				.map(|(nx, ny)| ny as usize * width as usize + nx as usize);
+			let humidity_from = |cell: Option<usize>| cell.map_or(params.border_humidity, |cell| humidity[cell]);
+			let altitude_from = |cell: Option<usize>| cell.map_or(altitude(id), &altitude);
+
+			let (side, diagonal) = (upwind(side), upwind(diagonal));
+			let mut air = humidity_from(side) * (1.0 - to_diagonal) + humidity_from(diagonal) * to_diagonal;
+			if is_sea[id] {
+				air += (1.0 - air) * params.evaporation;
+			} else {
+				let upwind_altitude = altitude_from(side) * (1.0 - to_diagonal) + altitude_from(diagonal) * to_diagonal;
+				let rise = (altitude(id) - upwind_altitude).max(0.0);
+				let rain = (air * (params.rain_rate + params.orographic_rain * rise)).min(air);
+				precipitation[id] = rain;
+				air -= rain;
+			}
+			let air = air.clamp(0.0, 1.0);
+			largest_change = largest_change.max((air - humidity[id]).abs());
+			humidity[id] = air;
+		}
+		if largest_change < SETTLED {
+			break;
+		}
+	}
+
+	// Moisture
+	let coast_distance = continents::distance_transform((size.0 as usize, size.1 as usize), landscape.topology(), &is_sea);
+	let coast_influence = params.coast_influence.clamp(0.0, 1.0);
+	let moisture = (0..heights.len())
+		.map(|id| {
+			if is_sea[id] {
+				return 1.0;
+			}
+			let near_sea = (-coast_distance[id] / params.coast_distance.max(f32::EPSILON)).exp();
+			let rain = if params.rain_rate > 0.0 { (precipitation[id] / params.rain_rate).min(1.0) } else { 0.0 };
+			coast_influence * near_sea + (1.0 - coast_influence) * rain
+		})
+		.collect();
+
+	Climate { size, temperature, moisture, precipitation }
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::map::Topology;
+	use super::*;
+	use super::super::generators::{LayeredNoiseParams, TerrainGenerator};
+
+	/// Landscape moved by `shift` cells across its wrapped edges
+	fn roll(landscape: &Landscape, shift: (u32, u32)) -> Landscape {
+		let (width, height) = landscape.size();
+		let heights = (0..width * height)
+			.map(|id| {
+				let (x, y) = ((id % width + width - shift.0) % width, (id / width + height - shift.1) % height);
+				landscape.height()[(y * width + x) as usize]
+			})
+			.collect();
+		Landscape::new((width, height), heights).with_topology(landscape.topology())
+	}
+
+	/// Sea in the west, flat land in the east and a north-south ridge at `ridge` if it is set
+	fn coast(ridge: Option<u32>) -> Landscape {
+		let size = (48_u32, 8_u32);
+		let heights = (0..size.0 * size.1)
+			.map(|id| {
+				let x = id % size.0;
+				let height = match ridge {
+					_ if x < 8 => 0.2,
+					Some(ridge) if x.abs_diff(ridge) <= 1 => 0.9,
+					_ => 0.5,
+				};
+				Height::from_num(height)
+			})
+			.collect();
+		Landscape::new(size, heights)
+	}
+
+	#[test]
+	fn lee_side_is_drier() {
+		let params = ClimateParams { wind: (1.0, 0.0), ..Default::default() };
+		let ridge = 24;
+		let flat = compute_climate(&coast(None), &params);
+		let climate = compute_climate(&coast(Some(ridge)), &params);
+		let width = coast(None).size().0;
+		let rain = |climate: &Climate, xs: std::ops::Range<u32>| xs.map(|x| climate.precipitation()[(4 * width + x) as usize]).sum::<f32>();
+
+		let windward = rain(&climate, ridge - 6..ridge - 1);
+		let lee = rain(&climate, ridge + 2..ridge + 7);
+		assert!(lee < windward, "{lee} on the lee side, {windward} on the windward one");
+		assert!(lee < rain(&flat, ridge + 2..ridge + 7));
+		assert!(rain(&climate, ridge - 1..ridge + 2) > rain(&flat, ridge - 1..ridge + 2));
+		// The other way round the shadow moves to the other side
+		let reversed = compute_climate(&coast(Some(ridge)), &ClimateParams { wind: (-1.0, 0.0), ..params.clone() });
+		assert!(rain(&reversed, ridge - 6..ridge - 1) < rain(&reversed, ridge + 2..ridge + 7));
+	}
+
+	#[test]
+	fn temperature_falls_with_latitude_and_altitude() {
+		let params = ClimateParams { equator: 0.5, ..Default::default() };
+		let size = (4, 21);
+		let low = Landscape::new(size, vec![params.sea_level; 4 * 21].into_boxed_slice());
+		let high_level = params.sea_level + Height::from_num(0.25);
+		let high = Landscape::new(size, vec![high_level; 4 * 21].into_boxed_slice());
+		let (low, high) = (compute_climate(&low, &params), compute_climate(&high, &params));
+		let row = |climate: &Climate, y: u32| climate.temperature()[(y * size.0) as usize];
+
+		assert!((row(&low, 10) - params.equator_temperature).abs() < 1e-4);
+		assert!((row(&low, 0) - params.pole_temperature).abs() < 1e-4);
+		assert!((row(&low, 20) - params.pole_temperature).abs() < 1e-4);
+		for y in 0..10 {
+			assert!(row(&low, y) < row(&low, y + 1), "row {y}");
+			assert!(row(&low, 20 - y) < row(&low, 19 - y), "row {}", 20 - y);
+		}
+		for y in 0..size.1 {
+			let drop = row(&low, y) - row(&high, y);
+			assert!((drop - params.lapse_rate * 0.25).abs() < 1e-3, "row {y}: {drop}");
+		}
+	}
+
+	#[test]
+	fn wrapped_map_has_no_seams() {
+		let params = ClimateParams { wind: (1.0, 0.4), ..Default::default() };
+		for (topology, shift) in [
+			(Topology { wrap_x: true, wrap_y: false }, (37, 0)),
+			(Topology { wrap_x: true, wrap_y: true }, (37, 21)),
+		] {
+			let landscape = LayeredNoiseParams::default().generate((96, 64), topology, 5);
+			let climate = compute_climate(&landscape, &params);
+			let rolled = compute_climate(&roll(&landscape, shift), &params);
+			// Moving the map across its seams moves the climate with it
+			let (width, height) = landscape.size();
+			for id in 0..(width * height) {
+				let (x, y) = ((id % width + shift.0) % width, (id / width + shift.1) % height);
+				let rolled_id = (y * width + x) as usize;
+				let id = id as usize;
+				assert!((climate.precipitation()[id] - rolled.precipitation()[rolled_id]).abs() < 1e-3, "{topology:?} at {id}");
+				assert!((climate.moisture()[id] - rolled.moisture()[rolled_id]).abs() < 1e-3, "{topology:?} at {id}");
+			}
+		}
+	}
+}
@@ -0,0 +1,329 @@
+use rand::Rng;
+use rand::rngs::StdRng;
+
+use crate::map::Topology;
+use super::generate_shape;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContinentPlacement {
+	/// Continents are placed row by row on a regular grid
+	Grid { columns: u32 },
+	Random,
+	/// Random positions, but no closer than `min_distance` cells to each other (if possible).
+	/// Distances are measured across the wrapped edges
+	PoissonDisk { min_distance: f32 },
+}
+
+impl ContinentPlacement {
+	pub fn localized_name(&self) -> &'static str {
+		match self {
+			ContinentPlacement::Grid { .. } => "Grid",
+			ContinentPlacement::Random => "Random",
+			ContinentPlacement::PoissonDisk { .. } => "Poisson disk",
+		}
+	}
+}
+
+/// Settings of the "Brownian movement" continents generator (`terrain::generate_map`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContinentParams {
+	pub count: u32,
+	pub placement: ContinentPlacement,
+	/// Random walk length of each continent is picked from this range (inclusive)
+	pub walk_length: (u32, u32),
+	/// If set, continents keep growing until this share of the map is land.
+	/// Sea areas enclosed by land are filled afterwards, so the result can be a bit higher
+	pub land_fraction: Option<f32>,
+	/// Height of the land right at the coastline
+	pub coast_height: f32,
+	/// Height which the land approaches far away from the coastline
+	pub max_height: f32,
+	/// Distance from the coastline (in cells) at which the land gets ~63% of the way
+	/// from `coast_height` to `max_height`
+	pub coast_falloff: f32,
+}
+
+impl Default for ContinentParams {
+	fn default() -> Self {
+		ContinentParams {
+			count: 4,
+			placement: ContinentPlacement::Grid { columns: 5 },
+			walk_length: (1, 100000),
+			land_fraction: None,
+			coast_height: 0.625,
+			max_height: 1.0,
+			coast_falloff: 16.0,
+		}
+	}
+}
+
+impl ContinentParams {
+	/// Height of a land cell which is `distance` cells away from the nearest sea cell
+	pub fn land_height(&self, distance: f32) -> f32 {
+		let falloff = self.coast_falloff.max(f32::EPSILON);
+		self.coast_height + (self.max_height - self.coast_height) * (1.0 - (-distance / falloff).exp())
+	}
+}
+
+pub fn continent_centers(size: (u64, u64), topology: Topology, params: &ContinentParams, rng: &mut StdRng) -> Vec<(u64, u64)> {
+	let mut centers = Vec::with_capacity(params.count as usize);
+
+	for i in 0..params.count {
+		let (x, y) = match params.placement {
+			ContinentPlacement::Grid { columns } => {
+				let columns = columns.max(1) as u64;
+				let rows = (params.count as u64).div_ceil(columns);
+				let i = i as u64;
+				(((i % columns) + 1) * size.0 / (columns + 1), ((i / columns) + 1) * size.1 / (rows + 1))
+			}
+			ContinentPlacement::Random => (rng.gen_range(0..size.0), rng.gen_range(0..size.1)),
+			ContinentPlacement::PoissonDisk { min_distance } => {
+				const MAX_ATTEMPTS: u32 = 30;
+				let sqr_min_distance = min_distance * min_distance;
+				let map_size = (size.0 as u32, size.1 as u32);
+				let is_far_enough = |(x, y): (u64, u64)| centers.iter().all(|(cx, cy): &(u64, u64)| {
+					let (dx, dy) = topology.delta(map_size, (x as f32, y as f32), (*cx as f32, *cy as f32));
+					dx * dx + dy * dy >= sqr_min_distance
+				});
+
+				// If the map is too small for such distance, the last candidate is taken anyway
+				let mut candidate = (rng.gen_range(0..size.0), rng.gen_range(0..size.1));
+				for _ in 1..MAX_ATTEMPTS {
+					if is_far_enough(candidate) {
+						break;
+					}
+					candidate = (rng.gen_range(0..size.0), rng.gen_range(0..size.1));
+				}
+				candidate
+			}
+		};
+
+		centers.push((x.min(size.0 - 1), y.min(size.1 - 1)));
+	}
+
+	centers
+}
+
+/// Grows continents from their centers with random walks and marks visited cells as land.
+/// Walkers move in turns, so with `land_fraction` set no continent takes all the land.
+pub fn walk_continents(size: (u64, u64), topology: Topology, params: &ContinentParams, centers: &[(u64, u64)], rng: &mut StdRng, land: &mut [bool]) {
+	const WALK_CHUNK: u32 = 1024;
+	const MAX_EXTRA_ROUNDS: u32 = 64;
+
+	let map_area = (size.0 * size.1) as usize;
+	let target_land = params.land_fraction
+		.map(|fraction| (fraction.clamp(0.0, 1.0) as f64 * map_area as f64) as usize);
+	let (min_length, max_length) = (params.walk_length.0.min(params.walk_length.1), params.walk_length.0.max(params.walk_length.1));
+
+	let mut land_cells = land.iter().filter(|is_land| **is_land).count();
+	let mut walkers: Vec<((u64, u64), u32)> = centers.iter()
+		.map(|pos| (*pos, rng.gen_range(min_length..=max_length)))
+		.collect();
+
+	let mut extra_rounds = 0;
+	loop {
+		while walkers.iter().any(|(_, steps_left)| *steps_left > 0) {
+			for (pos, steps_left) in walkers.iter_mut() {
+				let steps = (*steps_left).min(WALK_CHUNK);
+				*steps_left -= steps;
+				*pos = generate_shape(size, topology, land, steps, *pos, rng, &mut land_cells);
+
+				if target_land.is_some_and(|target| land_cells >= target) {
+					return;
+				}
+			}
+		}
+
+		// Walks are over, but there is not enough land yet
+		match target_land {
+			Some(target) if land_cells < target && extra_rounds < MAX_EXTRA_ROUNDS && !walkers.is_empty() => {
+				extra_rounds += 1;
+				for (_, steps_left) in walkers.iter_mut() {
+					*steps_left = rng.gen_range(min_length..=max_length);
+				}
+			}
+			_ => return,
+		}
+	}
+}
+
+/// Euclidean distance from each cell to the nearest cell with `is_target` set, also
+/// across the wrapped edges. If there are no such cells, distance is `f32::INFINITY`.
+///
+/// Felzenszwalb & Huttenlocher, "Distance Transforms of Sampled Functions".
+pub fn distance_transform(size: (usize, usize), topology: Topology, is_target: &[bool]) -> Box<[f32]> {
+	const INF: f64 = 1e20;
+	let (width, height) = size;
+	let mut sqr_dist: Box<[f64]> = is_target.iter()
+		.map(|is_target| if *is_target { 0.0 } else { INF })
+		.collect();
+
+	// A wrapped line is repeated three times, the middle copy is the result
+	let longest = width.max(height) * 3;
+	let mut line = vec![0.0; longest];
+	let mut result = vec![0.0; longest];
+	let mut hull = vec![0_usize; longest];
+	let mut bounds = vec![0.0; longest + 1];
+	let mut transform_line = |len: usize, wrap: bool, get: &dyn Fn(usize) -> f64, set: &mut dyn FnMut(usize, f64)| {
+		let (copies, offset) = if wrap { (3, len) } else { (1, 0) };
+		for (i, value) in line[..len * copies].iter_mut().enumerate() {
+			*value = get(i % len);
+		}
+		distance_transform_1d(&line[..len * copies], &mut result[..len * copies], &mut hull, &mut bounds);
+		for i in 0..len {
+			set(i, result[offset + i]);
+		}
+	};
+
+	let mut rows = sqr_dist.to_vec();
+	for y in 0..height {
+		let row = &sqr_dist[y * width..(y + 1) * width];
+		transform_line(width, topology.wrap_x, &|x| row[x], &mut |x, value| rows[y * width + x] = value);
+	}
+
+	for x in 0..width {
+		transform_line(height, topology.wrap_y, &|y| rows[y * width + x], &mut |y, value| sqr_dist[y * width + x] = value);
+	}
+
+	sqr_dist.iter()
+		.map(|sqr_dist| if *sqr_dist >= INF { f32::INFINITY } else { sqr_dist.sqrt() as f32 })
+		.collect()
+}
+
+fn distance_transform_1d(func: &[f64], result: &mut [f64], hull: &mut [usize], bounds: &mut [f64]) {
+	let n = func.len();
+	if n == 0 {
+		return;
+	}
+
+	let intersection = |q: usize, p: usize| {
+		((func[q] + (q * q) as f64) - (func[p] + (p * p) as f64)) / (2.0 * q as f64 - 2.0 * p as f64)
+	};
+
+	let mut k = 0;
+	hull[0] = 0;
+	bounds[0] = f64::NEG_INFINITY;
+	bounds[1] = f64::INFINITY;
+
+	for q in 1..n {
+		let mut s = intersection(q, hull[k]);
+		while s <= bounds[k] {
+			k -= 1;
+			s = intersection(q, hull[k]);
+		}
+		k += 1;
+		hull[k] = q;
+		bounds[k] = s;
+		bounds[k + 1] = f64::INFINITY;
+	}
+
+	k = 0;
+	for (q, result) in result.iter_mut().enumerate().take(n) {
+		while bounds[k + 1] < q as f64 {
+			k += 1;
+		}
+		let dq = q as f64 - hull[k] as f64;
+		*result = dq * dq + func[hull[k]];
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use rand::SeedableRng;
+
+	use super::*;
+
+	const TOPOLOGIES: [Topology; 4] = [
+		Topology::FLAT,
+		Topology { wrap_x: true, wrap_y: false },
+		Topology { wrap_x: false, wrap_y: true },
+		Topology { wrap_x: true, wrap_y: true },
+	];
+
+	#[test]
+	fn grid_rows_fill_the_map() {
+		let params = ContinentParams { count: 5, placement: ContinentPlacement::Grid { columns: 3 }, ..Default::default() };
+		let centers = continent_centers((400, 300), Topology::FLAT, &params, &mut StdRng::seed_from_u64(1));
+		assert_eq!(centers, vec![(100, 100), (200, 100), (300, 100), (100, 200), (200, 200)]);
+
+		let params = ContinentParams { count: 2, placement: ContinentPlacement::Grid { columns: 5 }, ..Default::default() };
+		let centers = continent_centers((600, 300), Topology::FLAT, &params, &mut StdRng::seed_from_u64(1));
+		assert_eq!(centers, vec![(100, 150), (200, 150)]);
+	}
+
+	#[test]
+	fn poisson_disk_keeps_distance() {
+		let min_distance = 60.0;
+		let params = ContinentParams { count: 12, placement: ContinentPlacement::PoissonDisk { min_distance }, ..Default::default() };
+		for topology in TOPOLOGIES {
+			for seed in 0..16 {
+				let centers = continent_centers((512, 384), topology, &params, &mut StdRng::seed_from_u64(seed));
+				assert_eq!(centers.len(), 12);
+				for (i, (ax, ay)) in centers.iter().enumerate() {
+					assert!(*ax < 512 && *ay < 384);
+					for (bx, by) in &centers[i + 1..] {
+						// Across a wrapped edge the centers may be closer than on the map
+						let (dx, dy) = topology.delta((512, 384), (*ax as f32, *ay as f32), (*bx as f32, *by as f32));
+						assert!((dx * dx + dy * dy).sqrt() >= min_distance, "{topology:?} seed {seed}: {:?} and {:?}", (ax, ay), (bx, by));
+					}
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn walks_stop_at_land_fraction() {
+		let size = (160, 120);
+		let area = (size.0 * size.1) as usize;
+		for (topology, fraction) in TOPOLOGIES.iter().zip([0.05, 0.2, 0.4, 0.6]) {
+			let params = ContinentParams { land_fraction: Some(fraction), walk_length: (2000, 4000), ..Default::default() };
+			let mut rng = StdRng::seed_from_u64(7);
+			let centers = continent_centers(size, *topology, &params, &mut rng);
+			let mut land = vec![false; area];
+			walk_continents(size, *topology, &params, &centers, &mut rng, &mut land);
+
+			// Walkers stop after the step which reaches the target
+			let land_cells = land.iter().filter(|is_land| **is_land).count();
+			let target = (fraction as f64 * area as f64) as usize;
+			assert!(land_cells >= target && land_cells <= target + 1024, "{land_cells} cells of {target}");
+		}
+	}
+
+	#[test]
+	fn distance_reaches_across_wrapped_edges() {
+		let mut is_target = vec![false; 100];
+		is_target[0] = true;
+		let torus = distance_transform((10, 10), TOPOLOGIES[3], &is_target);
+		assert_eq!(torus[99], 2.0_f32.sqrt());
+		assert_eq!(torus[55], 50.0_f32.sqrt());
+		let cylinder = distance_transform((10, 10), TOPOLOGIES[1], &is_target);
+		assert_eq!(cylinder[9], 1.0);
+		assert_eq!(cylinder[90], 9.0);
+		let flat = distance_transform((10, 10), Topology::FLAT, &is_target);
+		assert_eq!(flat[9], 9.0);
+	}
+
+	#[test]
+	fn matches_brute_force() {
+		let size = (13, 9);
+		let mut rng = StdRng::seed_from_u64(3);
+		let is_target: Vec<bool> = (0..size.0 * size.1).map(|_| rng.gen_bool(0.05)).collect();
+		let targets: Vec<(f32, f32)> = (0..size.0 * size.1)
+			.filter(|id| is_target[*id])
+			.map(|id| ((id % size.0) as f32, (id / size.0) as f32))
+			.collect();
+		for topology in TOPOLOGIES {
+			let distance = distance_transform(size, topology, &is_target);
+			for (id, distance) in distance.iter().enumerate() {
+				let cell = ((id % size.0) as f32, (id / size.0) as f32);
+				let expected = targets.iter()
+					.map(|target| {
+						let (dx, dy) = topology.delta((size.0 as u32, size.1 as u32), cell, *target);
+						(dx * dx + dy * dy).sqrt()
+					})
+					.fold(f32::INFINITY, f32::min);
+				assert!((distance - expected).abs() < 1e-4, "{topology:?} at {cell:?}: {distance} instead of {expected}");
+			}
+		}
+	}
+}
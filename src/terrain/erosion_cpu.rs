@@ -0,0 +1,221 @@
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::map::{self, Height, Landscape, Topology};
+use super::{BrushKernel, ErosionParams};
+use super::tiles::{self, Tile, TileGrid};
+
+const EPS: f32 = 0.000001;
+const INITIAL_WATER_VOLUME: f32 = 1.0;
+
+/// CPU backend of the droplets model of `assets/terrain/erosion.glsl`, for maps
+/// which do not fit into the GPU memory. The map is eroded tile by tile, so on top
+/// of the landscape it needs memory only for one tile with its halo per thread.
+///
+/// Droplets start in the core of a tile and the halo is wide enough for them never
+/// to leave it. Every tile draws its droplets from its own random numbers, so the
+/// result does not depend on the number of threads, but it does on the tile size,
+/// which splits the droplets differently. Unlike on the GPU,
+/// droplets see the changes made by the previous ones right away. Terrain layers,
+/// thermal erosion and tectonics are not simulated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ErosionCpu {
+	pub tile_size: u32,
+	pub threads: usize,
+}
+
+impl ErosionCpu {
+	pub const DEFAULT_TILE_SIZE: u32 = 512;
+
+	/// How far from its start a droplet can change the landscape
+	pub fn halo(params: &ErosionParams) -> u32 {
+		params.max_droplet_lifetime + params.brush_radius + 2
+	}
+
+	/// Runs `iterations` iterations of `params.droplets_for_map` droplets each, which
+	/// are spread over the tiles by their area.
+	pub fn erode(&self, landscape: &mut Landscape, iterations: u64, seed: u64, params: &ErosionParams) {
+		let params = params.clamped();
+		let size = landscape.size();
+		let grid = TileGrid::new(size, landscape.topology(), self.tile_size, Self::halo(&params));
+		let brush = BrushKernel::new(params.brush_radius);
+		let droplets_per_cell = params.droplets_for_map((size.0 as u64, size.1 as u64)) as f64 / (size.0 as f64 * size.1 as f64);
+
+		for iteration in 0..iterations {
+			let iteration_seed = seed.wrapping_add(iteration).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+			grid.process(landscape.height_mut(), self.threads, |tile, region| {
+				let tile_id = (tile.row as u64) * (grid.columns() as u64) + tile.column as u64;
+				let mut rng = StdRng::seed_from_u64(iteration_seed ^ tile_id);
+				let droplets = droplets_per_cell * tile.core.area() as f64;
+				let droplets = droplets as u64 + rng.gen_bool(droplets.fract()) as u64;
+				erode_tile(tile, region, droplets, &brush, &params, &mut rng);
+			});
+		}
+	}
+}
+
+impl Default for ErosionCpu {
+	fn default() -> Self {
+		ErosionCpu {
+			tile_size: Self::DEFAULT_TILE_SIZE,
+			threads: tiles::available_threads(),
+		}
+	}
+}
+
+/// Simulates `droplets` droplets which start in the core of `tile`. `region` is the halo region of it.
+pub fn erode_tile(tile: &Tile, region: &mut [Height], droplets: u64, brush: &BrushKernel, params: &ErosionParams, rng: &mut StdRng) {
+	let core = tile.local_core();
+	let mut region = Region { heights: region, size: (tile.halo.width, tile.halo.height), topology: tile.topology };
+	for _ in 0..droplets {
+		let pos = (
+			(core.x + rng.gen_range(0..core.width)) as f32,
+			(core.y + rng.gen_range(0..core.height)) as f32,
+		);
+		simulate_droplet(&mut region, pos, brush, params);
+	}
+}
+
+struct Region<'a> {
+	heights: &'a mut [Height],
+	size: (u32, u32),
+	topology: Topology,
+}
+
+impl<'a> Region<'a> {
+	fn id(&self, x: i64, y: i64) -> Option<usize> {
+		self.topology.cell(self.size, x, y).map(|(x, y)| y as usize * self.size.0 as usize + x as usize)
+	}
+
+	/// Clamped to the edges which do not wrap
+	fn get_pixel(&self, x: i64, y: i64) -> f32 {
+		let (x, y) = self.topology.clamp_cell(self.size, x, y);
+		self.heights[y as usize * self.size.0 as usize + x as usize].to_num::<f32>()
+	}
+
+	/// Cells outside of the region are skipped
+	fn add_pixel(&mut self, x: i64, y: i64, diff: f32) {
+		if let Some(id) = self.id(x, y) {
+			self.heights[id] = self.heights[id].saturating_add(map::height_from_f32(diff));
+		}
+	}
+}
+
+/// Same as `CalculateHeightAndGradient` in `gradient.glsl`
+fn height_and_gradient(region: &Region, pos: (f32, f32)) -> (f32, (f32, f32)) {
+	let (cell_x, cell_y) = (pos.0 as i64, pos.1 as i64);
+	let (x, y) = (pos.0.fract(), pos.1.fract());
+	let node = |dx: i64, dy: i64| region.get_pixel(cell_x + dx, cell_y + dy);
+
+	let (nx_ny, px_ny, nx_py, px_py) = (node(0, 0), node(1, 0), node(0, 1), node(1, 1));
+	let dx = (px_ny - nx_ny) * (1.0 - y) + (px_py - nx_py) * y;
+	let dy = (nx_py - nx_ny) * (1.0 - x) + (px_py - px_ny) * x;
+	let height = nx_ny * (1.0 - x) * (1.0 - y) + px_ny * x * (1.0 - y) + nx_py * (1.0 - x) * y + px_py * x * y;
+
+	(height, (dx, dy))
+}
+
+fn simulate_droplet(region: &mut Region, mut pos: (f32, f32), brush: &BrushKernel, params: &ErosionParams) {
+	let size = region.size;
+	let mut vel = (0.0_f32, 0.0_f32);
+	let mut water_volume = INITIAL_WATER_VOLUME;
+	let mut soil_amount = 0.0_f32;
+
+	for _ in 0..params.max_droplet_lifetime {
+		let current_texel = (pos.0 as i64, pos.1 as i64);
+		let cell_offset = (pos.0.fract(), pos.1.fract());
+
+		let (height, gradient) = height_and_gradient(region, pos);
+		vel = (
+			vel.0 * params.inertia - gradient.0 * (1.0 - params.inertia),
+			vel.1 * params.inertia - gradient.1 * (1.0 - params.inertia),
+		);
+
+		let vel_length = (vel.0 * vel.0 + vel.1 * vel.1).sqrt();
+		pos = region.topology.wrap_point(size, (pos.0 + vel.0 / vel_length, pos.1 + vel.1 / vel_length));
+
+		// Droplets flow over wrapped edges and die at the others
+		if vel_length < EPS || !(pos.0 >= 0.0 && pos.1 >= 0.0 && pos.0 < size.0 as f32 && pos.1 < size.1 as f32) {
+			break;
+		}
+
+		let delta_height = height_and_gradient(region, pos).0 - height;
+		let soil_capacity = (-delta_height * vel_length * water_volume * params.soil_capacity_per_water).max(params.min_soil_capacity);
+
+		if soil_amount > soil_capacity || delta_height > 0.0 {
+			let amount_to_deposit = if delta_height > 0.0 {
+				delta_height.min(soil_amount)
+			} else {
+				(soil_amount - soil_capacity) * params.deposit_speed
+			};
+			soil_amount -= amount_to_deposit;
+
+			let (x, y) = current_texel;
+			let (fx, fy) = cell_offset;
+			region.add_pixel(x, y, amount_to_deposit * (1.0 - fx) * (1.0 - fy));
+			region.add_pixel(x + 1, y, amount_to_deposit * fx * (1.0 - fy));
+			region.add_pixel(x, y + 1, amount_to_deposit * (1.0 - fx) * fy);
+			region.add_pixel(x + 1, y + 1, amount_to_deposit * fx * fy);
+		} else {
+			// Clamped to the height change, so the droplet does not dig a hole behind itself
+			let amount_to_erode = ((soil_capacity - soil_amount) * params.erode_speed).min(-delta_height);
+
+			let center = (current_texel.0 as i32, current_texel.1 as i32);
+			for (x, y, weight) in brush.cells((size.0 as u64, size.1 as u64), region.topology, center) {
+				let weighed_amount = amount_to_erode * weight;
+				let pixel_value = region.get_pixel(x as i64, y as i64);
+				let delta_soil = if pixel_value < weighed_amount { pixel_value.max(0.0) } else { weighed_amount };
+				region.add_pixel(x as i64, y as i64, -delta_soil);
+				soil_amount += delta_soil;
+			}
+		}
+		water_volume *= 1.0 - params.evaporate_speed;
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::terrain::generators::{LayeredNoiseParams, TerrainGenerator};
+
+	#[test]
+	fn result_does_not_depend_on_threads() {
+		for topology in [Topology::FLAT, Topology { wrap_x: true, wrap_y: true }] {
+			let landscape = LayeredNoiseParams::default().generate((160, 140), topology, 3);
+			let params = ErosionParams { droplets_count: 1024, ..Default::default() };
+			for tile_size in [64, 100] {
+				let mut single = landscape.clone();
+				let mut parallel = landscape.clone();
+				ErosionCpu { tile_size, threads: 1 }.erode(&mut single, 5, 9, &params);
+				ErosionCpu { tile_size, threads: 4 }.erode(&mut parallel, 5, 9, &params);
+				assert_eq!(single.height(), parallel.height());
+				assert_ne!(single.height(), landscape.height());
+			}
+		}
+	}
+
+	/// FNV-1a over every height, so that a change anywhere on the map is noticed
+	fn checksum(heights: &[Height]) -> u64 {
+		heights.iter().fold(0xcbf2_9ce4_8422_2325, |hash, height| {
+			(hash ^ height.to_bits() as u32 as u64).wrapping_mul(0x0100_0000_01b3)
+		})
+	}
+
+	/// Takes a few minutes and 2 GiB of memory, run with `cargo test --release -- --ignored`
+	#[test]
+	#[ignore]
+	fn erodes_largest_map() {
+		let size = map::MAX_MAP_SIZE;
+		let mut generator = LayeredNoiseParams::default();
+		generator.layers.truncate(1);
+		let mut landscape = generator.generate((size.0 as u64, size.1 as u64), Topology::FLAT, 1);
+		let before = checksum(landscape.height());
+
+		let params = ErosionParams { droplets_count: 256, ..Default::default() };
+		ErosionCpu::default().erode(&mut landscape, 4, 5, &params);
+
+		assert_eq!(landscape.size(), size);
+		assert_ne!(checksum(landscape.height()), before);
+		assert!(landscape.height().iter().all(|height| *height > Height::from_num(-1) && *height < Height::from_num(2)));
+	}
+}
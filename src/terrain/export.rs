@@ -0,0 +1,204 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use image::{ImageBuffer, Luma, Rgb, RgbImage};
+
+use crate::map::{self, Biome, Height, Landscape, Map};
+
+/// Colours of land from the coast (`0.0`) to the highest possible height (`1.0`)
+const LAND_COLORS: [(f32, [f32; 3]); 5] = [
+	(0.0, [90.0, 160.0, 80.0]),
+	(0.3, [150.0, 170.0, 90.0]),
+	(0.6, [140.0, 115.0, 85.0]),
+	(0.85, [200.0, 200.0, 200.0]),
+	(1.0, [255.0, 255.0, 255.0]),
+];
+const DEEP_SEA_COLOR: [f32; 3] = [20.0, 50.0, 110.0];
+const SHALLOW_SEA_COLOR: [f32; 3] = [70.0, 140.0, 200.0];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HillshadeParams {
+	pub sea_level: Height,
+	/// Direction the light comes from, clockwise from north (the top row of the map), degrees
+	pub azimuth: f32,
+	/// Angle of the light above the horizon, degrees
+	pub altitude: f32,
+	/// Heights are multiplied by it, with `1.0` a cell is as wide as `1.0` of height
+	pub exaggeration: f32,
+	/// Share of the light which reaches the slopes facing away from it
+	pub ambient: f32,
+}
+
+impl Default for HillshadeParams {
+	fn default() -> Self {
+		HillshadeParams {
+			sea_level: Height::from_num(map::DEFAULT_SEA_LEVEL),
+			azimuth: 315.0,
+			altitude: 45.0,
+			exaggeration: 20.0,
+			ambient: 0.35,
+		}
+	}
+}
+
+impl HillshadeParams {
+	pub const AZIMUTH_RANGE: RangeInclusive<f32> = 0.0..=360.0;
+	pub const ALTITUDE_RANGE: RangeInclusive<f32> = 0.0..=90.0;
+	pub const EXAGGERATION_RANGE: RangeInclusive<f32> = 0.0..=1000.0;
+	pub const AMBIENT_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+}
+
+/// Heights `0.0..=1.0` as `0..=u16::MAX`, the rest is clamped. `Height` has more
+/// bits than that, so a bit of precision is lost.
+pub fn height_image(landscape: &Landscape) -> ImageBuffer<Luma<u16>, Vec<u16>> {
+	let (width, height) = landscape.size();
+	let pixels = landscape.height().iter()
+		.map(|height| (height.to_num::<f32>().clamp(0.0, 1.0) * u16::MAX as f32).round() as u16)
+		.collect();
+	ImageBuffer::from_raw(width, height, pixels).unwrap()
+}
+
+pub fn export_height_png(landscape: &Landscape, path: impl AsRef<Path>) -> image::ImageResult<()> {
+	height_image(landscape).save_with_format(path, image::ImageFormat::Png)
+}
+
+fn height_color(height: f32, sea_level: f32) -> [f32; 3] {
+	let lerp = |from: [f32; 3], to: [f32; 3], t: f32| [0, 1, 2].map(|i| from[i] + (to[i] - from[i]) * t);
+	if height <= sea_level {
+		let depth = if sea_level > 0.0 { (1.0 - height / sea_level).clamp(0.0, 1.0) } else { 0.0 };
+		return lerp(SHALLOW_SEA_COLOR, DEEP_SEA_COLOR, depth);
+	}
+
+	let altitude = if sea_level < 1.0 { ((height - sea_level) / (1.0 - sea_level)).clamp(0.0, 1.0) } else { 1.0 };
+	let upper = LAND_COLORS.iter().position(|(stop, _)| *stop >= altitude).unwrap_or(LAND_COLORS.len() - 1).max(1);
+	let ((from, from_color), (to, to_color)) = (LAND_COLORS[upper - 1], LAND_COLORS[upper]);
+	lerp(from_color, to_color, ((altitude - from) / (to - from)).clamp(0.0, 1.0))
+}
+
+/// Colours by height, lit from one side so the relief can be seen
+pub fn hillshade_image(landscape: &Landscape, params: &HillshadeParams) -> RgbImage {
+	let size = landscape.size();
+	let topology = landscape.topology();
+	let heights = landscape.height();
+	let height_at = |x: i64, y: i64| {
+		let (x, y) = topology.clamp_cell(size, x, y);
+		heights[y as usize * size.0 as usize + x as usize].to_num::<f32>()
+	};
+
+	let (azimuth, altitude) = (params.azimuth.to_radians(), params.altitude.to_radians());
+	// North is `-y`
+	let light = [altitude.cos() * azimuth.sin(), -altitude.cos() * azimuth.cos(), altitude.sin()];
+	let sea_level = params.sea_level.to_num::<f32>();
+
+	RgbImage::from_fn(size.0, size.1, |x, y| {
+		let (x, y) = (x as i64, y as i64);
+		let dx = (height_at(x + 1, y) - height_at(x - 1, y)) / 2.0 * params.exaggeration;
+		let dy = (height_at(x, y + 1) - height_at(x, y - 1)) / 2.0 * params.exaggeration;
+		let length = (dx * dx + dy * dy + 1.0).sqrt();
+		let normal = [-dx / length, -dy / length, 1.0 / length];
+		let lit = (0..3).map(|i| normal[i] * light[i]).sum::<f32>().max(0.0);
+		let shade = params.ambient + (1.0 - params.ambient) * lit;
+
+		let color = height_color(height_at(x, y), sea_level);
+		Rgb(color.map(|channel| (channel * shade).round().clamp(0.0, 255.0) as u8))
+	})
+}
+
+pub fn export_hillshade_png(landscape: &Landscape, params: &HillshadeParams, path: impl AsRef<Path>) -> image::ImageResult<()> {
+	hillshade_image(landscape, params).save_with_format(path, image::ImageFormat::Png)
+}
+
+/// Indexed PNG, pixel values are the indices in `Biome::all` and the palette holds `Biome::color`
+pub fn write_biome_png(map: &Map, writer: impl Write) -> io::Result<()> {
+	let (width, height) = map.size();
+	let palette: Vec<u8> = Biome::all().iter().flat_map(|biome| biome.color()).collect();
+	let pixels: Vec<u8> = map.cells().iter()
+		.map(|(biome, _)| Biome::all().iter().position(|known| known == biome).unwrap() as u8)
+		.collect();
+
+	let mut encoder = png::Encoder::new(writer, width, height);
+	encoder.set_color(png::ColorType::Indexed);
+	encoder.set_depth(png::BitDepth::Eight);
+	encoder.set_palette(palette);
+	let mut writer = encoder.write_header()?;
+	writer.write_image_data(&pixels)?;
+	writer.finish()?;
+	Ok(())
+}
+
+/// Palette of `write_biome_png`: index, key, name and colour of each biome
+pub fn biome_legend_json() -> String {
+	let entries: Vec<String> = Biome::all().iter().enumerate()
+		.map(|(index, biome)| {
+			let [r, g, b] = biome.color();
+			format!("    {{\"index\": {}, \"key\": \"{}\", \"name\": \"{}\", \"color\": \"#{:02x}{:02x}{:02x}\"}}",
+					index, biome.key(), biome.localized_name(), r, g, b)
+		})
+		.collect();
+	format!("{{\n  \"biomes\": [\n{}\n  ]\n}}\n", entries.join(",\n"))
+}
+
+/// Writes the map to `path` and its legend next to it, with the `.json` extension
+pub fn export_biome_map(map: &Map, path: impl AsRef<Path>) -> io::Result<()> {
+	let path = path.as_ref();
+	let mut writer = BufWriter::new(File::create(path)?);
+	write_biome_png(map, &mut writer)?;
+	writer.flush()?;
+	fs::write(path.with_extension("json"), biome_legend_json())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use image::{DynamicImage, ImageOutputFormat};
+
+	use crate::map::MAX_RESOURCES_IN_CELL;
+	use crate::terrain::heightmap::{self, HeightmapParams};
+	use super::*;
+
+	#[test]
+	fn height_png_goes_back_to_the_same_landscape() {
+		let size = (40, 30);
+		let heights = (0..size.0 * size.1)
+			.map(|id| Height::from_num(id as f32 / (size.0 * size.1) as f32))
+			.collect();
+		let landscape = Landscape::new(size, heights);
+
+		let mut bytes = Cursor::new(vec![]);
+		DynamicImage::ImageLuma16(height_image(&landscape)).write_to(&mut bytes, ImageOutputFormat::Png).unwrap();
+		let image = image::load_from_memory(bytes.get_ref()).unwrap();
+		let imported = heightmap::import_heightmap(&image, size, Height::from_num(0.5), &HeightmapParams::default());
+
+		// Half of a 16-bit step is lost at most
+		let step = 1.0 / u16::MAX as f32;
+		for (imported, original) in imported.height().iter().zip(landscape.height()) {
+			assert!((*imported - *original).abs().to_num::<f32>() <= step / 2.0 + Height::DELTA.to_num::<f32>(), "{imported} instead of {original}");
+		}
+	}
+
+	#[test]
+	fn heights_out_of_range_are_clamped() {
+		let heights = [-0.5, 0.0, 1.0, 1.5].map(Height::from_num);
+		let image = height_image(&Landscape::new((4, 1), heights.into()));
+		assert_eq!(image.as_raw(), &vec![0, 0, u16::MAX, u16::MAX]);
+	}
+
+	#[test]
+	fn biome_png_holds_indices_and_palette() {
+		let biomes = Biome::all();
+		let cells: Box<[_]> = (0..12).map(|i| (biomes[i % biomes.len()], [None; MAX_RESOURCES_IN_CELL])).collect();
+		let map = Map::new((4, 3), cells);
+		let mut bytes = vec![];
+		write_biome_png(&map, &mut bytes).unwrap();
+
+		let mut reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+		let palette = reader.info().palette.as_ref().unwrap().to_vec();
+		assert_eq!(palette, biomes.iter().flat_map(|biome| biome.color()).collect::<Vec<u8>>());
+		let mut pixels = vec![0; reader.output_buffer_size()];
+		reader.next_frame(&mut pixels).unwrap();
+		assert_eq!(pixels, (0..12).map(|i| (i % biomes.len()) as u8).collect::<Vec<u8>>());
+	}
+}
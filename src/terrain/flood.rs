@@ -0,0 +1,301 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+use crate::map::{Height, Landscape};
+
+const NEIGHBOURS: [(i32, i32); 8] = [
+	(-1, -1), (0, -1), (1, -1),
+	(-1, 0), (1, 0),
+	(-1, 1), (0, 1), (1, 1),
+];
+
+/// Closed basin which water fills up to `spill_height` before it flows out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lake {
+	pub spill_height: Height,
+	pub max_depth: Height,
+	pub area: u32,
+}
+
+/// Lakes of a landscape. `ids` has one value per cell: `0` if the cell is not a
+/// part of any lake, otherwise `index + 1` of the lake in `lakes`.
+#[derive(Debug, Clone)]
+pub struct Lakes {
+	size: (u32, u32),
+	ids: Box<[u32]>,
+	lakes: Vec<Lake>,
+}
+
+impl Lakes {
+	#[allow(dead_code)]
+	pub fn size(&self) -> (u32, u32) {
+		self.size
+	}
+
+	pub fn ids(&self) -> &[u32] {
+		&self.ids
+	}
+
+	pub fn lakes(&self) -> &[Lake] {
+		&self.lakes
+	}
+
+	pub fn lake_at(&self, x: u32, y: u32) -> Option<&Lake> {
+		match self.ids[(y as usize) * (self.size.0 as usize) + (x as usize)] {
+			0 => None,
+			id => self.lakes.get(id as usize - 1),
+		}
+	}
+
+	pub fn is_lake(&self, id: usize) -> bool {
+		self.ids[id] != 0
+	}
+
+	/// One byte per cell, `1` for lakes. Same layout as the `R8UI` textures.
+	pub fn mask(&self) -> Box<[u8]> {
+		self.ids.iter().map(|id| (*id != 0) as u8).collect()
+	}
+}
+
+/// Priority-flood (Barnes et al., 2014) from the outlets. Returns the level of
+/// water surface for each cell, which is never below the cell height.
+///
+/// Water leaves the map into the sea (every cell not above `sea_level`) and through
+/// the edges which do not wrap, and flows across the wrapped ones. If there is no
+/// outlet at all, it leaves through the lowest cell. Each flooded cell is raised
+/// `epsilon` above the cell it was flooded from, so with a non-zero `epsilon` every
+/// cell gets a strictly downhill path to an outlet.
+pub fn priority_flood(landscape: &Landscape, sea_level: Height, epsilon: Height) -> Box<[Height]> {
+	let size = landscape.size();
+	let topology = landscape.topology();
+	let (width, height) = (size.0 as i32, size.1 as i32);
+	let heights = landscape.height();
+	let mut levels: Box<[Height]> = heights.to_vec().into_boxed_slice();
+	let mut closed: Box<[bool]> = vec![false; heights.len()].into_boxed_slice();
+
+	let id = |x: i32, y: i32| (y * width + x) as usize;
+
+	// Ties are resolved by cell index, so the result does not depend on heap internals
+	let mut open: BinaryHeap<Reverse<(Height, usize)>> = BinaryHeap::new();
+	let mut pit: VecDeque<usize> = VecDeque::new();
+
+	let is_outlet = |x: i32, y: i32| {
+		heights[id(x, y)] <= sea_level ||
+			(!topology.wrap_x && (x == 0 || x == width - 1)) || (!topology.wrap_y && (y == 0 || y == height - 1))
+	};
+	for y in 0..height {
+		for x in 0..width {
+			if is_outlet(x, y) {
+				closed[id(x, y)] = true;
+				open.push(Reverse((levels[id(x, y)], id(x, y))));
+			}
+		}
+	}
+	if open.is_empty() {
+		if let Some(lowest) = (0..heights.len()).min_by_key(|id| (heights[*id], *id)) {
+			closed[lowest] = true;
+			open.push(Reverse((levels[lowest], lowest)));
+		}
+	}
+
+	loop {
+		let cell = if let Some(cell) = pit.pop_front() {
+			cell
+		} else if let Some(Reverse((_, cell))) = open.pop() {
+			cell
+		} else {
+			break;
+		};
+
+		let (x, y) = ((cell as i32) % width, (cell as i32) / width);
+		for (dx, dy) in NEIGHBOURS {
+			let neighbour = match topology.cell(size, (x + dx) as i64, (y + dy) as i64) {
+				Some((nx, ny)) => id(nx as i32, ny as i32),
+				None => continue,
+			};
+			if closed[neighbour] {
+				continue;
+			}
+			closed[neighbour] = true;
+
+			let flooded_level = levels[cell].saturating_add(epsilon);
+			if levels[neighbour] <= flooded_level {
+				levels[neighbour] = flooded_level;
+				pit.push_back(neighbour);
+			} else {
+				open.push(Reverse((levels[neighbour], neighbour)));
+			}
+		}
+	}
+
+	levels
+}
+
+/// Fills all closed depressions which are above `sea_level`, so that water from
+/// any land cell can flow to an outlet (see `priority_flood`). Sea floor is not changed.
+pub fn fill_depressions(landscape: &mut Landscape, sea_level: Height, epsilon: Height) {
+	let levels = priority_flood(landscape, sea_level, epsilon);
+	for (height, level) in landscape.height_mut().iter_mut().zip(levels.iter()) {
+		if *level > sea_level {
+			*height = (*height).max(*level);
+		}
+	}
+}
+
+/// Finds depressions above `sea_level` without changing the landscape. Each
+/// 8-connected group of flooded cells becomes one lake, also across the wrapped edges.
+pub fn detect_lakes(landscape: &Landscape, sea_level: Height) -> Lakes {
+	let size = landscape.size();
+	let topology = landscape.topology();
+	let width = size.0 as i32;
+	let heights = landscape.height();
+	let levels = priority_flood(landscape, sea_level, Height::ZERO);
+
+	let is_flooded = |id: usize| levels[id] > heights[id] && levels[id] > sea_level;
+	let id = |x: i32, y: i32| (y * width + x) as usize;
+
+	let mut ids: Box<[u32]> = vec![0; heights.len()].into_boxed_slice();
+	let mut lakes: Vec<Lake> = vec![];
+	let mut queue: VecDeque<usize> = VecDeque::new();
+
+	for start in 0..heights.len() {
+		if ids[start] != 0 || !is_flooded(start) {
+			continue;
+		}
+
+		let lake_id = lakes.len() as u32 + 1;
+		let mut lake = Lake {
+			spill_height: levels[start],
+			max_depth: Height::ZERO,
+			area: 0,
+		};
+
+		ids[start] = lake_id;
+		queue.push_back(start);
+		while let Some(cell) = queue.pop_front() {
+			lake.area += 1;
+			lake.spill_height = lake.spill_height.max(levels[cell]);
+			lake.max_depth = lake.max_depth.max(levels[cell] - heights[cell]);
+
+			let (x, y) = ((cell as i32) % width, (cell as i32) / width);
+			for (dx, dy) in NEIGHBOURS {
+				let neighbour = match topology.cell(size, (x + dx) as i64, (y + dy) as i64) {
+					Some((nx, ny)) => id(nx as i32, ny as i32),
+					None => continue,
+				};
+				if ids[neighbour] == 0 && is_flooded(neighbour) {
+					ids[neighbour] = lake_id;
+					queue.push_back(neighbour);
+				}
+			}
+		}
+
+		lakes.push(lake);
+	}
+
+	Lakes { size, ids, lakes }
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::map::{self, Topology};
+	use super::*;
+
+	fn landscape(size: (u32, u32), topology: Topology, height: impl Fn(u32, u32) -> f32) -> Landscape {
+		let heights = (0..size.0 * size.1).map(|id| map::height_from_f32(height(id % size.0, id / size.0))).collect();
+		Landscape::new(size, heights).with_topology(topology)
+	}
+
+	#[test]
+	fn lake_across_the_seam_is_one_lake() {
+		let pit = |x: u32, y: u32| if (x == 0 || x == 11) && (3..5).contains(&y) { 0.4 } else { 0.6 };
+		let cylinder = detect_lakes(&landscape((12, 8), Topology { wrap_x: true, wrap_y: false }, pit), Height::from_num(0.1));
+		assert_eq!(cylinder.lakes().len(), 1);
+		assert_eq!(cylinder.lakes()[0].area, 4);
+		assert_eq!(cylinder.lake_at(0, 3), cylinder.lake_at(11, 4));
+		// On a flat map the pit is on the edges, where the water leaves the map
+		let flat = detect_lakes(&landscape((12, 8), Topology::FLAT, pit), Height::from_num(0.1));
+		assert!(flat.lakes().is_empty());
+	}
+
+	#[test]
+	fn torus_drains_through_the_lowest_cell() {
+		let torus = Topology { wrap_x: true, wrap_y: true };
+		let pits = |x: u32, y: u32| match (x, y) {
+			(2, 2) => 0.2,
+			(8, 5) => 0.3,
+			_ => 0.5,
+		};
+		let landscape = landscape((12, 8), torus, pits);
+		let levels = priority_flood(&landscape, Height::from_num(0.1), Height::ZERO);
+		assert_eq!(levels[2 * 12 + 2], landscape.height()[2 * 12 + 2]);
+		assert_eq!(levels[5 * 12 + 8], map::height_from_f32(0.5));
+		assert!(levels.iter().zip(landscape.height()).all(|(level, height)| level >= height));
+
+		let lakes = detect_lakes(&landscape, Height::from_num(0.1));
+		assert_eq!(lakes.lakes().len(), 1);
+		assert!(lakes.lake_at(8, 5).is_some());
+	}
+
+	#[test]
+	fn seas_are_not_lakes_on_a_torus() {
+		let torus = Topology { wrap_x: true, wrap_y: true };
+		// Two oceans in separate stripes, and one pit in the land between them
+		let height = |x: u32, y: u32| match (x, y) {
+			(0..=3, _) => 0.1,
+			(8..=11, _) => 0.05,
+			(6, 4) => 0.4,
+			_ => 0.6,
+		};
+		let landscape = landscape((16, 8), torus, height);
+		let sea_level = map::height_from_f32(0.2);
+
+		let levels = priority_flood(&landscape, sea_level, Height::ZERO);
+		for (level, height) in levels.iter().zip(landscape.height()) {
+			if *height <= sea_level {
+				assert_eq!(level, height);
+			}
+		}
+
+		let lakes = detect_lakes(&landscape, sea_level);
+		assert_eq!(lakes.lakes().len(), 1);
+		assert!(lakes.lake_at(6, 4).is_some());
+		assert_eq!(lakes.lakes()[0].area, 1);
+
+		let mut filled = landscape.clone();
+		fill_depressions(&mut filled, sea_level, Height::ZERO);
+		assert!(filled.height().iter().zip(landscape.height()).all(|(after, before)| *before > sea_level || after == before));
+	}
+
+	#[test]
+	fn filled_land_slopes_down_to_an_outlet() {
+		// Bowl with a flat floor, and a ridge with a plateau behind it
+		let height = |x: u32, y: u32| {
+			let (dx, dy) = (x as f32 - 10.0, y as f32 - 8.0);
+			let distance = (dx * dx + dy * dy).sqrt();
+			if distance < 4.0 { 0.4 } else if distance < 6.0 { 0.7 } else { 0.5 }
+		};
+		let sea_level = map::height_from_f32(0.1);
+		for topology in [Topology::FLAT, Topology { wrap_x: true, wrap_y: false }] {
+			let mut landscape = landscape((24, 16), topology, height);
+			fill_depressions(&mut landscape, sea_level, Height::DELTA);
+
+			let (size, heights) = (landscape.size(), landscape.height());
+			let outlet = |x: u32, y: u32| {
+				(!topology.wrap_x && (x == 0 || x == size.0 - 1)) || (!topology.wrap_y && (y == 0 || y == size.1 - 1))
+			};
+			for y in 0..size.1 {
+				for x in 0..size.0 {
+					if outlet(x, y) {
+						continue;
+					}
+					let here = heights[(y * size.0 + x) as usize];
+					let downhill = NEIGHBOURS.iter()
+						.filter_map(|(dx, dy)| topology.cell(size, x as i64 + *dx as i64, y as i64 + *dy as i64))
+						.any(|(nx, ny)| heights[(ny * size.0 + nx) as usize] < here);
+					assert!(downhill, "no way down from ({}, {}) on {:?}", x, y, topology);
+				}
+			}
+		}
+	}
+}
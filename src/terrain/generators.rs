@@ -0,0 +1,761 @@
+use noise::{Billow, Fbm, MultiFractal, NoiseFn, Perlin, RidgedMulti};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+
+use crate::map::{self, Height, Landscape, Topology};
+use crate::seed::SeedStage;
+use super::continents::ContinentParams;
+use super::ShapeSmoother;
+use super::tiles;
+
+/// Source of the initial landscape of a world. Heights are roughly in `0.0..=1.0`,
+/// and the same `seed` always gives the same landscape. Along the wrapped axes of
+/// `topology` the landscape has no seams, and it is returned with that topology.
+pub trait TerrainGenerator {
+	fn generate(&self, size: (u64, u64), topology: Topology, seed: u64) -> Landscape;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GeneratorKind {
+	/// Random walks (`super::generate_map`)
+	Continents,
+	LayeredNoise,
+	DiamondSquare,
+	TectonicPlates,
+}
+
+impl GeneratorKind {
+	pub fn all() -> &'static [GeneratorKind] {
+		use GeneratorKind::*;
+		&[Continents, LayeredNoise, DiamondSquare, TectonicPlates]
+	}
+
+	pub fn localized_name(&self) -> &'static str {
+		match self {
+			GeneratorKind::Continents => "Brownian movement",
+			GeneratorKind::LayeredNoise => "Perlin noise",
+			GeneratorKind::DiamondSquare => "Diamond-square",
+			GeneratorKind::TectonicPlates => "Tectonic plates",
+		}
+	}
+
+	/// Stage of the `WorldSeed` which seeds the generator
+	pub fn seed_stage(&self) -> SeedStage {
+		match self {
+			GeneratorKind::LayeredNoise => SeedStage::LandscapeNoise,
+			GeneratorKind::Continents | GeneratorKind::DiamondSquare | GeneratorKind::TectonicPlates => SeedStage::Continents,
+		}
+	}
+}
+
+/// Selected generator and the settings of each of them
+#[derive(Debug, Clone, PartialEq)]
+pub struct TerrainParams {
+	pub generator: GeneratorKind,
+	pub noise: LayeredNoiseParams,
+	pub diamond_square: DiamondSquareParams,
+	pub plates: PlateParams,
+}
+
+impl Default for TerrainParams {
+	fn default() -> Self {
+		TerrainParams {
+			generator: GeneratorKind::Continents,
+			noise: LayeredNoiseParams::default(),
+			diamond_square: DiamondSquareParams::default(),
+			plates: PlateParams::default(),
+		}
+	}
+}
+
+fn to_landscape(size: (u64, u64), topology: Topology, heights: impl Iterator<Item = f32>) -> Landscape {
+	let height: Box<[Height]> = heights.map(map::height_from_f32).collect();
+	Landscape::new((size.0 as u32, size.1 as u32), height).with_topology(topology)
+}
+
+/// 2D noise which is periodic along the wrapped axes of a map. A wrapped axis is
+/// bent into a circle of the same length, which takes one more dimension of the
+/// noise, so the features keep their size. On a torus only `x` is bent, and `y` is
+/// blended with its copy one map height away: 4D Perlin noise of the `noise` crate
+/// is not continuous. On a flat map it is the noise itself.
+pub struct WrappedNoise<N> {
+	noise: N,
+	size: (f64, f64),
+	topology: Topology,
+}
+
+impl<N> WrappedNoise<N> {
+	pub fn new(noise: N, size: (u64, u64), topology: Topology) -> Self {
+		WrappedNoise { noise, size: (size.0 as f64, size.1 as f64), topology }
+	}
+}
+
+impl<N: NoiseFn<f64, 2> + NoiseFn<f64, 3>> NoiseFn<f64, 2> for WrappedNoise<N> {
+	fn get(&self, point: [f64; 2]) -> f64 {
+		let circle = |value: f64, side: f64| {
+			let angle = value / side * std::f64::consts::TAU;
+			let radius = side / std::f64::consts::TAU;
+			(angle.cos() * radius, angle.sin() * radius)
+		};
+		let [x, y] = point;
+		let wrapped_x = |y: f64| {
+			let (x1, x2) = circle(x, self.size.0);
+			self.noise.get([x1, x2, y])
+		};
+		match (self.topology.wrap_x, self.topology.wrap_y) {
+			(false, false) => NoiseFn::<f64, 2>::get(&self.noise, point),
+			(true, false) => wrapped_x(y),
+			(false, true) => {
+				let (y1, y2) = circle(y, self.size.1);
+				self.noise.get([x, y1, y2])
+			}
+			(true, true) => {
+				let y = y.rem_euclid(self.size.1);
+				let t = y / self.size.1;
+				wrapped_x(y) * (1.0 - t) + wrapped_x(y - self.size.1) * t
+			}
+		}
+	}
+}
+
+/// Random walk continents with Fbm Perlin noise on top of them
+pub struct BrownianContinents<'a, N: NoiseFn<f64, 2>> {
+	pub params: &'a ContinentParams,
+	pub smoother: &'a ShapeSmoother,
+	pub noise: N,
+}
+
+impl<'a, N: NoiseFn<f64, 2> + NoiseFn<f64, 3> + Sync> TerrainGenerator for BrownianContinents<'a, N> {
+	fn generate(&self, size: (u64, u64), topology: Topology, seed: u64) -> Landscape {
+		let noise = WrappedNoise::new(&self.noise, size, topology);
+		super::generate_map(size, topology, self.params, seed, self.smoother, noise)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NoiseKind {
+	Fbm,
+	/// Sharp ridges, good for mountain ranges
+	Ridged,
+	/// Rounded bumps, good for hills
+	Billow,
+}
+
+impl NoiseKind {
+	pub fn all() -> &'static [NoiseKind] {
+		&[NoiseKind::Fbm, NoiseKind::Ridged, NoiseKind::Billow]
+	}
+
+	pub fn localized_name(&self) -> &'static str {
+		match self {
+			NoiseKind::Fbm => "Fbm",
+			NoiseKind::Ridged => "Ridged",
+			NoiseKind::Billow => "Billow",
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NoiseLayer {
+	pub kind: NoiseKind,
+	/// Features per cell of the first octave
+	pub frequency: f64,
+	pub octaves: usize,
+	/// Frequency multiplier of each next octave
+	pub lacunarity: f64,
+	/// Amplitude multiplier of each next octave
+	pub persistence: f64,
+	/// The layer adds from `-amplitude` to `amplitude` to the height
+	pub amplitude: f32,
+}
+
+impl Default for NoiseLayer {
+	fn default() -> Self {
+		NoiseLayer {
+			kind: NoiseKind::Fbm,
+			frequency: 0.005,
+			octaves: 6,
+			lacunarity: 2.0,
+			persistence: 0.5,
+			amplitude: 0.35,
+		}
+	}
+}
+
+/// Shifts the sampled points by another noise, which bends the shapes of all layers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DomainWarp {
+	pub frequency: f64,
+	/// Maximum shift, in cells
+	pub strength: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayeredNoiseParams {
+	/// Height where all the layers are zero
+	pub base_height: f32,
+	/// Summed up in order
+	pub layers: Vec<NoiseLayer>,
+	pub domain_warp: Option<DomainWarp>,
+}
+
+impl Default for LayeredNoiseParams {
+	fn default() -> Self {
+		LayeredNoiseParams {
+			base_height: 0.45,
+			layers: vec![
+				NoiseLayer::default(),
+				NoiseLayer { kind: NoiseKind::Ridged, frequency: 0.01, octaves: 5, amplitude: 0.15, ..Default::default() },
+			],
+			domain_warp: Some(DomainWarp { frequency: 0.01, strength: 20.0 }),
+		}
+	}
+}
+
+impl LayeredNoiseParams {
+	pub const OCTAVES_RANGE: std::ops::RangeInclusive<usize> = 1..=16;
+}
+
+impl TerrainGenerator for LayeredNoiseParams {
+	fn generate(&self, size: (u64, u64), topology: Topology, seed: u64) -> Landscape {
+		let mut rng = StdRng::seed_from_u64(seed);
+		let layers: Vec<(Box<dyn NoiseFn<f64, 2> + Sync>, f32)> = self.layers.iter()
+			.map(|layer| {
+				let octaves = layer.octaves.clamp(*Self::OCTAVES_RANGE.start(), *Self::OCTAVES_RANGE.end());
+				let noise: Box<dyn NoiseFn<f64, 2> + Sync> = match layer.kind {
+					NoiseKind::Fbm => Box::new(WrappedNoise::new(Fbm::<Perlin>::new(rng.gen())
+						.set_frequency(layer.frequency).set_octaves(octaves)
+						.set_lacunarity(layer.lacunarity).set_persistence(layer.persistence), size, topology)),
+					NoiseKind::Ridged => Box::new(WrappedNoise::new(RidgedMulti::<Perlin>::new(rng.gen())
+						.set_frequency(layer.frequency).set_octaves(octaves)
+						.set_lacunarity(layer.lacunarity).set_persistence(layer.persistence), size, topology)),
+					NoiseKind::Billow => Box::new(WrappedNoise::new(Billow::<Perlin>::new(rng.gen())
+						.set_frequency(layer.frequency).set_octaves(octaves)
+						.set_lacunarity(layer.lacunarity).set_persistence(layer.persistence), size, topology)),
+				};
+				(noise, layer.amplitude)
+			})
+			.collect();
+
+		let warp = self.domain_warp.map(|warp| {
+			let noise = |seed: u32| WrappedNoise::new(Fbm::<Perlin>::new(seed).set_frequency(warp.frequency).set_octaves(3), size, topology);
+			(noise(rng.gen()), noise(rng.gen()), warp.strength)
+		});
+
+		let mut heights = vec![Height::ZERO; (size.0 * size.1) as usize].into_boxed_slice();
+		tiles::fill_rows(&mut heights, size.0 as usize, |y, row| {
+			for (x, cell) in row.iter_mut().enumerate() {
+				let mut point = [x as f64, y as f64];
+				// The layers are periodic too, so the shifted point may leave the map
+				if let Some((warp_x, warp_y, strength)) = &warp {
+					point = [point[0] + warp_x.get(point) * strength, point[1] + warp_y.get(point) * strength];
+				}
+				let height = self.base_height + layers.iter()
+					.map(|(noise, amplitude)| noise.get(point).clamp(-1.0, 1.0) as f32 * amplitude)
+					.sum::<f32>();
+				*cell = map::height_from_f32(height);
+			}
+		});
+		Landscape::new((size.0 as u32, size.1 as u32), heights).with_topology(topology)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiamondSquareParams {
+	/// `0.0` - every level of detail is as strong as the previous one, `1.0` - each next is twice weaker
+	pub smoothness: f32,
+	/// The result is stretched to this range
+	pub height_range: (f32, f32),
+}
+
+impl Default for DiamondSquareParams {
+	fn default() -> Self {
+		DiamondSquareParams {
+			smoothness: 0.8,
+			height_range: (0.1, 0.9),
+		}
+	}
+}
+
+impl TerrainGenerator for DiamondSquareParams {
+	/// Works on the smallest `2^n + 1` square which covers the map, then crops it.
+	/// If the map wraps, see `periodic_grid`.
+	fn generate(&self, size: (u64, u64), topology: Topology, seed: u64) -> Landscape {
+		let mut rng = StdRng::seed_from_u64(seed);
+		let decay = 0.5_f32.powf(self.smoothness.clamp(0.0, 1.0));
+		let heights: Vec<f32> = if topology.is_flat() {
+			let side = (size.0.max(size.1).max(2) - 1).next_power_of_two() as usize + 1;
+			let grid = self.flat_grid(side, decay, &mut rng);
+			(0..size.0 * size.1).map(|i| grid[(i / size.0) as usize * side + (i % size.0) as usize]).collect()
+		} else {
+			self.periodic_grid(size, topology, decay, &mut rng)
+		};
+
+		let min = heights.iter().copied().fold(f32::INFINITY, f32::min);
+		let max = heights.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+		let range = if max > min { max - min } else { 1.0 };
+		let (low, high) = self.height_range;
+		to_landscape(size, topology, heights.into_iter().map(|value| low + (value - min) / range * (high - low)))
+	}
+}
+
+impl DiamondSquareParams {
+	fn flat_grid(&self, side: usize, decay: f32, rng: &mut StdRng) -> Vec<f32> {
+		let mut grid = vec![0.0_f32; side * side];
+		let id = |x: usize, y: usize| y * side + x;
+
+		for (x, y) in [(0, 0), (side - 1, 0), (0, side - 1), (side - 1, side - 1)] {
+			grid[id(x, y)] = rng.gen_range(-1.0..=1.0);
+		}
+
+		let mut amplitude = 1.0;
+		let mut step = side - 1;
+		while step > 1 {
+			let half = step / 2;
+
+			// Diamond step: centers of the squares
+			for y in (half..side).step_by(step) {
+				for x in (half..side).step_by(step) {
+					let average = (grid[id(x - half, y - half)] + grid[id(x + half, y - half)] +
+						grid[id(x - half, y + half)] + grid[id(x + half, y + half)]) / 4.0;
+					grid[id(x, y)] = average + rng.gen_range(-amplitude..=amplitude);
+				}
+			}
+
+			// Square step: middles of the edges, neighbours outside of the grid are skipped
+			for y in (0..side).step_by(half) {
+				let x_start = if (y / half) % 2 == 0 { half } else { 0 };
+				for x in (x_start..side).step_by(step) {
+					let neighbours = [(0, -1), (0, 1), (-1, 0), (1, 0)].iter()
+						.map(|(dx, dy)| (x as i64 + dx * half as i64, y as i64 + dy * half as i64))
+						.filter(|(nx, ny)| *nx >= 0 && *ny >= 0 && *nx < side as i64 && *ny < side as i64)
+						.map(|(nx, ny)| grid[id(nx as usize, ny as usize)]);
+					let (sum, count) = neighbours.fold((0.0, 0), |(sum, count), value| (sum + value, count + 1));
+					grid[id(x, y)] = sum / count as f32 + rng.gen_range(-amplitude..=amplitude);
+				}
+			}
+
+			amplitude *= decay;
+			step = half;
+		}
+		grid
+	}
+
+	/// Same steps on a `2^n` by `2^m` grid whose edges are glued together, so every
+	/// neighbour exists. Wrapped axes of the map are stretched over the whole grid,
+	/// the others are cropped.
+	fn periodic_grid(&self, size: (u64, u64), topology: Topology, decay: f32, rng: &mut StdRng) -> Vec<f32> {
+		let sides = (size.0.max(2).next_power_of_two() as usize, size.1.max(2).next_power_of_two() as usize);
+		let mut grid = vec![0.0_f32; sides.0 * sides.1];
+		let id = |x: usize, y: usize| (y % sides.1) * sides.0 + x % sides.0;
+		let neighbour = |x: usize, y: usize, dx: isize, dy: isize| id(
+			(x as isize + dx).rem_euclid(sides.0 as isize) as usize,
+			(y as isize + dy).rem_euclid(sides.1 as isize) as usize,
+		);
+
+		let mut step = sides.0.min(sides.1);
+		for y in (0..sides.1).step_by(step) {
+			for x in (0..sides.0).step_by(step) {
+				grid[id(x, y)] = rng.gen_range(-1.0..=1.0);
+			}
+		}
+
+		let mut amplitude = 1.0;
+		while step > 1 {
+			let half = step / 2;
+			let offset = half as isize;
+
+			for y in (half..sides.1).step_by(step) {
+				for x in (half..sides.0).step_by(step) {
+					let average = [(-1, -1), (1, -1), (-1, 1), (1, 1)].iter()
+						.map(|(dx, dy)| grid[neighbour(x, y, dx * offset, dy * offset)])
+						.sum::<f32>() / 4.0;
+					grid[id(x, y)] = average + rng.gen_range(-amplitude..=amplitude);
+				}
+			}
+
+			for y in (0..sides.1).step_by(half) {
+				let x_start = if (y / half) % 2 == 0 { half } else { 0 };
+				for x in (x_start..sides.0).step_by(step) {
+					let average = [(0, -1), (0, 1), (-1, 0), (1, 0)].iter()
+						.map(|(dx, dy)| grid[neighbour(x, y, dx * offset, dy * offset)])
+						.sum::<f32>() / 4.0;
+					grid[id(x, y)] = average + rng.gen_range(-amplitude..=amplitude);
+				}
+			}
+
+			amplitude *= decay;
+			step = half;
+		}
+
+		// Bilinear sampling, the grid is periodic so the cell after the last one is the first one
+		let scale = |wrap: bool, side: usize, map_side: u64| if wrap { side as f32 / map_side as f32 } else { 1.0 };
+		let scale = (scale(topology.wrap_x, sides.0, size.0), scale(topology.wrap_y, sides.1, size.1));
+		(0..size.0 * size.1)
+			.map(|i| {
+				let (x, y) = ((i % size.0) as f32 * scale.0, (i / size.0) as f32 * scale.1);
+				let (cell_x, cell_y) = (x as usize, y as usize);
+				let (fx, fy) = (x.fract(), y.fract());
+				let top = grid[id(cell_x, cell_y)] * (1.0 - fx) + grid[id(cell_x + 1, cell_y)] * fx;
+				let bottom = grid[id(cell_x, cell_y + 1)] * (1.0 - fx) + grid[id(cell_x + 1, cell_y + 1)] * fx;
+				top * (1.0 - fy) + bottom * fy
+			})
+			.collect()
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlateParams {
+	pub count: u32,
+	/// Share of the plates which are oceanic, the rest are continental
+	pub oceanic_fraction: f32,
+	pub ocean_height: f32,
+	pub continent_height: f32,
+	/// Height added where plates collide head-on with unit speed
+	pub uplift: f32,
+	/// Depth of the rifts where plates move apart with unit speed
+	pub rift_depth: f32,
+	/// Distance from the plate boundary (in cells) at which uplift and rifts fade out
+	pub boundary_width: f32,
+	/// Maximum shift of the boundaries by noise, in cells
+	pub boundary_noise: f32,
+	/// Amplitude of the Fbm noise added to the whole map
+	pub detail: f32,
+}
+
+impl Default for PlateParams {
+	fn default() -> Self {
+		PlateParams {
+			count: 12,
+			oceanic_fraction: 0.6,
+			ocean_height: 0.25,
+			continent_height: 0.5,
+			uplift: 0.35,
+			rift_depth: 0.1,
+			boundary_width: 24.0,
+			boundary_noise: 24.0,
+			detail: 0.05,
+		}
+	}
+}
+
+pub struct Plate {
+	pub center: (f32, f32),
+	/// Cells per tick, at most `1.0`
+	pub velocity: (f32, f32),
+	pub height: f32,
+}
+
+/// Plates of `PlateParams` with the Voronoi boundaries shifted by noise.
+/// Distances are measured across the wrapped edges.
+pub struct PlateLayout {
+	plates: Vec<Plate>,
+	noise_x: WrappedNoise<Fbm<Perlin>>,
+	noise_y: WrappedNoise<Fbm<Perlin>>,
+	boundary_noise: f64,
+	size: (u32, u32),
+	topology: Topology,
+}
+
+impl PlateLayout {
+	pub fn new(params: &PlateParams, size: (u64, u64), topology: Topology, rng: &mut StdRng) -> Self {
+		let count = params.count.max(1) as usize;
+		let oceanic_count = (count as f32 * params.oceanic_fraction.clamp(0.0, 1.0)).round() as usize;
+		let plates: Vec<Plate> = (0..count)
+			.map(|i| {
+				let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+				let speed = rng.gen_range(0.0..=1.0);
+				Plate {
+					center: (rng.gen_range(0.0..size.0 as f32), rng.gen_range(0.0..size.1 as f32)),
+					velocity: (angle.cos() * speed, angle.sin() * speed),
+					height: if i < oceanic_count { params.ocean_height } else { params.continent_height },
+				}
+			})
+			.collect();
+
+		let boundary_noise = |seed: u32| WrappedNoise::new(Fbm::<Perlin>::new(seed).set_frequency(0.01).set_octaves(4), size, topology);
+		let (noise_x, noise_y) = (boundary_noise(rng.gen()), boundary_noise(rng.gen()));
+		PlateLayout {
+			plates,
+			noise_x,
+			noise_y,
+			boundary_noise: params.boundary_noise as f64,
+			size: (size.0 as u32, size.1 as u32),
+			topology,
+		}
+	}
+
+	pub fn plates(&self) -> &[Plate] {
+		&self.plates
+	}
+
+	/// Shortest offset from `point` to the center of `plate`
+	pub fn offset(&self, point: (f32, f32), plate: &Plate) -> (f32, f32) {
+		self.topology.delta(self.size, point, plate.center)
+	}
+
+	/// Cell position shifted by the boundary noise
+	pub fn warp(&self, x: u64, y: u64) -> (f32, f32) {
+		let (x, y) = (x as f64, y as f64);
+		((x + self.noise_x.get([x, y]) * self.boundary_noise) as f32, (y + self.noise_y.get([x, y]) * self.boundary_noise) as f32)
+	}
+
+	/// Closest and second closest plates to a warped point
+	pub fn closest(&self, point: (f32, f32)) -> (usize, Option<usize>) {
+		let sqr_distance = |plate: &Plate| {
+			let offset = self.offset(point, plate);
+			offset.0 * offset.0 + offset.1 * offset.1
+		};
+		let (mut first, mut second) = (0, None);
+		for i in 1..self.plates.len() {
+			if sqr_distance(&self.plates[i]) < sqr_distance(&self.plates[first]) {
+				second = Some(first);
+				first = i;
+			} else if second.map_or(true, |second| sqr_distance(&self.plates[i]) < sqr_distance(&self.plates[second])) {
+				second = Some(i);
+			}
+		}
+		(first, second)
+	}
+}
+
+impl TerrainGenerator for PlateParams {
+	/// Voronoi cells around random centers are the plates, each one moves in a random
+	/// direction. Heights change near the boundaries depending on the relative speed
+	/// of the two closest plates along the line between their centers, then the
+	/// result is blurred to hide the seams.
+	fn generate(&self, size: (u64, u64), topology: Topology, seed: u64) -> Landscape {
+		let mut rng = StdRng::seed_from_u64(seed);
+		let layout = PlateLayout::new(self, size, topology, &mut rng);
+		let plates = layout.plates();
+		let detail = WrappedNoise::new(Fbm::<Perlin>::new(rng.gen()).set_frequency(0.02).set_octaves(6), size, topology);
+		let width = self.boundary_width.max(1.0);
+
+		let raw: Vec<f32> = (0..size.0 * size.1).map(|id| {
+			let point = layout.warp(id % size.0, id / size.0);
+			let (first, second) = layout.closest(point);
+			let Some(second) = second else {
+				return plates[first].height;
+			};
+
+			// Across a wrapped edge the centers are taken at their closest copies to the point
+			let (a, b) = (&plates[first], &plates[second]);
+			let (to_a, to_b) = (layout.offset(point, a), layout.offset(point, b));
+			let axis = (to_b.0 - to_a.0, to_b.1 - to_a.1);
+			let axis_length = (axis.0 * axis.0 + axis.1 * axis.1).sqrt().max(f32::EPSILON);
+			// Distance to the bisector of the two centers, which is the boundary
+			let sqr_length = |offset: (f32, f32)| offset.0 * offset.0 + offset.1 * offset.1;
+			let distance = (sqr_length(to_b) - sqr_length(to_a)) / (2.0 * axis_length);
+			let closeness = (1.0 - distance / width).max(0.0);
+			let convergence = ((a.velocity.0 - b.velocity.0) * axis.0 + (a.velocity.1 - b.velocity.1) * axis.1) / axis_length;
+			let boundary = if convergence > 0.0 {
+				convergence * self.uplift * closeness * closeness
+			} else {
+				convergence * self.rift_depth * closeness * closeness
+			};
+			a.height + boundary
+		}).collect();
+
+		// Plates and the boundary features have sharp edges, three box blurs are close to a gaussian one
+		let mut smooth = raw;
+		let radius = (width / 4.0).round() as usize;
+		for _ in 0..3 {
+			smooth = box_blur((size.0 as usize, size.1 as usize), topology, &smooth, radius);
+		}
+
+		let heights = smooth.into_iter().enumerate()
+			.map(|(id, height)| {
+				let (x, y) = ((id as u64 % size.0) as f64, (id as u64 / size.0) as f64);
+				height + detail.get([x, y]) as f32 * self.detail
+			});
+		to_landscape(size, topology, heights)
+	}
+}
+
+/// Average over the `(2 * radius + 1)` square around each cell. Cells beyond a wrapped
+/// edge come from the other side, beyond the other edges they repeat the edge.
+pub fn box_blur(size: (usize, usize), topology: Topology, values: &[f32], radius: usize) -> Vec<f32> {
+	if radius == 0 {
+		return values.to_vec();
+	}
+	let blur_line = |line: &[f32], wrap: bool| -> Vec<f32> {
+		let len = line.len() as i64;
+		let at = |i: i64| line[if wrap { i.rem_euclid(len) } else { i.clamp(0, len - 1) } as usize];
+		let mut sum: f32 = (-(radius as i64)..=radius as i64).map(at).sum();
+		let mut result = Vec::with_capacity(line.len());
+		for i in 0..len {
+			result.push(sum / (2 * radius + 1) as f32);
+			sum += at(i + radius as i64 + 1) - at(i - radius as i64);
+		}
+		result
+	};
+
+	let (width, height) = size;
+	let mut rows = Vec::with_capacity(values.len());
+	for row in values.chunks_exact(width) {
+		rows.extend(blur_line(row, topology.wrap_x));
+	}
+	let mut result = vec![0.0; values.len()];
+	for x in 0..width {
+		let column: Vec<f32> = (0..height).map(|y| rows[y * width + x]).collect();
+		for (y, value) in blur_line(&column, topology.wrap_y).into_iter().enumerate() {
+			result[y * width + x] = value;
+		}
+	}
+	result
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const WRAPPED: [Topology; 3] = [
+		Topology { wrap_x: true, wrap_y: false },
+		Topology { wrap_x: false, wrap_y: true },
+		Topology { wrap_x: true, wrap_y: true },
+	];
+
+	/// Mean height step across the `x` and the `y` seams, relative to the mean step inside the map
+	fn seam_steps(landscape: &Landscape) -> (f32, f32) {
+		let (width, height) = (landscape.size().0 as usize, landscape.size().1 as usize);
+		let at = |x: usize, y: usize| landscape.height()[y * width + x].to_num::<f32>();
+		let mean = |steps: &mut dyn Iterator<Item = f32>| {
+			let (sum, count) = steps.fold((0.0, 0), |(sum, count), step| (sum + step, count + 1));
+			sum / count as f32
+		};
+		let inner_x = mean(&mut (0..height).flat_map(|y| (1..width).map(move |x| (x, y))).map(|(x, y)| (at(x, y) - at(x - 1, y)).abs()));
+		let inner_y = mean(&mut (1..height).flat_map(|y| (0..width).map(move |x| (x, y))).map(|(x, y)| (at(x, y) - at(x, y - 1)).abs()));
+		let seam_x = mean(&mut (0..height).map(|y| (at(0, y) - at(width - 1, y)).abs()));
+		let seam_y = mean(&mut (0..width).map(|x| (at(x, 0) - at(x, height - 1)).abs()));
+		(seam_x / inner_x, seam_y / inner_y)
+	}
+
+	#[test]
+	fn wrapped_noise_repeats_along_wrapped_axes() {
+		let size = (96, 64);
+		for topology in WRAPPED {
+			let noise = WrappedNoise::new(Fbm::<Perlin>::new(7).set_frequency(0.05), size, topology);
+			for [x, y] in [[0.0, 0.0], [13.5, 7.25], [95.0, 63.0]] {
+				let value = noise.get([x, y]);
+				if topology.wrap_x {
+					assert!((noise.get([x + 96.0, y]) - value).abs() < 1e-9, "{topology:?} at {x}, {y}");
+				}
+				if topology.wrap_y {
+					assert!((noise.get([x, y + 64.0]) - value).abs() < 1e-9, "{topology:?} at {x}, {y}");
+				}
+			}
+		}
+	}
+
+	#[test]
+	fn wrapped_maps_have_no_seams() {
+		for size in [(256, 256), (300, 170)] {
+			for topology in WRAPPED {
+				let generators: [(&str, &dyn TerrainGenerator); 3] = [
+					("layered noise", &LayeredNoiseParams::default()),
+					("diamond-square", &DiamondSquareParams::default()),
+					("plates", &PlateParams::default()),
+				];
+				for (name, generator) in generators {
+					let landscape = generator.generate(size, topology, 4);
+					assert_eq!(landscape.topology(), topology);
+					let (seam_x, seam_y) = seam_steps(&landscape);
+					if topology.wrap_x {
+						assert!(seam_x < 2.0, "{name} {size:?} {topology:?}: x seam is {seam_x} steps");
+					}
+					if topology.wrap_y {
+						assert!(seam_y < 2.0, "{name} {size:?} {topology:?}: y seam is {seam_y} steps");
+					}
+				}
+			}
+		}
+	}
+
+	fn heights(landscape: &Landscape) -> Vec<f32> {
+		landscape.height().iter().map(|height| height.to_num()).collect()
+	}
+
+	#[test]
+	fn diamond_square_is_seeded_and_fills_the_map() {
+		let params = DiamondSquareParams::default();
+		let (low, high) = params.height_range;
+		for size in [(65, 65), (64, 64), (100, 37)] {
+			for topology in [Topology::FLAT, Topology { wrap_x: true, wrap_y: true }] {
+				let landscape = params.generate(size, topology, 3);
+				assert_eq!(landscape.size(), (size.0 as u32, size.1 as u32));
+				let values = heights(&landscape);
+				assert_eq!(values, heights(&params.generate(size, topology, 3)), "{size:?} {topology:?}");
+				assert_ne!(values, heights(&params.generate(size, topology, 4)), "{size:?} {topology:?}");
+
+				// Stretched to the whole range. Cells which were never set would all have the same height
+				let min = values.iter().copied().fold(f32::INFINITY, f32::min);
+				let max = values.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+				assert!((min - low).abs() < 1e-5 && (max - high).abs() < 1e-5, "{size:?} {topology:?}: {min}..{max}");
+				let mut sorted = values.clone();
+				sorted.sort_by(f32::total_cmp);
+				let repeats = sorted.windows(2).filter(|pair| pair[0] == pair[1]).count();
+				assert!(repeats < values.len() / 100, "{size:?} {topology:?}: {repeats} repeated heights");
+			}
+		}
+	}
+
+	#[test]
+	fn plates_rise_at_convergent_boundaries() {
+		let size = (256, 256);
+		let params = PlateParams {
+			ocean_height: 0.4,
+			continent_height: 0.4,
+			boundary_noise: 0.0,
+			detail: 0.0,
+			..Default::default()
+		};
+		let landscape = params.generate(size, Topology::FLAT, 11);
+		let values = heights(&landscape);
+		// The generator starts with the same layout
+		let layout = PlateLayout::new(&params, size, Topology::FLAT, &mut StdRng::seed_from_u64(11));
+		let plates = layout.plates();
+
+		let (mut boundary, mut interior) = (vec![], vec![]);
+		for (id, height) in values.iter().enumerate() {
+			let point = layout.warp(id as u64 % size.0, id as u64 / size.0);
+			let (first, Some(second)) = layout.closest(point) else { continue };
+			let (a, b) = (&plates[first], &plates[second]);
+			let (to_a, to_b) = (layout.offset(point, a), layout.offset(point, b));
+			let axis = (to_b.0 - to_a.0, to_b.1 - to_a.1);
+			let axis_length = (axis.0 * axis.0 + axis.1 * axis.1).sqrt();
+			let distance = (to_b.0 * to_b.0 + to_b.1 * to_b.1 - to_a.0 * to_a.0 - to_a.1 * to_a.1) / (2.0 * axis_length);
+			let convergence = ((a.velocity.0 - b.velocity.0) * axis.0 + (a.velocity.1 - b.velocity.1) * axis.1) / axis_length;
+			if distance < 4.0 && convergence > 0.5 {
+				boundary.push(*height);
+			} else if distance > 2.0 * params.boundary_width {
+				interior.push(*height);
+			}
+		}
+		let mean = |values: &[f32]| values.iter().sum::<f32>() / values.len() as f32;
+		assert!(boundary.len() > 100 && interior.len() > 100, "{} boundary and {} interior cells", boundary.len(), interior.len());
+		assert!((mean(&interior) - 0.4).abs() < 0.01, "interior at {}", mean(&interior));
+		assert!(mean(&boundary) > 0.4 + 0.1, "convergent boundaries at {}", mean(&boundary));
+	}
+
+	#[test]
+	fn noise_layers_and_warp_change_the_output() {
+		let size = (128, 96);
+		let layer = NoiseLayer { frequency: 0.02, octaves: 4, ..Default::default() };
+		let generate = |layers: Vec<NoiseLayer>, domain_warp: Option<DomainWarp>| {
+			let params = LayeredNoiseParams { base_height: 0.5, layers, domain_warp };
+			heights(&params.generate(size, Topology::FLAT, 5))
+		};
+		let difference = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(a, b)| (a - b).abs()).sum::<f32>() / a.len() as f32;
+
+		let fbm = generate(vec![layer], None);
+		assert_eq!(fbm, generate(vec![layer], None));
+		let ridged = generate(vec![NoiseLayer { kind: NoiseKind::Ridged, ..layer }], None);
+		let billow = generate(vec![NoiseLayer { kind: NoiseKind::Billow, ..layer }], None);
+		let warped = generate(vec![layer], Some(DomainWarp { frequency: 0.01, strength: 20.0 }));
+		let added = generate(vec![layer, NoiseLayer { kind: NoiseKind::Ridged, amplitude: 0.1, ..layer }], None);
+		for (name, other) in [("ridged", &ridged), ("billow", &billow), ("warp", &warped), ("second layer", &added)] {
+			assert!(difference(&fbm, other) > 0.01, "{name} changes by {}", difference(&fbm, other));
+		}
+		assert!(difference(&ridged, &billow) > 0.01);
+	}
+}
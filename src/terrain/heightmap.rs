@@ -0,0 +1,213 @@
+use std::ops::RangeInclusive;
+
+use image::{DynamicImage, ImageBuffer, Luma};
+use image::imageops::{self, FilterType};
+
+use crate::map::{self, Height, Landscape};
+
+/// Which values of the image become heights
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HeightChannel {
+	/// Brightness, the only channel of grayscale images
+	Luma,
+	Red,
+	Green,
+	Blue,
+	Alpha,
+}
+
+impl HeightChannel {
+	pub fn all() -> &'static [HeightChannel] {
+		&[HeightChannel::Luma, HeightChannel::Red, HeightChannel::Green, HeightChannel::Blue, HeightChannel::Alpha]
+	}
+
+	pub fn localized_name(&self) -> &'static str {
+		match self {
+			HeightChannel::Luma => "Brightness",
+			HeightChannel::Red => "Red",
+			HeightChannel::Green => "Green",
+			HeightChannel::Blue => "Blue",
+			HeightChannel::Alpha => "Alpha",
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resampling {
+	Nearest,
+	Bilinear,
+	Bicubic,
+	Lanczos,
+}
+
+impl Resampling {
+	pub fn all() -> &'static [Resampling] {
+		&[Resampling::Nearest, Resampling::Bilinear, Resampling::Bicubic, Resampling::Lanczos]
+	}
+
+	pub fn localized_name(&self) -> &'static str {
+		match self {
+			Resampling::Nearest => "Nearest",
+			Resampling::Bilinear => "Bilinear",
+			Resampling::Bicubic => "Bicubic",
+			Resampling::Lanczos => "Lanczos",
+		}
+	}
+
+	fn filter(&self) -> FilterType {
+		match self {
+			Resampling::Nearest => FilterType::Nearest,
+			Resampling::Bilinear => FilterType::Triangle,
+			Resampling::Bicubic => FilterType::CatmullRom,
+			Resampling::Lanczos => FilterType::Lanczos3,
+		}
+	}
+}
+
+/// How image values (`0.0..=1.0` whatever the bit depth is) become heights
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeightmapParams {
+	pub channel: HeightChannel,
+	/// Height of the black pixels
+	pub min_height: f32,
+	/// Height of the white pixels
+	pub max_height: f32,
+	/// The darkest pixel of the image becomes black and the brightest one white
+	pub stretch: bool,
+	/// Image value which lands exactly on the sea level, the values below and above
+	/// it are stretched separately. `None` - one linear mapping for all values
+	pub sea_value: Option<f32>,
+	pub resampling: Resampling,
+}
+
+impl Default for HeightmapParams {
+	fn default() -> Self {
+		HeightmapParams {
+			channel: HeightChannel::Luma,
+			min_height: 0.0,
+			max_height: 1.0,
+			stretch: false,
+			sea_value: None,
+			resampling: Resampling::Bilinear,
+		}
+	}
+}
+
+impl HeightmapParams {
+	pub const HEIGHT_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+	pub const VALUE_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+
+	pub fn clamped(&self) -> Self {
+		let clamp = |value: f32, range: &RangeInclusive<f32>| value.clamp(*range.start(), *range.end());
+		HeightmapParams {
+			min_height: clamp(self.min_height, &Self::HEIGHT_RANGE),
+			max_height: clamp(self.max_height, &Self::HEIGHT_RANGE),
+			sea_value: self.sea_value.map(|value| clamp(value, &Self::VALUE_RANGE)),
+			..self.clone()
+		}
+	}
+
+	/// Height of an image value
+	fn height(&self, value: f32, sea_level: f32) -> f32 {
+		let lerp = |from: f32, to: f32, t: f32| from + (to - from) * t;
+		match self.sea_value {
+			Some(sea_value) if value <= sea_value && sea_value > 0.0 => lerp(self.min_height, sea_level, value / sea_value),
+			Some(sea_value) if value > sea_value && sea_value < 1.0 => lerp(sea_level, self.max_height, (value - sea_value) / (1.0 - sea_value)),
+			Some(_) => sea_level,
+			None => lerp(self.min_height, self.max_height, value),
+		}
+	}
+}
+
+/// Values of one channel, `0.0..=1.0`
+fn channel_values(image: &DynamicImage, channel: HeightChannel) -> ImageBuffer<Luma<f32>, Vec<f32>> {
+	let index = match channel {
+		HeightChannel::Luma => return image.to_luma32f(),
+		HeightChannel::Red => 0,
+		HeightChannel::Green => 1,
+		HeightChannel::Blue => 2,
+		HeightChannel::Alpha => 3,
+	};
+	let rgba = image.to_rgba32f();
+	ImageBuffer::from_fn(rgba.width(), rgba.height(), |x, y| Luma([rgba.get_pixel(x, y).0[index]]))
+}
+
+/// Landscape of `size` from a grayscale or colour image (8 or 16 bits per channel,
+/// any format `image` can read). The image is resampled to `size` if it differs.
+pub fn import_heightmap(image: &DynamicImage, size: (u32, u32), sea_level: Height, params: &HeightmapParams) -> Landscape {
+	let params = params.clamped();
+	let mut values = channel_values(image, params.channel);
+	if values.dimensions() != size && size.0 > 0 && size.1 > 0 {
+		values = imageops::resize(&values, size.0, size.1, params.resampling.filter());
+	}
+
+	let (min, max) = match params.stretch {
+		true => values.pixels().fold((f32::INFINITY, f32::NEG_INFINITY), |(min, max), pixel| (min.min(pixel.0[0]), max.max(pixel.0[0]))),
+		false => (0.0, 1.0),
+	};
+	let range = if max > min { max - min } else { 1.0 };
+
+	let sea_level = sea_level.to_num::<f32>();
+	let height = values.pixels()
+		.map(|pixel| {
+			// Filters other than `Nearest` may overshoot a bit
+			let value = ((pixel.0[0] - min) / range).clamp(0.0, 1.0);
+			map::height_from_f32(params.height(value, sea_level))
+		})
+		.collect();
+	Landscape::new(values.dimensions(), height)
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::Cursor;
+
+	use image::ImageOutputFormat;
+
+	use super::*;
+
+	/// Encodes `image` as PNG and decodes it back, like a file would go
+	fn through_png(image: DynamicImage) -> DynamicImage {
+		let mut bytes = Cursor::new(vec![]);
+		image.write_to(&mut bytes, ImageOutputFormat::Png).unwrap();
+		image::load_from_memory(bytes.get_ref()).unwrap()
+	}
+
+	#[test]
+	fn sixteen_bit_png_keeps_its_precision() {
+		// Neighbouring values differ by one 16-bit step, which 8 bits would merge
+		let pixels: Vec<u16> = (0..64 * 4).map(|i| 30000 + i as u16).collect();
+		let image = through_png(DynamicImage::ImageLuma16(ImageBuffer::from_raw(64, 4, pixels.clone()).unwrap()));
+		assert!(matches!(image, DynamicImage::ImageLuma16(_)));
+
+		let landscape = import_heightmap(&image, (64, 4), Height::from_num(0.5), &HeightmapParams::default());
+		assert_eq!(landscape.size(), (64, 4));
+		for (height, pixel) in landscape.height().iter().zip(&pixels) {
+			let expected = *pixel as f32 / u16::MAX as f32;
+			assert!((height.to_num::<f32>() - expected).abs() < 1e-5, "{height} instead of {expected}");
+		}
+		for pair in landscape.height().windows(2) {
+			assert!(pair[0] < pair[1]);
+		}
+	}
+
+	#[test]
+	fn values_map_around_the_sea_level() {
+		let sea_level = 0.43;
+		let params = HeightmapParams { min_height: 0.1, max_height: 0.9, sea_value: Some(0.25), ..Default::default() };
+		for (value, expected) in [(0.0, 0.1), (0.125, 0.265), (0.25, sea_level), (0.625, 0.665), (1.0, 0.9)] {
+			assert!((params.height(value, sea_level) - expected).abs() < 1e-6, "{value}");
+		}
+
+		let linear = HeightmapParams { min_height: 0.2, max_height: 0.6, ..Default::default() };
+		assert!((linear.height(0.5, sea_level) - 0.4).abs() < 1e-6);
+
+		// Stretching makes the darkest pixel black and the brightest one white
+		let image = DynamicImage::ImageLuma8(ImageBuffer::from_raw(3, 1, vec![64, 128, 192]).unwrap());
+		let stretched = import_heightmap(&image, (3, 1), Height::from_num(sea_level), &HeightmapParams { stretch: true, ..linear });
+		let heights: Vec<f32> = stretched.height().iter().map(|height| height.to_num()).collect();
+		for (height, expected) in heights.iter().zip([0.2, 0.4, 0.6]) {
+			assert!((height - expected).abs() < 1e-5, "{heights:?}");
+		}
+	}
+}
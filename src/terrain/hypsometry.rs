@@ -0,0 +1,153 @@
+use std::ops::RangeInclusive;
+
+use crate::map::{self, Height, Landscape};
+use super::tiles;
+
+/// Target distribution of heights (the hypsometric curve) for `normalize`. Cells
+/// are ranked by height, the lowest `1 - land_fraction` of them go below the sea
+/// level and the rest above it, and within each part the rank sets the height.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HypsometryParams {
+	/// Share of the cells above the sea level
+	pub land_fraction: f32,
+	/// Depth of the lowest cell below the sea level
+	pub max_depth: f32,
+	/// Height of the highest cell above the sea level
+	pub max_altitude: f32,
+	/// `1.0` - land heights are spread evenly, larger - mostly lowlands with a few
+	/// high mountains, smaller - mostly highlands
+	pub land_exponent: f32,
+	/// Same for the sea: larger - mostly shallow shelf, smaller - mostly deep sea
+	pub sea_exponent: f32,
+	/// `0.0` keeps the heights, `1.0` moves them onto the curve
+	pub strength: f32,
+}
+
+impl Default for HypsometryParams {
+	fn default() -> Self {
+		HypsometryParams {
+			land_fraction: 0.3,
+			max_depth: map::DEFAULT_SEA_LEVEL as f32,
+			max_altitude: 0.55,
+			land_exponent: 2.0,
+			sea_exponent: 0.5,
+			strength: 1.0,
+		}
+	}
+}
+
+impl HypsometryParams {
+	pub const FRACTION_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+	pub const HEIGHT_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+	pub const EXPONENT_RANGE: RangeInclusive<f32> = 0.1..=10.0;
+
+	pub fn clamped(&self) -> Self {
+		let clamp = |value: f32, range: &RangeInclusive<f32>| value.clamp(*range.start(), *range.end());
+		HypsometryParams {
+			land_fraction: clamp(self.land_fraction, &Self::FRACTION_RANGE),
+			max_depth: clamp(self.max_depth, &Self::HEIGHT_RANGE),
+			max_altitude: clamp(self.max_altitude, &Self::HEIGHT_RANGE),
+			land_exponent: clamp(self.land_exponent, &Self::EXPONENT_RANGE),
+			sea_exponent: clamp(self.sea_exponent, &Self::EXPONENT_RANGE),
+			strength: clamp(self.strength, &Self::FRACTION_RANGE),
+		}
+	}
+}
+
+/// Share of the cells above `sea_level`
+pub fn land_fraction(landscape: &Landscape, sea_level: Height) -> f32 {
+	let heights = landscape.height();
+	let land = heights.iter().filter(|height| **height > sea_level).count();
+	land as f32 / heights.len().max(1) as f32
+}
+
+/// Remaps the heights onto the curve of `params` around `sea_level`. The order of
+/// the cells by height is kept, and cells of the same height stay at the same
+/// height, so the land fraction is exact up to the flat areas at the sea level.
+pub fn normalize(landscape: &mut Landscape, sea_level: Height, params: &HypsometryParams) {
+	let params = params.clamped();
+	let width = landscape.size().0 as usize;
+	let heights = landscape.height_mut();
+	let cells = heights.len();
+	if cells == 0 {
+		return;
+	}
+
+	let mut sorted = heights.to_vec();
+	sorted.sort_unstable();
+	let sea_cells = ((1.0 - params.land_fraction) as f64 * cells as f64).round() as usize;
+	let land_cells = cells - sea_cells;
+
+	let target = |rank: f64| -> Height {
+		if rank < sea_cells as f64 {
+			// From the deepest cell (`1.0`) to the coast
+			let depth = 1.0 - (rank + 0.5) / sea_cells as f64;
+			sea_level - map::height_from_f32(params.max_depth * (depth as f32).powf(params.sea_exponent))
+		} else {
+			let altitude = (rank - sea_cells as f64 + 0.5) / land_cells as f64;
+			let height = sea_level + map::height_from_f32(params.max_altitude * (altitude as f32).powf(params.land_exponent));
+			// Land stays land even where the curve is flatter than the height precision
+			height.max(sea_level + Height::DELTA)
+		}
+	};
+
+	tiles::fill_rows(heights, width, |_, row| {
+		for cell in row.iter_mut() {
+			// Cells of the same height share the rank in the middle of them
+			let first = sorted.partition_point(|height| *height < *cell);
+			let last = sorted.partition_point(|height| *height <= *cell) - 1;
+			let target = target((first + last) as f64 / 2.0);
+			*cell = if params.strength >= 1.0 {
+				target
+			} else {
+				let (height, target) = (cell.to_num::<f32>(), target.to_num::<f32>());
+				map::height_from_f32(height + (target - height) * params.strength)
+			};
+		}
+	});
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Every cell of a different height, in a scrambled order
+	fn scrambled(size: (u32, u32)) -> Landscape {
+		let cells = size.0 * size.1;
+		let heights = (0..cells)
+			.map(|id| map::height_from_f32((id * 7919 % cells) as f32 / cells as f32))
+			.collect();
+		Landscape::new(size, heights)
+	}
+
+	#[test]
+	fn land_fraction_is_hit_exactly() {
+		let sea_level = Height::from_num(map::DEFAULT_SEA_LEVEL);
+		for fraction in [0.0, 0.05, 0.3, 0.5, 0.77, 1.0] {
+			let mut landscape = scrambled((100, 80));
+			normalize(&mut landscape, sea_level, &HypsometryParams { land_fraction: fraction, ..Default::default() });
+
+			let land = landscape.height().iter().filter(|height| **height > sea_level).count();
+			assert_eq!(land, 8000 - ((1.0 - fraction) as f64 * 8000.0).round() as usize, "{fraction}");
+			assert_eq!(land_fraction(&landscape, sea_level), land as f32 / 8000.0);
+		}
+	}
+
+	#[test]
+	fn order_of_cells_is_kept() {
+		let sea_level = Height::from_num(0.5);
+		let original = scrambled((40, 30));
+		let mut landscape = original.clone();
+		normalize(&mut landscape, sea_level, &HypsometryParams::default());
+
+		let mut ids: Vec<usize> = (0..original.height().len()).collect();
+		ids.sort_by_key(|id| original.height()[*id]);
+		for pair in ids.windows(2) {
+			assert!(landscape.height()[pair[0]] <= landscape.height()[pair[1]]);
+		}
+
+		let mut kept = original.clone();
+		normalize(&mut kept, sea_level, &HypsometryParams { strength: 0.0, ..Default::default() });
+		assert_eq!(kept.height(), original.height());
+	}
+}
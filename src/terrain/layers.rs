@@ -0,0 +1,229 @@
+use std::ops::RangeInclusive;
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+
+use crate::map::{self, Height, Landscape};
+use super::generators::WrappedNoise;
+
+/// Where the erodibility of the bedrock comes from
+#[derive(Debug, Clone, PartialEq)]
+pub enum HardnessSource {
+	/// The same rock everywhere
+	Uniform,
+	/// Veins of harder and softer rock. With `contrast` of `1.0` the hardness
+	/// covers the whole `0.0..=1.0` range, with `0.0` it is `0.5` everywhere
+	Noise { frequency: f64, contrast: f32 },
+	/// White is the hardest rock, stretched over the whole map
+	Image(image::GrayImage),
+}
+
+impl HardnessSource {
+	pub fn localized_name(&self) -> &'static str {
+		match self {
+			HardnessSource::Uniform => "Uniform",
+			HardnessSource::Noise { .. } => "Noise",
+			HardnessSource::Image(_) => "Hardness map",
+		}
+	}
+}
+
+/// Initial state of the terrain layers of a new world
+#[derive(Debug, Clone, PartialEq)]
+pub struct LayerParams {
+	pub hardness: HardnessSource,
+	/// Soil thickness on flat land
+	pub soil_depth: f32,
+	/// Soil gets thinner on slopes and there is none where the biggest height
+	/// difference with a side neighbour is above this
+	pub max_soil_slope: f32,
+	/// Soil at least this deep is fully fertile
+	pub fertile_depth: f32,
+}
+
+impl Default for LayerParams {
+	fn default() -> Self {
+		LayerParams {
+			hardness: HardnessSource::Noise { frequency: 0.02, contrast: 0.8 },
+			soil_depth: 0.02,
+			max_soil_slope: 0.03,
+			fertile_depth: 0.01,
+		}
+	}
+}
+
+/// How fast the droplets erode each layer, see `erosion.glsl`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ErodibilityParams {
+	pub soil: f32,
+	/// Multiplied by the erodibility of the bedrock in the cell
+	pub rock: f32,
+}
+
+impl ErodibilityParams {
+	pub const ERODIBILITY_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+
+	pub fn clamped(&self) -> Self {
+		let clamp_f32 = |val: f32, range: RangeInclusive<f32>| val.clamp(*range.start(), *range.end());
+
+		ErodibilityParams {
+			soil: clamp_f32(self.soil, Self::ERODIBILITY_RANGE),
+			rock: clamp_f32(self.rock, Self::ERODIBILITY_RANGE),
+		}
+	}
+
+	/// Splits `amount` of erosion of a cell with `soil` on top of bedrock of `rock_erodibility`
+	/// like `erode_layers` in `erosion.glsl`: the soil goes first and what is left after it is
+	/// spent on the bedrock. Returns the amounts taken from the soil and from the bedrock.
+	#[cfg(test)]
+	pub fn erode(&self, amount: f32, soil: f32, rock_erodibility: f32) -> (f32, f32) {
+		let from_soil = (amount * self.soil).min(soil.max(0.0));
+		let rest = if self.soil > f32::EPSILON { amount - from_soil / self.soil } else { amount };
+		(from_soil, rest * self.rock * rock_erodibility)
+	}
+}
+
+impl Default for ErodibilityParams {
+	fn default() -> Self {
+		ErodibilityParams {
+			soil: 1.0,
+			rock: 0.25,
+		}
+	}
+}
+
+/// The landscape height is the top of the soil, bedrock lies `soil` below it.
+#[derive(Debug, Clone)]
+pub struct TerrainLayers {
+	size: (u32, u32),
+	soil: Box<[Height]>,
+	/// Of the bedrock, `0.0` - does not erode, `1.0` - erodes as fast as it can
+	rock_erodibility: Box<[f32]>,
+}
+
+impl TerrainLayers {
+	pub fn new(landscape: &Landscape, params: &LayerParams, noise_seed: u32) -> Self {
+		let size = landscape.size();
+		let width = size.0 as i32;
+		let heights = landscape.height();
+		let topology = landscape.topology();
+
+		let slope = |x: i32, y: i32| {
+			let center = heights[(y * width + x) as usize];
+			[(-1, 0), (1, 0), (0, -1), (0, 1)].iter()
+				.filter_map(|(dx, dy)| topology.cell(size, (x + dx) as i64, (y + dy) as i64))
+				.map(|(nx, ny)| (center - heights[ny as usize * width as usize + nx as usize]).abs().to_num::<f32>())
+				.fold(0.0, f32::max)
+		};
+		let max_slope = params.max_soil_slope.max(f32::EPSILON);
+		let soil = (0..heights.len() as i32)
+			.map(|id| {
+				let steepness = (slope(id % width, id / width) / max_slope).min(1.0);
+				map::height_from_f32(params.soil_depth.max(0.0) * (1.0 - steepness))
+			})
+			.collect();
+
+		let rock_erodibility = match &params.hardness {
+			HardnessSource::Uniform => vec![1.0; heights.len()].into_boxed_slice(),
+			HardnessSource::Noise { frequency, contrast } => {
+				let noise = Fbm::<Perlin>::new(noise_seed).set_frequency(*frequency).set_octaves(4);
+				let noise = WrappedNoise::new(noise, (size.0 as u64, size.1 as u64), topology);
+				let contrast = contrast.clamp(0.0, 1.0);
+				(0..heights.len() as i32)
+					.map(|id| {
+						let value = noise.get([(id % width) as f64, (id / width) as f64]).clamp(-1.0, 1.0) as f32;
+						0.5 - value * contrast / 2.0
+					})
+					.collect()
+			}
+			HardnessSource::Image(image) => hardness_from_image(image, size).iter()
+				.map(|hardness| 1.0 - hardness)
+				.collect(),
+		};
+
+		TerrainLayers { size, soil, rock_erodibility }
+	}
+
+	/// `soil` and `rock_erodibility` have to be of `size`
+	pub fn from_parts(size: (u32, u32), soil: Box<[Height]>, rock_erodibility: Box<[f32]>) -> Self {
+		TerrainLayers { size, soil, rock_erodibility }
+	}
+
+	pub fn size(&self) -> (u32, u32) {
+		self.size
+	}
+
+	/// Thickness of the soil (and sediment) layer
+	pub fn soil(&self) -> &[Height] {
+		&self.soil
+	}
+
+	pub fn rock_erodibility(&self) -> &[f32] {
+		&self.rock_erodibility
+	}
+
+	/// Height of the bedrock under the landscape
+	pub fn bedrock(&self, landscape: &Landscape) -> Box<[Height]> {
+		landscape.height().iter().zip(self.soil.iter())
+			.map(|(height, soil)| height.saturating_sub((*soil).max(Height::ZERO)))
+			.collect()
+	}
+
+	/// `0.0` - bare rock, `1.0` - at least `fertile_depth` of soil
+	pub fn fertility(&self, fertile_depth: f32) -> Box<[f32]> {
+		let fertile_depth = fertile_depth.max(f32::EPSILON);
+		self.soil.iter()
+			.map(|soil| (soil.to_num::<f32>() / fertile_depth).clamp(0.0, 1.0))
+			.collect()
+	}
+}
+
+/// Hardness (`0.0..=1.0`) of each cell of a map of `size`, with the image stretched over it
+pub fn hardness_from_image(image: &image::GrayImage, size: (u32, u32)) -> Box<[f32]> {
+	let (width, height) = (image.width().max(1), image.height().max(1));
+	(0..size.0 as u64 * size.1 as u64)
+		.map(|id| {
+			let (x, y) = (id % size.0 as u64, id / size.0 as u64);
+			let (ix, iy) = ((x * width as u64 / size.0 as u64) as u32, (y * height as u64 / size.1 as u64) as u32);
+			image.get_pixel_checked(ix, iy).map_or(0.5, |pixel| pixel.0[0] as f32 / 255.0)
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn soil_erodes_faster_than_rock() {
+		let params = ErodibilityParams::default();
+		let (from_soil, from_rock) = params.erode(0.01, 1.0, 1.0);
+		assert_eq!((from_soil, from_rock), (0.01, 0.0));
+		let (from_soil, from_rock) = params.erode(0.01, 0.0, 1.0);
+		assert_eq!(from_soil, 0.0);
+		assert!((from_rock - 0.0025).abs() < 1e-6, "{from_rock}");
+
+		// Only a part of the amount is left for the bedrock when the soil runs out
+		let (from_soil, from_rock) = params.erode(0.01, 0.004, 1.0);
+		assert_eq!(from_soil, 0.004);
+		assert!((from_rock - 0.006 * 0.25).abs() < 1e-6, "{from_rock}");
+
+		// Harder bedrock erodes slower
+		assert!(params.erode(0.01, 0.0, 0.2).1 < params.erode(0.01, 0.0, 0.8).1);
+	}
+
+	#[test]
+	fn eroding_slows_down_at_the_bedrock() {
+		let params = ErodibilityParams::default();
+		let (mut soil, mut depth) = (0.05_f32, 0.0_f32);
+		let mut steps = vec![];
+		for _ in 0..20 {
+			let (from_soil, from_rock) = params.erode(0.01, soil, 1.0);
+			soil -= from_soil;
+			depth += from_soil + from_rock;
+			steps.push(from_soil + from_rock);
+		}
+		assert!(soil.abs() < 1e-6);
+		assert!(steps[0] > 3.0 * steps[19], "{steps:?}");
+		assert!(steps.windows(2).all(|pair| pair[1] <= pair[0] + 1e-6), "{steps:?}");
+		assert!((depth - (0.05 + 15.0 * 0.0025)).abs() < 1e-4, "{depth}");
+	}
+}
@@ -0,0 +1,617 @@
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use crate::map::{Biome, Height, Landscape, Map};
+
+/// Walls and bottom of a solid mesh
+const BASE_COLOR: [u8; 3] = [120, 110, 100];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MeshFormat {
+	/// Wavefront OBJ, vertex colours are written after the positions
+	Obj,
+	/// Binary STL, Z is up. Has no colours
+	Stl,
+	/// Binary glTF 2.0 (`.glb`)
+	Gltf,
+}
+
+impl MeshFormat {
+	pub fn all() -> &'static [MeshFormat] {
+		&[MeshFormat::Obj, MeshFormat::Stl, MeshFormat::Gltf]
+	}
+
+	pub fn localized_name(&self) -> &'static str {
+		match self {
+			MeshFormat::Obj => "OBJ",
+			MeshFormat::Stl => "STL",
+			MeshFormat::Gltf => "glTF",
+		}
+	}
+
+	pub fn extension(&self) -> &'static str {
+		match self {
+			MeshFormat::Obj => "obj",
+			MeshFormat::Stl => "stl",
+			MeshFormat::Gltf => "glb",
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeshParams {
+	/// Distance between neighbour cells in the mesh units
+	pub cell_size: f32,
+	/// Heights are multiplied by it, with `1.0` a cell is as wide as `1.0` of height
+	pub exaggeration: f32,
+	/// Quad-tree simplification: a quad is split while the landscape under it is farther than
+	/// this (in heights) from its triangles, so flat areas take few of them. `None` - a vertex for every cell
+	pub max_error: Option<f32>,
+	/// Adds a flat rectangle at this height over the whole map
+	pub sea_plane: Option<Height>,
+	/// Closes the mesh with walls and a flat bottom this much (in the mesh units) below
+	/// the lowest vertex, so it can be 3D-printed. `None` - only the surface
+	pub base: Option<f32>,
+}
+
+impl Default for MeshParams {
+	fn default() -> Self {
+		MeshParams {
+			cell_size: 1.0,
+			exaggeration: 20.0,
+			max_error: Some(0.002),
+			sea_plane: None,
+			base: None,
+		}
+	}
+}
+
+impl MeshParams {
+	pub const CELL_SIZE_RANGE: RangeInclusive<f32> = 0.001..=1000.0;
+	pub const EXAGGERATION_RANGE: RangeInclusive<f32> = 0.0..=1000.0;
+	pub const MAX_ERROR_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+	pub const BASE_RANGE: RangeInclusive<f32> = 0.0..=1000.0;
+
+	pub fn clamped(&self) -> Self {
+		let clamp = |value: f32, range: &RangeInclusive<f32>| value.clamp(*range.start(), *range.end());
+		MeshParams {
+			cell_size: clamp(self.cell_size, &Self::CELL_SIZE_RANGE),
+			exaggeration: clamp(self.exaggeration, &Self::EXAGGERATION_RANGE),
+			max_error: self.max_error.map(|error| clamp(error, &Self::MAX_ERROR_RANGE)),
+			sea_plane: self.sea_plane,
+			base: self.base.map(|base| clamp(base, &Self::BASE_RANGE)),
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum MeshError {
+	Io { io_error: io::Error },
+	/// Biome map is not of the landscape size
+	SizeMismatch { landscape: (u32, u32), map: (u32, u32) },
+}
+
+impl Display for MeshError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+		match self {
+			MeshError::Io { io_error } => write!(f, "Failed to write mesh: {}", io_error),
+			MeshError::SizeMismatch { landscape, map } => write!(
+				f, "Landscape is {}x{} but biomes map is {}x{}", landscape.0, landscape.1, map.0, map.1,
+			),
+		}
+	}
+}
+
+impl From<io::Error> for MeshError {
+	fn from(io_error: io::Error) -> Self {
+		MeshError::Io { io_error }
+	}
+}
+
+/// Triangle mesh, Y is up. X goes along the rows of the map and Z along the columns,
+/// so the first row is the farthest one (north) when looking along -Z
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TerrainMesh {
+	pub positions: Vec<[f32; 3]>,
+	pub normals: Vec<[f32; 3]>,
+	/// Present if the mesh was built with a biome map
+	pub colors: Option<Vec<[u8; 3]>>,
+	/// Counter-clockwise when seen from the outside
+	pub triangles: Vec<[u32; 3]>,
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+	[a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+	[a[1] * b[2] - a[2] * b[1], a[2] * b[0] - a[0] * b[2], a[0] * b[1] - a[1] * b[0]]
+}
+
+fn normalize(vector: [f32; 3]) -> [f32; 3] {
+	let length = vector.iter().map(|value| value * value).sum::<f32>().sqrt();
+	if length > 0.0 { vector.map(|value| value / length) } else { [0.0, 1.0, 0.0] }
+}
+
+impl TerrainMesh {
+	fn push_vertex(&mut self, position: [f32; 3], normal: [f32; 3], color: [u8; 3]) -> u32 {
+		self.positions.push(position);
+		self.normals.push(normal);
+		if let Some(colors) = &mut self.colors {
+			colors.push(color);
+		}
+		(self.positions.len() - 1) as u32
+	}
+
+	fn triangle_normal(&self, [a, b, c]: [u32; 3]) -> [f32; 3] {
+		let [a, b, c] = [a, b, c].map(|i| self.positions[i as usize]);
+		cross(sub(b, a), sub(c, a))
+	}
+
+	/// Flat quad of four new vertices, `corners` go counter-clockwise when seen from the outside
+	fn push_quad(&mut self, corners: [[f32; 3]; 4], color: [u8; 3]) {
+		let normal = normalize(cross(sub(corners[1], corners[0]), sub(corners[2], corners[0])));
+		let [a, b, c, d] = corners.map(|corner| self.push_vertex(corner, normal, color));
+		self.triangles.push([a, b, c]);
+		self.triangles.push([a, c, d]);
+	}
+
+	/// Lower and upper corners of the bounding box
+	pub fn bounds(&self) -> ([f32; 3], [f32; 3]) {
+		if self.positions.is_empty() {
+			return ([0.0; 3], [0.0; 3]);
+		}
+		self.positions.iter().fold(([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]), |(min, max), position| {
+			([0, 1, 2].map(|i| min[i].min(position[i])), [0, 1, 2].map(|i| max[i].max(position[i])))
+		})
+	}
+
+	pub fn write_obj(&self, mut writer: impl Write) -> io::Result<()> {
+		writeln!(writer, "# ecosim landscape")?;
+		for (i, [x, y, z]) in self.positions.iter().enumerate() {
+			match &self.colors {
+				Some(colors) => {
+					let [r, g, b] = colors[i].map(|channel| channel as f32 / u8::MAX as f32);
+					writeln!(writer, "v {} {} {} {:.4} {:.4} {:.4}", x, y, z, r, g, b)?;
+				}
+				None => writeln!(writer, "v {} {} {}", x, y, z)?,
+			}
+		}
+		for [x, y, z] in &self.normals {
+			writeln!(writer, "vn {:.4} {:.4} {:.4}", x, y, z)?;
+		}
+		for triangle in &self.triangles {
+			let [a, b, c] = triangle.map(|i| i + 1);
+			writeln!(writer, "f {}//{} {}//{} {}//{}", a, a, b, b, c, c)?;
+		}
+		Ok(())
+	}
+
+	/// Z is up in STL, the mesh is turned so that north is +Y
+	pub fn write_stl(&self, mut writer: impl Write) -> io::Result<()> {
+		let z_up = |[x, y, z]: [f32; 3]| [x, -z, y];
+		let mut header = [0; 80];
+		let title = b"ecosim landscape";
+		header[..title.len()].copy_from_slice(title);
+		writer.write_all(&header)?;
+		writer.write_all(&(self.triangles.len() as u32).to_le_bytes())?;
+		for triangle in &self.triangles {
+			let normal = normalize(self.triangle_normal(*triangle));
+			for vector in [normal].into_iter().chain(triangle.map(|i| self.positions[i as usize])) {
+				for value in z_up(vector) {
+					writer.write_all(&value.to_le_bytes())?;
+				}
+			}
+			writer.write_all(&0_u16.to_le_bytes())?;
+		}
+		Ok(())
+	}
+
+	/// Binary glTF with one mesh. Colours are converted to linear ones, as glTF wants them
+	pub fn write_glb(&self, mut writer: impl Write) -> io::Result<()> {
+		const ARRAY_BUFFER: u32 = 34962;
+		const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+		const FLOAT: u32 = 5126;
+		const UNSIGNED_INT: u32 = 5125;
+		let to_linear = |channel: u8| {
+			let channel = channel as f32 / u8::MAX as f32;
+			if channel <= 0.04045 { channel / 12.92 } else { ((channel + 0.055) / 1.055).powf(2.4) }
+		};
+
+		// One view per accessor, with the same index. Every value is 4 bytes long, so they all stay aligned
+		let mut buffer: Vec<u8> = vec![];
+		let mut views = vec![];
+		let mut add_view = |buffer: &mut Vec<u8>, bytes: Vec<u8>, target: u32| {
+			views.push(format!(
+				"{{\"buffer\": 0, \"byteOffset\": {}, \"byteLength\": {}, \"target\": {}}}", buffer.len(), bytes.len(), target,
+			));
+			buffer.extend(bytes);
+		};
+		let floats = |vectors: &mut dyn Iterator<Item = [f32; 3]>| -> Vec<u8> {
+			vectors.flat_map(|vector| vector.into_iter().flat_map(f32::to_le_bytes)).collect()
+		};
+
+		let (min, max) = self.bounds();
+		let count = self.positions.len();
+		let mut accessors = vec![format!(
+			"{{\"bufferView\": 0, \"componentType\": {}, \"count\": {}, \"type\": \"VEC3\", \"min\": [{}, {}, {}], \"max\": [{}, {}, {}]}}",
+			FLOAT, count, min[0], min[1], min[2], max[0], max[1], max[2],
+		)];
+		add_view(&mut buffer, floats(&mut self.positions.iter().copied()), ARRAY_BUFFER);
+		accessors.push(format!("{{\"bufferView\": 1, \"componentType\": {}, \"count\": {}, \"type\": \"VEC3\"}}", FLOAT, count));
+		add_view(&mut buffer, floats(&mut self.normals.iter().copied()), ARRAY_BUFFER);
+		let mut attributes = vec!["\"POSITION\": 0".to_owned(), "\"NORMAL\": 1".to_owned()];
+		if let Some(colors) = &self.colors {
+			attributes.push(format!("\"COLOR_0\": {}", accessors.len()));
+			accessors.push(format!(
+				"{{\"bufferView\": {}, \"componentType\": {}, \"count\": {}, \"type\": \"VEC3\"}}", accessors.len(), FLOAT, count,
+			));
+			add_view(&mut buffer, floats(&mut colors.iter().map(|color| color.map(to_linear))), ARRAY_BUFFER);
+		}
+		let indices = accessors.len();
+		accessors.push(format!(
+			"{{\"bufferView\": {}, \"componentType\": {}, \"count\": {}, \"type\": \"SCALAR\"}}",
+			indices, UNSIGNED_INT, self.triangles.len() * 3,
+		));
+		let index_bytes = self.triangles.iter().flatten().flat_map(|index| index.to_le_bytes()).collect();
+		add_view(&mut buffer, index_bytes, ELEMENT_ARRAY_BUFFER);
+
+		let mut json = format!(
+			concat!(
+				"{{\"asset\": {{\"version\": \"2.0\", \"generator\": \"ecosim\"}}, \"scene\": 0, \"scenes\": [{{\"nodes\": [0]}}], ",
+				"\"nodes\": [{{\"mesh\": 0, \"name\": \"landscape\"}}], ",
+				"\"meshes\": [{{\"primitives\": [{{\"attributes\": {{{}}}, \"indices\": {}, \"mode\": 4}}]}}], ",
+				"\"accessors\": [{}], \"bufferViews\": [{}], \"buffers\": [{{\"byteLength\": {}}}]}}",
+			),
+			attributes.join(", "), indices, accessors.join(", "), views.join(", "), buffer.len(),
+		).into_bytes();
+		// Chunks are padded to 4 bytes, JSON with spaces and binary data with zeros
+		json.resize(json.len().next_multiple_of(4), b' ');
+		buffer.resize(buffer.len().next_multiple_of(4), 0);
+
+		let length = 12 + 8 + json.len() + 8 + buffer.len();
+		writer.write_all(b"glTF")?;
+		writer.write_all(&2_u32.to_le_bytes())?;
+		writer.write_all(&(length as u32).to_le_bytes())?;
+		for (data, kind) in [(&json, b"JSON"), (&buffer, b"BIN\0")] {
+			writer.write_all(&(data.len() as u32).to_le_bytes())?;
+			writer.write_all(kind)?;
+			writer.write_all(data)?;
+		}
+		Ok(())
+	}
+
+	pub fn write(&self, format: MeshFormat, writer: impl Write) -> io::Result<()> {
+		match format {
+			MeshFormat::Obj => self.write_obj(writer),
+			MeshFormat::Stl => self.write_stl(writer),
+			MeshFormat::Gltf => self.write_glb(writer),
+		}
+	}
+}
+
+/// Square of vertices `x..=x + size`, `y..=y + size` which becomes one quad
+#[derive(Debug, Clone, Copy)]
+struct Node {
+	x: u32,
+	y: u32,
+	size: u32,
+}
+
+/// Grid points on the border of a rectangle which are vertices, clockwise on the map
+/// (the top row goes first, from left to right)
+fn border(active: &[bool], width: u32, (x0, y0): (u32, u32), (x1, y1): (u32, u32)) -> Vec<(u32, u32)> {
+	let top = (x0..x1).map(|x| (x, y0));
+	let right = (y0..y1).map(|y| (x1, y));
+	let bottom = (x0 + 1..=x1).rev().map(|x| (x, y1));
+	let left = (y0 + 1..=y1).rev().map(|y| (x0, y));
+	top.chain(right).chain(bottom).chain(left)
+		.filter(|(x, y)| active[*y as usize * width as usize + *x as usize])
+		.collect()
+}
+
+/// Quads of the surface. Each is split in four while the landscape inside it differs from
+/// the two triangles of the quad by more than `max_error`
+fn quad_tree(landscape: &Landscape, max_error: Option<f32>) -> Vec<Node> {
+	let (width, height) = landscape.size();
+	let heights = landscape.height();
+	let height_at = |x: u32, y: u32| heights[y as usize * width as usize + x as usize].to_num::<f32>();
+	let error = |node: Node| {
+		let Node { x, y, size } = node;
+		let [top_left, top_right, bottom_left, bottom_right] =
+			[height_at(x, y), height_at(x + size, y), height_at(x, y + size), height_at(x + size, y + size)];
+		let mut error = 0.0_f32;
+		for dy in 0..=size {
+			for dx in 0..=size {
+				// The quad is split along the diagonal from the top left corner
+				let (tx, ty) = (dx as f32 / size as f32, dy as f32 / size as f32);
+				let surface = match tx >= ty {
+					true => top_left + (top_right - top_left) * tx + (bottom_right - top_right) * ty,
+					false => top_left + (bottom_left - top_left) * ty + (bottom_right - bottom_left) * tx,
+				};
+				error = error.max((height_at(x + dx, y + dy) - surface).abs());
+			}
+		}
+		error
+	};
+
+	let (last_x, last_y) = (width - 1, height - 1);
+	let mut leaves = vec![];
+	let mut nodes = vec![Node { x: 0, y: 0, size: last_x.max(last_y).next_power_of_two() }];
+	while let Some(node) = nodes.pop() {
+		if node.x >= last_x || node.y >= last_y {
+			continue;
+		}
+		let inside = node.x + node.size <= last_x && node.y + node.size <= last_y;
+		let split = node.size > 1 && (!inside || max_error.map_or(true, |max_error| error(node) > max_error));
+		if !split {
+			leaves.push(node);
+			continue;
+		}
+		let half = node.size / 2;
+		for (dx, dy) in [(0, 0), (half, 0), (0, half), (half, half)] {
+			nodes.push(Node { x: node.x + dx, y: node.y + dy, size: half });
+		}
+	}
+	leaves
+}
+
+/// Mesh of the landscape surface, `map` gives the vertices the colours of their biomes
+pub fn build_mesh(landscape: &Landscape, map: Option<&Map>, params: &MeshParams) -> Result<TerrainMesh, MeshError> {
+	let params = params.clamped();
+	let (width, height) = landscape.size();
+	if let Some(map) = map {
+		if map.size() != landscape.size() {
+			return Err(MeshError::SizeMismatch { landscape: landscape.size(), map: map.size() });
+		}
+	}
+	let mut mesh = TerrainMesh { colors: map.map(|_| vec![]), ..Default::default() };
+	if width < 2 || height < 2 {
+		return Ok(mesh);
+	}
+
+	let index = |x: u32, y: u32| y as usize * width as usize + x as usize;
+	let leaves = quad_tree(landscape, params.max_error);
+	let mut active = vec![false; width as usize * height as usize];
+	for Node { x, y, size } in &leaves {
+		for (x, y) in [(*x, *y), (x + size, *y), (*x, y + size), (x + size, y + size)] {
+			active[index(x, y)] = true;
+		}
+	}
+	// Quads with vertices of smaller neighbours on their sides are fans around their centres,
+	// so there are no cracks between them
+	let mut fans = vec![];
+	for node in &leaves {
+		let border = border(&active, width, (node.x, node.y), (node.x + node.size, node.y + node.size));
+		fans.push(border.len() > 4);
+	}
+	for (node, _) in leaves.iter().zip(&fans).filter(|(_, fan)| **fan) {
+		active[index(node.x + node.size / 2, node.y + node.size / 2)] = true;
+	}
+
+	let heights = landscape.height();
+	let position = |x: u32, y: u32, height: f32| {
+		[x as f32, height * params.exaggeration, y as f32].map(|value| value * params.cell_size)
+	};
+	let mut vertices = vec![u32::MAX; active.len()];
+	for y in 0..height {
+		for x in 0..width {
+			let i = index(x, y);
+			if active[i] {
+				let color = map.map_or(BASE_COLOR, |map| map.cells()[i].0.color());
+				vertices[i] = mesh.push_vertex(position(x, y, heights[i].to_num()), [0.0; 3], color);
+			}
+		}
+	}
+
+	// The border goes clockwise when seen from above, so the triangles take it backwards
+	for (node, fan) in leaves.iter().zip(fans) {
+		let border: Vec<u32> = border(&active, width, (node.x, node.y), (node.x + node.size, node.y + node.size))
+			.into_iter()
+			.map(|(x, y)| vertices[index(x, y)])
+			.collect();
+		if fan {
+			let center = vertices[index(node.x + node.size / 2, node.y + node.size / 2)];
+			for i in 0..border.len() {
+				mesh.triangles.push([center, border[(i + 1) % border.len()], border[i]]);
+			}
+		} else {
+			mesh.triangles.push([border[0], border[2], border[1]]);
+			mesh.triangles.push([border[0], border[3], border[2]]);
+		}
+	}
+
+	// Smooth normals, each triangle adds its area-weighted one
+	for triangle in mesh.triangles.clone() {
+		let normal = mesh.triangle_normal(triangle);
+		for i in triangle {
+			let sum = &mut mesh.normals[i as usize];
+			*sum = [sum[0] + normal[0], sum[1] + normal[1], sum[2] + normal[2]];
+		}
+	}
+	for normal in &mut mesh.normals {
+		*normal = normalize(*normal);
+	}
+
+	let (last_x, last_y) = (width - 1, height - 1);
+	if let Some(base) = params.base {
+		let bottom = mesh.bounds().0[1] - base;
+		let lower = |[x, _, z]: [f32; 3]| [x, bottom, z];
+		let border: Vec<[f32; 3]> = border(&active, width, (0, 0), (last_x, last_y))
+			.into_iter()
+			.map(|(x, y)| mesh.positions[vertices[index(x, y)] as usize])
+			.collect();
+		let center = mesh.push_vertex(lower(position(last_x, last_y, 0.0).map(|value| value / 2.0)), [0.0, -1.0, 0.0], BASE_COLOR);
+		for (i, top) in border.iter().enumerate() {
+			let next_top = border[(i + 1) % border.len()];
+			mesh.push_quad([*top, next_top, lower(next_top), lower(*top)], BASE_COLOR);
+			let [a, b] = [*top, next_top].map(|top| mesh.push_vertex(lower(top), [0.0, -1.0, 0.0], BASE_COLOR));
+			mesh.triangles.push([center, a, b]);
+		}
+	}
+
+	if let Some(sea_level) = params.sea_plane {
+		let sea_color = Biome::Sea.color();
+		let sea_level = sea_level.to_num::<f32>();
+		mesh.push_quad([position(0, 0, sea_level), position(0, last_y, sea_level), position(last_x, last_y, sea_level), position(last_x, 0, sea_level)], sea_color);
+	}
+	Ok(mesh)
+}
+
+pub fn export_mesh(landscape: &Landscape, map: Option<&Map>, params: &MeshParams, format: MeshFormat, path: impl AsRef<Path>) -> Result<(), MeshError> {
+	let mesh = build_mesh(landscape, map, params)?;
+	let mut writer = BufWriter::new(File::create(path)?);
+	mesh.write(format, &mut writer)?;
+	writer.flush()?;
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use std::collections::HashMap;
+
+	use super::*;
+
+	/// Flat map with a cone in the top left corner, so the quad tree is uneven
+	fn hill(size: (u32, u32)) -> Landscape {
+		let heights = (0..size.0 * size.1)
+			.map(|id| {
+				let (x, y) = ((id % size.0) as f32, (id / size.0) as f32);
+				let distance = ((x - 4.0).powi(2) + (y - 4.0).powi(2)).sqrt();
+				Height::from_num(0.5 + (0.3 - distance * 0.1).max(0.0))
+			})
+			.collect();
+		Landscape::new(size, heights)
+	}
+
+	/// How many times each directed edge is used by the triangles, the ends are keyed by their positions
+	fn edges(mesh: &TerrainMesh) -> HashMap<([u32; 3], [u32; 3]), u32> {
+		let key = |i: u32| mesh.positions[i as usize].map(f32::to_bits);
+		let mut edges = HashMap::new();
+		for &[a, b, c] in &mesh.triangles {
+			for (from, to) in [(a, b), (b, c), (c, a)] {
+				*edges.entry((key(from), key(to))).or_insert(0) += 1;
+			}
+		}
+		edges
+	}
+
+	#[test]
+	fn full_grid_has_vertex_for_every_cell() {
+		let params = MeshParams { max_error: None, ..Default::default() };
+		let mesh = build_mesh(&hill((13, 9)), None, &params).unwrap();
+		assert_eq!(mesh.positions.len(), 13 * 9);
+		assert_eq!(mesh.normals.len(), 13 * 9);
+		assert_eq!(mesh.triangles.len(), 2 * 12 * 8);
+		assert!(mesh.colors.is_none());
+	}
+
+	#[test]
+	fn base_is_watertight() {
+		for max_error in [None, Some(0.01)] {
+			let params = MeshParams { max_error, base: Some(2.0), ..Default::default() };
+			let mesh = build_mesh(&hill((17, 12)), None, &params).unwrap();
+			let edges = edges(&mesh);
+			for (&(from, to), &count) in &edges {
+				assert_eq!(count, 1, "{max_error:?}: edge {from:?} -> {to:?}");
+				assert_eq!(edges.get(&(to, from)), Some(&1), "{max_error:?}: edge {from:?} -> {to:?} is open");
+			}
+		}
+	}
+
+	#[test]
+	fn simplified_surface_has_no_cracks() {
+		let size = (33, 33);
+		let full = build_mesh(&hill(size), None, &MeshParams { max_error: None, ..Default::default() }).unwrap();
+		let mesh = build_mesh(&hill(size), None, &MeshParams { max_error: Some(0.01), ..Default::default() }).unwrap();
+		assert!(mesh.triangles.len() < full.triangles.len() / 4, "{} triangles", mesh.triangles.len());
+
+		let on_border = |[x, _, z]: [u32; 3]| {
+			let [x, z] = [x, z].map(f32::from_bits);
+			x == 0.0 || z == 0.0 || x == (size.0 - 1) as f32 || z == (size.1 - 1) as f32
+		};
+		let edges = edges(&mesh);
+		for (&(from, to), &count) in &edges {
+			assert_eq!(count, 1, "edge {from:?} -> {to:?}");
+			let shared = edges.contains_key(&(to, from));
+			assert!(shared || on_border(from) && on_border(to), "crack at {:?} -> {:?}", from.map(f32::from_bits), to.map(f32::from_bits));
+		}
+	}
+
+	/// Simplified solid mesh of the hill with biome colours and a sea plane
+	fn coloured_mesh() -> TerrainMesh {
+		let size = (17, 12);
+		let cells = (0..size.0 * size.1)
+			.map(|id| (if id % 3 == 0 { Biome::Forest } else { Biome::Plains }, [None; crate::map::MAX_RESOURCES_IN_CELL]))
+			.collect();
+		let params = MeshParams { base: Some(2.0), sea_plane: Some(Height::from_num(0.55)), ..Default::default() };
+		build_mesh(&hill(size), Some(&Map::new(size, cells)), &params).unwrap()
+	}
+
+	fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+		u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+	}
+
+	#[test]
+	fn glb_chunks_are_aligned_and_sized() {
+		let mesh = coloured_mesh();
+		let mut bytes = vec![];
+		mesh.write_glb(&mut bytes).unwrap();
+
+		assert_eq!(&bytes[0..4], b"glTF");
+		assert_eq!(u32_at(&bytes, 4), 2);
+		assert_eq!(u32_at(&bytes, 8) as usize, bytes.len());
+
+		let json_length = u32_at(&bytes, 12) as usize;
+		assert_eq!(&bytes[16..20], b"JSON");
+		assert_eq!(json_length % 4, 0);
+		let json = std::str::from_utf8(&bytes[20..20 + json_length]).unwrap();
+		assert!(json.starts_with('{') && json.trim_end().ends_with('}'));
+		assert!(json.contains("\"COLOR_0\""));
+
+		let bin = 20 + json_length;
+		let bin_length = u32_at(&bytes, bin) as usize;
+		assert_eq!(&bytes[bin + 4..bin + 8], b"BIN\0");
+		assert_eq!(bin_length % 4, 0);
+		assert_eq!(bin + 8 + bin_length, bytes.len());
+		// Positions, normals and colours are three floats per vertex, indices are `u32`
+		let data_length = mesh.positions.len() * 3 * 12 + mesh.triangles.len() * 3 * 4;
+		assert!(json.contains(&format!("\"buffers\": [{{\"byteLength\": {}}}]", data_length)), "{json}");
+		assert!(bin_length >= data_length && bin_length < data_length + 4);
+	}
+
+	#[test]
+	fn stl_has_every_triangle() {
+		let mesh = coloured_mesh();
+		let mut bytes = vec![];
+		mesh.write_stl(&mut bytes).unwrap();
+		assert_eq!(u32_at(&bytes, 80) as usize, mesh.triangles.len());
+		// Normal, three vertices and the attribute byte count
+		assert_eq!(bytes.len(), 84 + mesh.triangles.len() * (4 * 3 * 4 + 2));
+	}
+
+	#[test]
+	fn obj_has_every_vertex_and_face() {
+		let mesh = coloured_mesh();
+		let mut bytes = vec![];
+		mesh.write_obj(&mut bytes).unwrap();
+		let text = String::from_utf8(bytes).unwrap();
+		let lines = |prefix: &str| text.lines().filter(|line| line.starts_with(prefix)).collect::<Vec<_>>();
+
+		let vertices = lines("v ");
+		assert_eq!(vertices.len(), mesh.positions.len());
+		assert!(vertices.iter().all(|line| line.split_whitespace().count() == 7), "colours are written after the positions");
+		assert_eq!(lines("vn ").len(), mesh.normals.len());
+		let faces = lines("f ");
+		assert_eq!(faces.len(), mesh.triangles.len());
+		for face in faces {
+			for corner in face.split_whitespace().skip(1) {
+				let index: usize = corner.split("//").next().unwrap().parse().unwrap();
+				assert!((1..=mesh.positions.len()).contains(&index), "{face}");
+			}
+		}
+	}
+}
@@ -0,0 +1,944 @@
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use glow::{Context, HasContext, NativeBuffer, NativeProgram, NativeTexture, PixelPackData, PixelUnpackData};
+use noise::NoiseFn;
+use rand::{Rng, SeedableRng};
+use rand::rngs::{StdRng, ThreadRng};
+use crate::glsl_expand::ShaderContext;
+use crate::map::{self, Height, Landscape, Topology};
+use crate::util::compile_program;
+
+use self::continents::ContinentParams;
+use self::layers::{ErodibilityParams, TerrainLayers};
+use self::shallow_water::{ShallowWaterGpu, ShallowWaterParams};
+use self::tectonics::{TectonicParams, TectonicsGpu};
+use self::thermal::ThermalParams;
+use self::tiles::MapDispatch;
+
+pub mod biomes;
+pub mod climate;
+pub mod continents;
+pub mod erosion_cpu;
+pub mod export;
+pub mod flood;
+pub mod generators;
+pub mod heightmap;
+pub mod hypsometry;
+pub mod layers;
+pub mod mesh;
+pub mod readback;
+pub mod resources;
+pub mod rivers;
+pub mod shallow_water;
+pub mod stats;
+pub mod tectonics;
+pub mod thermal;
+pub mod tiles;
+
+const CELL_EMPTY: u8 = 0;
+const CELL_FILLED: u8 = 1;
+const CELL_CHECKED: u8 = 2;
+
+/// Sea cells at the edges which do not wrap are where the flood fill starts. If
+/// every edge wraps, it starts from the sea cell which is the farthest from land.
+fn _into_cells(size: (usize, usize), topology: Topology, data: &[bool]) -> (Box<[u8]>, u32) {
+	let mut map: Box<[u8]> = data.into_iter()
+		.map(|x| *x as u8)
+		.collect();
+	let id = |x: usize, y: usize| y * size.0 + x;
+
+	let mut cells_taken = 0_u32;
+	let mut take = |map: &mut [u8], id: usize| {
+		if map[id] == CELL_EMPTY {
+			map[id] = CELL_CHECKED;
+			cells_taken += 1;
+		}
+	};
+	if !topology.wrap_y {
+		for x in 0..size.0 {
+			take(&mut map, id(x, 0));
+			take(&mut map, id(x, size.1 - 1));
+		}
+	}
+	if !topology.wrap_x {
+		for y in 0..size.1 {
+			take(&mut map, id(0, y));
+			take(&mut map, id(size.0 - 1, y));
+		}
+	}
+	if topology.wrap_x && topology.wrap_y {
+		let land_distance = continents::distance_transform(size, topology, data);
+		let open_sea = (0..map.len())
+			.filter(|id| map[*id] == CELL_EMPTY)
+			.max_by(|a, b| land_distance[*a].total_cmp(&land_distance[*b]));
+		if let Some(open_sea) = open_sea {
+			take(&mut map, open_sea);
+		}
+	}
+
+	(map, cells_taken)
+}
+
+pub struct ShapeSmoother {
+	gl: Arc<Context>,
+	program: NativeProgram,
+
+	texture_1: NativeTexture,
+	texture_2: NativeTexture,
+	cells_taken_buf: NativeBuffer,
+}
+
+impl ShapeSmoother {
+	pub fn new(gl: Arc<Context>, shader_context: &mut ShaderContext) -> Self {
+		let shader = shader_context
+			.get_file_processed("assets/shape/smoother.glsl").unwrap()
+			.current_text().clone();
+		let sources = [
+			(glow::COMPUTE_SHADER, shader.as_str())
+		];
+		let program = compile_program(&gl, sources).expect(format!("Failed to compile: \n{}", shader).as_str());
+
+		let mut texture_1;
+		let mut texture_2;
+		let cells_taken_buf;
+		unsafe {
+			let create = || {
+				let tex = gl.create_texture().unwrap();
+				gl.bind_texture(glow::TEXTURE_2D, Some(tex));
+				gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::NEAREST as i32);
+				gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::NEAREST as i32);
+				tex
+			};
+
+			// Create textures with data for shader
+			texture_1 = create();
+			texture_2 = create();
+			// Create buffer to store taken cells count
+			cells_taken_buf = gl.create_buffer().unwrap();
+		}
+
+		ShapeSmoother {
+			gl,
+			program,
+			texture_1,
+			texture_2,
+			cells_taken_buf,
+		}
+	}
+
+	/// `topology` has to be the one the shader is compiled with (`WORLD_WRAP_X` and `WORLD_WRAP_Y`)
+	pub fn smooth_out(&self, size: (usize, usize), topology: Topology, data: &mut [bool]) {
+		let gl = self.gl.clone();
+		let (mut map, mut cells_taken) = _into_cells(size, topology, data);
+		// let id = |x: usize, y: usize| y * size.0 + x;
+
+		let tex_image_2d = unsafe {
+			|data: Option<&[u8]>|
+				gl.tex_image_2d(glow::TEXTURE_2D, 0, glow::R8UI as i32,
+								size.0 as i32, size.1 as i32, 0,
+								glow::RED_INTEGER, glow::UNSIGNED_BYTE, data)
+		};
+
+		// Send data to GPU
+		unsafe {
+			gl.bind_buffer(glow::SHADER_STORAGE_BUFFER, Some(self.cells_taken_buf));
+			let pointer = std::slice::from_raw_parts(&cells_taken as *const u32 as *const u8, std::mem::size_of::<u32>());
+			gl.buffer_data_u8_slice(glow::SHADER_STORAGE_BUFFER, pointer, glow::DYNAMIC_DRAW);
+
+			gl.bind_texture(glow::TEXTURE_2D, Some(self.texture_1));
+			tex_image_2d(Some(&map));
+			gl.bind_texture(glow::TEXTURE_2D, Some(self.texture_2));
+			tex_image_2d(Some(&map));
+		}
+
+		const WORK_GROUP_SIZE: u32 = 32;
+		let dispatch = MapDispatch::new(&gl, (size.0 as u64, size.1 as u64), WORK_GROUP_SIZE);
+
+		let calls_per_cycle = (size.0.min(size.1) + 1) / 10;
+
+		let mut curr_image = self.texture_1;
+		let mut next_image = self.texture_2;
+
+		unsafe {
+			// Call shader enough times to compute everything.
+			gl.use_program(Some(self.program));
+			gl.uniform_2_i32(gl.get_uniform_location(self.program, "u_size").as_ref(), size.0 as i32, size.1 as i32);
+			gl.bind_buffer_base(glow::SHADER_STORAGE_BUFFER, 2, Some(self.cells_taken_buf));
+
+			loop {
+				for _ in 0..calls_per_cycle {
+					gl.bind_image_texture(0, curr_image, 0, false, 0, glow::READ_ONLY, glow::R8UI);
+					gl.bind_image_texture(1, next_image, 0, false, 0, glow::READ_WRITE, glow::R8UI);
+					dispatch.dispatch(&gl, self.program);
+					gl.memory_barrier(glow::ALL_BARRIER_BITS);
+
+					std::mem::swap(&mut curr_image, &mut next_image);
+				}
+
+				gl.finish();
+				let start_cells_taken = cells_taken;
+
+				// Get amount of taken cells
+				let pointer = &mut cells_taken as *mut u32 as *mut u8;
+				let pointer = std::slice::from_raw_parts_mut(pointer, std::mem::size_of::<u32>());
+				gl.bind_buffer(glow::SHADER_STORAGE_BUFFER, Some(self.cells_taken_buf));
+				gl.get_buffer_sub_data(glow::SHADER_STORAGE_BUFFER, 0, pointer);
+
+				// No new cells is taken
+				if cells_taken == start_cells_taken {
+					break;
+				}
+			}
+
+			// Copy data from GPU
+			gl.bind_texture(glow::TEXTURE_2D, Some(curr_image));
+			gl.get_tex_image(glow::TEXTURE_2D, 0, glow::RED_INTEGER, glow::UNSIGNED_BYTE, PixelPackData::Slice(&mut map));
+		}
+
+		println!("Copied");
+		for (value, write_to) in map.into_iter().zip(data.iter_mut()) {
+			*write_to = *value != CELL_CHECKED;
+		}
+	}
+}
+
+impl Drop for ShapeSmoother {
+	fn drop(&mut self) {
+		unsafe {
+			self.gl.delete_program(self.program);
+			self.gl.delete_texture(self.texture_1);
+			self.gl.delete_texture(self.texture_2);
+			self.gl.delete_buffer(self.cells_taken_buf);
+		}
+	}
+}
+
+pub fn generate_map(size: (u64, u64), topology: Topology, params: &ContinentParams, seed: u64, smoother: &ShapeSmoother, noise: impl NoiseFn<f64, 2> + Sync) -> Landscape {
+	let map_area = (size.0 * size.1) as usize;
+	let mut land: Box<[bool]> = vec![false; map_area].into_boxed_slice();
+
+	let mut rng = StdRng::seed_from_u64(seed);
+	let centers = continents::continent_centers(size, topology, params, &mut rng);
+	continents::walk_continents(size, topology, params, &centers, &mut rng, &mut land);
+
+	// Sea cells which have no way to the map border (or to the open sea, see `_into_cells`) become land
+	smoother.smooth_out((size.0 as usize, size.1 as usize), topology, &mut land);
+
+	let is_sea: Box<[bool]> = land.iter().map(|is_land| !*is_land).collect();
+	let coast_distance = continents::distance_transform((size.0 as usize, size.1 as usize), topology, &is_sea);
+
+	let mut map: Box<[Height]> = vec![Height::ZERO; map_area].into_boxed_slice();
+	tiles::fill_rows(&mut map, size.0 as usize, |y, row| {
+		for (x, cell) in row.iter_mut().enumerate() {
+			let i = y * size.0 as usize + x;
+			let height = if land[i] {
+				params.land_height(coast_distance[i])
+			} else {
+				0.0
+			};
+			let noise_component = (noise.get([x as f64, y as f64]).powf(3.0) / 10.0) as f32;
+			*cell = map::height_from_f32(height + noise_component);
+		}
+	});
+
+	Landscape::new((size.0 as u32, size.1 as u32), map).with_topology(topology)
+}
+
+/// Random walk of `motion_length` steps from `pos`, which marks visited cells in `buffer`.
+/// `marked_count` is increased for each newly marked cell. Returns the last position.
+/// The walk goes over the wrapped edges and stops at the others.
+pub fn generate_shape(size: (u64, u64), topology: Topology, buffer: &mut [bool], motion_length: u32, pos: (u64, u64), rng: &mut StdRng, marked_count: &mut usize) -> (u64, u64) {
+	let mut x = pos.0 as i32;
+	let mut y = pos.1 as i32;
+
+	for _ in 0..motion_length {
+		let id = y * (size.0 as i32) + x;
+		if !buffer[id as usize] {
+			buffer[id as usize] = true;
+			*marked_count += 1;
+		}
+
+		let dx = rng.gen_range(-1..=1);
+		let dy = rng.gen_range(-1..=1);
+
+		let (next_x, next_y) = topology.clamp_cell((size.0 as u32, size.1 as u32), (x + dx) as i64, (y + dy) as i64);
+		x = next_x as i32;
+		y = next_y as i32;
+	}
+
+	(x as u64, y as u64)
+}
+
+
+pub fn to_data(noise: &impl NoiseFn<f64, 2>, size: (u64, u64), func: impl Fn(f32) -> f32) -> Box<[i32]> {
+	let arr_size = size.0 * size.1;
+	let mut map: Box<[i32]> = vec![0; arr_size as usize].into_boxed_slice();
+
+	for x in 0..size.0 {
+		for y in 0..size.1 {
+			let id = y * size.0 + x;
+			let val = noise.get([x as f64, y as f64]) as f32;
+			let x = val / 2.0 + 0.5;
+			map[id as usize] = map::height_to_int(map::height_from_f32(func(x))); // range [0; HEIGHT_INT_RANGE]
+		}
+	}
+
+	map
+}
+
+/// `R32I` texture with raw `Height` bits (see `map::height_to_int`)
+pub fn convert_to_texture(gl: &Context, size: (u64, u64), data: &Box<[i32]>) -> NativeTexture {
+	let texture;
+	unsafe {
+		texture = gl.create_texture().unwrap();
+		gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+		gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::NEAREST as i32);
+		gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::NEAREST as i32);
+		let ptr = std::slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * std::mem::size_of::<i32>());
+		gl.tex_image_2d(glow::TEXTURE_2D, 0, glow::R32I as i32,
+						size.0 as i32, size.1 as i32, 0,
+						glow::RED_INTEGER, glow::INT, Some(ptr));
+	}
+	texture
+}
+
+pub fn upload_landscape(gl: &Context, landscape: &Landscape) -> NativeTexture {
+	let size = landscape.size();
+	let data: Box<[i32]> = landscape.height().iter().map(|height| map::height_to_int(*height)).collect();
+	convert_to_texture(gl, (size.0 as u64, size.1 as u64), &data)
+}
+
+/// Overwrites an existing landscape texture of the same size
+pub fn write_landscape(gl: &Context, texture: NativeTexture, landscape: &Landscape) {
+	let size = landscape.size();
+	let data: Box<[i32]> = landscape.height().iter().map(|height| map::height_to_int(*height)).collect();
+	unsafe {
+		let ptr = std::slice::from_raw_parts(data.as_ptr() as *const u8, data.len() * std::mem::size_of::<i32>());
+		gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+		gl.tex_sub_image_2d(glow::TEXTURE_2D, 0, 0, 0,
+							size.0 as i32, size.1 as i32,
+							glow::RED_INTEGER, glow::INT, PixelUnpackData::Slice(ptr));
+	}
+}
+
+/// Reads a landscape texture back. Waits for the GPU to finish all the work with it,
+/// use `LandscapeReadback` to avoid that.
+pub fn read_landscape(gl: &Context, texture: NativeTexture, size: (u32, u32)) -> Landscape {
+	let mut data = vec![0_u8; (size.0 as usize) * (size.1 as usize) * std::mem::size_of::<i32>()];
+	unsafe {
+		gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+		gl.get_tex_image(glow::TEXTURE_2D, 0, glow::RED_INTEGER, glow::INT, PixelPackData::Slice(&mut data));
+	}
+	landscape_from_bytes(size, &data)
+}
+
+/// Converts `R32I` texture data in the native byte order
+fn landscape_from_bytes(size: (u32, u32), data: &[u8]) -> Landscape {
+	let height = data.chunks_exact(std::mem::size_of::<i32>())
+		.map(|bytes| map::height_from_int(i32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])))
+		.collect();
+	Landscape::new(size, height)
+}
+
+// Erosion code is inspired from here: https://github.com/SebLague/Hydraulic-Erosion/blob/master/Assets/Scripts/Erosion.cs
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErosionModel {
+	/// Particles which run down the slopes (`erosion.glsl`)
+	Droplets,
+	/// Grid of water columns connected with virtual pipes (`shallow_water/*.glsl`)
+	ShallowWater,
+}
+
+impl ErosionModel {
+	pub fn all() -> &'static [ErosionModel] {
+		&[ErosionModel::Droplets, ErosionModel::ShallowWater]
+	}
+
+	pub fn localized_name(&self) -> &'static str {
+		match self {
+			ErosionModel::Droplets => "Droplets",
+			ErosionModel::ShallowWater => "Shallow water",
+		}
+	}
+}
+
+/// Erosion settings. They are uploaded to `erosion.glsl` (and `thermal.glsl`)
+/// as uniforms on each `ErosionGpu::erode` call.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ErosionParams {
+	pub model: ErosionModel,
+	/// Droplets per iteration for every `DROPLETS_BLOCK_SIZE`² block of the map, so
+	/// the erosion rate does not depend on the map size. See `droplets_for_map`.
+	pub droplets_count: u32,
+	pub brush_radius: u32,
+	pub max_droplet_lifetime: u32,
+	pub inertia: f32,
+	pub soil_capacity_per_water: f32,
+	pub min_soil_capacity: f32,
+	pub deposit_speed: f32,
+	pub erode_speed: f32,
+	pub evaporate_speed: f32,
+	/// If set, droplets erode soil and bedrock at different rates and deposit into the soil
+	pub layers: Option<ErodibilityParams>,
+	/// Used instead of the droplets settings by `ErosionModel::ShallowWater`
+	pub shallow_water: ShallowWaterParams,
+	/// Thermal passes which run after the hydraulic erosion on each iteration
+	pub thermal: Option<ThermalParams>,
+	/// Uplift and isostasy, run every `interval` iterations once `ErosionGpu` has an uplift field
+	pub tectonics: Option<TectonicParams>,
+}
+
+impl ErosionParams {
+	/// The original shader ran one 8×8 work group per 256×256 block of the map
+	pub const DROPLETS_BLOCK_SIZE: u32 = 256;
+	pub const DROPLETS_COUNT_RANGE: RangeInclusive<u32> = 1..=65536;
+	pub const BRUSH_RADIUS_RANGE: RangeInclusive<u32> = 1..=8;
+	pub const MAX_DROPLET_LIFETIME_RANGE: RangeInclusive<u32> = 1..=256;
+	pub const INERTIA_RANGE: RangeInclusive<f32> = 0.0..=0.99;
+	pub const SOIL_CAPACITY_PER_WATER_RANGE: RangeInclusive<f32> = 0.0..=32.0;
+	pub const MIN_SOIL_CAPACITY_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+	pub const SPEED_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+
+	/// Returns a copy with every field clamped into its valid range.
+	pub fn clamped(&self) -> Self {
+		let clamp_u32 = |val: u32, range: RangeInclusive<u32>| val.clamp(*range.start(), *range.end());
+		let clamp_f32 = |val: f32, range: RangeInclusive<f32>| val.clamp(*range.start(), *range.end());
+
+		ErosionParams {
+			model: self.model,
+			droplets_count: clamp_u32(self.droplets_count, Self::DROPLETS_COUNT_RANGE),
+			brush_radius: clamp_u32(self.brush_radius, Self::BRUSH_RADIUS_RANGE),
+			max_droplet_lifetime: clamp_u32(self.max_droplet_lifetime, Self::MAX_DROPLET_LIFETIME_RANGE),
+			inertia: clamp_f32(self.inertia, Self::INERTIA_RANGE),
+			soil_capacity_per_water: clamp_f32(self.soil_capacity_per_water, Self::SOIL_CAPACITY_PER_WATER_RANGE),
+			min_soil_capacity: clamp_f32(self.min_soil_capacity, Self::MIN_SOIL_CAPACITY_RANGE),
+			deposit_speed: clamp_f32(self.deposit_speed, Self::SPEED_RANGE),
+			erode_speed: clamp_f32(self.erode_speed, Self::SPEED_RANGE),
+			evaporate_speed: clamp_f32(self.evaporate_speed, Self::SPEED_RANGE),
+			layers: self.layers.map(|layers| layers.clamped()),
+			shallow_water: self.shallow_water.clamped(),
+			thermal: self.thermal.map(|thermal| thermal.clamped()),
+			tectonics: self.tectonics.map(|tectonics| tectonics.clamped()),
+		}
+	}
+
+	pub fn is_valid(&self) -> bool {
+		self.clamped() == *self
+	}
+
+	/// Droplets per iteration on a map of `size`. Partial blocks count as whole ones.
+	pub fn droplets_for_map(&self, size: (u64, u64)) -> u32 {
+		let blocks = |len: u64| len.div_ceil(Self::DROPLETS_BLOCK_SIZE as u64);
+		(blocks(size.0) * blocks(size.1) * self.droplets_count as u64).min(u32::MAX as u64) as u32
+	}
+}
+
+impl Default for ErosionParams {
+	fn default() -> Self {
+		ErosionPreset::Default.params()
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErosionPreset {
+	Default,
+	Gentle,
+	Canyon,
+	Coastal,
+}
+
+impl ErosionPreset {
+	pub fn all() -> &'static [ErosionPreset] {
+		&[ErosionPreset::Default, ErosionPreset::Gentle, ErosionPreset::Canyon, ErosionPreset::Coastal]
+	}
+
+	pub fn localized_name(&self) -> &'static str {
+		match self {
+			ErosionPreset::Default => "Default",
+			ErosionPreset::Gentle => "Gentle",
+			ErosionPreset::Canyon => "Canyon",
+			ErosionPreset::Coastal => "Coastal",
+		}
+	}
+
+	pub fn params(&self) -> ErosionParams {
+		match self {
+			ErosionPreset::Default => ErosionParams {
+				model: ErosionModel::Droplets,
+				droplets_count: 64,
+				brush_radius: 3,
+				max_droplet_lifetime: 30,
+				inertia: 0.2,
+				soil_capacity_per_water: 4.0,
+				min_soil_capacity: 0.02,
+				deposit_speed: 0.3,
+				erode_speed: 0.3,
+				evaporate_speed: 0.01,
+				layers: None,
+				shallow_water: ShallowWaterParams::default(),
+				thermal: None,
+				tectonics: None,
+			},
+			// Slow, wide droplets: softens slopes without cutting valleys
+			ErosionPreset::Gentle => ErosionParams {
+				model: ErosionModel::Droplets,
+				droplets_count: 64,
+				brush_radius: 5,
+				max_droplet_lifetime: 20,
+				inertia: 0.1,
+				soil_capacity_per_water: 2.0,
+				min_soil_capacity: 0.01,
+				deposit_speed: 0.5,
+				erode_speed: 0.1,
+				evaporate_speed: 0.02,
+				layers: None,
+				shallow_water: ShallowWaterParams::default(),
+				thermal: Some(ThermalParams { talus_angle: 30.0, ..Default::default() }),
+				tectonics: None,
+			},
+			// Long-living narrow droplets with high capacity dig deep channels
+			ErosionPreset::Canyon => ErosionParams {
+				model: ErosionModel::Droplets,
+				droplets_count: 128,
+				brush_radius: 2,
+				max_droplet_lifetime: 80,
+				inertia: 0.4,
+				soil_capacity_per_water: 8.0,
+				min_soil_capacity: 0.01,
+				deposit_speed: 0.1,
+				erode_speed: 0.6,
+				evaporate_speed: 0.005,
+				layers: None,
+				shallow_water: ShallowWaterParams::default(),
+				thermal: None,
+				tectonics: None,
+			},
+			// Droplets drop soil quickly, building up plains and beaches near the sea
+			ErosionPreset::Coastal => ErosionParams {
+				model: ErosionModel::Droplets,
+				droplets_count: 64,
+				brush_radius: 4,
+				max_droplet_lifetime: 40,
+				inertia: 0.05,
+				soil_capacity_per_water: 3.0,
+				min_soil_capacity: 0.05,
+				deposit_speed: 0.6,
+				erode_speed: 0.2,
+				evaporate_speed: 0.03,
+				layers: None,
+				shallow_water: ShallowWaterParams::default(),
+				thermal: Some(ThermalParams { talus_angle: 35.0, transfer_rate: 0.3, ..Default::default() }),
+				tectonics: None,
+			},
+		}
+	}
+}
+
+/// Erosion brush shared by every cell of the map: `(dx, dy, weight)` offsets
+/// of a disk with weights falling linearly from the center.
+///
+/// Cells of the disk beyond wrapped edges come from the other side of the map,
+/// the ones outside of it are dropped and the remaining weights are renormalised
+/// on the fly, so no per-cell data has to be stored.
+/// `assets/terrain/brush.glsl` computes exactly the same weights.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BrushKernel {
+	offsets: Vec<(i32, i32, f32)>,
+}
+
+impl BrushKernel {
+	pub fn new(radius: u32) -> Self {
+		let radius_i = radius as i32;
+		let mut offsets = vec![];
+
+		for dy in (-radius_i)..=radius_i {
+			for dx in (-radius_i)..=radius_i {
+				let sqr_dist = dx * dx + dy * dy;
+				if sqr_dist > (radius_i * radius_i) {
+					continue;
+				}
+
+				let weight = 1.0 - (sqr_dist as f32).sqrt() / (radius as f32);
+				offsets.push((dx, dy, weight));
+			}
+		}
+
+		BrushKernel { offsets }
+	}
+
+	/// Cells of the map covered by the brush placed at `center`, with weights
+	/// normalised to sum up to `1.0`.
+	pub fn cells(&self, map_size: (u64, u64), topology: Topology, center: (i32, i32)) -> impl Iterator<Item = (i32, i32, f32)> + '_ {
+		let size = (map_size.0 as u32, map_size.1 as u32);
+		let cell = move |(dx, dy): (i32, i32)| topology.cell(size, (center.0 + dx) as i64, (center.1 + dy) as i64);
+
+		let sum_weights: f32 = self.offsets.iter()
+			.filter(|(dx, dy, _)| cell((*dx, *dy)).is_some())
+			.map(|(_, _, weight)| *weight)
+			.sum();
+
+		self.offsets.iter()
+			.filter_map(move |(dx, dy, weight)| cell((*dx, *dy)).map(|(x, y)| (x as i32, y as i32, weight / sum_weights)))
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ErosionGpu {
+	gl: Arc<Context>,
+	copy_program: NativeProgram,
+	erosion_program: NativeProgram,
+	thermal_program: NativeProgram,
+	size: (u64, u64),
+	/// Of `copy_program` and `thermal_program`
+	dispatch: MapDispatch,
+
+	tmp_texture: NativeTexture,
+	/// Material moved by thermal erosion into each cell, in the landscape units
+	scree_texture: NativeTexture,
+	/// `R32I`, see `TerrainLayers::soil`
+	soil_texture: NativeTexture,
+	/// `R32F`, see `TerrainLayers::rock_erodibility`
+	rock_erodibility_texture: NativeTexture,
+	shallow_water: ShallowWaterGpu,
+	tectonics: TectonicsGpu,
+	/// Iterations since the last tectonic step
+	tectonic_ticks: u32,
+}
+
+impl ErosionGpu {
+	pub fn new(gl: Arc<Context>, glsl_manager: &mut ShaderContext, map_size: (u64, u64)) -> Self {
+		let mut load_program = |path: &str| {
+			let shader = glsl_manager
+				.get_file_processed(path).unwrap()
+				.current_text().clone();
+			let sources = [
+				(glow::COMPUTE_SHADER, shader.as_str())
+			];
+			compile_program(&gl, sources).expect(format!("Failed to compile: \n{}", shader).as_str())
+		};
+
+		const WORK_GROUP_SIZE: u32 = 32;
+		let copy_program = load_program("assets/copy_texture.glsl");
+		let erosion_program = load_program("assets/terrain/erosion.glsl");
+		let thermal_program = load_program("assets/terrain/thermal.glsl");
+
+		let tmp_texture;
+		unsafe {
+			tmp_texture = gl.create_texture().unwrap();
+			gl.bind_texture(glow::TEXTURE_2D, Some(tmp_texture));
+			gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::NEAREST as i32);
+			gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::NEAREST as i32);
+			gl.tex_image_2d(glow::TEXTURE_2D, 0, glow::R32I as i32,
+							map_size.0 as i32, map_size.1 as i32, 0,
+							glow::RED_INTEGER, glow::UNSIGNED_BYTE, None);
+		}
+		let no_scree: Box<[i32]> = vec![0; (map_size.0 * map_size.1) as usize].into_boxed_slice();
+		let scree_texture = convert_to_texture(&gl, map_size, &no_scree);
+		let soil_texture = convert_to_texture(&gl, map_size, &no_scree);
+		let rock_erodibility_texture;
+		unsafe {
+			let erodibility = vec![1.0_f32; (map_size.0 * map_size.1) as usize];
+			let ptr = std::slice::from_raw_parts(erodibility.as_ptr() as *const u8, erodibility.len() * std::mem::size_of::<f32>());
+			rock_erodibility_texture = gl.create_texture().unwrap();
+			gl.bind_texture(glow::TEXTURE_2D, Some(rock_erodibility_texture));
+			gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::NEAREST as i32);
+			gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::NEAREST as i32);
+			gl.tex_image_2d(glow::TEXTURE_2D, 0, glow::R32F as i32,
+							map_size.0 as i32, map_size.1 as i32, 0,
+							glow::RED, glow::FLOAT, Some(ptr));
+		}
+		let shallow_water = ShallowWaterGpu::new(gl.clone(), glsl_manager, map_size);
+		let tectonics = TectonicsGpu::new(gl.clone(), glsl_manager, map_size);
+		let dispatch = MapDispatch::new(&gl, map_size, WORK_GROUP_SIZE);
+
+		ErosionGpu {
+			gl,
+			copy_program,
+			erosion_program,
+			thermal_program,
+			size: map_size,
+			dispatch,
+			tmp_texture,
+			scree_texture,
+			soil_texture,
+			rock_erodibility_texture,
+			shallow_water,
+			tectonics,
+			tectonic_ticks: 0,
+		}
+	}
+
+	/// `R32I` texture with the total amount of material moved by thermal erosion into each cell
+	pub fn scree_texture(&self) -> NativeTexture {
+		self.scree_texture
+	}
+
+	/// `R32F` texture with the water depth of the shallow water model. Stays empty
+	/// while the droplets model is used.
+	pub fn water_texture(&self) -> NativeTexture {
+		self.shallow_water.water_texture()
+	}
+
+	/// `R32I` texture with the soil thickness, in the landscape units
+	pub fn soil_texture(&self) -> NativeTexture {
+		self.soil_texture
+	}
+
+	/// Uploads the soil and the bedrock erodibility, they have to be of the map size
+	pub fn set_layers(&mut self, layers: &TerrainLayers) {
+		let soil = Landscape::new(layers.size(), layers.soil().into());
+		write_landscape(&self.gl, self.soil_texture, &soil);
+		unsafe {
+			let erodibility = layers.rock_erodibility();
+			let ptr = std::slice::from_raw_parts(erodibility.as_ptr() as *const u8, std::mem::size_of_val(erodibility));
+			self.gl.bind_texture(glow::TEXTURE_2D, Some(self.rock_erodibility_texture));
+			self.gl.tex_sub_image_2d(glow::TEXTURE_2D, 0, 0, 0,
+									 self.size.0 as i32, self.size.1 as i32,
+									 glow::RED, glow::FLOAT, PixelUnpackData::Slice(ptr));
+		}
+	}
+
+	pub fn tectonics_mut(&mut self) -> &mut TectonicsGpu {
+		&mut self.tectonics
+	}
+
+	/// Runs `iterations` erosion iterations. Each one is a hydraulic erosion pass
+	/// of `params.model` followed by `params.thermal` passes, if there are any.
+	/// Every `params.tectonics.interval` iterations there is a tectonic step.
+	pub fn erode(&mut self, texture: NativeTexture, iterations: u64, rand_seed: i32, params: &ErosionParams) -> NativeTexture {
+		const DROPLETS_WORK_GROUP_SIZE: u32 = 64;
+		let params = params.clamped();
+		let gl = self.gl.clone();
+
+		let mut current_texture = texture;
+		let mut next_texture = self.tmp_texture;
+		unsafe {
+			gl.use_program(Some(self.erosion_program));
+			let loc = |name: &str| gl.get_uniform_location(self.erosion_program, name);
+			gl.uniform_2_i32(loc("u_map_size").as_ref(), self.size.0 as i32, self.size.1 as i32);
+			let droplets_count = params.droplets_for_map(self.size);
+			gl.uniform_1_u32(loc("u_droplets_count").as_ref(), droplets_count);
+			gl.uniform_1_i32(loc("u_brush_radius").as_ref(), params.brush_radius as i32);
+			gl.uniform_1_i32(loc("u_max_droplet_lifetime").as_ref(), params.max_droplet_lifetime as i32);
+			gl.uniform_1_f32(loc("u_inertia").as_ref(), params.inertia);
+			gl.uniform_1_f32(loc("u_soil_capacity_per_water").as_ref(), params.soil_capacity_per_water);
+			gl.uniform_1_f32(loc("u_min_soil_capacity").as_ref(), params.min_soil_capacity);
+			gl.uniform_1_f32(loc("u_deposit_speed").as_ref(), params.deposit_speed);
+			gl.uniform_1_f32(loc("u_erode_speed").as_ref(), params.erode_speed);
+			gl.uniform_1_f32(loc("u_evaporate_speed").as_ref(), params.evaporate_speed);
+			let layers = params.layers.unwrap_or_default();
+			gl.uniform_1_i32(loc("u_layered").as_ref(), params.layers.is_some() as i32);
+			gl.uniform_1_f32(loc("u_soil_erodibility").as_ref(), layers.soil);
+			gl.uniform_1_f32(loc("u_rock_erodibility").as_ref(), layers.rock);
+
+			if let Some(thermal) = &params.thermal {
+				gl.use_program(Some(self.thermal_program));
+				let loc = |name: &str| gl.get_uniform_location(self.thermal_program, name);
+				gl.uniform_2_i32(loc("u_map_size").as_ref(), self.size.0 as i32, self.size.1 as i32);
+				gl.uniform_1_f32(loc("u_talus_height").as_ref(), thermal.talus_height());
+				gl.uniform_1_f32(loc("u_transfer_rate").as_ref(), thermal.transfer_rate);
+			}
+
+			for i in 0..iterations {
+				match params.model {
+					ErosionModel::Droplets => {
+						// 1. Copy image to buffer
+						gl.use_program(Some(self.copy_program));
+						gl.bind_image_texture(0, current_texture, 0, false, 0, glow::READ_ONLY, glow::R32I);
+						gl.bind_image_texture(1, next_texture, 0, false, 0, glow::WRITE_ONLY, glow::R32I);
+						self.dispatch.dispatch(&gl, self.copy_program);
+						gl.memory_barrier(glow::ALL_BARRIER_BITS);
+
+						// 2. Emulate droplets
+						gl.use_program(Some(self.erosion_program));
+						gl.uniform_1_i32(gl.get_uniform_location(self.erosion_program, "u_random_seed").as_ref(), rand_seed.wrapping_add(i as i32));
+						gl.bind_image_texture(0, current_texture, 0, false, 0, glow::READ_ONLY, glow::R32I);
+						gl.bind_image_texture(1, next_texture, 0, false, 0, glow::WRITE_ONLY, glow::R32I);
+						gl.bind_image_texture(2, self.soil_texture, 0, false, 0, glow::READ_WRITE, glow::R32I);
+						gl.bind_image_texture(3, self.rock_erodibility_texture, 0, false, 0, glow::READ_ONLY, glow::R32F);
+						gl.dispatch_compute(droplets_count.div_ceil(DROPLETS_WORK_GROUP_SIZE), 1, 1);
+						gl.memory_barrier(glow::ALL_BARRIER_BITS);
+					}
+					// 1-2. Water flow writes every cell of the next texture, no copy needed
+					ErosionModel::ShallowWater => {
+						self.shallow_water.step(current_texture, next_texture, &params.shallow_water);
+					}
+				}
+
+				// 3. Swap buffers
+				std::mem::swap(&mut current_texture, &mut next_texture);
+
+				// 4. Let steep slopes collapse
+				let thermal_iterations = params.thermal.map_or(0, |thermal| thermal.iterations);
+				for _ in 0..thermal_iterations {
+					gl.use_program(Some(self.copy_program));
+					gl.bind_image_texture(0, current_texture, 0, false, 0, glow::READ_ONLY, glow::R32I);
+					gl.bind_image_texture(1, next_texture, 0, false, 0, glow::WRITE_ONLY, glow::R32I);
+					self.dispatch.dispatch(&gl, self.copy_program);
+					gl.memory_barrier(glow::ALL_BARRIER_BITS);
+
+					gl.use_program(Some(self.thermal_program));
+					gl.bind_image_texture(0, current_texture, 0, false, 0, glow::READ_ONLY, glow::R32I);
+					gl.bind_image_texture(1, next_texture, 0, false, 0, glow::READ_WRITE, glow::R32I);
+					gl.bind_image_texture(2, self.scree_texture, 0, false, 0, glow::READ_WRITE, glow::R32I);
+					self.dispatch.dispatch(&gl, self.thermal_program);
+					gl.memory_barrier(glow::ALL_BARRIER_BITS);
+
+					std::mem::swap(&mut current_texture, &mut next_texture);
+				}
+
+				// 5. Lift the mountains back up
+				if let Some(tectonics) = &params.tectonics {
+					if self.tectonics.has_field() {
+						self.tectonic_ticks += 1;
+						if self.tectonic_ticks >= tectonics.interval {
+							self.tectonics.step(current_texture, next_texture, tectonics, self.tectonic_ticks);
+							std::mem::swap(&mut current_texture, &mut next_texture);
+							self.tectonic_ticks = 0;
+						}
+					}
+				}
+			}
+
+			gl.finish();
+		}
+
+		self.tmp_texture = next_texture;
+		current_texture
+	}
+}
+
+impl Drop for ErosionGpu {
+	fn drop(&mut self) {
+		let gl = self.gl.clone();
+		unsafe {
+			gl.delete_program(self.copy_program);
+			gl.delete_program(self.erosion_program);
+			gl.delete_program(self.thermal_program);
+			gl.delete_texture(self.tmp_texture);
+			gl.delete_texture(self.scree_texture);
+			gl.delete_texture(self.soil_texture);
+			gl.delete_texture(self.rock_erodibility_texture);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn presets_are_valid() {
+		for preset in ErosionPreset::all() {
+			assert!(preset.params().is_valid(), "{:?}", preset);
+		}
+	}
+
+	#[test]
+	fn clamped_params_are_in_ranges() {
+		let mut too_low = ErosionParams {
+			droplets_count: 0,
+			brush_radius: 0,
+			max_droplet_lifetime: 0,
+			inertia: -1.0,
+			soil_capacity_per_water: -1.0,
+			min_soil_capacity: -1.0,
+			deposit_speed: -1.0,
+			erode_speed: -1.0,
+			evaporate_speed: -1.0,
+			layers: Some(ErodibilityParams { soil: -1.0, rock: -1.0 }),
+			thermal: Some(ThermalParams { talus_angle: -1.0, cell_width: 0.0, iterations: 0, transfer_rate: -1.0 }),
+			..Default::default()
+		};
+		assert!(!too_low.is_valid());
+		too_low = too_low.clamped();
+		assert!(too_low.is_valid());
+		assert_eq!(too_low.droplets_count, *ErosionParams::DROPLETS_COUNT_RANGE.start());
+		assert_eq!(too_low.brush_radius, *ErosionParams::BRUSH_RADIUS_RANGE.start());
+		assert_eq!(too_low.max_droplet_lifetime, *ErosionParams::MAX_DROPLET_LIFETIME_RANGE.start());
+		assert_eq!(too_low.inertia, *ErosionParams::INERTIA_RANGE.start());
+		assert_eq!(too_low.soil_capacity_per_water, *ErosionParams::SOIL_CAPACITY_PER_WATER_RANGE.start());
+		assert_eq!(too_low.min_soil_capacity, *ErosionParams::MIN_SOIL_CAPACITY_RANGE.start());
+		for speed in [too_low.deposit_speed, too_low.erode_speed, too_low.evaporate_speed] {
+			assert_eq!(speed, *ErosionParams::SPEED_RANGE.start());
+		}
+		assert_eq!(too_low.layers, Some(ErodibilityParams { soil: 0.0, rock: 0.0 }));
+		let thermal = too_low.thermal.unwrap();
+		assert_eq!(thermal.talus_angle, *ThermalParams::TALUS_ANGLE_RANGE.start());
+		assert_eq!(thermal.cell_width, *ThermalParams::CELL_WIDTH_RANGE.start());
+		assert_eq!(thermal.iterations, *ThermalParams::ITERATIONS_RANGE.start());
+
+		let too_high = ErosionParams {
+			droplets_count: u32::MAX,
+			brush_radius: u32::MAX,
+			max_droplet_lifetime: u32::MAX,
+			inertia: 1.0,
+			soil_capacity_per_water: f32::MAX,
+			min_soil_capacity: 2.0,
+			deposit_speed: 2.0,
+			erode_speed: 2.0,
+			evaporate_speed: 2.0,
+			..Default::default()
+		}.clamped();
+		assert!(too_high.is_valid());
+		assert_eq!(too_high.droplets_count, *ErosionParams::DROPLETS_COUNT_RANGE.end());
+		assert_eq!(too_high.brush_radius, *ErosionParams::BRUSH_RADIUS_RANGE.end());
+		assert_eq!(too_high.max_droplet_lifetime, *ErosionParams::MAX_DROPLET_LIFETIME_RANGE.end());
+		assert_eq!(too_high.inertia, *ErosionParams::INERTIA_RANGE.end());
+		assert_eq!(too_high.soil_capacity_per_water, *ErosionParams::SOIL_CAPACITY_PER_WATER_RANGE.end());
+		assert_eq!(too_high.min_soil_capacity, *ErosionParams::MIN_SOIL_CAPACITY_RANGE.end());
+		for speed in [too_high.deposit_speed, too_high.erode_speed, too_high.evaporate_speed] {
+			assert_eq!(speed, *ErosionParams::SPEED_RANGE.end());
+		}
+	}
+
+	#[test]
+	fn default_droplets_match_original_shader() {
+		// One 8×8 work group per started 256×256 block
+		let params = ErosionParams::default();
+		assert_eq!(params.droplets_for_map((1024, 1024)), 1024);
+		assert_eq!(params.droplets_for_map((256, 256)), 64);
+		assert_eq!(params.droplets_for_map((257, 100)), 128);
+		let largest = (map::MAX_MAP_SIZE.0 as u64, map::MAX_MAP_SIZE.1 as u64);
+		let most = ErosionParams { droplets_count: *ErosionParams::DROPLETS_COUNT_RANGE.end(), ..params };
+		assert_eq!(most.droplets_for_map(largest) as u64, largest.0 * largest.1);
+	}
+
+	#[test]
+	fn brush_weights_are_renormalised_at_the_edges() {
+		let brush = BrushKernel::new(3);
+		let size = (16, 12);
+		let torus = Topology { wrap_x: true, wrap_y: true };
+		let cells = |topology: Topology, center: (i32, i32)| brush.cells(size, topology, center).collect::<Vec<_>>();
+		let sum = |cells: &[(i32, i32, f32)]| cells.iter().map(|(_, _, weight)| weight).sum::<f32>();
+		let weight_at = |cells: &[(i32, i32, f32)], x: i32, y: i32| cells.iter().find(|cell| (cell.0, cell.1) == (x, y)).map(|cell| cell.2);
+
+		let middle = cells(Topology::FLAT, (8, 6));
+		assert_eq!(middle.len(), brush.offsets.len());
+		assert!((sum(&middle) - 1.0).abs() < 1e-6);
+
+		// Only a quarter of the disk is left in the corner of a flat map, and it weighs more
+		let corner = cells(Topology::FLAT, (0, 0));
+		assert_eq!(corner.len(), brush.offsets.iter().filter(|(dx, dy, _)| *dx >= 0 && *dy >= 0).count());
+		assert!(corner.iter().all(|(x, y, _)| (0..=3).contains(x) && (0..=3).contains(y)));
+		assert!((sum(&corner) - 1.0).abs() < 1e-6);
+		assert!(weight_at(&corner, 0, 0) > weight_at(&middle, 8, 6));
+		assert_eq!(weight_at(&corner, 15, 11), None);
+
+		// On a torus the corner cells come back from the other sides, with the same weights as in the middle
+		let wrapped = cells(torus, (0, 0));
+		assert_eq!(wrapped.len(), brush.offsets.len());
+		assert!((sum(&wrapped) - 1.0).abs() < 1e-6);
+		assert_eq!(weight_at(&wrapped, 0, 0), weight_at(&middle, 8, 6));
+		assert_eq!(weight_at(&wrapped, 15, 11), weight_at(&middle, 7, 5));
+		assert_eq!(weight_at(&wrapped, 13, 0), weight_at(&middle, 5, 6));
+
+		// Wrapped only across `x`: the disk is cut by the top edge but not by the left one
+		let cylinder = cells(Topology { wrap_x: true, wrap_y: false }, (0, 0));
+		assert_eq!(cylinder.len(), brush.offsets.iter().filter(|(_, dy, _)| *dy >= 0).count());
+		assert!((sum(&cylinder) - 1.0).abs() < 1e-6);
+		assert!(weight_at(&cylinder, 15, 1).is_some());
+		assert_eq!(weight_at(&cylinder, 15, 11), None);
+	}
+}
@@ -0,0 +1,103 @@
+use std::sync::Arc;
+use glow::{Context, Fence, HasContext, NativeBuffer, NativeTexture, PixelPackData};
+
+use crate::map::Landscape;
+
+/// Reads `R32I` landscape textures back without stalling: the copy goes into a
+/// pixel buffer object, and the CPU picks it up only once the GPU is done with it.
+/// Several textures of the same size (like the landscape and its soil) are copied
+/// together, so they are of the same moment.
+#[derive(Debug, Clone)]
+pub struct LandscapeReadback {
+	gl: Arc<Context>,
+	size: (u32, u32),
+	buffers: Box<[NativeBuffer]>,
+	fence: Option<Fence>,
+}
+
+impl LandscapeReadback {
+	/// Reads `count` textures at once
+	pub fn new(gl: Arc<Context>, size: (u32, u32), count: usize) -> Self {
+		let buffers = (0..count)
+			.map(|_| unsafe {
+				let buffer = gl.create_buffer().unwrap();
+				gl.bind_buffer(glow::PIXEL_PACK_BUFFER, Some(buffer));
+				gl.buffer_data_size(glow::PIXEL_PACK_BUFFER, Self::bytes_count(size) as i32, glow::STREAM_READ);
+				buffer
+			})
+			.collect();
+		unsafe {
+			gl.bind_buffer(glow::PIXEL_PACK_BUFFER, None);
+		}
+
+		LandscapeReadback { gl, size, buffers, fence: None }
+	}
+
+	fn bytes_count(size: (u32, u32)) -> usize {
+		(size.0 as usize) * (size.1 as usize) * std::mem::size_of::<i32>()
+	}
+
+	pub fn is_pending(&self) -> bool {
+		self.fence.is_some()
+	}
+
+	/// Starts copying `textures`. They have to be of the readback size and count.
+	/// A previous request which was not picked up yet is dropped.
+	pub fn request(&mut self, textures: &[NativeTexture]) {
+		assert_eq!(textures.len(), self.buffers.len(), "Texture count differs from the readback one");
+		let gl = self.gl.clone();
+		unsafe {
+			if let Some(fence) = self.fence.take() {
+				gl.delete_sync(fence);
+			}
+
+			for (buffer, texture) in self.buffers.iter().zip(textures) {
+				gl.bind_buffer(glow::PIXEL_PACK_BUFFER, Some(*buffer));
+				gl.bind_texture(glow::TEXTURE_2D, Some(*texture));
+				gl.get_tex_image(glow::TEXTURE_2D, 0, glow::RED_INTEGER, glow::INT, PixelPackData::BufferOffset(0));
+			}
+			gl.bind_buffer(glow::PIXEL_PACK_BUFFER, None);
+
+			self.fence = gl.fence_sync(glow::SYNC_GPU_COMMANDS_COMPLETE, 0).ok();
+		}
+	}
+
+	/// Returns the textures in the requested order if the copy is done, never waits for the GPU
+	pub fn poll(&mut self) -> Option<Vec<Landscape>> {
+		let gl = self.gl.clone();
+		let fence = self.fence?;
+		unsafe {
+			if gl.get_sync_status(fence) != glow::SIGNALED {
+				return None;
+			}
+			gl.delete_sync(fence);
+			self.fence = None;
+
+			let mut data = vec![0_u8; Self::bytes_count(self.size)];
+			let landscapes = self.buffers.iter()
+				.map(|buffer| {
+					gl.bind_buffer(glow::PIXEL_PACK_BUFFER, Some(*buffer));
+					gl.get_buffer_sub_data(glow::PIXEL_PACK_BUFFER, 0, &mut data);
+					super::landscape_from_bytes(self.size, &data)
+				})
+				.collect();
+			gl.bind_buffer(glow::PIXEL_PACK_BUFFER, None);
+
+			Some(landscapes)
+		}
+	}
+}
+
+impl Drop for LandscapeReadback {
+	fn drop(&mut self) {
+		let gl = self.gl.clone();
+		unsafe {
+			if let Some(fence) = self.fence.take() {
+				gl.delete_sync(fence);
+			}
+			for buffer in self.buffers.iter() {
+				gl.delete_buffer(*buffer);
+			}
+		}
+	}
+}
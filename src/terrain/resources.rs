@@ -0,0 +1,416 @@
+use std::collections::HashMap;
+use noise::{NoiseFn, Perlin};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+
+use crate::map::{Biome, Map, Resource};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResourceKind {
+	Compound,
+	Rock,
+	Fruit,
+	PoisonedFruit,
+	FireCrystal,
+	EnigmaticCrystal,
+}
+
+impl ResourceKind {
+	pub fn all() -> &'static [ResourceKind] {
+		use ResourceKind::*;
+		&[Compound, Rock, Fruit, PoisonedFruit, FireCrystal, EnigmaticCrystal]
+	}
+
+	pub fn localized_name(&self) -> &'static str {
+		match self {
+			ResourceKind::Compound => "Compound",
+			ResourceKind::Rock => "Rock",
+			ResourceKind::Fruit => "Fruit",
+			ResourceKind::PoisonedFruit => "Poisoned fruit",
+			ResourceKind::FireCrystal => "Fire crystal",
+			ResourceKind::EnigmaticCrystal => "Enigmatic crystal",
+		}
+	}
+
+	/// Colour of the resource in exported images
+	pub fn color(&self) -> [u8; 3] {
+		match self {
+			ResourceKind::Compound => [235, 200, 40],
+			ResourceKind::Rock => [110, 110, 115],
+			ResourceKind::Fruit => [215, 50, 50],
+			ResourceKind::PoisonedFruit => [145, 55, 170],
+			ResourceKind::FireCrystal => [255, 125, 20],
+			ResourceKind::EnigmaticCrystal => [50, 215, 215],
+		}
+	}
+
+	pub fn of(resource: &Resource) -> Self {
+		match resource {
+			Resource::Compound { .. } => ResourceKind::Compound,
+			Resource::Rock => ResourceKind::Rock,
+			Resource::Fruit => ResourceKind::Fruit,
+			Resource::PoisonedFruit => ResourceKind::PoisonedFruit,
+			Resource::FireCrystal => ResourceKind::FireCrystal,
+			Resource::EnigmaticCrystal => ResourceKind::EnigmaticCrystal,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Distribution {
+	/// Every cell gets the resource independently
+	Uniform,
+	/// Chance is modulated by Perlin noise, so resources form patches. With `strength`
+	/// of `0.0` it is the same as `Uniform`, with `1.0` there is nothing between the patches
+	Clustered { frequency: f64, strength: f32 },
+	/// No two resources of the rule are closer than `min_distance` cells
+	PoissonDisk { min_distance: f32 },
+}
+
+impl Distribution {
+	pub fn localized_name(&self) -> &'static str {
+		match self {
+			Distribution::Uniform => "Uniform",
+			Distribution::Clustered { .. } => "Clustered",
+			Distribution::PoissonDisk { .. } => "Poisson disk",
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpawnRule {
+	pub resource: ResourceKind,
+	/// Average chance of a cell to get the resource
+	pub density: f32,
+	pub distribution: Distribution,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpawnTable {
+	pub biome: Biome,
+	pub rules: Vec<SpawnRule>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResourceParams {
+	pub tables: Vec<SpawnTable>,
+	/// Each compound resource gets a random amount up to this
+	pub max_compound_count: u8,
+	/// If set, compound amounts are rescaled to give this total
+	pub compound_total: Option<u64>,
+}
+
+impl Default for ResourceParams {
+	fn default() -> Self {
+		use Distribution::*;
+		let rule = |resource: ResourceKind, density: f32, distribution: Distribution| SpawnRule { resource, density, distribution };
+		let patches = Clustered { frequency: 0.05, strength: 0.8 };
+		let table = |biome: Biome, rules: Vec<SpawnRule>| SpawnTable { biome, rules };
+
+		ResourceParams {
+			tables: vec![
+				table(Biome::Sea, vec![rule(ResourceKind::Compound, 0.01, Uniform)]),
+				table(Biome::Shoal, vec![rule(ResourceKind::Compound, 0.05, patches)]),
+				table(Biome::Beach, vec![
+					rule(ResourceKind::Compound, 0.02, Uniform),
+					rule(ResourceKind::Rock, 0.02, Uniform),
+				]),
+				table(Biome::Plains, vec![
+					rule(ResourceKind::Compound, 0.05, patches),
+					rule(ResourceKind::Fruit, 0.02, patches),
+				]),
+				table(Biome::Swamp, vec![
+					rule(ResourceKind::Compound, 0.05, Uniform),
+					rule(ResourceKind::PoisonedFruit, 0.05, patches),
+				]),
+				table(Biome::Forest, vec![
+					rule(ResourceKind::Compound, 0.03, Uniform),
+					rule(ResourceKind::Fruit, 0.08, patches),
+					rule(ResourceKind::PoisonedFruit, 0.02, patches),
+				]),
+				table(Biome::Desert, vec![
+					rule(ResourceKind::Rock, 0.03, Uniform),
+					rule(ResourceKind::FireCrystal, 0.01, PoissonDisk { min_distance: 8.0 }),
+				]),
+				table(Biome::Scree, vec![rule(ResourceKind::Rock, 0.2, Uniform)]),
+				table(Biome::Mountain, vec![
+					rule(ResourceKind::Rock, 0.1, Uniform),
+					rule(ResourceKind::FireCrystal, 0.01, PoissonDisk { min_distance: 6.0 }),
+					rule(ResourceKind::EnigmaticCrystal, 0.005, PoissonDisk { min_distance: 12.0 }),
+				]),
+				table(Biome::SnowyMountain, vec![
+					rule(ResourceKind::Rock, 0.05, Uniform),
+					rule(ResourceKind::EnigmaticCrystal, 0.01, PoissonDisk { min_distance: 10.0 }),
+				]),
+			],
+			max_compound_count: 16,
+			compound_total: None,
+		}
+	}
+}
+
+/// Number of resources of each kind on a map
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ResourceCounts {
+	counts: HashMap<ResourceKind, u64>,
+	compound_amount: u64,
+}
+
+impl ResourceCounts {
+	pub fn of(map: &Map) -> Self {
+		let mut counts = ResourceCounts::default();
+		for resource in map.cells().iter().flat_map(|(_, resources)| resources.iter().flatten()) {
+			*counts.counts.entry(ResourceKind::of(resource)).or_insert(0) += 1;
+			if let Resource::Compound { count } = resource {
+				counts.compound_amount += *count as u64;
+			}
+		}
+		counts
+	}
+
+	pub fn get(&self, kind: ResourceKind) -> u64 {
+		self.counts.get(&kind).copied().unwrap_or(0)
+	}
+
+	/// Sum of the `count` of all compound resources
+	pub fn compound_amount(&self) -> u64 {
+		self.compound_amount
+	}
+}
+
+/// Puts a resource into the first free slot of the cell. Returns `false` if the cell is full.
+fn put_resource(map: &mut Map, id: usize, resource: Resource) -> bool {
+	match map.cells_mut()[id].1.iter_mut().find(|slot| slot.is_none()) {
+		Some(slot) => {
+			*slot = Some(resource);
+			true
+		}
+		None => false,
+	}
+}
+
+/// Cells of the rule's biome which get the resource. `fertility` scales the chance of each cell.
+fn spawn_cells(map: &Map, biome: Biome, rule: &SpawnRule, fertility: Option<&[f32]>, rng: &mut StdRng) -> Vec<usize> {
+	let (width, _) = map.size();
+	let candidates: Vec<usize> = map.cells().iter().enumerate()
+		.filter(|(_, (cell_biome, _))| *cell_biome == biome)
+		.map(|(id, _)| id)
+		.collect();
+	let density = rule.density.clamp(0.0, 1.0) as f64;
+	let cell_density = |id: usize| density * fertility.map_or(1.0, |fertility| fertility[id].clamp(0.0, 1.0) as f64);
+	let position = |id: usize| ((id % width as usize) as f64, (id / width as usize) as f64);
+
+	match rule.distribution {
+		Distribution::Uniform => candidates.into_iter()
+			.filter(|id| rng.gen_bool(cell_density(*id)))
+			.collect(),
+		Distribution::Clustered { frequency, strength } => {
+			let noise = Perlin::new(rng.gen());
+			let strength = strength.clamp(0.0, 1.0) as f64;
+			candidates.into_iter()
+				.filter(|id| {
+					let (x, y) = position(*id);
+					let value = (noise.get([x * frequency, y * frequency]) / 2.0 + 0.5).clamp(0.0, 1.0);
+					// Mean of `3 * value^2` is `1.0` for an evenly distributed value
+					let chance = cell_density(*id) * (1.0 - strength + strength * 3.0 * value * value);
+					rng.gen_bool(chance.clamp(0.0, 1.0))
+				})
+				.collect()
+		}
+		Distribution::PoissonDisk { min_distance } => {
+			// Dart throwing in random order, with a bucket grid to find the neighbours
+			let min_distance = min_distance.max(1.0) as f64;
+			let mut order = candidates;
+			order.shuffle(rng);
+
+			let mut buckets: HashMap<(i64, i64), Vec<(f64, f64)>> = HashMap::new();
+			let bucket = |(x, y): (f64, f64)| ((x / min_distance).floor() as i64, (y / min_distance).floor() as i64);
+			let mut result = vec![];
+
+			for id in order {
+				if !rng.gen_bool(cell_density(id)) {
+					continue;
+				}
+				let point = position(id);
+				let (bx, by) = bucket(point);
+				let is_far_enough = (-1..=1).flat_map(|dy| (-1..=1).map(move |dx| (bx + dx, by + dy)))
+					.filter_map(|key| buckets.get(&key))
+					.flatten()
+					.all(|(x, y)| (x - point.0).powi(2) + (y - point.1).powi(2) >= min_distance * min_distance);
+
+				if is_far_enough {
+					buckets.entry((bx, by)).or_default().push(point);
+					result.push(id);
+				}
+			}
+			result.sort();
+			result
+		}
+	}
+}
+
+/// Fills the free resource slots of the map using the spawn tables. Tables and their
+/// rules are applied in order, so earlier rules win when a cell is full. Fruits grow
+/// less on poor soil if `fertility` is given. The same `seed` always gives the same resources.
+pub fn place_resources(map: &mut Map, params: &ResourceParams, fertility: Option<&[f32]>, seed: u64) -> ResourceCounts {
+	let mut rng = StdRng::seed_from_u64(seed);
+	let max_compound_count = params.max_compound_count.max(1);
+
+	for table in &params.tables {
+		for rule in &table.rules {
+			let fertility = match rule.resource {
+				ResourceKind::Fruit | ResourceKind::PoisonedFruit => fertility,
+				_ => None,
+			};
+			for id in spawn_cells(map, table.biome, rule, fertility, &mut rng) {
+				let resource = match rule.resource {
+					ResourceKind::Compound => Resource::Compound { count: rng.gen_range(1..=max_compound_count) },
+					ResourceKind::Rock => Resource::Rock,
+					ResourceKind::Fruit => Resource::Fruit,
+					ResourceKind::PoisonedFruit => Resource::PoisonedFruit,
+					ResourceKind::FireCrystal => Resource::FireCrystal,
+					ResourceKind::EnigmaticCrystal => Resource::EnigmaticCrystal,
+				};
+				put_resource(map, id, resource);
+			}
+		}
+	}
+
+	if let Some(total) = params.compound_total {
+		set_compound_total(map, total, &mut rng);
+	}
+	ResourceCounts::of(map)
+}
+
+/// Rescales the counts of the existing compound resources so that they sum up to
+/// `total`. Compounds which get `0` are removed. Returns the reached total, which
+/// is smaller if there are not enough compound resources to hold `total`.
+pub fn set_compound_total(map: &mut Map, total: u64, rng: &mut impl Rng) -> u64 {
+	let slots: Vec<(usize, usize)> = map.cells().iter().enumerate()
+		.flat_map(|(id, (_, resources))| resources.iter().enumerate()
+			.filter(|(_, resource)| matches!(resource, Some(Resource::Compound { .. })))
+			.map(move |(slot, _)| (id, slot)))
+		.collect();
+	let count_at = |map: &Map, (id, slot): (usize, usize)| match map.cells()[id].1[slot] {
+		Some(Resource::Compound { count }) => count as u64,
+		_ => 0,
+	};
+
+	let current: u64 = slots.iter().map(|slot| count_at(map, *slot)).sum();
+	let capacity = slots.len() as u64 * u8::MAX as u64;
+	let total = total.min(capacity);
+
+	// Proportional rescale first, then the remainder goes one by one to random compounds
+	let mut counts: Vec<u64> = slots.iter()
+		.map(|slot| match current {
+			0 => total / slots.len().max(1) as u64,
+			_ => (count_at(map, *slot) as u128 * total as u128 / current as u128) as u64,
+		})
+		.map(|count| count.min(u8::MAX as u64))
+		.collect();
+	let mut remainder = total - counts.iter().sum::<u64>();
+	let mut order: Vec<usize> = (0..slots.len()).collect();
+	while remainder > 0 {
+		order.shuffle(rng);
+		for i in order.iter().copied() {
+			if remainder == 0 {
+				break;
+			}
+			if counts[i] < u8::MAX as u64 {
+				counts[i] += 1;
+				remainder -= 1;
+			}
+		}
+	}
+
+	for ((id, slot), count) in slots.into_iter().zip(counts) {
+		map.cells_mut()[id].1[slot] = match count {
+			0 => None,
+			count => Some(Resource::Compound { count: count as u8 }),
+		};
+	}
+	total
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Vertical stripes of all the biomes, 8 columns each
+	fn stripes() -> Map {
+		let biomes = Biome::all();
+		let size = (8 * biomes.len() as u32, 64);
+		let cells = (0..size.0 * size.1)
+			.map(|id| (biomes[(id % size.0 / 8) as usize], [None; crate::map::MAX_RESOURCES_IN_CELL]))
+			.collect();
+		Map::new(size, cells)
+	}
+
+	fn resources(map: &Map) -> Vec<(usize, Biome, Resource)> {
+		map.cells().iter().enumerate()
+			.flat_map(|(id, (biome, resources))| resources.iter().flatten().map(move |resource| (id, *biome, *resource)))
+			.collect()
+	}
+
+	#[test]
+	fn same_seed_gives_same_resources() {
+		let params = ResourceParams { compound_total: Some(5000), ..Default::default() };
+		let place = |seed: u64| {
+			let mut map = stripes();
+			let counts = place_resources(&mut map, &params, None, seed);
+			(resources(&map), counts)
+		};
+		let (first, counts) = place(7);
+		assert!(!first.is_empty());
+		assert_eq!(counts.compound_amount(), 5000);
+		assert_eq!(place(7), (first.clone(), counts));
+		assert_ne!(place(8).0, first);
+	}
+
+	#[test]
+	fn resources_follow_spawn_tables() {
+		let mut map = stripes();
+		let params = ResourceParams::default();
+		place_resources(&mut map, &params, None, 1);
+		for (id, biome, resource) in resources(&map) {
+			let table = params.tables.iter().find(|table| table.biome == biome).unwrap();
+			let kind = ResourceKind::of(&resource);
+			assert!(table.rules.iter().any(|rule| rule.resource == kind), "{kind:?} in {biome:?} at {id}");
+			if let Resource::Compound { count } = resource {
+				assert!((1..=params.max_compound_count).contains(&count));
+			}
+		}
+
+		// Only the desert has a table, its rules are followed at their densities and distances
+		let mut map = stripes();
+		let min_distance = 5.0;
+		let params = ResourceParams {
+			tables: vec![SpawnTable { biome: Biome::Desert, rules: vec![
+				SpawnRule { resource: ResourceKind::Rock, density: 0.5, distribution: Distribution::Uniform },
+				SpawnRule { resource: ResourceKind::FireCrystal, density: 1.0, distribution: Distribution::PoissonDisk { min_distance } },
+			] }],
+			..Default::default()
+		};
+		let counts = place_resources(&mut map, &params, None, 1);
+		let placed = resources(&map);
+		assert!(placed.iter().all(|(_, biome, _)| *biome == Biome::Desert));
+		assert_eq!(counts.get(ResourceKind::Rock) + counts.get(ResourceKind::FireCrystal), placed.len() as u64);
+
+		let desert_cells = 8 * 64;
+		let rocks = counts.get(ResourceKind::Rock) as f32;
+		assert!((rocks / desert_cells as f32 - 0.5).abs() < 0.1, "{rocks} rocks");
+
+		let width = map.size().0 as usize;
+		let crystals: Vec<(f32, f32)> = placed.iter()
+			.filter(|(_, _, resource)| *resource == Resource::FireCrystal)
+			.map(|(id, _, _)| ((id % width) as f32, (id / width) as f32))
+			.collect();
+		assert!(crystals.len() > 4, "{} crystals", crystals.len());
+		for (i, a) in crystals.iter().enumerate() {
+			for b in &crystals[i + 1..] {
+				assert!((a.0 - b.0).hypot(a.1 - b.1) >= min_distance, "{a:?} and {b:?}");
+			}
+		}
+	}
+}
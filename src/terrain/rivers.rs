@@ -0,0 +1,418 @@
+use std::f32::consts::FRAC_PI_4;
+
+use crate::map::{Height, Landscape};
+use super::continents;
+use super::flood;
+
+/// D8 neighbours, each one is `FRAC_PI_4` radians counter-clockwise from the previous one
+/// (`y` axis points down, as in the map arrays).
+const DIRECTIONS: [(i32, i32); 8] = [
+	(1, 0), (1, -1), (0, -1), (-1, -1),
+	(-1, 0), (-1, 1), (0, 1), (1, 1),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlowModel {
+	/// All water of a cell goes to its steepest downhill neighbour
+	#[allow(dead_code)]
+	D8,
+	/// Water is split between two neighbours along the steepest slope (Tarboton, 1997)
+	DInfinity,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RiverParams {
+	/// Flow model used for the flow accumulation. River network itself always follows D8
+	pub flow_model: FlowModel,
+	/// Minimum accumulated flow (in cells of rain) for a cell to become a river
+	pub threshold: f32,
+	/// Cells at or below sea level take all water and never become rivers
+	pub sea_level: Height,
+	/// Lowlands at most this distance (in cells) from a river become swamps
+	pub swamp_radius: f32,
+	/// Lowlands are cells at most this high above the sea level
+	pub swamp_max_height: Height,
+}
+
+impl Default for RiverParams {
+	fn default() -> Self {
+		RiverParams {
+			flow_model: FlowModel::DInfinity,
+			threshold: 100.0,
+			sea_level: Height::from_num(crate::map::DEFAULT_SEA_LEVEL),
+			swamp_radius: 2.0,
+			swamp_max_height: Height::from_num(0.05),
+		}
+	}
+}
+
+/// Where the water of each cell flows: up to two receivers with the share of
+/// water each one gets. Cells with no receivers are outlets.
+#[derive(Debug, Clone)]
+pub struct FlowDirections {
+	size: (u32, u32),
+	receivers: Box<[[(u32, f32); 2]]>,
+}
+
+const NO_RECEIVER: (u32, f32) = (u32::MAX, 0.0);
+
+impl FlowDirections {
+	/// Water flows across the wrapped edges of the landscape, and leaves it through the others
+	pub fn d8(landscape: &Landscape) -> Self {
+		let size = landscape.size();
+		let topology = landscape.topology();
+		let (width, height) = (size.0 as i32, size.1 as i32);
+		let heights = landscape.height();
+		let mut receivers = vec![[NO_RECEIVER; 2]; heights.len()].into_boxed_slice();
+
+		for y in 0..height {
+			for x in 0..width {
+				let id = (y * width + x) as usize;
+				let mut steepest = 0.0;
+
+				for (dx, dy) in DIRECTIONS {
+					let neighbour = match topology.cell(size, (x + dx) as i64, (y + dy) as i64) {
+						Some((nx, ny)) => ny as usize * width as usize + nx as usize,
+						None => continue,
+					};
+					let distance = if dx != 0 && dy != 0 { std::f32::consts::SQRT_2 } else { 1.0 };
+					let slope = (heights[id] - heights[neighbour]).to_num::<f32>() / distance;
+					if slope > steepest {
+						steepest = slope;
+						receivers[id][0] = (neighbour as u32, 1.0);
+					}
+				}
+			}
+		}
+
+		FlowDirections { size: landscape.size(), receivers }
+	}
+
+	/// Same edges as `d8`
+	pub fn d_infinity(landscape: &Landscape) -> Self {
+		let size = landscape.size();
+		let topology = landscape.topology();
+		let (width, height) = (size.0 as i32, size.1 as i32);
+		let heights = landscape.height();
+		let mut receivers = vec![[NO_RECEIVER; 2]; heights.len()].into_boxed_slice();
+		let cell = |x: i32, y: i32| topology.cell(size, x as i64, y as i64)
+			.map(|(x, y)| y as usize * width as usize + x as usize);
+
+		for y in 0..height {
+			for x in 0..width {
+				let id = (y * width + x) as usize;
+				let center = heights[id].to_num::<f32>();
+				let mut steepest = 0.0;
+
+				// Facet `k` is a triangle between a cardinal and a diagonal neighbour
+				for k in 0..8 {
+					let (cardinal, diagonal) = if k % 2 == 0 {
+						(DIRECTIONS[k], DIRECTIONS[(k + 1) % 8])
+					} else {
+						(DIRECTIONS[(k + 1) % 8], DIRECTIONS[k])
+					};
+					let (cardinal, diagonal) = match (cell(x + cardinal.0, y + cardinal.1), cell(x + diagonal.0, y + diagonal.1)) {
+						(Some(cardinal), Some(diagonal)) => (cardinal, diagonal),
+						_ => continue,
+					};
+					let (cardinal_height, diagonal_height) = (heights[cardinal].to_num::<f32>(), heights[diagonal].to_num::<f32>());
+
+					let s1 = center - cardinal_height;
+					let s2 = cardinal_height - diagonal_height;
+					let (mut angle, mut slope) = (s2.atan2(s1), (s1 * s1 + s2 * s2).sqrt());
+					if angle < 0.0 {
+						angle = 0.0;
+						slope = s1;
+					} else if angle > FRAC_PI_4 {
+						angle = FRAC_PI_4;
+						slope = (center - diagonal_height) / std::f32::consts::SQRT_2;
+					}
+
+					if slope > steepest {
+						steepest = slope;
+						let to_diagonal = angle / FRAC_PI_4;
+						receivers[id] = [
+							(cardinal as u32, 1.0 - to_diagonal),
+							(diagonal as u32, to_diagonal),
+						];
+					}
+				}
+			}
+		}
+
+		FlowDirections { size: landscape.size(), receivers }
+	}
+
+	#[allow(dead_code)]
+	pub fn size(&self) -> (u32, u32) {
+		self.size
+	}
+
+	/// Receivers of the cell which get any water, with their shares
+	pub fn receivers(&self, id: usize) -> impl Iterator<Item = (usize, f32)> + '_ {
+		self.receivers[id].iter()
+			.filter(|(receiver, share)| *receiver != u32::MAX && *share > 0.0)
+			.map(|(receiver, share)| (*receiver as usize, *share))
+	}
+
+	/// The receiver which gets the biggest share of water
+	pub fn main_receiver(&self, id: usize) -> Option<usize> {
+		self.receivers(id)
+			.fold(None, |best: Option<(usize, f32)>, (receiver, share)| match best {
+				Some((_, best_share)) if best_share >= share => best,
+				_ => Some((receiver, share)),
+			})
+			.map(|(receiver, _)| receiver)
+	}
+}
+
+/// Cell indices from the highest to the lowest. Every cell's receivers are lower,
+/// so this is the order in which water can be passed downhill.
+fn downhill_order(landscape: &Landscape) -> Vec<usize> {
+	let heights = landscape.height();
+	let mut order: Vec<usize> = (0..heights.len()).collect();
+	order.sort_by(|a, b| heights[*b].cmp(&heights[*a]).then(a.cmp(b)));
+	order
+}
+
+/// Amount of water which passes through each cell, if every cell gets `rainfall`
+/// (or `1.0` if it is `None`) of rain.
+pub fn flow_accumulation(landscape: &Landscape, directions: &FlowDirections, rainfall: Option<&[f32]>) -> Box<[f32]> {
+	let mut accumulation: Box<[f32]> = match rainfall {
+		Some(rainfall) => rainfall.to_vec().into_boxed_slice(),
+		None => vec![1.0; landscape.height().len()].into_boxed_slice(),
+	};
+
+	for id in downhill_order(landscape) {
+		let water = accumulation[id];
+		for (receiver, share) in directions.receivers(id) {
+			accumulation[receiver] += water * share;
+		}
+	}
+
+	accumulation
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RiverPolyline {
+	/// Strahler order of the river at the start of the polyline
+	pub order: u8,
+	/// Cell centers, from upstream to downstream
+	pub points: Vec<(f32, f32)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RiverNetwork {
+	size: (u32, u32),
+	accumulation: Box<[f32]>,
+	order: Box<[u8]>,
+	polylines: Vec<RiverPolyline>,
+}
+
+impl RiverNetwork {
+	#[allow(dead_code)]
+	pub fn size(&self) -> (u32, u32) {
+		self.size
+	}
+
+	#[allow(dead_code)]
+	pub fn accumulation(&self) -> &[f32] {
+		&self.accumulation
+	}
+
+	/// Strahler order of each cell, `0` for cells which are not rivers.
+	/// Same layout as the `R8UI` textures.
+	pub fn order(&self) -> &[u8] {
+		&self.order
+	}
+
+	#[allow(dead_code)]
+	pub fn is_river(&self, id: usize) -> bool {
+		self.order[id] > 0
+	}
+
+	#[allow(dead_code)]
+	pub fn polylines(&self) -> &[RiverPolyline] {
+		&self.polylines
+	}
+
+	/// Lowland cells near rivers, which are wet enough to become swamps.
+	pub fn swamp_mask(&self, landscape: &Landscape, params: &RiverParams) -> Box<[bool]> {
+		let is_river: Box<[bool]> = self.order.iter().map(|order| *order > 0).collect();
+		let distance = continents::distance_transform((self.size.0 as usize, self.size.1 as usize), landscape.topology(), &is_river);
+		let max_height = params.sea_level.saturating_add(params.swamp_max_height);
+
+		landscape.height().iter()
+			.zip(distance.iter())
+			.map(|(height, distance)| *height > params.sea_level && *height <= max_height && *distance <= params.swamp_radius)
+			.collect()
+	}
+}
+
+/// Finds rivers of the landscape. Closed depressions are filled first, so rivers
+/// pass through lakes instead of ending in them.
+pub fn extract_rivers(landscape: &Landscape, params: &RiverParams, rainfall: Option<&[f32]>) -> RiverNetwork {
+	let size = landscape.size();
+	let width = size.0 as usize;
+
+	let mut filled = landscape.clone();
+	flood::fill_depressions(&mut filled, params.sea_level, Height::DELTA);
+	let is_sea = |id: usize| filled.height()[id] <= params.sea_level;
+
+	let directions = match params.flow_model {
+		FlowModel::D8 => FlowDirections::d8(&filled),
+		FlowModel::DInfinity => FlowDirections::d_infinity(&filled),
+	};
+	let accumulation = flow_accumulation(&filled, &directions, rainfall);
+
+	// River network is a tree, so it follows D8 even if accumulation does not
+	let d8 = match params.flow_model {
+		FlowModel::D8 => directions,
+		FlowModel::DInfinity => FlowDirections::d8(&filled),
+	};
+	let is_river = |id: usize| accumulation[id] >= params.threshold && !is_sea(id);
+
+	// Strahler order: 1 for sources, increased where two rivers of the same order meet
+	let mut order: Box<[u8]> = vec![0; accumulation.len()].into_boxed_slice();
+	let mut upstream_max: Box<[u8]> = vec![0; accumulation.len()].into_boxed_slice();
+	let mut upstream_max_count: Box<[u8]> = vec![0; accumulation.len()].into_boxed_slice();
+	let mut upstream_rivers: Box<[u8]> = vec![0; accumulation.len()].into_boxed_slice();
+
+	for id in downhill_order(&filled) {
+		if !is_river(id) {
+			continue;
+		}
+
+		order[id] = match (upstream_max[id], upstream_max_count[id]) {
+			(0, _) => 1,
+			(max, count) if count >= 2 => max.saturating_add(1),
+			(max, _) => max,
+		};
+
+		if let Some(receiver) = d8.main_receiver(id) {
+			upstream_rivers[receiver] = upstream_rivers[receiver].saturating_add(1);
+			if order[id] > upstream_max[receiver] {
+				upstream_max[receiver] = order[id];
+				upstream_max_count[receiver] = 1;
+			} else if order[id] == upstream_max[receiver] {
+				upstream_max_count[receiver] = upstream_max_count[receiver].saturating_add(1);
+			}
+		}
+	}
+
+	// Polylines start at sources and confluences and end at the next confluence or mouth
+	let center = |id: usize| ((id % width) as f32 + 0.5, (id / width) as f32 + 0.5);
+	let mut polylines = vec![];
+	for start in 0..order.len() {
+		if order[start] == 0 || upstream_rivers[start] == 1 {
+			continue;
+		}
+
+		let mut points = vec![center(start)];
+		let mut current = start;
+		while let Some(receiver) = d8.main_receiver(current) {
+			points.push(center(receiver));
+			if order[receiver] == 0 || upstream_rivers[receiver] >= 2 {
+				break;
+			}
+			current = receiver;
+		}
+
+		polylines.push(RiverPolyline { order: order[start], points });
+	}
+
+	RiverNetwork { size, accumulation, order, polylines }
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::map::{self, Topology};
+	use super::*;
+
+	/// `0.5` high, with a trench along the last column
+	fn trench(topology: Topology) -> Landscape {
+		let size = (12, 8);
+		let heights = (0..size.0 * size.1)
+			.map(|id| map::height_from_f32(if id % size.0 == size.0 - 1 { 0.3 } else { 0.5 }))
+			.collect();
+		Landscape::new(size, heights).with_topology(topology)
+	}
+
+	#[test]
+	fn flow_accumulates_down_a_tilted_plane() {
+		let size = (10, 6);
+		let heights = (0..size.0 * size.1)
+			.map(|id| map::height_from_f32(0.9 - (id % size.0) as f32 * 0.05))
+			.collect();
+		let plane = Landscape::new(size, heights);
+		let rainfall = vec![2.0; (size.0 * size.1) as usize];
+
+		for directions in [FlowDirections::d8(&plane), FlowDirections::d_infinity(&plane)] {
+			let accumulation = flow_accumulation(&plane, &directions, None);
+			let rained = flow_accumulation(&plane, &directions, Some(&rainfall));
+			for id in 0..accumulation.len() {
+				let x = id as u32 % size.0;
+				assert_eq!(accumulation[id], (x + 1) as f32, "at {id}");
+				assert_eq!(rained[id], 2.0 * (x + 1) as f32, "at {id}");
+			}
+			// All the rain leaves through the low edge
+			let outflow: f32 = (0..size.1).map(|y| accumulation[(y * size.0 + size.0 - 1) as usize]).sum();
+			assert_eq!(outflow, (size.0 * size.1) as f32);
+		}
+	}
+
+	#[test]
+	fn streams_of_same_order_merge_into_higher_order() {
+		// Two arms of a Y-shaped valley meet at (4, 5), the trunk goes down to the sea in the last row
+		let left = [(2, 0), (2, 1), (2, 2), (2, 3), (3, 4)];
+		let right = [(6, 0), (6, 1), (6, 2), (6, 3), (5, 4)];
+		let trunk = [(4, 5), (4, 6), (4, 7), (4, 8), (4, 9), (4, 10)];
+		let size = (9_u32, 12_u32);
+		let mut heights = vec![map::height_from_f32(1.0); (size.0 * size.1) as usize];
+		let mut rainfall = vec![0.0; heights.len()];
+		for x in 0..size.0 {
+			heights[(11 * size.0 + x) as usize] = Height::ZERO;
+		}
+		for arm in [&left[..], &right[..]] {
+			for (i, (x, y)) in arm.iter().chain(&trunk).enumerate() {
+				heights[(y * size.0 + x) as usize] = map::height_from_f32(0.75 - i as f32 * 0.05);
+				rainfall[(y * size.0 + x) as usize] = 1.0;
+			}
+		}
+		let landscape = Landscape::new(size, heights.into_boxed_slice());
+		let params = RiverParams { flow_model: FlowModel::D8, threshold: 1.0, sea_level: map::height_from_f32(0.1), ..Default::default() };
+		let rivers = extract_rivers(&landscape, &params, Some(&rainfall));
+
+		let order = |(x, y): (u32, u32)| rivers.order()[(y * size.0 + x) as usize];
+		for cell in left.iter().chain(&right) {
+			assert_eq!(order(*cell), 1, "at {:?}", cell);
+		}
+		for cell in trunk {
+			assert_eq!(order(cell), 2, "at {:?}", cell);
+		}
+		assert_eq!(rivers.order().iter().filter(|order| **order > 0).count(), left.len() + right.len() + trunk.len());
+		assert_eq!(rivers.accumulation()[(10 * size.0 + 4) as usize], (left.len() + right.len() + trunk.len()) as f32);
+
+		let mut polylines: Vec<(u8, (f32, f32), usize)> = rivers.polylines().iter()
+			.map(|polyline| (polyline.order, polyline.points[0], polyline.points.len()))
+			.collect();
+		polylines.sort_by(|a, b| a.partial_cmp(b).unwrap());
+		assert_eq!(polylines, vec![(1, (2.5, 0.5), 6), (1, (6.5, 0.5), 6), (2, (4.5, 5.5), 7)]);
+	}
+
+	#[test]
+	fn water_flows_across_the_seam() {
+		let cylinder = trench(Topology { wrap_x: true, wrap_y: false });
+		let (first, last) = (4 * 12, 4 * 12 + 11);
+		for directions in [FlowDirections::d8(&cylinder), FlowDirections::d_infinity(&cylinder)] {
+			assert_eq!(directions.main_receiver(first), Some(last));
+			assert_eq!(flow_accumulation(&cylinder, &directions, None)[last], 3.0);
+		}
+
+		let flat = trench(Topology::FLAT);
+		for directions in [FlowDirections::d8(&flat), FlowDirections::d_infinity(&flat)] {
+			assert_eq!(directions.main_receiver(first), None);
+			assert_eq!(flow_accumulation(&flat, &directions, None)[last], 2.0);
+		}
+	}
+}
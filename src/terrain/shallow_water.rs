@@ -0,0 +1,473 @@
+// Virtual pipes shallow water model from Mei, Decaudin, Hu, "Fast Hydraulic Erosion
+// Simulation and Visualization on GPU" (2007).
+//
+// `ShallowWater` is the CPU version (used to check the model), `ShallowWaterGpu`
+// runs the same steps as compute passes from `assets/terrain/shallow_water/`.
+
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use glow::{Context, HasContext, NativeProgram, NativeTexture};
+
+use crate::glsl_expand::ShaderContext;
+use crate::map;
+#[cfg(test)]
+use crate::map::{Height, Landscape, Topology};
+use crate::util::compile_program;
+use super::tiles::MapDispatch;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShallowWaterParams {
+	pub time_step: f32,
+	/// Water added to each cell per time unit
+	pub rain_rate: f32,
+	pub gravity: f32,
+	/// Cross section area of a virtual pipe
+	pub pipe_area: f32,
+	/// Distance between cells, in height units
+	pub cell_width: f32,
+	pub sediment_capacity: f32,
+	pub dissolve_rate: f32,
+	pub deposit_rate: f32,
+	/// Share of water which evaporates per time unit
+	pub evaporation_rate: f32,
+	/// Terrain tilt (sine) used on the flat cells, so that water still carries some sediment there
+	pub min_tilt: f32,
+	/// Water which gets to cells below this height leaves the map (flows into the sea)
+	pub sea_level: f32,
+}
+
+impl ShallowWaterParams {
+	pub const TIME_STEP_RANGE: RangeInclusive<f32> = 0.0001..=0.05;
+	pub const RATE_RANGE: RangeInclusive<f32> = 0.0..=10.0;
+	pub const CELL_WIDTH_RANGE: RangeInclusive<f32> = 0.001..=1.0;
+
+	pub fn clamped(&self) -> Self {
+		let clamp = |val: f32, range: RangeInclusive<f32>| val.clamp(*range.start(), *range.end());
+
+		ShallowWaterParams {
+			time_step: clamp(self.time_step, Self::TIME_STEP_RANGE),
+			rain_rate: clamp(self.rain_rate, Self::RATE_RANGE),
+			gravity: self.gravity.max(0.0),
+			pipe_area: self.pipe_area.max(0.0),
+			cell_width: clamp(self.cell_width, Self::CELL_WIDTH_RANGE),
+			sediment_capacity: clamp(self.sediment_capacity, Self::RATE_RANGE),
+			dissolve_rate: clamp(self.dissolve_rate, Self::RATE_RANGE),
+			deposit_rate: clamp(self.deposit_rate, Self::RATE_RANGE),
+			evaporation_rate: clamp(self.evaporation_rate, Self::RATE_RANGE),
+			min_tilt: self.min_tilt.clamp(0.0, 1.0),
+			sea_level: self.sea_level,
+		}
+	}
+}
+
+impl Default for ShallowWaterParams {
+	fn default() -> Self {
+		ShallowWaterParams {
+			time_step: 0.005,
+			rain_rate: 0.05,
+			gravity: 9.81,
+			pipe_area: 0.0025,
+			cell_width: 0.05,
+			sediment_capacity: 0.1,
+			dissolve_rate: 0.1,
+			deposit_rate: 0.3,
+			evaporation_rate: 0.5,
+			min_tilt: 0.05,
+			sea_level: map::DEFAULT_SEA_LEVEL as f32,
+		}
+	}
+}
+
+/// Flux indices: outflow to the left, right, top (`y - 1`) and bottom (`y + 1`) neighbours
+#[cfg(test)]
+const LEFT: usize = 0;
+#[cfg(test)]
+const RIGHT: usize = 1;
+#[cfg(test)]
+const TOP: usize = 2;
+#[cfg(test)]
+const BOTTOM: usize = 3;
+#[cfg(test)]
+const EPS: f32 = 0.000001;
+
+#[cfg(test)]
+#[derive(Debug, Clone)]
+pub struct ShallowWater {
+	size: (u32, u32),
+	topology: Topology,
+	terrain: Box<[f32]>,
+	water: Box<[f32]>,
+	sediment: Box<[f32]>,
+	flux: Box<[[f32; 4]]>,
+	velocity: Box<[(f32, f32)]>,
+}
+
+#[cfg(test)]
+impl ShallowWater {
+	pub fn new(landscape: &Landscape) -> Self {
+		let area = landscape.height().len();
+		ShallowWater {
+			size: landscape.size(),
+			topology: landscape.topology(),
+			terrain: landscape.height().iter().map(|height| height.to_num::<f32>()).collect(),
+			water: vec![0.0; area].into_boxed_slice(),
+			sediment: vec![0.0; area].into_boxed_slice(),
+			flux: vec![[0.0; 4]; area].into_boxed_slice(),
+			velocity: vec![(0.0, 0.0); area].into_boxed_slice(),
+		}
+	}
+
+	pub fn terrain(&self) -> &[f32] {
+		&self.terrain
+	}
+
+	pub fn water_depth(&self) -> &[f32] {
+		&self.water
+	}
+
+	pub fn sediment(&self) -> &[f32] {
+		&self.sediment
+	}
+
+	pub fn velocity(&self) -> &[(f32, f32)] {
+		&self.velocity
+	}
+
+	fn neighbour(&self, x: i32, y: i32, direction: usize) -> Option<usize> {
+		let (dx, dy) = match direction {
+			LEFT => (-1, 0),
+			RIGHT => (1, 0),
+			TOP => (0, -1),
+			_ => (0, 1),
+		};
+		self.topology.cell(self.size, (x + dx) as i64, (y + dy) as i64)
+			.map(|(nx, ny)| ny as usize * self.size.0 as usize + nx as usize)
+	}
+
+	pub fn step(&mut self, params: &ShallowWaterParams) {
+		let params = params.clamped();
+		let (width, height) = (self.size.0 as i32, self.size.1 as i32);
+		let dt = params.time_step;
+		let l = params.cell_width;
+		let cells = || (0..height).flat_map(move |y| (0..width).map(move |x| (x, y, (y * width + x) as usize)));
+
+		// 1. Rain
+		let water_1: Box<[f32]> = self.water.iter().map(|water| water + dt * params.rain_rate).collect();
+
+		// 2. Outflow flux through the pipes
+		let mut flux = self.flux.clone();
+		for (x, y, id) in cells() {
+			let surface = self.terrain[id] + water_1[id];
+			let mut total = 0.0;
+			for direction in 0..4 {
+				flux[id][direction] = match self.neighbour(x, y, direction) {
+					Some(n) => {
+						let diff = surface - (self.terrain[n] + water_1[n]);
+						(self.flux[id][direction] + dt * params.pipe_area * params.gravity * diff / l).max(0.0)
+					}
+					None => 0.0,
+				};
+				total += flux[id][direction];
+			}
+
+			// Never let out more water than the cell has
+			if total > EPS {
+				let scale = (water_1[id] * l * l / (total * dt)).min(1.0);
+				for direction in 0..4 {
+					flux[id][direction] *= scale;
+				}
+			}
+		}
+
+		// 3. Water height and velocity
+		let inflow_from = |n: Option<usize>, direction: usize| n.map_or(0.0, |n| flux[n][direction]);
+		let mut water_2 = water_1.clone();
+		for (x, y, id) in cells() {
+			let from_left = inflow_from(self.neighbour(x, y, LEFT), RIGHT);
+			let from_right = inflow_from(self.neighbour(x, y, RIGHT), LEFT);
+			let from_top = inflow_from(self.neighbour(x, y, TOP), BOTTOM);
+			let from_bottom = inflow_from(self.neighbour(x, y, BOTTOM), TOP);
+			let outflow: f32 = flux[id].iter().sum();
+
+			let volume_change = dt * (from_left + from_right + from_top + from_bottom - outflow);
+			water_2[id] = (water_1[id] + volume_change / (l * l)).max(0.0);
+
+			let avg_water = (water_1[id] + water_2[id]) / 2.0;
+			let flow_x = (from_left - flux[id][LEFT] + flux[id][RIGHT] - from_right) / 2.0;
+			let flow_y = (from_top - flux[id][TOP] + flux[id][BOTTOM] - from_bottom) / 2.0;
+			self.velocity[id] = if avg_water > EPS {
+				(flow_x / (l * avg_water), flow_y / (l * avg_water))
+			} else {
+				(0.0, 0.0)
+			};
+		}
+		self.flux = flux;
+
+		// 4. Erosion and deposition
+		let mut terrain = self.terrain.clone();
+		let mut sediment = self.sediment.clone();
+		for (x, y, id) in cells() {
+			let terrain_at = |direction: usize| self.neighbour(x, y, direction).map_or(self.terrain[id], |n| self.terrain[n]);
+			let grad_x = (terrain_at(RIGHT) - terrain_at(LEFT)) / (2.0 * l);
+			let grad_y = (terrain_at(BOTTOM) - terrain_at(TOP)) / (2.0 * l);
+			let grad = (grad_x * grad_x + grad_y * grad_y).sqrt();
+			let tilt = (grad / (1.0 + grad * grad).sqrt()).max(params.min_tilt);
+
+			let (vx, vy) = self.velocity[id];
+			let capacity = params.sediment_capacity * tilt * (vx * vx + vy * vy).sqrt();
+			if capacity > sediment[id] {
+				let amount = params.dissolve_rate * (capacity - sediment[id]) * dt;
+				terrain[id] -= amount;
+				sediment[id] += amount;
+			} else {
+				let amount = params.deposit_rate * (sediment[id] - capacity) * dt;
+				terrain[id] += amount;
+				sediment[id] -= amount;
+			}
+		}
+		self.terrain = terrain;
+
+		// 5. Sediment transport and 6. evaporation. Sediment leaves a cell in the same
+		// share as its water does, so none of it is lost on the way
+		let outflow_share = |id: usize, direction: usize| {
+			if water_1[id] > EPS {
+				self.flux[id][direction] * dt / (l * l * water_1[id])
+			} else {
+				0.0
+			}
+		};
+		for (x, y, id) in cells() {
+			let inflow_from = |direction: usize, pipe: usize| {
+				self.neighbour(x, y, direction).map_or(0.0, |n| sediment[n] * outflow_share(n, pipe))
+			};
+			let outflow: f32 = (0..4).map(|direction| outflow_share(id, direction)).sum();
+			self.sediment[id] = sediment[id] * (1.0 - outflow).max(0.0) +
+				inflow_from(LEFT, RIGHT) + inflow_from(RIGHT, LEFT) + inflow_from(TOP, BOTTOM) + inflow_from(BOTTOM, TOP);
+
+			self.water[id] = if self.terrain[id] <= params.sea_level {
+				0.0
+			} else {
+				water_2[id] * (1.0 - params.evaporation_rate * dt).max(0.0)
+			};
+		}
+	}
+}
+
+const WORK_GROUP_SIZE: u32 = 32;
+
+#[derive(Debug, Clone)]
+pub struct ShallowWaterGpu {
+	gl: Arc<Context>,
+	flux_program: NativeProgram,
+	velocity_program: NativeProgram,
+	erode_program: NativeProgram,
+	transport_program: NativeProgram,
+	size: (u64, u64),
+	dispatch: MapDispatch,
+
+	water: (NativeTexture, NativeTexture),
+	flux: (NativeTexture, NativeTexture),
+	sediment: (NativeTexture, NativeTexture),
+	velocity: NativeTexture,
+}
+
+impl ShallowWaterGpu {
+	pub fn new(gl: Arc<Context>, glsl_manager: &mut ShaderContext, map_size: (u64, u64)) -> Self {
+		let mut load_program = |path: &str| {
+			let shader = glsl_manager
+				.get_file_processed(path).unwrap()
+				.current_text().clone();
+			let sources = [
+				(glow::COMPUTE_SHADER, shader.as_str())
+			];
+			compile_program(&gl, sources).unwrap_or_else(|_| panic!("Failed to compile: \n{}", shader))
+		};
+
+		let flux_program = load_program("assets/terrain/shallow_water/flux.glsl");
+		let velocity_program = load_program("assets/terrain/shallow_water/velocity.glsl");
+		let erode_program = load_program("assets/terrain/shallow_water/erode.glsl");
+		let transport_program = load_program("assets/terrain/shallow_water/transport.glsl");
+
+		let create = |internal_format: u32, format: u32, channels: usize| unsafe {
+			let zeros = vec![0_u8; (map_size.0 * map_size.1) as usize * channels * std::mem::size_of::<f32>()];
+			let tex = gl.create_texture().unwrap();
+			gl.bind_texture(glow::TEXTURE_2D, Some(tex));
+			gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::NEAREST as i32);
+			gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::NEAREST as i32);
+			gl.tex_image_2d(glow::TEXTURE_2D, 0, internal_format as i32,
+							map_size.0 as i32, map_size.1 as i32, 0,
+							format, glow::FLOAT, Some(&zeros));
+			tex
+		};
+
+		let water = (create(glow::R32F, glow::RED, 1), create(glow::R32F, glow::RED, 1));
+		let flux = (create(glow::RGBA32F, glow::RGBA, 4), create(glow::RGBA32F, glow::RGBA, 4));
+		let sediment = (create(glow::R32F, glow::RED, 1), create(glow::R32F, glow::RED, 1));
+		let velocity = create(glow::RG32F, glow::RG, 2);
+		let dispatch = MapDispatch::new(&gl, map_size, WORK_GROUP_SIZE);
+
+		ShallowWaterGpu {
+			gl,
+			flux_program,
+			velocity_program,
+			erode_program,
+			transport_program,
+			size: map_size,
+			dispatch,
+			water,
+			flux,
+			sediment,
+			velocity,
+		}
+	}
+
+	/// `R32F` texture with the water depth of each cell
+	pub fn water_texture(&self) -> NativeTexture {
+		self.water.0
+	}
+
+	/// Makes one simulation step. Reads terrain from `terrain` and writes the
+	/// changed terrain into `terrain_next` (both are `R32I` landscape textures).
+	pub fn step(&mut self, terrain: NativeTexture, terrain_next: NativeTexture, params: &ShallowWaterParams) {
+		let params = params.clamped();
+		let gl = self.gl.clone();
+
+		unsafe {
+			let set_uniforms = |program: NativeProgram| {
+				gl.use_program(Some(program));
+				let loc = |name: &str| gl.get_uniform_location(program, name);
+				gl.uniform_2_i32(loc("u_map_size").as_ref(), self.size.0 as i32, self.size.1 as i32);
+				gl.uniform_1_f32(loc("u_time_step").as_ref(), params.time_step);
+				gl.uniform_1_f32(loc("u_rain_rate").as_ref(), params.rain_rate);
+				gl.uniform_1_f32(loc("u_gravity").as_ref(), params.gravity);
+				gl.uniform_1_f32(loc("u_pipe_area").as_ref(), params.pipe_area);
+				gl.uniform_1_f32(loc("u_cell_width").as_ref(), params.cell_width);
+				gl.uniform_1_f32(loc("u_sediment_capacity").as_ref(), params.sediment_capacity);
+				gl.uniform_1_f32(loc("u_dissolve_rate").as_ref(), params.dissolve_rate);
+				gl.uniform_1_f32(loc("u_deposit_rate").as_ref(), params.deposit_rate);
+				gl.uniform_1_f32(loc("u_evaporation_rate").as_ref(), params.evaporation_rate);
+				gl.uniform_1_f32(loc("u_min_tilt").as_ref(), params.min_tilt);
+				gl.uniform_1_f32(loc("u_sea_level").as_ref(), params.sea_level);
+			};
+			let dispatch = |program: NativeProgram| {
+				self.dispatch.dispatch(&gl, program);
+				gl.memory_barrier(glow::ALL_BARRIER_BITS);
+			};
+
+			// 1-2. Rain and outflow flux
+			set_uniforms(self.flux_program);
+			gl.bind_image_texture(0, terrain, 0, false, 0, glow::READ_ONLY, glow::R32I);
+			gl.bind_image_texture(1, self.water.0, 0, false, 0, glow::READ_ONLY, glow::R32F);
+			gl.bind_image_texture(2, self.flux.0, 0, false, 0, glow::READ_ONLY, glow::RGBA32F);
+			gl.bind_image_texture(3, self.flux.1, 0, false, 0, glow::WRITE_ONLY, glow::RGBA32F);
+			dispatch(self.flux_program);
+
+			// 3. Water height and velocity
+			set_uniforms(self.velocity_program);
+			gl.bind_image_texture(0, self.water.0, 0, false, 0, glow::READ_ONLY, glow::R32F);
+			gl.bind_image_texture(1, self.flux.1, 0, false, 0, glow::READ_ONLY, glow::RGBA32F);
+			gl.bind_image_texture(2, self.water.1, 0, false, 0, glow::WRITE_ONLY, glow::R32F);
+			gl.bind_image_texture(3, self.velocity, 0, false, 0, glow::WRITE_ONLY, glow::RG32F);
+			dispatch(self.velocity_program);
+
+			// 4. Erosion and deposition
+			set_uniforms(self.erode_program);
+			gl.bind_image_texture(0, terrain, 0, false, 0, glow::READ_ONLY, glow::R32I);
+			gl.bind_image_texture(1, self.velocity, 0, false, 0, glow::READ_ONLY, glow::RG32F);
+			gl.bind_image_texture(2, self.sediment.0, 0, false, 0, glow::READ_ONLY, glow::R32F);
+			gl.bind_image_texture(3, terrain_next, 0, false, 0, glow::WRITE_ONLY, glow::R32I);
+			gl.bind_image_texture(4, self.sediment.1, 0, false, 0, glow::WRITE_ONLY, glow::R32F);
+			dispatch(self.erode_program);
+
+			// 5-6. Sediment transport and evaporation. Water of the previous step is needed for
+			// the sediment share of each pipe, so the evaporated water stays in the second texture
+			set_uniforms(self.transport_program);
+			gl.bind_image_texture(0, terrain_next, 0, false, 0, glow::READ_ONLY, glow::R32I);
+			gl.bind_image_texture(1, self.flux.1, 0, false, 0, glow::READ_ONLY, glow::RGBA32F);
+			gl.bind_image_texture(2, self.sediment.1, 0, false, 0, glow::READ_ONLY, glow::R32F);
+			gl.bind_image_texture(3, self.water.0, 0, false, 0, glow::READ_ONLY, glow::R32F);
+			gl.bind_image_texture(4, self.sediment.0, 0, false, 0, glow::WRITE_ONLY, glow::R32F);
+			gl.bind_image_texture(5, self.water.1, 0, false, 0, glow::READ_WRITE, glow::R32F);
+			dispatch(self.transport_program);
+		}
+
+		std::mem::swap(&mut self.flux.0, &mut self.flux.1);
+		std::mem::swap(&mut self.water.0, &mut self.water.1);
+	}
+}
+
+impl Drop for ShallowWaterGpu {
+	fn drop(&mut self) {
+		let gl = self.gl.clone();
+		unsafe {
+			gl.delete_program(self.flux_program);
+			gl.delete_program(self.velocity_program);
+			gl.delete_program(self.erode_program);
+			gl.delete_program(self.transport_program);
+			for texture in [self.water.0, self.water.1, self.flux.0, self.flux.1, self.sediment.0, self.sediment.1, self.velocity] {
+				gl.delete_texture(texture);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn landscape(size: (u32, u32), topology: Topology, height: impl Fn(u32, u32) -> f32) -> Landscape {
+		let heights = (0..size.1)
+			.flat_map(|y| (0..size.0).map(move |x| (x, y)))
+			.map(|(x, y)| Height::from_num(height(x, y)))
+			.collect();
+		Landscape::new(size, heights).with_topology(topology)
+	}
+
+	fn total(values: &[f32]) -> f64 {
+		values.iter().map(|value| *value as f64).sum()
+	}
+
+	#[test]
+	fn water_and_sediment_are_conserved() {
+		let land = landscape((32, 32), Topology { wrap_x: true, wrap_y: true }, |x, y| {
+			0.5 + 0.1 * ((x as f32 / 32.0 * std::f32::consts::TAU).sin() + (y as f32 / 32.0 * std::f32::consts::TAU).cos())
+		});
+		let params = ShallowWaterParams { evaporation_rate: 0.0, sea_level: -1.0, ..Default::default() };
+		let mut water = ShallowWater::new(&land);
+		let material = total(water.terrain());
+
+		let steps = 200;
+		for _ in 0..steps {
+			water.step(&params);
+		}
+
+		let rain = params.rain_rate * params.time_step * steps as f32 * water.terrain().len() as f32;
+		assert!((total(water.water_depth()) - rain as f64).abs() < rain as f64 * 1e-3);
+		assert!(total(water.sediment()) > 0.0, "water has to carry some sediment");
+		let material_after = total(water.terrain()) + total(water.sediment());
+		assert!((material_after - material).abs() < material * 1e-4, "{} -> {}", material, material_after);
+	}
+
+	#[test]
+	fn still_lake_stays_still() {
+		let level = 0.6;
+		let land = landscape((24, 24), Topology::FLAT, |x, y| {
+			let (dx, dy) = (x as f32 - 11.5, y as f32 - 11.5);
+			0.5 + 0.002 * (dx * dx + dy * dy)
+		});
+		let mut water = ShallowWater::new(&land);
+		for (depth, terrain) in water.water.iter_mut().zip(water.terrain.iter()) {
+			*depth = (level - terrain).max(0.0);
+		}
+		let depth = water.water.clone();
+
+		let params = ShallowWaterParams { rain_rate: 0.0, evaporation_rate: 0.0, sea_level: 0.0, ..Default::default() };
+		for _ in 0..100 {
+			water.step(&params);
+		}
+
+		assert!(water.velocity().iter().all(|(vx, vy)| vx.abs() < 1e-3 && vy.abs() < 1e-3));
+		assert!(water.water_depth().iter().zip(depth.iter()).all(|(after, before)| (after - before).abs() < 1e-4));
+		assert!(water.terrain().iter().zip(land.height()).all(|(after, before)| (after - before.to_num::<f32>()).abs() < 1e-4));
+	}
+}
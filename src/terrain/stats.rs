@@ -0,0 +1,523 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use crate::map::{self, Biome, Height, Landscape, Map};
+use super::flood;
+use super::rivers::FlowDirections;
+
+/// Compass sectors of `TerrainStats::aspect`, clockwise from north (the top row of the map)
+pub const ASPECT_NAMES: [&str; 8] = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+
+const SIDES: [(i64, i64); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StatsParams {
+	/// Cells at or below it are sea, unless they are a part of a lake
+	pub sea_level: Height,
+	pub height_bins: u32,
+	/// Bins of the slope histogram, they split `0°..=90°` evenly
+	pub slope_bins: u32,
+	/// Width of a cell in units of height, sets the angle of slopes
+	pub cell_width: f32,
+}
+
+impl Default for StatsParams {
+	fn default() -> Self {
+		StatsParams {
+			sea_level: Height::from_num(map::DEFAULT_SEA_LEVEL),
+			height_bins: 20,
+			slope_bins: 18,
+			cell_width: 0.05,
+		}
+	}
+}
+
+impl StatsParams {
+	pub const BINS_RANGE: RangeInclusive<u32> = 1..=256;
+	pub const CELL_WIDTH_RANGE: RangeInclusive<f32> = 0.0001..=1.0;
+
+	pub fn clamped(&self) -> Self {
+		StatsParams {
+			sea_level: self.sea_level,
+			height_bins: self.height_bins.clamp(*Self::BINS_RANGE.start(), *Self::BINS_RANGE.end()),
+			slope_bins: self.slope_bins.clamp(*Self::BINS_RANGE.start(), *Self::BINS_RANGE.end()),
+			cell_width: self.cell_width.clamp(*Self::CELL_WIDTH_RANGE.start(), *Self::CELL_WIDTH_RANGE.end()),
+		}
+	}
+}
+
+/// Cells counted in even bins from `min` to `max`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+	pub min: f32,
+	pub max: f32,
+	pub counts: Vec<u32>,
+}
+
+impl Histogram {
+	fn new(min: f32, max: f32, bins: u32, values: impl Iterator<Item = f32>) -> Self {
+		let mut counts = vec![0; bins as usize];
+		let width = (max - min) / bins as f32;
+		for value in values {
+			let bin = if width > 0.0 { ((value - min) / width) as usize } else { 0 };
+			counts[bin.min(bins as usize - 1)] += 1;
+		}
+		Histogram { min, max, counts }
+	}
+
+	/// Values of the bin, the last bin includes `max`
+	pub fn bin_range(&self, bin: usize) -> (f32, f32) {
+		let width = (self.max - self.min) / self.counts.len() as f32;
+		(self.min + width * bin as f32, self.min + width * (bin + 1) as f32)
+	}
+
+	pub fn total(&self) -> u32 {
+		self.counts.iter().sum()
+	}
+
+	/// Share of the counted cells in the bin
+	pub fn share(&self, bin: usize) -> f32 {
+		self.counts[bin] as f32 / self.total().max(1) as f32
+	}
+}
+
+/// Numbers of cells of each kind. Lakes are found with `flood::detect_lakes` and
+/// are never counted as sea, even if they lie below the sea level.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AreaStats {
+	pub cells: u32,
+	pub land: u32,
+	pub sea: u32,
+	pub lake: u32,
+	pub lakes_count: u32,
+}
+
+impl AreaStats {
+	pub fn land_share(&self) -> f32 {
+		self.land as f32 / self.cells.max(1) as f32
+	}
+
+	pub fn sea_share(&self) -> f32 {
+		self.sea as f32 / self.cells.max(1) as f32
+	}
+
+	pub fn lake_share(&self) -> f32 {
+		self.lake as f32 / self.cells.max(1) as f32
+	}
+}
+
+/// Summary of a landscape (and optionally of its biome map) to compare generator
+/// parameter sets. Lengths are in cells, areas are in numbers of cells.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TerrainStats {
+	pub size: (u32, u32),
+	pub sea_level: f32,
+	/// Of all cells, from the lowest to the highest one
+	pub heights: Histogram,
+	/// Of all cells, in degrees
+	pub slopes: Histogram,
+	/// Cells by the direction their slope faces (downhill), see `ASPECT_NAMES`
+	pub aspect: [u32; 8],
+	/// Cells with no slope at all, they have no aspect
+	pub flat_cells: u32,
+	pub area: AreaStats,
+	/// Side-by-side pairs of land and sea cells (lake shores are not counted)
+	pub coastline_length: u32,
+	/// Areas of the side-connected groups of land and lake cells, largest first
+	pub islands: Vec<u32>,
+	/// Areas of the land and lake cells draining to the same outlet, largest first.
+	/// Outlets are the coast, the map border and the pits without a lower neighbour.
+	pub drainage_basins: Vec<u32>,
+	/// Share of the cells of each biome, in the order of `Biome::all`. Only if the map was given
+	pub biomes: Option<Vec<(Biome, f32)>>,
+}
+
+pub fn compute_stats(landscape: &Landscape, map: Option<&Map>, params: &StatsParams) -> TerrainStats {
+	let params = params.clamped();
+	let size = landscape.size();
+	let topology = landscape.topology();
+	let heights = landscape.height();
+	let width = size.0 as usize;
+	let height_at = |x: i64, y: i64| topology.cell(size, x, y)
+		.map(|(x, y)| heights[y as usize * width + x as usize].to_num::<f32>());
+
+	// Heights
+	let min = heights.iter().min().map_or(0.0, |height| height.to_num::<f32>());
+	let max = heights.iter().max().map_or(0.0, |height| height.to_num::<f32>());
+	let height_histogram = Histogram::new(min, max, params.height_bins, heights.iter().map(|height| height.to_num::<f32>()));
+
+	// Slopes and aspect, from central differences (one-sided at the borders)
+	let mut slopes = Vec::with_capacity(heights.len());
+	let mut aspect = [0; 8];
+	let mut flat_cells = 0;
+	for y in 0..size.1 as i64 {
+		for x in 0..size.0 as i64 {
+			let center = height_at(x, y).unwrap();
+			let derivative = |dx: i64, dy: i64| match (height_at(x + dx, y + dy), height_at(x - dx, y - dy)) {
+				(Some(next), Some(previous)) => (next - previous) / 2.0,
+				(Some(next), None) => next - center,
+				(None, Some(previous)) => center - previous,
+				(None, None) => 0.0,
+			};
+			let gradient = (derivative(1, 0), derivative(0, 1));
+			let steepness = (gradient.0 * gradient.0 + gradient.1 * gradient.1).sqrt();
+			slopes.push((steepness / params.cell_width).atan().to_degrees());
+
+			if steepness == 0.0 {
+				flat_cells += 1;
+			} else {
+				// Downhill is `-gradient`, north is `-y`
+				let bearing = (-gradient.0).atan2(gradient.1).to_degrees().rem_euclid(360.0);
+				aspect[(bearing / 45.0).round() as usize % 8] += 1;
+			}
+		}
+	}
+	let slope_histogram = Histogram::new(0.0, 90.0, params.slope_bins, slopes.into_iter());
+
+	// Land, sea and lakes
+	let lakes = flood::detect_lakes(landscape, params.sea_level);
+	let is_sea: Box<[bool]> = (0..heights.len())
+		.map(|id| !lakes.is_lake(id) && heights[id] <= params.sea_level)
+		.collect();
+	let sea = is_sea.iter().filter(|sea| **sea).count() as u32;
+	let lake = lakes.ids().iter().filter(|id| **id != 0).count() as u32;
+	let area = AreaStats {
+		cells: heights.len() as u32,
+		land: heights.len() as u32 - sea - lake,
+		sea,
+		lake,
+		lakes_count: lakes.lakes().len() as u32,
+	};
+
+	// Coastline. Each pair is counted from its land cell
+	let mut coastline_length = 0;
+	for y in 0..size.1 as i64 {
+		for x in 0..size.0 as i64 {
+			let id = y as usize * width + x as usize;
+			if is_sea[id] || lakes.is_lake(id) {
+				continue;
+			}
+			coastline_length += SIDES.iter()
+				.filter_map(|(dx, dy)| topology.cell(size, x + dx, y + dy))
+				.filter(|(nx, ny)| is_sea[*ny as usize * width + *nx as usize])
+				.count() as u32;
+		}
+	}
+
+	// Islands
+	let mut islands = vec![];
+	let mut visited = vec![false; heights.len()];
+	let mut queue = VecDeque::new();
+	for start in 0..heights.len() {
+		if visited[start] || is_sea[start] {
+			continue;
+		}
+
+		let mut island = 0;
+		visited[start] = true;
+		queue.push_back(start);
+		while let Some(cell) = queue.pop_front() {
+			island += 1;
+			let (x, y) = ((cell % width) as i64, (cell / width) as i64);
+			for (dx, dy) in SIDES {
+				if let Some((nx, ny)) = topology.cell(size, x + dx, y + dy) {
+					let neighbour = ny as usize * width + nx as usize;
+					if !visited[neighbour] && !is_sea[neighbour] {
+						visited[neighbour] = true;
+						queue.push_back(neighbour);
+					}
+				}
+			}
+		}
+		islands.push(island);
+	}
+	islands.sort_unstable_by(|a, b| b.cmp(a));
+
+	// Drainage basins. Receivers are always lower, so going uphill every cell
+	// finds the basin of its receiver already known.
+	let directions = FlowDirections::d8(landscape);
+	let mut order: Vec<usize> = (0..heights.len()).filter(|id| !is_sea[*id]).collect();
+	order.sort_by(|a, b| heights[*a].cmp(&heights[*b]).then(a.cmp(b)));
+	let mut basin_of = vec![usize::MAX; heights.len()];
+	let mut drainage_basins: Vec<u32> = vec![];
+	for id in order {
+		let basin = match directions.main_receiver(id) {
+			Some(receiver) if !is_sea[receiver] => basin_of[receiver],
+			_ => {
+				drainage_basins.push(0);
+				drainage_basins.len() - 1
+			}
+		};
+		basin_of[id] = basin;
+		drainage_basins[basin] += 1;
+	}
+	drainage_basins.sort_unstable_by(|a, b| b.cmp(a));
+
+	// Biomes
+	let biomes = map.map(|map| {
+		let cells = map.cells();
+		Biome::all().iter()
+			.map(|biome| {
+				let count = cells.iter().filter(|(cell_biome, _)| cell_biome == biome).count();
+				(*biome, count as f32 / cells.len().max(1) as f32)
+			})
+			.collect()
+	});
+
+	TerrainStats {
+		size,
+		sea_level: params.sea_level.to_num(),
+		heights: height_histogram,
+		slopes: slope_histogram,
+		aspect,
+		flat_cells,
+		area,
+		coastline_length,
+		islands,
+		drainage_basins,
+		biomes,
+	}
+}
+
+impl TerrainStats {
+	/// Writes `metric,key,value` rows with a header. Histogram bins are keyed by
+	/// their lower bound, islands and basins by their place in the list.
+	pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+		writeln!(writer, "metric,key,value")?;
+		writeln!(writer, "size,width,{}", self.size.0)?;
+		writeln!(writer, "size,height,{}", self.size.1)?;
+		writeln!(writer, "sea_level,,{}", self.sea_level)?;
+		for (name, histogram) in [("height", &self.heights), ("slope", &self.slopes)] {
+			for (bin, count) in histogram.counts.iter().enumerate() {
+				writeln!(writer, "{}_histogram,{},{}", name, histogram.bin_range(bin).0, count)?;
+			}
+		}
+		for (name, count) in ASPECT_NAMES.iter().zip(self.aspect) {
+			writeln!(writer, "aspect,{},{}", name, count)?;
+		}
+		writeln!(writer, "aspect,flat,{}", self.flat_cells)?;
+		writeln!(writer, "area,land,{}", self.area.land)?;
+		writeln!(writer, "area,sea,{}", self.area.sea)?;
+		writeln!(writer, "area,lake,{}", self.area.lake)?;
+		writeln!(writer, "lakes_count,,{}", self.area.lakes_count)?;
+		writeln!(writer, "coastline_length,,{}", self.coastline_length)?;
+		writeln!(writer, "islands_count,,{}", self.islands.len())?;
+		for (i, island) in self.islands.iter().enumerate() {
+			writeln!(writer, "island_area,{},{}", i, island)?;
+		}
+		writeln!(writer, "drainage_basins_count,,{}", self.drainage_basins.len())?;
+		for (i, basin) in self.drainage_basins.iter().enumerate() {
+			writeln!(writer, "drainage_basin_area,{},{}", i, basin)?;
+		}
+		if let Some(biomes) = &self.biomes {
+			for (biome, share) in biomes {
+				writeln!(writer, "biome_share,{},{}", biome.key(), share)?;
+			}
+		}
+		Ok(())
+	}
+
+	/// Same values as `write_csv`, as one JSON object
+	pub fn write_json(&self, mut writer: impl Write) -> io::Result<()> {
+		let list = |values: &[u32]| values.iter().map(|value| value.to_string()).collect::<Vec<_>>().join(", ");
+		let histogram = |histogram: &Histogram| format!("{{\"min\": {}, \"max\": {}, \"counts\": [{}]}}",
+														histogram.min, histogram.max, list(&histogram.counts));
+
+		writeln!(writer, "{{")?;
+		writeln!(writer, "  \"size\": [{}, {}],", self.size.0, self.size.1)?;
+		writeln!(writer, "  \"sea_level\": {},", self.sea_level)?;
+		writeln!(writer, "  \"height_histogram\": {},", histogram(&self.heights))?;
+		writeln!(writer, "  \"slope_histogram\": {},", histogram(&self.slopes))?;
+		let aspect: Vec<String> = ASPECT_NAMES.iter().zip(self.aspect)
+			.map(|(name, count)| format!("\"{}\": {}", name, count))
+			.collect();
+		writeln!(writer, "  \"aspect\": {{{}, \"flat\": {}}},", aspect.join(", "), self.flat_cells)?;
+		writeln!(writer, "  \"area\": {{\"land\": {}, \"sea\": {}, \"lake\": {}}},", self.area.land, self.area.sea, self.area.lake)?;
+		writeln!(writer, "  \"lakes_count\": {},", self.area.lakes_count)?;
+		writeln!(writer, "  \"coastline_length\": {},", self.coastline_length)?;
+		writeln!(writer, "  \"islands\": [{}],", list(&self.islands))?;
+		write!(writer, "  \"drainage_basins\": [{}]", list(&self.drainage_basins))?;
+		if let Some(biomes) = &self.biomes {
+			let biomes: Vec<String> = biomes.iter()
+				.map(|(biome, share)| format!("\"{}\": {}", biome.key(), share))
+				.collect();
+			write!(writer, ",\n  \"biomes\": {{{}}}", biomes.join(", "))?;
+		}
+		writeln!(writer, "\n}}")
+	}
+
+	pub fn export_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+		let mut writer = BufWriter::new(File::create(path)?);
+		self.write_csv(&mut writer)?;
+		writer.flush()
+	}
+
+	pub fn export_json(&self, path: impl AsRef<Path>) -> io::Result<()> {
+		let mut writer = BufWriter::new(File::create(path)?);
+		self.write_json(&mut writer)?;
+		writer.flush()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::map::Topology;
+	use super::*;
+
+	/// `#` is land and `.` is sea
+	fn landscape(rows: &[&str], topology: Topology) -> Landscape {
+		let size = (rows[0].len() as u32, rows.len() as u32);
+		let heights = rows.iter()
+			.flat_map(|row| row.chars())
+			.map(|cell| Height::from_num(if cell == '#' { 0.6 } else { 0.1 }))
+			.collect();
+		Landscape::new(size, heights).with_topology(topology)
+	}
+
+	#[test]
+	fn islands_and_coastline_of_a_tiny_map() {
+		let rows = [
+			"........",
+			".##.....",
+			".##..#..",
+			".....#..",
+			".#......",
+			"........",
+		];
+		let stats = compute_stats(&landscape(&rows, Topology::FLAT), None, &StatsParams::default());
+		assert_eq!(stats.islands, vec![4, 2, 1]);
+		assert_eq!(stats.coastline_length, 8 + 6 + 4);
+		assert_eq!(stats.area, AreaStats { cells: 48, land: 7, sea: 41, lake: 0, lakes_count: 0 });
+	}
+
+	#[test]
+	fn islands_join_across_wrapped_edges() {
+		let rows = [
+			"#......#",
+			"#......#",
+			"........",
+		];
+		let flat = compute_stats(&landscape(&rows, Topology::FLAT), None, &StatsParams::default());
+		assert_eq!(flat.islands, vec![2, 2]);
+		// Only the inner and the lower sides touch the sea
+		assert_eq!(flat.coastline_length, 6);
+
+		let cylinder = compute_stats(&landscape(&rows, Topology { wrap_x: true, wrap_y: false }), None, &StatsParams::default());
+		assert_eq!(cylinder.islands, vec![4]);
+		assert_eq!(cylinder.coastline_length, 6);
+
+		// The upper sides touch the sea of the last row
+		let torus = compute_stats(&landscape(&rows, Topology { wrap_x: true, wrap_y: true }), None, &StatsParams::default());
+		assert_eq!(torus.islands, vec![4]);
+		assert_eq!(torus.coastline_length, 8);
+	}
+
+	fn plane(size: (u32, u32), height: impl Fn(u32, u32) -> f32) -> Landscape {
+		let heights = (0..size.0 * size.1)
+			.map(|id| Height::from_num(height(id % size.0, id / size.0)))
+			.collect();
+		Landscape::new(size, heights)
+	}
+
+	#[test]
+	fn tilted_plane_faces_downhill() {
+		let params = StatsParams { sea_level: Height::ZERO, ..Default::default() };
+		let size = (12, 10);
+		let cells = size.0 * size.1;
+		// Rising to the east and to the south, north is the first row
+		let diagonal = 0.03 / std::f32::consts::SQRT_2;
+		for (name, (dx, dy)) in [("W", (0.03, 0.0)), ("E", (-0.03, 0.0)), ("N", (0.0, 0.03)), ("SE", (-diagonal, -diagonal))] {
+			let stats = compute_stats(&plane(size, |x, y| 0.5 + dx * x as f32 + dy * y as f32), None, &params);
+			let sector = ASPECT_NAMES.iter().position(|aspect| *aspect == name).unwrap();
+			assert_eq!(stats.aspect[sector], cells, "{name}: {:?}", stats.aspect);
+			assert_eq!(stats.flat_cells, 0);
+
+			// atan(0.03 / 0.05) is 31°, in the bin of 30°..35°
+			assert_eq!(stats.slopes.total(), cells);
+			assert_eq!(stats.slopes.counts[6], cells, "{name}: {:?}", stats.slopes.counts);
+			assert_eq!(stats.slopes.bin_range(6), (30.0, 35.0));
+		}
+
+		let flat = compute_stats(&plane(size, |_, _| 0.5), None, &params);
+		assert_eq!(flat.flat_cells, cells);
+		assert_eq!(flat.aspect, [0; 8]);
+		assert_eq!(flat.slopes.counts[0], cells);
+	}
+
+	#[test]
+	fn two_valleys_are_two_basins() {
+		let params = StatsParams { sea_level: Height::ZERO, ..Default::default() };
+		let pits = [(5.0, 5.0), (16.0, 5.0)];
+		let landscape = plane((21, 11), |x, y| {
+			let distance = |(px, py): (f32, f32)| ((x as f32 - px).powi(2) + (y as f32 - py).powi(2)).sqrt();
+			0.5 + 0.01 * distance(pits[0]).min(distance(pits[1]))
+		});
+		let stats = compute_stats(&landscape, None, &params);
+		assert_eq!(stats.drainage_basins.len(), 2, "{:?}", stats.drainage_basins);
+		assert_eq!(stats.drainage_basins.iter().sum::<u32>(), 21 * 11);
+		// The ridge between the pits is nearer the right one, so the left basin is larger
+		assert!(stats.drainage_basins[0] > stats.drainage_basins[1]);
+
+		// With the sea in the last row every cell next to it is an outlet of its own
+		let coast = plane((21, 11), |x, y| if y == 10 { 0.1 } else { 0.5 + 0.01 * (x as f32 - 10.0).abs() + 0.01 * (10 - y) as f32 });
+		let stats = compute_stats(&coast, None, &StatsParams { sea_level: Height::from_num(0.2), ..Default::default() });
+		assert_eq!(stats.drainage_basins.iter().sum::<u32>(), 21 * 10);
+		assert!(stats.drainage_basins.len() > 2);
+	}
+
+	#[test]
+	fn json_and_csv_have_every_value() {
+		let rows = [
+			"........",
+			".##.....",
+			".##..#..",
+			"........",
+		];
+		let land = landscape(&rows, Topology::FLAT);
+		let cells = (0..32).map(|id| (if id % 2 == 0 { Biome::Sea } else { Biome::Plains }, [None; map::MAX_RESOURCES_IN_CELL])).collect();
+		let stats = compute_stats(&land, Some(&Map::new((8, 4), cells)), &StatsParams::default());
+
+		let mut json = vec![];
+		stats.write_json(&mut json).unwrap();
+		let json = String::from_utf8(json).unwrap();
+		let keys = [
+			"size", "sea_level", "height_histogram", "slope_histogram", "min", "max", "counts", "aspect", "flat",
+			"area", "land", "sea", "lake", "lakes_count", "coastline_length", "islands", "drainage_basins", "biomes",
+		];
+		for key in keys.iter().chain(&ASPECT_NAMES) {
+			assert!(json.contains(&format!("\"{}\": ", key)), "no {key} in {json}");
+		}
+		assert!(json.contains("\"islands\": [4, 1]"), "{json}");
+		assert!(json.contains("\"plains\": 0.5"), "{json}");
+
+		let mut depth = Vec::new();
+		for char in json.chars() {
+			match char {
+				'{' | '[' => depth.push(char),
+				'}' => assert_eq!(depth.pop(), Some('{'), "{json}"),
+				']' => assert_eq!(depth.pop(), Some('['), "{json}"),
+				_ => {}
+			}
+		}
+		assert!(depth.is_empty(), "{json}");
+		assert!(json.trim().starts_with('{') && json.trim().ends_with('}'));
+		let compact: String = json.split_whitespace().collect();
+		assert!(!compact.contains(",}") && !compact.contains(",]"), "{json}");
+
+		let mut csv = vec![];
+		stats.write_csv(&mut csv).unwrap();
+		let csv = String::from_utf8(csv).unwrap();
+		let lines: Vec<&str> = csv.lines().collect();
+		assert_eq!(lines[0], "metric,key,value");
+		assert!(lines.iter().all(|line| line.split(',').count() == 3), "{csv}");
+		let rows_of = |metric: &str| lines.iter().filter(|line| line.starts_with(&format!("{},", metric))).count();
+		assert_eq!(rows_of("height_histogram"), stats.heights.counts.len());
+		assert_eq!(rows_of("slope_histogram"), stats.slopes.counts.len());
+		assert_eq!(rows_of("aspect"), 9);
+		assert_eq!(rows_of("island_area"), 2);
+		assert_eq!(rows_of("biome_share"), Biome::all().len());
+	}
+}
@@ -0,0 +1,453 @@
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use glow::{Context, HasContext, NativeProgram, NativeTexture};
+use rand::Rng;
+
+use crate::glsl_expand::ShaderContext;
+#[cfg(test)]
+use crate::map;
+use crate::map::{Height, Landscape, Topology};
+use crate::util::compile_program;
+use super::generators::{self, PlateLayout};
+use super::tiles::MapDispatch;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PlateSource {
+	/// Plates of the tectonic plates generator (`generators::PlateLayout`)
+	Voronoi,
+	/// Each continent of the current landscape is a plate, the sea does not move
+	Continents,
+}
+
+impl PlateSource {
+	pub fn all() -> &'static [PlateSource] {
+		&[PlateSource::Voronoi, PlateSource::Continents]
+	}
+
+	pub fn localized_name(&self) -> &'static str {
+		match self {
+			PlateSource::Voronoi => "Voronoi plates",
+			PlateSource::Continents => "Continents",
+		}
+	}
+}
+
+/// Slow tectonic processes which run between the erosion iterations, so that
+/// mountains keep growing while erosion wears them down.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TectonicParams {
+	pub source: PlateSource,
+	/// Height added per tick where plates collide head-on with unit relative speed
+	pub uplift_rate: f32,
+	/// Height removed per tick where plates move apart with unit relative speed
+	pub subsidence_rate: f32,
+	/// Share of the eroded material which the crust gives back by rising. Deposited
+	/// material pushes the crust down in the same way
+	pub isostasy: f32,
+	/// Width of the zones around plate boundaries, in cells
+	pub boundary_width: f32,
+	/// Ticks between two tectonic steps. Each step applies the uplift of all of them at once
+	pub interval: u32,
+}
+
+impl TectonicParams {
+	pub const RATE_RANGE: RangeInclusive<f32> = 0.0..=0.01;
+	pub const ISOSTASY_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+	pub const BOUNDARY_WIDTH_RANGE: RangeInclusive<f32> = 1.0..=1024.0;
+	pub const INTERVAL_RANGE: RangeInclusive<u32> = 1..=1000;
+
+	pub fn clamped(&self) -> Self {
+		let clamp_f32 = |val: f32, range: RangeInclusive<f32>| val.clamp(*range.start(), *range.end());
+
+		TectonicParams {
+			source: self.source,
+			uplift_rate: clamp_f32(self.uplift_rate, Self::RATE_RANGE),
+			subsidence_rate: clamp_f32(self.subsidence_rate, Self::RATE_RANGE),
+			isostasy: clamp_f32(self.isostasy, Self::ISOSTASY_RANGE),
+			boundary_width: clamp_f32(self.boundary_width, Self::BOUNDARY_WIDTH_RANGE),
+			interval: self.interval.clamp(*Self::INTERVAL_RANGE.start(), *Self::INTERVAL_RANGE.end()),
+		}
+	}
+
+	/// Height change per tick (in `map::height_to_int` units) of a cell with `convergence`.
+	/// `assets/terrain/tectonics.glsl` rounds it in the same way.
+	#[cfg(test)]
+	fn rate(&self, convergence: f32) -> i32 {
+		let rate = if convergence > 0.0 { convergence * self.uplift_rate } else { convergence * self.subsidence_rate };
+		(rate * map::HEIGHT_INT_RANGE as f32 + 0.5).floor() as i32
+	}
+}
+
+impl Default for TectonicParams {
+	fn default() -> Self {
+		TectonicParams {
+			source: PlateSource::Voronoi,
+			uplift_rate: 0.0002,
+			subsidence_rate: 0.0001,
+			isostasy: 0.2,
+			boundary_width: 24.0,
+			interval: 10,
+		}
+	}
+}
+
+/// Velocity of the plate each cell belongs to
+pub fn voronoi_velocities(layout: &PlateLayout, size: (u64, u64)) -> Box<[(f32, f32)]> {
+	(0..size.0 * size.1)
+		.map(|id| {
+			let (plate, _) = layout.closest(layout.warp(id % size.0, id / size.0));
+			layout.plates()[plate].velocity
+		})
+		.collect()
+}
+
+/// Every 4-connected area of land above `sea_level` gets a random velocity, the sea stays still.
+/// Areas connected across the wrapped edges of the landscape are one continent.
+pub fn continent_velocities(landscape: &Landscape, sea_level: Height, rng: &mut impl Rng) -> Box<[(f32, f32)]> {
+	let size = landscape.size();
+	let topology = landscape.topology();
+	let width = size.0 as usize;
+	let is_land: Box<[bool]> = landscape.height().iter().map(|height| *height > sea_level).collect();
+	let mut velocities = vec![(0.0, 0.0); is_land.len()].into_boxed_slice();
+	let mut visited = vec![false; is_land.len()];
+	let mut stack = vec![];
+
+	for start in 0..is_land.len() {
+		if !is_land[start] || visited[start] {
+			continue;
+		}
+		let angle = rng.gen_range(0.0..std::f32::consts::TAU);
+		let speed = rng.gen_range(0.0..=1.0);
+		let velocity = (angle.cos() * speed, angle.sin() * speed);
+
+		visited[start] = true;
+		stack.push(start);
+		while let Some(id) = stack.pop() {
+			velocities[id] = velocity;
+			let (x, y) = ((id % width) as i64, (id / width) as i64);
+			let neighbours = [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)].into_iter()
+				.filter_map(|(x, y)| topology.cell(size, x, y))
+				.map(|(x, y)| y as usize * width + x as usize);
+			for neighbour in neighbours {
+				if is_land[neighbour] && !visited[neighbour] {
+					visited[neighbour] = true;
+					stack.push(neighbour);
+				}
+			}
+		}
+	}
+	velocities
+}
+
+/// How fast the crust is squeezed in each cell: positive near convergent plate
+/// boundaries, negative near divergent ones. Head-on collision of plates with
+/// unit relative speed gives `1.0` right at the boundary.
+#[derive(Debug, Clone)]
+pub struct UpliftField {
+	convergence: Box<[f32]>,
+}
+
+impl UpliftField {
+	/// Minus divergence of the velocity field blurred over `boundary_width` cells
+	pub fn new(size: (u32, u32), topology: Topology, velocities: &[(f32, f32)], boundary_width: f32) -> Self {
+		let (width, height) = (size.0 as usize, size.1 as usize);
+		let radius = (boundary_width / 4.0).round().max(1.0) as usize;
+		let blur = |values: Vec<f32>| (0..3).fold(values, |values, _| generators::box_blur((width, height), topology, &values, radius));
+		let vx = blur(velocities.iter().map(|velocity| velocity.0).collect());
+		let vy = blur(velocities.iter().map(|velocity| velocity.1).collect());
+
+		// Blurred step of height `1.0` has the largest slope of `1 / (sigma * sqrt(2 * pi))`
+		let sigma = ((radius * (radius + 1)) as f32).sqrt();
+		let scale = sigma * (2.0 * std::f32::consts::PI).sqrt();
+
+		let mut convergence = vec![0.0; width * height].into_boxed_slice();
+		// Central differences, one-sided at the edges which do not wrap
+		let span = |low: usize, high: usize, wrap: bool| if wrap { 2 } else { (high - low).max(1) };
+		for y in 0..height {
+			for x in 0..width {
+				let (left, top) = topology.clamp_cell(size, x as i64 - 1, y as i64 - 1);
+				let (right, bottom) = topology.clamp_cell(size, x as i64 + 1, y as i64 + 1);
+				let (left, right, top, bottom) = (left as usize, right as usize, top as usize, bottom as usize);
+				let dx = (vx[y * width + right] - vx[y * width + left]) / span(left, right, topology.wrap_x) as f32;
+				let dy = (vy[bottom * width + x] - vy[top * width + x]) / span(top, bottom, topology.wrap_y) as f32;
+				convergence[y * width + x] = -(dx + dy) * scale;
+			}
+		}
+
+		UpliftField { convergence }
+	}
+
+	#[cfg(test)]
+	pub fn convergence(&self) -> &[f32] {
+		&self.convergence
+	}
+}
+
+/// Material added to and removed from the landscape by a tectonic step, in
+/// `map::height_to_int` units summed over all cells.
+#[cfg(test)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MassBudget {
+	pub uplift: i64,
+	/// Negative or zero
+	pub subsidence: i64,
+	/// Negative if there was more deposition than erosion
+	pub rebound: i64,
+}
+
+#[cfg(test)]
+impl MassBudget {
+	pub fn total(&self) -> i64 {
+		self.uplift + self.subsidence + self.rebound
+	}
+
+	/// Sum of all heights, so that `mass_of(after) - mass_of(before) == budget.total()`
+	pub fn mass_of(landscape: &Landscape) -> i64 {
+		landscape.height().iter().map(|height| map::height_to_int(*height) as i64).sum()
+	}
+}
+
+/// CPU version of the tectonic step made by `TectonicsGpu`
+#[cfg(test)]
+#[derive(Debug, Clone)]
+pub struct Tectonics {
+	field: UpliftField,
+	/// Heights right after the previous step
+	reference: Option<Box<[i32]>>,
+}
+
+#[cfg(test)]
+impl Tectonics {
+	pub fn new(field: UpliftField) -> Self {
+		Tectonics { field, reference: None }
+	}
+
+	/// Forgets the previous step, so the next one has no isostatic rebound
+	pub fn reset(&mut self) {
+		self.reference = None;
+	}
+
+	/// Applies `ticks` ticks of uplift and subsidence. The crust under each cell
+	/// rebounds by `isostasy` of the material which was removed from it since the
+	/// previous step.
+	pub fn step(&mut self, landscape: &mut Landscape, params: &TectonicParams, ticks: u32) -> MassBudget {
+		let params = params.clamped();
+		let mut budget = MassBudget::default();
+		let reference = self.reference.take();
+		let mut next_reference = Vec::with_capacity(landscape.height().len());
+
+		for (id, height) in landscape.height_mut().iter_mut().enumerate() {
+			let current = map::height_to_int(*height);
+			let eroded = reference.as_ref().map_or(0, |reference| reference[id] - current);
+			let rebound = (eroded as f32 * params.isostasy + 0.5).floor() as i32;
+			let uplift = params.rate(self.field.convergence[id]) * ticks as i32;
+
+			let result = current + rebound + uplift;
+			*height = map::height_from_int(result);
+			next_reference.push(result);
+
+			budget.rebound += rebound as i64;
+			if uplift > 0 {
+				budget.uplift += uplift as i64;
+			} else {
+				budget.subsidence += uplift as i64;
+			}
+		}
+		self.reference = Some(next_reference.into_boxed_slice());
+		budget
+	}
+}
+
+const WORK_GROUP_SIZE: u32 = 32;
+
+#[derive(Debug, Clone)]
+pub struct TectonicsGpu {
+	gl: Arc<Context>,
+	program: NativeProgram,
+	size: (u64, u64),
+	dispatch: MapDispatch,
+	/// `R32F`, see `UpliftField`
+	convergence: NativeTexture,
+	/// `R32I`, heights right after the previous step
+	reference: NativeTexture,
+	has_field: bool,
+	has_reference: bool,
+}
+
+impl TectonicsGpu {
+	pub fn new(gl: Arc<Context>, glsl_manager: &mut ShaderContext, map_size: (u64, u64)) -> Self {
+		let shader = glsl_manager
+			.get_file_processed("assets/terrain/tectonics.glsl").unwrap()
+			.current_text().clone();
+		let sources = [
+			(glow::COMPUTE_SHADER, shader.as_str())
+		];
+		let program = compile_program(&gl, sources).unwrap_or_else(|_| panic!("Failed to compile: \n{}", shader));
+
+		let create = |internal_format: u32, format: u32, data_type: u32| unsafe {
+			let zeros = vec![0_u8; (map_size.0 * map_size.1) as usize * 4];
+			let tex = gl.create_texture().unwrap();
+			gl.bind_texture(glow::TEXTURE_2D, Some(tex));
+			gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MIN_FILTER, glow::NEAREST as i32);
+			gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAG_FILTER, glow::NEAREST as i32);
+			gl.tex_image_2d(glow::TEXTURE_2D, 0, internal_format as i32,
+							map_size.0 as i32, map_size.1 as i32, 0,
+							format, data_type, Some(&zeros));
+			tex
+		};
+		let convergence = create(glow::R32F, glow::RED, glow::FLOAT);
+		let reference = create(glow::R32I, glow::RED_INTEGER, glow::INT);
+		let dispatch = MapDispatch::new(&gl, map_size, WORK_GROUP_SIZE);
+
+		TectonicsGpu {
+			gl,
+			program,
+			size: map_size,
+			dispatch,
+			convergence,
+			reference,
+			has_field: false,
+			has_reference: false,
+		}
+	}
+
+	pub fn has_field(&self) -> bool {
+		self.has_field
+	}
+
+	/// Uploads the field, it has to be of the map size
+	pub fn set_field(&mut self, field: &UpliftField) {
+		let gl = self.gl.clone();
+		unsafe {
+			let ptr = std::slice::from_raw_parts(field.convergence.as_ptr() as *const u8, field.convergence.len() * std::mem::size_of::<f32>());
+			gl.bind_texture(glow::TEXTURE_2D, Some(self.convergence));
+			gl.tex_sub_image_2d(glow::TEXTURE_2D, 0, 0, 0,
+								self.size.0 as i32, self.size.1 as i32,
+								glow::RED, glow::FLOAT, glow::PixelUnpackData::Slice(ptr));
+		}
+		self.has_field = true;
+		self.has_reference = false;
+	}
+
+	/// Forgets the previous step, so the next one has no isostatic rebound
+	pub fn reset(&mut self) {
+		self.has_reference = false;
+	}
+
+	/// Reads `terrain` and writes it with `ticks` ticks of tectonics applied into `terrain_next`
+	pub fn step(&mut self, terrain: NativeTexture, terrain_next: NativeTexture, params: &TectonicParams, ticks: u32) {
+		let params = params.clamped();
+		let gl = self.gl.clone();
+
+		unsafe {
+			gl.use_program(Some(self.program));
+			let loc = |name: &str| gl.get_uniform_location(self.program, name);
+			gl.uniform_2_i32(loc("u_map_size").as_ref(), self.size.0 as i32, self.size.1 as i32);
+			gl.uniform_1_i32(loc("u_has_reference").as_ref(), self.has_reference as i32);
+			gl.uniform_1_f32(loc("u_uplift_rate").as_ref(), params.uplift_rate);
+			gl.uniform_1_f32(loc("u_subsidence_rate").as_ref(), params.subsidence_rate);
+			gl.uniform_1_f32(loc("u_isostasy").as_ref(), params.isostasy);
+			gl.uniform_1_i32(loc("u_ticks").as_ref(), ticks as i32);
+
+			gl.bind_image_texture(0, terrain, 0, false, 0, glow::READ_ONLY, glow::R32I);
+			gl.bind_image_texture(1, terrain_next, 0, false, 0, glow::WRITE_ONLY, glow::R32I);
+			gl.bind_image_texture(2, self.reference, 0, false, 0, glow::READ_WRITE, glow::R32I);
+			gl.bind_image_texture(3, self.convergence, 0, false, 0, glow::READ_ONLY, glow::R32F);
+			self.dispatch.dispatch(&gl, self.program);
+			gl.memory_barrier(glow::ALL_BARRIER_BITS);
+		}
+		self.has_reference = true;
+	}
+}
+
+impl Drop for TectonicsGpu {
+	fn drop(&mut self) {
+		let gl = self.gl.clone();
+		unsafe {
+			gl.delete_program(self.program);
+			gl.delete_texture(self.convergence);
+			gl.delete_texture(self.reference);
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const SIZE: (u32, u32) = (64, 32);
+
+	/// Plates of the left and right halves of the map move towards each other with `speed` each
+	fn head_on_field(speed: f32) -> UpliftField {
+		let velocities: Box<[(f32, f32)]> = (0..SIZE.0 * SIZE.1)
+			.map(|id| if id % SIZE.0 < SIZE.0 / 2 { (speed, 0.0) } else { (-speed, 0.0) })
+			.collect();
+		UpliftField::new(SIZE, Topology::FLAT, &velocities, 8.0)
+	}
+
+	fn rough_landscape() -> Landscape {
+		let heights = (0..SIZE.0 * SIZE.1)
+			.map(|id| Height::from_num(0.3 + 0.4 * ((id * 7919) % 101) as f32 / 100.0))
+			.collect();
+		Landscape::new(SIZE, heights)
+	}
+
+	/// Takes a share of the height above `0.5` away, like erosion between the steps
+	fn erode(landscape: &mut Landscape) {
+		let level = Height::from_num(0.5);
+		for height in landscape.height_mut() {
+			if *height > level {
+				*height -= (*height - level) / 8;
+			}
+		}
+	}
+
+	#[test]
+	fn budget_matches_mass_change() {
+		let mut landscape = rough_landscape();
+		let mut tectonics = Tectonics::new(head_on_field(0.5));
+		let params = TectonicParams { isostasy: 0.6, ..Default::default() };
+		for _ in 0..10 {
+			let before = MassBudget::mass_of(&landscape);
+			let budget = tectonics.step(&mut landscape, &params, 10);
+			assert_eq!(MassBudget::mass_of(&landscape) - before, budget.total());
+			erode(&mut landscape);
+		}
+	}
+
+	#[test]
+	fn no_rebound_after_reset() {
+		let mut landscape = rough_landscape();
+		let mut tectonics = Tectonics::new(head_on_field(0.5));
+		let params = TectonicParams::default();
+
+		assert_eq!(tectonics.step(&mut landscape, &params, 1).rebound, 0);
+		erode(&mut landscape);
+		assert!(tectonics.step(&mut landscape, &params, 1).rebound > 0);
+		erode(&mut landscape);
+		tectonics.reset();
+		assert_eq!(tectonics.step(&mut landscape, &params, 1).rebound, 0);
+	}
+
+	#[test]
+	fn head_on_plates_uplift_and_diverging_subside() {
+		let params = TectonicParams::default();
+		let boundary = (SIZE.1 / 2 * SIZE.0 + SIZE.0 / 2) as usize;
+
+		let convergent = head_on_field(0.5);
+		assert!(convergent.convergence()[boundary] > 0.5);
+		assert!(convergent.convergence().iter().all(|convergence| *convergence >= -1e-6));
+		let mut landscape = rough_landscape();
+		let budget = Tectonics::new(convergent).step(&mut landscape, &params, 10);
+		assert!(budget.uplift > 0);
+		assert_eq!(budget.subsidence, 0);
+
+		let divergent = head_on_field(-0.5);
+		assert!(divergent.convergence()[boundary] < -0.5);
+		assert!(divergent.convergence().iter().all(|convergence| *convergence <= 1e-6));
+		let mut landscape = rough_landscape();
+		let budget = Tectonics::new(divergent).step(&mut landscape, &params, 10);
+		assert!(budget.subsidence < 0);
+		assert_eq!(budget.uplift, 0);
+	}
+}
@@ -0,0 +1,211 @@
+use std::ops::RangeInclusive;
+
+#[cfg(test)]
+use crate::map::{self, Height, Landscape, Topology};
+
+/// Same neighbours and order as in `assets/terrain/thermal.glsl`
+#[cfg(test)]
+const NEIGHBOURS: [(i32, i32); 8] = [
+	(-1, -1), (0, -1), (1, -1),
+	(-1, 0), (1, 0),
+	(-1, 1), (0, 1), (1, 1),
+];
+
+/// Thermal (talus) erosion: material slides down from slopes steeper than the
+/// talus angle and piles up below them as scree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThermalParams {
+	/// Steepest stable slope, in degrees
+	pub talus_angle: f32,
+	/// Horizontal size of one cell, in the same units as heights
+	pub cell_width: f32,
+	/// Thermal passes per erosion iteration
+	pub iterations: u32,
+	/// Share of the unstable material which moves in one pass
+	pub transfer_rate: f32,
+}
+
+impl ThermalParams {
+	pub const TALUS_ANGLE_RANGE: RangeInclusive<f32> = 0.0..=89.0;
+	pub const CELL_WIDTH_RANGE: RangeInclusive<f32> = 0.0001..=1.0;
+	pub const ITERATIONS_RANGE: RangeInclusive<u32> = 1..=64;
+	pub const TRANSFER_RATE_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+
+	pub fn clamped(&self) -> Self {
+		let clamp_f32 = |val: f32, range: RangeInclusive<f32>| val.clamp(*range.start(), *range.end());
+
+		ThermalParams {
+			talus_angle: clamp_f32(self.talus_angle, Self::TALUS_ANGLE_RANGE),
+			cell_width: clamp_f32(self.cell_width, Self::CELL_WIDTH_RANGE),
+			iterations: self.iterations.clamp(*Self::ITERATIONS_RANGE.start(), *Self::ITERATIONS_RANGE.end()),
+			transfer_rate: clamp_f32(self.transfer_rate, Self::TRANSFER_RATE_RANGE),
+		}
+	}
+
+	/// Biggest stable height difference between two side-adjacent cells
+	pub fn talus_height(&self) -> f32 {
+		self.talus_angle.to_radians().tan() * self.cell_width
+	}
+}
+
+impl Default for ThermalParams {
+	fn default() -> Self {
+		ThermalParams {
+			talus_angle: 40.0,
+			cell_width: 0.05,
+			iterations: 1,
+			transfer_rate: 0.5,
+		}
+	}
+}
+
+/// Amounts of material which one cell gives to each of `NEIGHBOURS`.
+/// Shared by the CPU pass and mirrored by the shader.
+#[cfg(test)]
+fn outflow(heights: &[Height], size: (u32, u32), topology: Topology, x: i32, y: i32, talus_height: f32, transfer_rate: f32) -> [Height; 8] {
+	let width = size.0 as i32;
+	let center = heights[(y * width + x) as usize].to_num::<f32>();
+	let mut excess = [0.0_f32; 8];
+	let mut max_excess = 0.0_f32;
+	let mut total_excess = 0.0_f32;
+
+	for (i, (dx, dy)) in NEIGHBOURS.iter().enumerate() {
+		let (nx, ny) = match topology.cell(size, (x + dx) as i64, (y + dy) as i64) {
+			Some(cell) => cell,
+			None => continue,
+		};
+
+		let distance = if *dx != 0 && *dy != 0 { std::f32::consts::SQRT_2 } else { 1.0 };
+		let diff = center - heights[ny as usize * size.0 as usize + nx as usize].to_num::<f32>();
+		let cell_excess = diff - talus_height * distance;
+		if cell_excess > 0.0 {
+			excess[i] = cell_excess;
+			max_excess = max_excess.max(cell_excess);
+			total_excess += cell_excess;
+		}
+	}
+
+	let mut result = [Height::ZERO; 8];
+	if total_excess <= 0.0 {
+		return result;
+	}
+
+	// Half of the difference, so that two cells never swap their order.
+	// Truncated to whole height units like `int()` does in the shader
+	let moved = transfer_rate * max_excess / 2.0;
+	for i in 0..8 {
+		result[i] = map::height_from_int((moved * excess[i] / total_excess * map::HEIGHT_INT_RANGE as f32) as i32);
+	}
+	result
+}
+
+/// CPU reference of the thermal pass of `ErosionGpu`. Runs `params.iterations`
+/// passes and returns how much material each cell received. Total height of the
+/// landscape is preserved exactly.
+#[cfg(test)]
+pub fn thermal_erosion(landscape: &mut Landscape, params: &ThermalParams) -> Box<[f32]> {
+	let params = params.clamped();
+	let (size, topology) = (landscape.size(), landscape.topology());
+	let (width, height) = (size.0 as i32, size.1 as i32);
+	let mut deposits: Box<[f32]> = vec![0.0; landscape.height().len()].into_boxed_slice();
+	let mut current: Box<[Height]> = landscape.height().to_vec().into_boxed_slice();
+	let talus_height = params.talus_height();
+
+	for _ in 0..params.iterations {
+		let mut next = current.clone();
+
+		for y in 0..height {
+			for x in 0..width {
+				let flows = outflow(&current, size, topology, x, y, talus_height, params.transfer_rate);
+
+				for (i, (dx, dy)) in NEIGHBOURS.iter().enumerate() {
+					if flows[i] == Height::ZERO {
+						continue;
+					}
+					let (nx, ny) = topology.clamp_cell(size, (x + dx) as i64, (y + dy) as i64);
+					let neighbour = ny as usize * size.0 as usize + nx as usize;
+					next[(y * width + x) as usize] -= flows[i];
+					next[neighbour] += flows[i];
+					deposits[neighbour] += flows[i].to_num::<f32>();
+				}
+			}
+		}
+
+		current = next;
+	}
+
+	landscape.height_mut().copy_from_slice(&current);
+	deposits
+}
+
+/// Cells which received at least `threshold` of material
+pub fn scree_mask(deposits: &[f32], threshold: f32) -> Box<[bool]> {
+	deposits.iter().map(|amount| *amount >= threshold).collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// A cone on a flat plain, far steeper than any talus angle
+	fn spike(topology: Topology) -> Landscape {
+		let size = (24, 20);
+		let heights = (0..size.1 as i32)
+			.flat_map(|y| (0..size.0 as i32).map(move |x| (x, y)))
+			.map(|(x, y)| {
+				let distance = (((x - 12).pow(2) + (y - 10).pow(2)) as f32).sqrt();
+				Height::from_num(0.1 + (0.8 - distance * 0.2).max(0.0))
+			})
+			.collect();
+		Landscape::new(size, heights).with_topology(topology)
+	}
+
+	fn total(landscape: &Landscape) -> i64 {
+		landscape.height().iter().map(|height| map::height_to_int(*height) as i64).sum()
+	}
+
+	#[test]
+	fn mass_is_conserved() {
+		for topology in [Topology::FLAT, Topology { wrap_x: true, wrap_y: false }, Topology { wrap_x: true, wrap_y: true }] {
+			let mut landscape = spike(topology);
+			let before = total(&landscape);
+			let params = ThermalParams { iterations: 16, ..Default::default() };
+			let deposits = thermal_erosion(&mut landscape, &params);
+			assert_eq!(total(&landscape), before);
+			assert!(deposits.iter().any(|amount| *amount > 0.0));
+		}
+	}
+
+	#[test]
+	fn slopes_settle_at_talus_angle() {
+		let params = ThermalParams { talus_angle: 30.0, iterations: 64, transfer_rate: 1.0, ..Default::default() };
+		let mut landscape = spike(Topology::FLAT);
+		for _ in 0..8 {
+			thermal_erosion(&mut landscape, &params);
+		}
+		let (size, heights) = (landscape.size(), landscape.height());
+		let talus_height = Height::from_num(params.talus_height());
+		let tolerance = Height::from_num(0.001);
+
+		let mut steepest = Height::ZERO;
+		for y in 0..size.1 as i64 {
+			for x in 0..size.0 as i64 {
+				let center = heights[(y * size.0 as i64 + x) as usize];
+				for (nx, ny) in [(x + 1, y), (x, y + 1)] {
+					if let Some((nx, ny)) = Topology::FLAT.cell(size, nx, ny) {
+						let diff = (center - heights[(ny * size.0 + nx) as usize]).abs();
+						steepest = steepest.max(diff);
+					}
+				}
+			}
+		}
+		assert!(steepest <= talus_height + tolerance, "{} > {}", steepest, talus_height);
+		assert!(steepest >= talus_height - tolerance, "{} < {}", steepest, talus_height);
+
+		// Stable slopes stay as they are
+		let settled = landscape.clone();
+		let deposits = thermal_erosion(&mut landscape, &ThermalParams { talus_angle: 45.0, ..params });
+		assert_eq!(landscape.height(), settled.height());
+		assert!(deposits.iter().all(|amount| *amount == 0.0));
+	}
+}
@@ -0,0 +1,412 @@
+use glow::{Context, HasContext, NativeProgram};
+
+use crate::map::Topology;
+
+/// Rectangle of map cells
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+	pub x: u32,
+	pub y: u32,
+	pub width: u32,
+	pub height: u32,
+}
+
+impl Rect {
+	pub fn area(&self) -> usize {
+		self.width as usize * self.height as usize
+	}
+}
+
+/// A tile of a `TileGrid`. The tile owns its `core` cells, `halo` is the core
+/// grown by the grid halo on every side, without the cells outside of the map.
+/// Across wrapped edges of the map the halo continues on the other side, so its
+/// `x + width` (or `y + height`) may be beyond the map.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tile {
+	pub column: u32,
+	pub row: u32,
+	pub core: Rect,
+	pub halo: Rect,
+	/// Of the halo region. It wraps along the axes on which the tile is the whole map.
+	pub topology: Topology,
+	pub map_size: (u32, u32),
+}
+
+impl Tile {
+	/// The core in the coordinates of the halo region
+	pub fn local_core(&self) -> Rect {
+		// The halo of the first tile on a wrapped axis starts at the other side of the map
+		let local = |core: u32, halo: u32, side: u32| if core >= halo { core - halo } else { core + side - halo };
+		Rect {
+			x: local(self.core.x, self.halo.x, self.map_size.0),
+			y: local(self.core.y, self.halo.y, self.map_size.1),
+			..self.core
+		}
+	}
+}
+
+/// Splits a map into square tiles which are processed one by one, so a pass over
+/// a huge map needs memory only for a few tiles at a time. Processes which reach
+/// further than one cell (droplets, brushes) read and write the halo around the tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TileGrid {
+	map_size: (u32, u32),
+	topology: Topology,
+	tile_size: u32,
+	halo: u32,
+}
+
+/// Tiles along one axis of a `TileGrid`
+#[derive(Debug, Clone, Copy)]
+struct Axis {
+	side: u32,
+	wrap: bool,
+	count: u32,
+}
+
+impl Axis {
+	/// Along a wrapped axis the tiles are of the same size (up to one cell) and at least
+	/// `tile_size`, so every one is wide enough to separate the halos of its neighbours.
+	fn new(side: u32, wrap: bool, tile_size: u32) -> Self {
+		let count = match wrap {
+			true => (side / tile_size).max(1),
+			false => side.div_ceil(tile_size),
+		};
+		Axis { side, wrap, count }
+	}
+
+	/// Start and length of the core of tile `index`
+	fn core(&self, index: u32, tile_size: u32) -> (u32, u32) {
+		match self.wrap {
+			true => {
+				let start = |index: u32| (index as u64 * self.side as u64 / self.count as u64) as u32;
+				(start(index), start(index + 1) - start(index))
+			}
+			false => (index * tile_size, tile_size.min(self.side - index * tile_size)),
+		}
+	}
+
+	/// Start and length of the halo region of tile `index`, and whether the region wraps
+	fn halo(&self, index: u32, tile_size: u32, halo: u32) -> (u32, u32, bool) {
+		let (start, length) = self.core(index, tile_size);
+		match (self.wrap, self.count) {
+			// The tile is the whole axis, it continues into itself
+			(true, 1) => (0, self.side, true),
+			(true, _) => ((start + self.side - halo) % self.side, length + halo * 2, false),
+			(false, _) => {
+				let halo_start = start.saturating_sub(halo);
+				(halo_start, (start + length + halo).min(self.side) - halo_start, false)
+			}
+		}
+	}
+
+	/// Tiles with the same parity are never neighbours. When an odd number of tiles
+	/// wraps around, the last one gets a parity of its own.
+	fn parity(&self, index: u32) -> u32 {
+		if self.wrap && self.count > 1 && self.count % 2 == 1 && index == self.count - 1 {
+			2
+		} else {
+			index % 2
+		}
+	}
+}
+
+impl TileGrid {
+	/// `tile_size` is raised to at least twice the `halo`, see `phases`
+	pub fn new(map_size: (u32, u32), topology: Topology, tile_size: u32, halo: u32) -> Self {
+		TileGrid {
+			map_size,
+			topology,
+			tile_size: tile_size.max(halo * 2).max(1),
+			halo,
+		}
+	}
+
+	fn axes(&self) -> (Axis, Axis) {
+		(
+			Axis::new(self.map_size.0, self.topology.wrap_x, self.tile_size),
+			Axis::new(self.map_size.1, self.topology.wrap_y, self.tile_size),
+		)
+	}
+
+	#[allow(dead_code)]
+	pub fn map_size(&self) -> (u32, u32) {
+		self.map_size
+	}
+
+	#[allow(dead_code)]
+	pub fn topology(&self) -> Topology {
+		self.topology
+	}
+
+	#[allow(dead_code)]
+	pub fn tile_size(&self) -> u32 {
+		self.tile_size
+	}
+
+	#[allow(dead_code)]
+	pub fn halo(&self) -> u32 {
+		self.halo
+	}
+
+	pub fn columns(&self) -> u32 {
+		self.axes().0.count
+	}
+
+	pub fn rows(&self) -> u32 {
+		self.axes().1.count
+	}
+
+	pub fn tile(&self, column: u32, row: u32) -> Tile {
+		let (axis_x, axis_y) = self.axes();
+		let (x, width) = axis_x.core(column, self.tile_size);
+		let (y, height) = axis_y.core(row, self.tile_size);
+		let (halo_x, halo_width, wrap_x) = axis_x.halo(column, self.tile_size, self.halo);
+		let (halo_y, halo_height, wrap_y) = axis_y.halo(row, self.tile_size, self.halo);
+
+		Tile {
+			column,
+			row,
+			core: Rect { x, y, width, height },
+			halo: Rect { x: halo_x, y: halo_y, width: halo_width, height: halo_height },
+			topology: Topology { wrap_x, wrap_y },
+			map_size: self.map_size,
+		}
+	}
+
+	/// Row by row
+	pub fn tiles(&self) -> impl Iterator<Item = Tile> + '_ {
+		(0..self.rows()).flat_map(move |row| (0..self.columns()).map(move |column| self.tile(column, row)))
+	}
+
+	/// The tiles grouped by the parity of the column and the row. Halo regions of
+	/// tiles of one group never overlap, so they can be processed at the same time.
+	pub fn phases(&self) -> Vec<Vec<Tile>> {
+		let (axis_x, axis_y) = self.axes();
+		let mut phases: Vec<Vec<Tile>> = vec![vec![]; 9];
+		for tile in self.tiles() {
+			phases[(axis_y.parity(tile.row) * 3 + axis_x.parity(tile.column)) as usize].push(tile);
+		}
+		phases.retain(|phase| !phase.is_empty());
+		phases
+	}
+
+	/// Cells in the biggest halo region
+	#[allow(dead_code)]
+	pub fn max_region_area(&self) -> usize {
+		self.tiles().map(|tile| tile.halo.area()).max().unwrap_or(0)
+	}
+
+	/// Runs `process` on the halo region of every tile, with up to `threads` tiles at
+	/// once. The whole region is written back, so `process` may change the halo too.
+	/// Besides the map itself it takes `threads * max_region_area()` cells of memory.
+	pub fn process<T: Copy + Send>(&self, map: &mut [T], threads: usize, process: impl Fn(&Tile, &mut [T]) + Sync) {
+		let process = &process;
+		for phase in self.phases() {
+			for batch in phase.chunks(threads.max(1)) {
+				let mut regions: Vec<Vec<T>> = batch.iter()
+					.map(|tile| read_region(map, self.map_size.0, tile.halo))
+					.collect();
+
+				if let ([tile], [region]) = (batch, regions.as_mut_slice()) {
+					process(tile, region);
+				} else {
+					std::thread::scope(|scope| {
+						for (tile, region) in batch.iter().zip(regions.iter_mut()) {
+							scope.spawn(move || process(tile, region));
+						}
+					});
+				}
+
+				for (tile, region) in batch.iter().zip(regions.iter()) {
+					write_region(map, self.map_size.0, tile.halo, region);
+				}
+			}
+		}
+	}
+}
+
+/// Copies `rect` out of a map which is `map_width` cells wide. Parts of `rect` which
+/// are beyond the right or the bottom edge come from the other side of the map.
+pub fn read_region<T: Copy>(map: &[T], map_width: u32, rect: Rect) -> Vec<T> {
+	let map_height = (map.len() / map_width as usize) as u32;
+	let mut region = Vec::with_capacity(rect.area());
+	for y in rect.y..(rect.y + rect.height) {
+		let row = (y % map_height) as usize * map_width as usize;
+		if rect.x + rect.width <= map_width {
+			region.extend_from_slice(&map[(row + rect.x as usize)..(row + (rect.x + rect.width) as usize)]);
+		} else {
+			region.extend((rect.x..(rect.x + rect.width)).map(|x| map[row + (x % map_width) as usize]));
+		}
+	}
+	region
+}
+
+/// Opposite of `read_region`
+pub fn write_region<T: Copy>(map: &mut [T], map_width: u32, rect: Rect, region: &[T]) {
+	let map_height = (map.len() / map_width as usize) as u32;
+	for (values, y) in region.chunks_exact(rect.width as usize).zip(rect.y..(rect.y + rect.height)) {
+		let row = (y % map_height) as usize * map_width as usize;
+		if rect.x + rect.width <= map_width {
+			map[(row + rect.x as usize)..(row + (rect.x + rect.width) as usize)].copy_from_slice(values);
+		} else {
+			for (value, x) in values.iter().zip(rect.x..(rect.x + rect.width)) {
+				map[row + (x % map_width) as usize] = *value;
+			}
+		}
+	}
+}
+
+pub fn available_threads() -> usize {
+	std::thread::available_parallelism().map_or(1, |threads| threads.get())
+}
+
+/// Fills a map which is `width` cells wide in bands of rows, one per thread.
+/// `fill_row` gets the row index and the row.
+pub fn fill_rows<T: Send>(map: &mut [T], width: usize, fill_row: impl Fn(usize, &mut [T]) + Sync) {
+	let rows = map.len() / width.max(1);
+	if rows == 0 {
+		return;
+	}
+	let band = rows.div_ceil(available_threads());
+
+	let fill_row = &fill_row;
+	std::thread::scope(|scope| {
+		for (band_id, band_cells) in map.chunks_mut(band * width).enumerate() {
+			scope.spawn(move || {
+				for (row_id, row) in band_cells.chunks_mut(width).enumerate() {
+					fill_row(band_id * band + row_id, row);
+				}
+			});
+		}
+	});
+}
+
+/// Dispatches a compute shader over every cell of a map. If the map needs more
+/// work groups than `GL_MAX_COMPUTE_WORK_GROUP_COUNT` allows, the dispatch is split
+/// into several ones, and the shader adds `uniform uvec2 u_tile_offset` to its position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapDispatch {
+	groups: (u32, u32),
+	group_size: u32,
+	max_groups: (u32, u32),
+}
+
+impl MapDispatch {
+	/// `group_size` is the `local_size_x` and `local_size_y` of the shader
+	pub fn new(gl: &Context, map_size: (u64, u64), group_size: u32) -> Self {
+		let max_groups = unsafe {(
+			gl.get_parameter_indexed_i32(glow::MAX_COMPUTE_WORK_GROUP_COUNT, 0).max(1) as u32,
+			gl.get_parameter_indexed_i32(glow::MAX_COMPUTE_WORK_GROUP_COUNT, 1).max(1) as u32,
+		)};
+		let groups = (
+			(map_size.0 as u32).div_ceil(group_size),
+			(map_size.1 as u32).div_ceil(group_size),
+		);
+		MapDispatch { groups, group_size, max_groups }
+	}
+
+	/// `program` has to be in use. Does not wait for the memory barrier.
+	pub fn dispatch(&self, gl: &Context, program: NativeProgram) {
+		unsafe {
+			let offset_location = gl.get_uniform_location(program, "u_tile_offset");
+			for group_y in (0..self.groups.1).step_by(self.max_groups.1 as usize) {
+				for group_x in (0..self.groups.0).step_by(self.max_groups.0 as usize) {
+					gl.uniform_2_u32(offset_location.as_ref(), group_x * self.group_size, group_y * self.group_size);
+					gl.dispatch_compute(
+						(self.groups.0 - group_x).min(self.max_groups.0),
+						(self.groups.1 - group_y).min(self.max_groups.1),
+						1,
+					);
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const TOPOLOGIES: [Topology; 4] = [
+		Topology::FLAT,
+		Topology { wrap_x: true, wrap_y: false },
+		Topology { wrap_x: false, wrap_y: true },
+		Topology { wrap_x: true, wrap_y: true },
+	];
+
+	/// Map cells of the halo region, wrapped onto the map
+	fn halo_cells(tile: &Tile) -> impl Iterator<Item = usize> + '_ {
+		let (width, height) = tile.map_size;
+		(tile.halo.y..tile.halo.y + tile.halo.height)
+			.flat_map(move |y| (tile.halo.x..tile.halo.x + tile.halo.width).map(move |x| (x, y)))
+			.map(move |(x, y)| (y % height) as usize * width as usize + (x % width) as usize)
+	}
+
+	/// Adds one to every core cell through the halo region
+	fn mark_cores(grid: &TileGrid, map: &mut [u32]) {
+		grid.process(map, 3, |tile, region| {
+			let core = tile.local_core();
+			for y in core.y..core.y + core.height {
+				for x in core.x..core.x + core.width {
+					region[(y * tile.halo.width + x) as usize] += 1;
+				}
+			}
+		});
+	}
+
+	#[test]
+	fn halos_of_one_phase_do_not_overlap() {
+		for (size, tile_size, halo) in [((1000, 700), 100, 40), ((300, 200), 64, 16), ((250, 90), 100, 20), ((500, 500), 100, 30)] {
+			for topology in TOPOLOGIES {
+				let grid = TileGrid::new(size, topology, tile_size, halo);
+				let area = size.0 as usize * size.1 as usize;
+				assert_eq!(grid.tiles().map(|tile| tile.core.area()).sum::<usize>(), area);
+
+				for phase in grid.phases() {
+					let mut owner = vec![usize::MAX; area];
+					for (i, tile) in phase.iter().enumerate() {
+						for id in halo_cells(tile) {
+							assert!(owner[id] == usize::MAX || owner[id] == i, "{:?} {:?} {:?}", size, topology, tile);
+							owner[id] = i;
+						}
+					}
+				}
+
+				let mut map = vec![0; area];
+				mark_cores(&grid, &mut map);
+				assert!(map.iter().all(|count| *count == 1), "{:?} {:?}", size, topology);
+			}
+		}
+	}
+
+	#[test]
+	fn single_tile_wraps_into_itself() {
+		let topology = Topology { wrap_x: true, wrap_y: false };
+		let grid = TileGrid::new((100, 300), topology, 128, 20);
+		assert_eq!(grid.columns(), 1);
+
+		for tile in grid.tiles() {
+			assert_eq!(tile.topology, Topology { wrap_x: true, wrap_y: false });
+			assert_eq!((tile.halo.x, tile.halo.width), (0, 100));
+			assert_eq!(tile.local_core().x, 0);
+		}
+
+		let mut map = vec![0; 100 * 300];
+		mark_cores(&grid, &mut map);
+		assert!(map.iter().all(|count| *count == 1));
+	}
+
+	#[test]
+	fn regions_roundtrip_across_wrapped_edges() {
+		let map: Vec<u32> = (0..40).collect();
+		let rect = Rect { x: 6, y: 3, width: 4, height: 3 };
+		let region = read_region(&map, 8, rect);
+		assert_eq!(region, [30, 31, 24, 25, 38, 39, 32, 33, 6, 7, 0, 1]);
+
+		let mut copy = vec![u32::MAX; 40];
+		write_region(&mut copy, 8, rect, &region);
+		assert_eq!(read_region(&copy, 8, rect), region);
+		assert_eq!(copy.iter().filter(|value| **value == u32::MAX).count(), 40 - region.len());
+	}
+}
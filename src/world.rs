@@ -1,13 +1,30 @@
 use std::sync::Arc;
-use glow::{Context, HasContext, NativeTexture, Program, VertexArray};
+use glow::{Context, HasContext, NativeTexture, PixelPackData, PixelUnpackData, Program, VertexArray};
 use noise::{Fbm, MultiFractal, Perlin};
 use rand::Rng;
 use crate::app::AntiAliasing;
 use crate::glsl_expand::ShaderContext;
+use crate::map::{self, Height, Landscape, Map, Topology};
+use crate::save::WorldSave;
+use crate::seed::{SeedStage, WorldSeed};
 use crate::terrain;
-use crate::terrain::{ErosionGpu, ShapeSmoother};
+use crate::terrain::{ErosionGpu, ErosionParams, ShapeSmoother};
+use crate::terrain::climate::{self, Climate, ClimateParams};
+use crate::terrain::continents::ContinentParams;
+use crate::terrain::flood;
+use crate::terrain::generators::{BrownianContinents, GeneratorKind, PlateLayout, TerrainGenerator, TerrainParams};
+use crate::terrain::hypsometry::{self, HypsometryParams};
+use crate::terrain::layers::{LayerParams, TerrainLayers};
+use crate::terrain::readback::LandscapeReadback;
+use crate::terrain::rivers::{self, RiverParams};
+use crate::terrain::shallow_water::ShallowWaterParams;
+use crate::terrain::tectonics::{self, PlateSource, UpliftField};
+use crate::terrain::tiles::MapDispatch;
 use crate::util::{compile_program, TickCounter};
 
+/// `local_size_x` and `local_size_y` of `assets/game_of_life.glsl`
+const CELLS_WORK_GROUP_SIZE: u32 = 32;
+
 const RENDER_VERT_SOURCE: &str =
 r#"
 	#version 330
@@ -38,37 +55,145 @@ pub struct PaintData {
 	pub render_mode: u32,
 }
 
+/// Everything a world is generated from. The same settings always give the same world.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WorldSettings {
+	pub seed: WorldSeed,
+	/// Shaders are compiled for it, see `World::new`
+	pub topology: Topology,
+	/// Used by rendering, lakes, climate and biomes instead of `ClimateParams::sea_level`
+	pub sea_level: Height,
+	pub terrain: TerrainParams,
+	/// Applied to the generated landscape, see `hypsometry::normalize`
+	pub hypsometry: Option<HypsometryParams>,
+	pub continents: ContinentParams,
+	pub climate: ClimateParams,
+	pub layers: LayerParams,
+}
+
+impl Default for WorldSettings {
+	fn default() -> Self {
+		WorldSettings {
+			seed: WorldSeed::default(),
+			topology: Topology::default(),
+			sea_level: Height::from_num(map::DEFAULT_SEA_LEVEL),
+			terrain: TerrainParams::default(),
+			hypsometry: None,
+			continents: ContinentParams::default(),
+			climate: ClimateParams::default(),
+			layers: LayerParams::default(),
+		}
+	}
+}
+
+impl WorldSettings {
+	/// `climate` with the world sea level
+	pub fn climate_params(&self) -> ClimateParams {
+		ClimateParams { sea_level: self.sea_level, ..self.climate.clone() }
+	}
+
+	/// `params` with the world sea level, where the shallow water leaves the map
+	pub fn erosion_params(&self, params: &ErosionParams) -> ErosionParams {
+		let shallow_water = ShallowWaterParams { sea_level: self.sea_level.to_num(), ..params.shallow_water };
+		ErosionParams { shallow_water, ..*params }
+	}
+}
+
+/// Eroded state of the world, see `World::request_layers_snapshot`
+#[derive(Debug, Clone)]
+pub struct LayersSnapshot {
+	pub landscape: Landscape,
+	/// Current soil thickness and the bedrock erodibility
+	pub layers: TerrainLayers,
+	/// Material each cell has received from thermal erosion, see `thermal::scree_mask`
+	pub scree: Box<[f32]>,
+}
+
 #[derive(Clone, Debug)]
 pub struct World {
 	gl: Arc<Context>,
 
 	program: Program,
+	dispatch: MapDispatch,
 	current_buf: NativeTexture,
 	next_buf: NativeTexture,
 
 	landscape: NativeTexture,
+	readback: LandscapeReadback,
+	/// Landscape, soil and scree at once, see `request_layers_snapshot`
+	layers_readback: LandscapeReadback,
+	lakes: NativeTexture,
+	rivers: NativeTexture,
+	swamps: NativeTexture,
 	erosion: ErosionGpu,
+	erosion_params: ErosionParams,
+	/// Source and boundary width of the uplift field uploaded to `erosion`
+	tectonic_field: Option<(PlateSource, f32)>,
+	climate: Climate,
+	/// Initial layers, the soil changes with erosion on the GPU
+	layers: TerrainLayers,
 
 	size: (u64, u64),
+	settings: WorldSettings,
 
 	tps: TickCounter,
 	tick: u64,
 
 	render_program: Program,
 	vertex_array: VertexArray,
-	
-	max_work_group_count: (usize, usize),
 }
 
-fn create_landscape(gl: &Context, size: (u64, u64), smoother: &ShapeSmoother) -> NativeTexture {
-	let noise: Fbm<Perlin> = Fbm::new(46).set_frequency(0.1);
-	let height: Box<[i32]> = terrain::generate_map(size, 100000, 4, smoother, &noise);
-
-	terrain::convert_to_texture(gl, size, &height)
+fn create_landscape(size: (u64, u64), settings: &WorldSettings, smoother: &ShapeSmoother) -> Landscape {
+	let seed = &settings.seed;
+	let terrain = &settings.terrain;
+	let topology = settings.topology;
+	let generator_seed = seed.derive(terrain.generator.seed_stage());
+	let mut landscape = match terrain.generator {
+		GeneratorKind::Continents => {
+			let noise: Fbm<Perlin> = Fbm::new(seed.noise_seed(SeedStage::LandscapeNoise)).set_frequency(0.1);
+			let generator = BrownianContinents { params: &settings.continents, smoother, noise };
+			generator.generate(size, topology, generator_seed)
+		}
+		GeneratorKind::LayeredNoise => terrain.noise.generate(size, topology, generator_seed),
+		GeneratorKind::DiamondSquare => terrain.diamond_square.generate(size, topology, generator_seed),
+		GeneratorKind::TectonicPlates => terrain.plates.generate(size, topology, generator_seed),
+	};
+	if let Some(hypsometry) = &settings.hypsometry {
+		hypsometry::normalize(&mut landscape, settings.sea_level, hypsometry);
+	}
+	landscape
 }
 
 impl World {
-	pub fn new(gl: Arc<Context>, size: (u64, u64), glsl_manager: &mut ShaderContext) -> Self {
+	/// Compiles the shaders with `settings.topology`, so they have to be compiled again for another world
+	pub fn new(gl: Arc<Context>, size: (u64, u64), settings: WorldSettings, glsl_manager: &mut ShaderContext) -> Self {
+		define_topology(glsl_manager, settings.topology);
+		let smoother = ShapeSmoother::new(gl.clone(), glsl_manager);
+		let landscape = create_landscape(size, &settings, &smoother);
+		Self::from_landscape(gl, landscape, settings, glsl_manager)
+	}
+
+	/// World of a saved state. `settings` are taken for everything the save does not keep
+	pub fn from_save(gl: Arc<Context>, save: &WorldSave, settings: WorldSettings, glsl_manager: &mut ShaderContext) -> Self {
+		let settings = WorldSettings {
+			seed: save.seed,
+			topology: save.landscape.topology(),
+			sea_level: save.sea_level,
+			..settings
+		};
+		let mut world = Self::from_landscape(gl, save.landscape.clone(), settings, glsl_manager);
+		if let Some(cells) = &save.cells {
+			write_cells(&world.gl, world.current_buf, world.size, cells);
+		}
+		world.tick = save.tick;
+		world
+	}
+
+	/// Same as `new`, but with the landscape already generated. Its topology has to match `settings.topology`
+	fn from_landscape(gl: Arc<Context>, cpu_landscape: Landscape, settings: WorldSettings, glsl_manager: &mut ShaderContext) -> Self {
+		let size = (cpu_landscape.size().0 as u64, cpu_landscape.size().1 as u64);
+		define_topology(glsl_manager, settings.topology);
+
 		let render_shader = glsl_manager
 			.get_file_processed("assets/render.glsl").unwrap()
 			.current_text().clone();
@@ -78,7 +203,7 @@ impl World {
 			.current_text().clone();
 
 		let arr_size = size.0 * size.1;
-		let mut rng = rand::thread_rng();
+		let mut rng = settings.seed.rng(SeedStage::InitialCells);
 
 		let mut initial_state: Box<[u8]> = vec![0_u8;arr_size as usize].into_boxed_slice();
 		let empty_state: Box<[u8]> = vec![0_u8;arr_size as usize].into_boxed_slice();
@@ -111,8 +236,11 @@ impl World {
 		let current_buf = create_texture(&initial_state);
 		let next_buf = create_texture(&empty_state);
 
-		let smoother = ShapeSmoother::new(gl.clone(), glsl_manager);
-		let landscape = create_landscape(gl.as_ref(), size, &smoother);
+		let landscape = terrain::upload_landscape(gl.as_ref(), &cpu_landscape);
+		let overlays = Overlays::compute(&cpu_landscape, &settings);
+		let lakes = create_texture(&overlays.lakes);
+		let rivers = create_texture(&overlays.rivers);
+		let swamps = create_texture(&overlays.swamps);
 
 		let sources = [
 			(glow::COMPUTE_SHADER, game_of_life_shader.as_str())
@@ -126,30 +254,40 @@ impl World {
 		let render_program = compile_program(&gl, render_sources).unwrap();
 		let vertex_array = unsafe { gl.create_vertex_array().unwrap() };
 
-		let max_wg_x;
-		let max_wg_y;
 		unsafe {
 			gl.use_program(Some(program));
 			gl.uniform_2_i32( gl.get_uniform_location(program, "world_size").as_ref(), size.0 as i32, size.1 as i32);
-			max_wg_x = gl.get_parameter_indexed_i32(glow::MAX_COMPUTE_WORK_GROUP_COUNT, 0) as usize;
-			max_wg_y = gl.get_parameter_indexed_i32(glow::MAX_COMPUTE_WORK_GROUP_COUNT, 1) as usize;
 		}
 
-		let erosion = ErosionGpu::new(gl.clone(), glsl_manager, size);
+		let layers = TerrainLayers::new(&cpu_landscape, &settings.layers, settings.seed.noise_seed(SeedStage::Layers));
+		let mut erosion = ErosionGpu::new(gl.clone(), glsl_manager, size);
+		erosion.set_layers(&layers);
+		let readback = LandscapeReadback::new(gl.clone(), (size.0 as u32, size.1 as u32), 1);
+		let layers_readback = LandscapeReadback::new(gl.clone(), (size.0 as u32, size.1 as u32), 3);
 
 		World {
+			dispatch: MapDispatch::new(&gl, size, CELLS_WORK_GROUP_SIZE),
 			gl,
 			program,
 			current_buf,
 			next_buf,
 			landscape,
+			readback,
+			layers_readback,
+			lakes,
+			rivers,
+			swamps,
 			erosion,
+			erosion_params: settings.erosion_params(&ErosionParams::default()),
+			tectonic_field: None,
+			climate: overlays.climate,
+			layers,
 			size,
+			settings,
 			tps: TickCounter::new(30),
 			tick: 0,
 			render_program,
 			vertex_array,
-			max_work_group_count: (max_wg_x, max_wg_y),
 		}
 	}
 
@@ -157,6 +295,111 @@ impl World {
 		self.size.clone()
 	}
 
+	pub fn settings(&self) -> &WorldSettings {
+		&self.settings
+	}
+
+	pub fn sea_level(&self) -> Height {
+		self.settings.sea_level
+	}
+
+	/// Climate of the generated landscape. It is not updated by erosion, see `recompute_overlays`
+	pub fn climate(&self) -> &Climate {
+		&self.climate
+	}
+
+	/// Current (eroded) landscape. Waits for the GPU, so it is better not to call it every tick
+	pub fn landscape_snapshot(&self) -> Landscape {
+		terrain::read_landscape(&self.gl, self.landscape, (self.size.0 as u32, self.size.1 as u32))
+			.with_topology(self.settings.topology)
+	}
+
+	/// Current state of the world with `landscape` taken by `poll_landscape_snapshot`.
+	/// `map` is saved along if it is of the world size. Cells are read right away,
+	/// waiting for the GPU
+	pub fn to_save(&self, landscape: Landscape, map: Option<&Map>) -> WorldSave {
+		WorldSave {
+			seed: self.settings.seed,
+			sea_level: self.settings.sea_level,
+			tick: self.tick,
+			landscape,
+			map: map.filter(|map| map.size() == (self.size.0 as u32, self.size.1 as u32)).cloned(),
+			cells: Some(read_cells(&self.gl, self.current_buf, self.size)),
+		}
+	}
+
+	/// Starts copying the current landscape, pick it up with `poll_landscape_snapshot`.
+	/// A previous request which was not picked up yet is dropped.
+	pub fn request_landscape_snapshot(&mut self) {
+		self.readback.request(&[self.landscape]);
+	}
+
+	/// Landscape as it was at the last `request_landscape_snapshot`, once the GPU has copied it
+	pub fn poll_landscape_snapshot(&mut self) -> Option<Landscape> {
+		let topology = self.settings.topology;
+		self.readback.poll()
+			.and_then(|landscapes| landscapes.into_iter().next())
+			.map(|landscape| landscape.with_topology(topology))
+	}
+
+	pub fn is_landscape_snapshot_pending(&self) -> bool {
+		self.readback.is_pending()
+	}
+
+	/// Starts copying the current landscape with its soil and scree, pick them up
+	/// with `poll_layers_snapshot`. A previous request which was not picked up yet is dropped.
+	pub fn request_layers_snapshot(&mut self) {
+		let textures = [self.landscape, self.erosion.soil_texture(), self.erosion.scree_texture()];
+		self.layers_readback.request(&textures);
+	}
+
+	/// State as it was at the last `request_layers_snapshot`, once the GPU has copied it
+	pub fn poll_layers_snapshot(&mut self) -> Option<LayersSnapshot> {
+		let [landscape, soil, scree]: [Landscape; 3] = self.layers_readback.poll()?.try_into().ok()?;
+		Some(LayersSnapshot {
+			landscape: landscape.with_topology(self.settings.topology),
+			layers: TerrainLayers::from_parts(self.layers.size(), soil.disassemble().1, self.layers.rock_erodibility().into()),
+			scree: scree.height().iter().map(|amount| amount.to_num::<f32>()).collect(),
+		})
+	}
+
+	pub fn is_layers_snapshot_pending(&self) -> bool {
+		self.layers_readback.is_pending()
+	}
+
+	/// Replaces the landscape, erosion continues from it. Lakes, rivers, swamps and climate
+	/// are recomputed for it. Panics if the size differs from the world size. The world
+	/// keeps its own topology.
+	pub fn set_landscape(&mut self, landscape: &Landscape) {
+		assert_eq!(landscape.size(), (self.size.0 as u32, self.size.1 as u32), "Landscape size differs from the world size");
+		terrain::write_landscape(&self.gl, self.landscape, landscape);
+		self.erosion.tectonics_mut().reset();
+		self.update_overlays(landscape);
+	}
+
+	/// Lakes, rivers, swamps and climate are computed once for the generated landscape,
+	/// this computes them again for the current (eroded) one. Waits for the GPU, like `landscape_snapshot`
+	pub fn recompute_overlays(&mut self) {
+		let landscape = self.landscape_snapshot();
+		self.update_overlays(&landscape);
+	}
+
+	fn update_overlays(&mut self, landscape: &Landscape) {
+		let overlays = Overlays::compute(landscape, &self.settings);
+		write_cells(&self.gl, self.lakes, self.size, &overlays.lakes);
+		write_cells(&self.gl, self.rivers, self.size, &overlays.rivers);
+		write_cells(&self.gl, self.swamps, self.size, &overlays.swamps);
+		self.climate = overlays.climate;
+	}
+
+	/// Remaps the current (eroded) landscape onto `params` around the world sea level,
+	/// see `hypsometry::normalize`. Waits for the GPU, like `landscape_snapshot`.
+	pub fn normalize_landscape(&mut self, params: &HypsometryParams) {
+		let mut landscape = self.landscape_snapshot();
+		hypsometry::normalize(&mut landscape, self.settings.sea_level, params);
+		self.set_landscape(&landscape);
+	}
+
 	pub fn cur_tick(&self) -> u64 {
 		self.tick
 	}
@@ -165,6 +408,40 @@ impl World {
 		&self.tps
 	}
 
+	pub fn erosion_params(&self) -> &ErosionParams {
+		&self.erosion_params
+	}
+
+	pub fn set_erosion_params(&mut self, params: ErosionParams) {
+		self.erosion_params = self.settings.erosion_params(&params.clamped());
+
+		let field = self.erosion_params.tectonics.map(|tectonics| (tectonics.source, tectonics.boundary_width));
+		if let Some((source, boundary_width)) = field {
+			if self.tectonic_field != field {
+				let uplift = self.uplift_field(source, boundary_width);
+				self.erosion.tectonics_mut().set_field(&uplift);
+				self.tectonic_field = field;
+			}
+		}
+	}
+
+	fn uplift_field(&self, source: PlateSource, boundary_width: f32) -> UpliftField {
+		let size = (self.size.0 as u32, self.size.1 as u32);
+		let velocities = match source {
+			PlateSource::Voronoi => {
+				// Same seed as the tectonic plates generator, so the plates match
+				let mut rng = self.settings.seed.rng(SeedStage::Continents);
+				let layout = PlateLayout::new(&self.settings.terrain.plates, self.size, self.settings.topology, &mut rng);
+				tectonics::voronoi_velocities(&layout, self.size)
+			}
+			PlateSource::Continents => {
+				let mut rng = self.settings.seed.rng(SeedStage::Tectonics);
+				tectonics::continent_velocities(&self.landscape_snapshot(), self.settings.sea_level, &mut rng)
+			}
+		};
+		UpliftField::new(size, self.settings.topology, &velocities, boundary_width)
+	}
+
 	pub fn use_program(&self) {
 		unsafe {
 			self.gl.use_program(Some(self.program));
@@ -172,42 +449,20 @@ impl World {
 	}
 
 	pub fn update(&mut self) {
-		self.landscape = self.erosion.erode(self.landscape, 1, self.tick as i32);
-		self.tps.tick();
-		self.tick += 1;
-		/*const WORK_GROUP_SIZE: u64 = 32;
-		unsafe {
-			// self.gl.use_program(Some(self.program));
-			self.gl.bind_image_texture(0, self.current_buf, 0, false, 0, glow::READ_WRITE, glow::R8UI);
-			self.gl.bind_image_texture(1, self.next_buf, 0, false, 0, glow::READ_WRITE, glow::R8UI);
-			let calls_x = (self.size().0 + WORK_GROUP_SIZE - 1) / WORK_GROUP_SIZE;
-			let calls_y = (self.size().1 + WORK_GROUP_SIZE - 1) / WORK_GROUP_SIZE;
-
-			// self.gl.uniform_1_u32(self.gl.get_uniform_location(self.program, "current_tick").as_ref(), self.tick as u32);
-
-			if calls_x <= self.max_work_group_count.0 as u64 && calls_y <= self.max_work_group_count.1 as u64 {
-				if self.tick == 0 {
-					self.gl.uniform_2_u32( self.gl.get_uniform_location(self.program, "tile_offset").as_ref(), 0, 0);
-				}
-				self.gl.dispatch_compute(calls_x as u32, calls_y as u32, 1);
-			} else {
-				// Tiling
-				for tile_x in (0..calls_x).step_by(self.max_work_group_count.0) {
-					let tile_x_size = (calls_x - tile_x).min(self.max_work_group_count.0 as u64);
-
-					for tile_y in (0..calls_y).step_by(self.max_work_group_count.1) {
-						let tile_y_size = (calls_y - tile_y).min(self.max_work_group_count.1 as u64);
-						self.gl.uniform_2_u32( self.gl.get_uniform_location(self.program, "tile_offset").as_ref(), tile_x as u32, tile_y as u32);
-						self.gl.dispatch_compute(tile_x_size as u32, tile_y_size as u32, 1);
-					}
-				}
-			}
+		let erosion_seed = (self.settings.seed.derive(SeedStage::Erosion) as i32).wrapping_add(self.tick as i32);
+		self.landscape = self.erosion.erode(self.landscape, 1, erosion_seed, &self.erosion_params);
 
-			self.gl.memory_barrier(glow::ALL_BARRIER_BITS);
-			self.tps.tick();
-			self.tick += 1;
+		let gl = &self.gl;
+		unsafe {
+			gl.use_program(Some(self.program));
+			gl.bind_image_texture(0, self.current_buf, 0, false, 0, glow::READ_ONLY, glow::R8UI);
+			gl.bind_image_texture(1, self.next_buf, 0, false, 0, glow::WRITE_ONLY, glow::R8UI);
+			self.dispatch.dispatch(gl, self.program);
+			gl.memory_barrier(glow::ALL_BARRIER_BITS);
 		}
-		std::mem::swap(&mut self.current_buf, &mut self.next_buf);*/
+		std::mem::swap(&mut self.current_buf, &mut self.next_buf);
+		self.tps.tick();
+		self.tick += 1;
 	}
 
 	pub fn render(&self, data: PaintData) {
@@ -224,7 +479,28 @@ impl World {
 			gl.bind_texture(glow::TEXTURE_2D, Some(self.landscape));
 			gl.uniform_1_i32(loc("u_landscape").as_ref(), 3);
 
+			gl.active_texture(glow::TEXTURE4);
+			gl.bind_texture(glow::TEXTURE_2D, Some(self.lakes));
+			gl.uniform_1_i32(loc("u_lakes").as_ref(), 4);
+
+			gl.active_texture(glow::TEXTURE5);
+			gl.bind_texture(glow::TEXTURE_2D, Some(self.rivers));
+			gl.uniform_1_i32(loc("u_rivers").as_ref(), 5);
+
+			gl.active_texture(glow::TEXTURE6);
+			gl.bind_texture(glow::TEXTURE_2D, Some(self.swamps));
+			gl.uniform_1_i32(loc("u_swamps").as_ref(), 6);
+
+			gl.active_texture(glow::TEXTURE7);
+			gl.bind_texture(glow::TEXTURE_2D, Some(self.erosion.scree_texture()));
+			gl.uniform_1_i32(loc("u_scree").as_ref(), 7);
+
+			gl.active_texture(glow::TEXTURE8);
+			gl.bind_texture(glow::TEXTURE_2D, Some(self.erosion.water_texture()));
+			gl.uniform_1_i32(loc("u_water").as_ref(), 8);
+
 			gl.uniform_1_u32(loc("u_render_type").as_ref(), data.render_mode);
+			gl.uniform_1_f32(loc("u_sea_level").as_ref(), self.settings.sea_level.to_num());
 			gl.uniform_1_i32(loc("u_antialiasing").as_ref(), data.antialiasing as i32);
 
 			gl.uniform_2_f32(loc("u_world_size").as_ref(), self.size.0 as f32, self.size.1 as f32);
@@ -242,11 +518,69 @@ impl World {
 	}
 }
 
+/// Everything `World` derives from the landscape on the CPU, the masks are of the `R8UI` textures
+struct Overlays {
+	lakes: Box<[u8]>,
+	/// Strahler order of the river in each cell, `0` if there is none
+	rivers: Box<[u8]>,
+	swamps: Box<[u8]>,
+	climate: Climate,
+}
+
+impl Overlays {
+	fn compute(landscape: &Landscape, settings: &WorldSettings) -> Self {
+		let lakes = flood::detect_lakes(landscape, settings.sea_level);
+		let river_params = RiverParams { sea_level: settings.sea_level, ..Default::default() };
+		let river_network = rivers::extract_rivers(landscape, &river_params, None);
+		let swamps = river_network.swamp_mask(landscape, &river_params).iter()
+			.map(|is_swamp| *is_swamp as u8)
+			.collect();
+
+		Overlays {
+			lakes: lakes.mask(),
+			rivers: river_network.order().into(),
+			swamps,
+			climate: climate::compute_climate(landscape, &settings.climate_params()),
+		}
+	}
+}
+
+fn define_topology(glsl_manager: &mut ShaderContext, topology: Topology) {
+	glsl_manager.define("WORLD_WRAP_X", topology.wrap_x as i32);
+	glsl_manager.define("WORLD_WRAP_Y", topology.wrap_y as i32);
+}
+
+/// Reads an `R8UI` texture of the world size
+fn read_cells(gl: &Context, texture: NativeTexture, size: (u64, u64)) -> Box<[u8]> {
+	let mut data = vec![0_u8; (size.0 * size.1) as usize].into_boxed_slice();
+	unsafe {
+		gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+		gl.pixel_store_i32(glow::PACK_ALIGNMENT, 1);
+		gl.get_tex_image(glow::TEXTURE_2D, 0, glow::RED_INTEGER, glow::UNSIGNED_BYTE, PixelPackData::Slice(&mut data));
+		gl.pixel_store_i32(glow::PACK_ALIGNMENT, 4);
+	}
+	data
+}
+
+fn write_cells(gl: &Context, texture: NativeTexture, size: (u64, u64), cells: &[u8]) {
+	unsafe {
+		gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+		gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+		gl.tex_sub_image_2d(glow::TEXTURE_2D, 0, 0, 0, size.0 as i32, size.1 as i32,
+							glow::RED_INTEGER, glow::UNSIGNED_BYTE, PixelUnpackData::Slice(cells));
+		gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+	}
+}
+
 impl Drop for World {
 	fn drop(&mut self) {
 		unsafe {
 			self.gl.delete_texture(self.current_buf);
 			self.gl.delete_texture(self.next_buf);
+			self.gl.delete_texture(self.landscape);
+			self.gl.delete_texture(self.lakes);
+			self.gl.delete_texture(self.rivers);
+			self.gl.delete_texture(self.swamps);
 			self.gl.delete_vertex_array(self.vertex_array);
 			self.gl.delete_program(self.program);
 			self.gl.delete_program(self.render_program);